@@ -19,10 +19,50 @@ fn main() {
         .map(|p| p.parse::<u32>().unwrap())
         .unwrap_or(20);
 
+    let burst_period: u32 = option_env!("BURST_PERIOD")
+        .map(|p| p.parse::<u32>().unwrap())
+        .unwrap_or(0);
+
+    let burst_length: u32 = option_env!("BURST_LENGTH")
+        .map(|p| p.parse::<u32>().unwrap())
+        .unwrap_or(20);
+
+    let burst_position_age: u32 = option_env!("BURST_POSITION_AGE")
+        .map(|p| p.parse::<u32>().unwrap())
+        .unwrap_or(0);
+
+    // Output frequency of the packages [Hz], must be one of the rates in `waves::RATES`. The
+    // `20Hz` feature is kept for existing builds and selects the 26 Hz FIR rate.
+    let output_freq: u32 = option_env!("OUTPUT_FREQ")
+        .map(|p| p.parse::<u32>().unwrap())
+        .unwrap_or(if env::var_os("CARGO_FEATURE_20HZ").is_some() {
+            26
+        } else if env::var_os("CARGO_FEATURE_FIR").is_some() {
+            52
+        } else {
+            208
+        });
+
+    // Order of the packages that are sent after being spilled to the SD-card.
+    let unsent_order = option_env!("UNSENT_ORDER").unwrap_or("oldest");
+    assert!(
+        ["oldest", "newest"].contains(&unsent_order),
+        "UNSENT_ORDER must be oldest or newest"
+    );
+
     let fd = fs::File::create(&dest_path).unwrap();
     writeln!(&fd, "pub const GPS_PERIOD: u32 = {gps_period};").unwrap();
     writeln!(&fd, "pub const GPS_HEARTBEAT: i32 = {gps_heartbeat};").unwrap();
     writeln!(&fd, "pub const SYNC_PERIOD: u32 = {sync_period};").unwrap();
+    writeln!(&fd, "pub const BURST_PERIOD: u32 = {burst_period};").unwrap();
+    writeln!(&fd, "pub const BURST_LENGTH: u32 = {burst_length};").unwrap();
+    writeln!(
+        &fd,
+        "pub const BURST_POSITION_AGE: u32 = {burst_position_age};"
+    )
+    .unwrap();
+    writeln!(&fd, "pub const OUTPUT_FREQ: u32 = {output_freq};").unwrap();
+    writeln!(&fd, "pub const UNSENT_ORDER: &str = \"{unsent_order}\";").unwrap();
 
     if option_env!("BUOYSN").is_none() {
         println!("cargo:warning=BUOYSN: No buoy name supplied, using device id or previously configured.");
@@ -49,6 +49,7 @@ use hal::{
 
 use sfy::log::log;
 use sfy::note::Notecarrier;
+use sfy::watchdog::{self, ResetReason, ResetStatus, PROGRESS};
 use sfy::waves::Waves;
 use sfy::{gps::EgpsTime, Imu, Location, SharedState, State, NOTEQ};
 #[cfg(feature = "storage")]
@@ -114,6 +115,14 @@ fn main() -> ! {
     let mut dp = hal::pac::Peripherals::take().unwrap();
     let core = hal::pac::CorePeripherals::take().unwrap();
     let mut delay = hal::delay::Delay::new(core.SYST, &mut dp.CLKGEN);
+    let mut scb = core.SCB;
+
+    // Read and clear the reset status before anything else can cause a reset.
+    let reset_reason = read_reset_reason(&dp.RSTGEN);
+    let resets = watchdog::count_reset(reset_reason);
+
+    // Take any crash dump from before the reset, and start recording log messages for the next.
+    let crash = sfy::crash::init();
 
     let pins = hal::gpio::Pins::new(dp.GPIO);
 
@@ -135,20 +144,31 @@ fn main() -> ! {
     println!("version .....: {}", git_version!());
     println!("storage .....: {}", cfg!(feature = "storage"));
     println!("fir .........: {}", cfg!(feature = "fir"));
-    println!("raw .........: {}", cfg!(feature = "raw"));
     println!("20Hz ........: {}", cfg!(feature = "20Hz"));
+    println!("raw .........: {}", cfg!(feature = "raw"));
+    println!("spectrum ....: {}", cfg!(feature = "spectrum"));
     println!("continuous ..: {}", cfg!(feature = "continuous"));
     println!("cont-post ...: {}", cfg!(feature = "continuous-post"));
     println!("deploy ......: {}", cfg!(feature = "deploy"));
     println!("defmt-serial : {}", cfg!(feature = "defmt-serial"));
     println!("EXT-GPS .....: true");
+    println!("ubx .........: {}", cfg!(feature = "ubx"));
     println!("NOTEQ_SZ ....: {}", sfy::NOTEQ_SZ);
     println!("IMUQ_SZ .....: {}", sfy::IMUQ_SZ);
     println!("STORAGEQ_SZ .: {}", sfy::STORAGEQ_SZ);
     println!("GPS_PERIOD ..: {}", sfy::note::GPS_PERIOD);
     println!("GPS_HEARTBEAT: {}", sfy::note::GPS_HEARTBEAT);
     println!("SYNC_PERIOD .: {}", sfy::note::SYNC_PERIOD);
+    println!("OUTPUT_FREQ .: {}", sfy::note::OUTPUT_FREQ);
+    println!("BURST_PERIOD : {}", sfy::note::BURST_PERIOD);
+    println!("BURST_LENGTH : {}", sfy::note::BURST_LENGTH);
     println!("EXT_SIM_APN .: {}", sfy::note::EXT_APN);
+    println!("reset reason : {}", reset_reason.as_str());
+    println!("resets ......: {}", resets);
+
+    if let Some(crash) = &crash {
+        warn!("crash dump from before reset: {}", crash);
+    }
 
     info!("Setting up IOM and RTC.");
     delay.delay_ms(1_000u32);
@@ -172,8 +192,13 @@ fn main() -> ! {
 
     // Set up GPS serial
     info!("Setting up GPS serial..");
+    #[cfg(not(feature = "ubx"))]
     let gps_serial = hal::uart::new_12_13(dp.UART1, pins.a16, pins.a0, 100_000);
 
+    // The receiver must be set up to send UBX-NAV-PVT on UART1 at this baud rate.
+    #[cfg(feature = "ubx")]
+    let gps_serial = hal::uart::new_12_13(dp.UART1, pins.a16, pins.a0, 115_200);
+
     // Set up GPS GPIO interrupt on pin A2
     info!("Setting up GPS interrupt.");
     let mut a2 = pins.a2.into_input();
@@ -230,6 +255,13 @@ fn main() -> ! {
             })
             .ok();
 
+        if let Some(crash) = &crash {
+            storage
+                .store_crash(crash)
+                .inspect_err(|e| defmt::error!("Failed to store crash dump: {}", e))
+                .ok();
+        }
+
         storage
     };
 
@@ -239,8 +271,18 @@ fn main() -> ! {
     #[cfg(feature = "storage")]
     let (note_p, mut imu_queue) = unsafe { NOTEQ.split() };
 
+    // GPS packages go through the SD-card before the Notecard when storage is enabled.
+    let (gps_p, gps_consumer) = unsafe { sfy::gps::EGPSQ.split() };
+
     #[cfg(feature = "storage")]
-    let mut storage_manager = sfy::StorageManager::new(storage, storage_consumer, note_p);
+    let (gps_note_p, mut gps_queue) = unsafe { sfy::gps::EGPS_NOTEQ.split() };
+
+    #[cfg(feature = "storage")]
+    let mut storage_manager =
+        sfy::StorageManager::new(storage, storage_consumer, note_p, gps_consumer, gps_note_p);
+
+    #[cfg(not(feature = "storage"))]
+    let mut gps_queue = gps_consumer;
 
     #[cfg(not(feature = "storage"))]
     let (imu_p, mut imu_queue) = unsafe { NOTEQ.split() };
@@ -250,26 +292,35 @@ fn main() -> ! {
 
     info!("Send startup-message over cellular.");
 
-    let mut w = heapless::String::<100>::new();
+    let mut w = heapless::String::<160>::new();
     w.push_str("SFY (v").unwrap();
     w.push_str(git_version!()).unwrap();
     w.push_str(") (sn: ").unwrap();
-    match sfy::note::BUOYSN {
+    match &note.config().name {
         Some(sn) => w.push_str(sn).unwrap(),
         None => w.push_str("None").unwrap(),
     };
-    w.push_str(") started up.").unwrap();
+    w.push_str(") started up (reset: ").unwrap();
+    w.push_str(reset_reason.as_str()).unwrap();
+    w.push_str(").").unwrap();
     info!("{}", w);
 
     note.hub()
         .log(&mut delay, w.as_str(), false, false)
         .and_then(|r| r.wait(&mut delay))
         .ok(); // this will fail if more than 100 notes is added.
-               //
-               // Move state into globally available variables and set reference to NOTE for
-               // logging on panic and hard resets.
-               //
-               // TODO: Should maybe `pin_mut!` NOTE to prevent it being moved on the stack.
+
+    if let Some(crash) = &crash {
+        info!("Sending crash dump..");
+        note.send_crash(crash, &mut delay)
+            .inspect_err(|e| error!("Failed to send crash dump: {:?}", e))
+            .ok();
+    }
+
+    // Move state into globally available variables and set reference to NOTE for
+    // logging on panic and hard resets.
+    //
+    // TODO: Should maybe `pin_mut!` NOTE to prevent it being moved on the stack.
     free(|cs| unsafe {
         log::NOTE = Some(&mut note as *mut _);
 
@@ -301,7 +352,9 @@ fn main() -> ! {
     );
 
     info!("Setting up IMU..");
-    let mut waves = Waves::new(i2c3).unwrap();
+    let rate = note.config().rate();
+    sfy::waves::request_rate(rate);
+    let mut waves = Waves::new(i2c3, rate).unwrap();
     waves
         .take_buf(
             now.map(|t| t.timestamp_millis()).unwrap_or(0),
@@ -311,13 +364,17 @@ fn main() -> ! {
         )
         .unwrap(); // set timestamp.
 
+    // Packages sampled from start-up until the first burst ends belong to a burst starting now.
+    if !note.config().schedule().is_continuous() {
+        waves.burst_start = now.map(|t| t.timestamp_millis()).unwrap_or(0);
+    }
+
     info!("Enable IMU.");
     waves.enable_fifo(&mut delay).unwrap();
 
     let imu = sfy::Imu::new(waves, imu_p);
 
     info!("Setting up ext-gps..");
-    let (gps_p, mut gps_queue) = unsafe { sfy::gps::EGPSQ.split() };
     let gps = sfy::gps::Gps::new(gps_serial, gps_p);
 
     // Move IMU and GPS into temporary variables for moving it into the `RTC`  and GPIO
@@ -333,11 +390,26 @@ fn main() -> ! {
         cortex_m::interrupt::enable();
     });
 
+    info!("Setting up watchdog..");
+    setup_watchdog(&dp.WDT, &dp.RSTGEN);
+
+    // The watchdog is only fed when all of these have made progress.
+    #[cfg(feature = "storage")]
+    const WATCHDOG_TASKS: u8 = watchdog::IMU | watchdog::STORAGE | watchdog::NOTE;
+    #[cfg(not(feature = "storage"))]
+    const WATCHDOG_TASKS: u8 = watchdog::IMU | watchdog::NOTE;
+
     info!("Entering main loop");
     const GOOD_TRIES: u32 = 15;
 
     let mut last: i64 = 0;
+    let mut status = sfy::status::Status::new(
+        STATE.now().map(|t| t.timestamp_millis()).unwrap_or(0),
+        resets,
+        reset_reason,
+    );
     let mut good_tries: u32 = GOOD_TRIES;
+    let mut burst = sfy::schedule::Burst::new();
     #[cfg(feature = "storage")]
     let mut sd_good: bool = true; // Do not spam with log messags.
 
@@ -371,13 +443,52 @@ fn main() -> ! {
             _ => {}
         };
 
+        // Move GPS packages to SD card and enqueue for Notecard.
+        #[cfg(feature = "storage")]
+        storage_manager
+            .drain_gps_queue()
+            .inspect_err(|e| error!("Failed to write GPS package to SD card: {:?}", e))
+            .ok();
+
+        #[cfg(feature = "storage")]
+        PROGRESS.set(watchdog::STORAGE);
+
         // XXX: This needs to be adapted to frequency, and queue length. Maybe just remove when we
         // have the remaining space check? Check after Hjeltefjorden deployment.
         const LOOP_DELAY: u32 = 3_000;
         const SHORT_LOOP_DELAY: u32 = 3_000;
 
+        // Between bursts the Notecard is only checked this often, must be less than the
+        // watchdog timeout.
+        const SLEEP_LOOP_DELAY: u32 = 15 * 60_000;
+
+        // Start or stop sampling according to the schedule.
+        if let Some(now) = now {
+            // The position is otherwise only updated in the Notecard iteration.
+            if !burst.sampling() {
+                location.set_from_egps(&STATE, &EGPS_TIME);
+            }
+
+            let (_, position_time, _, _) = STATE.get();
+
+            if let Some(sampling) = burst.update(&note.config().schedule(), now, position_time) {
+                info!("Burst: sampling: {}", sampling);
+                sfy::schedule::request(sampling);
+                deep_sleep(!sampling, &mut scb);
+
+                // Send remaining packages and set the hub mode right away.
+                last = 0;
+            }
+        }
+
+        let loop_delay = if burst.sampling() {
+            LOOP_DELAY
+        } else {
+            SLEEP_LOOP_DELAY
+        };
+
         // Process data and communication for the Notecard.
-        if ((now.unwrap_or(sfy::FUTURE.timestamp_millis()) - last) > LOOP_DELAY as i64)
+        if ((now.unwrap_or(sfy::FUTURE.timestamp_millis()) - last) > loop_delay as i64)
             || ((imu_queue.capacity() - imu_queue.len()) < 3
                 && (now.unwrap_or(sfy::FUTURE.timestamp_millis()) - last) > SHORT_LOOP_DELAY as i64)
         {
@@ -420,10 +531,67 @@ fn main() -> ! {
                 .inspect_err(|e| defmt::error!("drain log: {:?}", e))
                 .ok();
 
+            // Apply changes to the configuration from the environment variables on Notehub.
+            if let Some(now) = now {
+                note.check_config(now, &mut delay)
+                    .inspect_err(|e| defmt::error!("check config: {:?}", e))
+                    .ok();
+            }
+            sfy::waves::request_rate(note.config().rate());
+
             let nd = note.drain_queue(&mut imu_queue, &mut delay);
             let ng = note.drain_egps_queue(&mut gps_queue, &mut delay);
+
+            // Resend packages requested from the SD-card, behind the live packages.
+            #[cfg(feature = "storage")]
+            storage_manager
+                .queue_requested_packages(&mut note, &mut delay)
+                .inspect_err(|e| error!("Failed to queue requested packages: {:?}", e))
+                .ok();
+
+            #[cfg(feature = "storage")]
+            storage_manager
+                .queue_requested_gps(&mut note, &mut delay)
+                .inspect_err(|e| error!("Failed to queue requested GPS packages: {:?}", e))
+                .ok();
+
+            // Resend packages that were spilled to the SD-card while the Notecard was full.
+            #[cfg(feature = "storage")]
+            storage_manager
+                .queue_unsent(&mut note, &mut delay)
+                .inspect_err(|e| error!("Failed to queue unsent packages: {:?}", e))
+                .ok();
+
+            // Send health and status of the buoy.
+            if status.tick(now) {
+                let mut queues = sfy::status::Queues::default();
+                queues.note = imu_queue.len();
+                queues.egps = gps_queue.len();
+
+                #[cfg(feature = "storage")]
+                {
+                    queues.storage = storage_manager.storage_queue.len();
+                }
+
+                let mut s = status.note(now.unwrap_or(0), queues);
+
+                #[cfg(feature = "storage")]
+                storage_manager.status(&mut s);
+
+                note.send_status(s, &mut delay)
+                    .inspect_err(|e| error!("Failed to send status: {:?}", e))
+                    .ok();
+            }
+
             let ns = note.check_and_sync(&mut delay);
 
+            // Let the Notecard sleep between bursts.
+            if note.low_power() == burst.sampling() {
+                note.set_low_power(!burst.sampling(), &mut delay)
+                    .inspect_err(|e| error!("Failed to set hub mode: {:?}", e))
+                    .ok();
+            }
+
             match (nd, ng, ns) {
                 (Ok(_), Ok(_), Ok(_)) => good_tries = GOOD_TRIES,
                 (dq, dg, cs) => {
@@ -460,6 +628,7 @@ fn main() -> ! {
                 }
             };
             last = now.unwrap_or(0);
+            PROGRESS.set(watchdog::NOTE);
         }
 
         #[cfg(not(feature = "deploy"))]
@@ -470,8 +639,10 @@ fn main() -> ! {
 
         // defmt::flush();
 
-        // TODO:
-        // * Set up and feed watchdog.
+        if PROGRESS.check(WATCHDOG_TASKS) {
+            trace!("Feeding watchdog.");
+            feed_watchdog();
+        }
     }
 }
 
@@ -492,6 +663,80 @@ fn reset<I: Read + Write>(note: &mut Notecarrier<I>, delay: &mut impl DelayMs<u1
     cortex_m::peripheral::SCB::sys_reset()
 }
 
+/// Read the reason for the last reset from the reset generator, and clear the status.
+fn read_reset_reason(rstgen: &hal::pac::RSTGEN) -> ResetReason {
+    let stat = rstgen.stat.read();
+
+    let status = ResetStatus {
+        power_on: stat.porstat().bit_is_set(),
+        brownout: stat.borstat().bit_is_set(),
+        watchdog: stat.wdrstat().bit_is_set(),
+        software: stat.swrstat().bit_is_set() || stat.poirstat().bit_is_set(),
+        external: stat.exrstat().bit_is_set(),
+        debugger: stat.dbgrstat().bit_is_set(),
+    };
+
+    rstgen.clrstat.write(|w| w.clrstat().set_bit());
+
+    let reason = ResetReason::from_status(status, watchdog::take_reset_marker());
+    info!("Reset status: {}, reason: {}", status, reason);
+
+    reason
+}
+
+/// Go to deep sleep between bursts, the RTC only needs to wake up the MCU every second when the
+/// IMU is not sampling.
+fn deep_sleep(sleep: bool, scb: &mut cortex_m::peripheral::SCB) {
+    free(|cs| {
+        let mut state = STATE.borrow(cs).borrow_mut();
+        let state = state.as_mut().unwrap();
+
+        state.rtc.set_alarm_repeat(if sleep {
+            hal::rtc::AlarmRepeat::Second
+        } else {
+            hal::rtc::AlarmRepeat::DeciSecond
+        });
+    });
+
+    if sleep {
+        scb.set_sleepdeep();
+    } else {
+        scb.clear_sleepdeep();
+    }
+}
+
+/// Time before the watchdog resets the system if it is not fed [s].
+const WATCHDOG_TIMEOUT: u32 = 20 * 60;
+
+/// Set up the watchdog to reset the system after `WATCHDOG_TIMEOUT`.
+fn setup_watchdog(wdt: &hal::pac::WDT, rstgen: &hal::pac::RSTGEN) {
+    // Let the watchdog reset the system, not just interrupt.
+    rstgen.cfg.modify(|_, w| w.wdren().set_bit());
+
+    // The slowest watchdog clock is 1/16 Hz, and the reset value is 8 bits: max ~68 minutes.
+    wdt.cfg.write(|w| unsafe {
+        w.clksel()
+            .bits(4) // 1/16 Hz
+            .resval()
+            .bits((WATCHDOG_TIMEOUT / 16) as u8)
+            .resen()
+            .set_bit()
+            .wdten()
+            .set_bit()
+    });
+
+    feed_watchdog();
+}
+
+/// Restart the watchdog counter.
+fn feed_watchdog() {
+    unsafe {
+        (*(hal::pac::WDT::ptr()))
+            .rstrt
+            .write(|w| w.rstrt().bits(0xb2));
+    }
+}
+
 #[cfg(not(feature = "host-tests"))]
 #[allow(non_snake_case)]
 #[interrupt]
@@ -601,6 +846,37 @@ fn RTC() {
 
         COUNT.store((now / 1000).try_into().unwrap_or(0), Ordering::Relaxed);
 
+        // Start or stop sampling when requested by the schedule.
+        let sampling = sfy::schedule::requested();
+        if sampling != imu.sampling() {
+            let mut delay = hal::delay::FlashDelay;
+
+            let r = if sampling {
+                imu.wake(now, position_time, lon, lat, &mut delay)
+            } else {
+                imu.sleep()
+            };
+
+            r.inspect_err(|e| error!("IMU: failed to start or stop sampling: {:?}", e))
+                .ok();
+        }
+
+        // Change the output rate when requested by the configuration.
+        let rate = sfy::waves::requested_rate();
+        if rate != imu.rate() {
+            let mut delay = hal::delay::FlashDelay;
+
+            imu.set_rate(rate, now, position_time, lon, lat, &mut delay)
+                .inspect_err(|e| error!("IMU: failed to change output rate: {:?}", e))
+                .ok();
+        }
+
+        if !imu.sampling() {
+            // The IMU is not expected to make progress between bursts.
+            PROGRESS.set(watchdog::IMU);
+            return;
+        }
+
         // XXX: This is the most time-critical part of the program.
         //
         // It seems that the IMU I2C communication sometimes fails with a NAK, causing a module
@@ -609,6 +885,7 @@ fn RTC() {
             |cs| match imu.check_retrieve(now, position_time, lon, lat) {
                 Ok(_) => {
                     *GOOD_TRIES = 5;
+                    PROGRESS.set(watchdog::IMU);
                 }
                 Err(e) => {
                     error!("IMU ISR failed: {:?}, resetting IMU..", e);
@@ -651,6 +928,21 @@ unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
         "hard fault exception: {:#?}. resetting system.",
         defmt::Debug2Format(ef)
     );
+    sfy::crash::save(
+        ResetReason::HardFault,
+        Some([
+            ef.r0(),
+            ef.r1(),
+            ef.r2(),
+            ef.r3(),
+            ef.r12(),
+            ef.lr(),
+            ef.pc(),
+            ef.xpsr(),
+        ]),
+        "",
+    );
+    watchdog::set_reset_marker(ResetReason::HardFault);
     cortex_m::peripheral::SCB::sys_reset()
 }
 
@@ -665,11 +957,13 @@ fn panic(info: &PanicInfo) -> ! {
         .inspect_err(|e| defmt::error!("failed to format panic: {:?}", defmt::Debug2Format(e)))
         .ok();
     log(&msg);
+    sfy::crash::save(ResetReason::Panic, None, &msg);
 
     let mut delay = hal::delay::FlashDelay;
 
     free(|_| unsafe { sfy::log::panic_drain_log(log::NOTE, &mut delay) });
 
     defmt::error!("panic logged, resetting..");
+    watchdog::set_reset_marker(ResetReason::Panic);
     cortex_m::peripheral::SCB::sys_reset();
 }
@@ -0,0 +1,139 @@
+//! Definitions of the messages from the buoy that are shared between the buoy (`sfy`) and the
+//! receiving server (`sfy-data`).
+//!
+//! > `sfy-processing/sfy/axl.py` must be kept in sync by hand.
+#![no_std]
+
+use serde::{Deserialize, Serialize};
+
+pub mod rice;
+
+// From Adafruit Sensors library.
+// pub const SENSORS_RADS_TO_DPS: f64 = 57.29577793;
+pub const SENSORS_DPS_TO_RADS: f64 = 0.017453293;
+pub const SENSORS_GRAVITY_STANDARD: f64 = 9.80665;
+
+/// Encoding of the samples in the payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// The samples as little endian `u16`s.
+    Raw = 0,
+
+    /// Delta and Rice coded samples, see [`rice`] (added in v8).
+    Rice = 1,
+}
+
+/// How the samples of a transmitted package were reduced, the full package is always stored on
+/// the SD-card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// All the samples.
+    Full = 0,
+
+    /// Every `DECIMATION`th sample, with `freq` and `offset` for the remaining samples. Sent
+    /// when the uplink cannot keep up.
+    Decimated = 1,
+}
+
+fn f32_not_normal(f: &f32) -> bool {
+    !f32::is_subnormal(*f)
+}
+
+fn default_storage_version() -> u32 {
+    1
+}
+
+fn default_freq() -> f32 {
+    208.
+}
+
+fn default_accel_range() -> f32 {
+    1. // added in v6
+}
+
+fn default_gyro_range() -> f32 {
+    125. // added in v6
+}
+
+/// The body of an `axl.qo` note. The Notecard drops fields with default values, so most fields
+/// have defaults when the note is deserialized.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct AxlPacketMeta {
+    /// Timestamp of sample at `offset` in ms.
+    #[serde(default)]
+    pub timestamp: i64,
+
+    /// Offset in IMU FIFO at time of timestamp.
+    #[serde(default)]
+    pub offset: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_id: Option<u32>,
+
+    #[serde(default = "default_storage_version")]
+    pub storage_version: u32,
+
+    #[serde(default)]
+    pub position_time: u32,
+    #[serde(default)]
+    pub lon: f64,
+    #[serde(default)]
+    pub lat: f64,
+
+    #[serde(default, skip_serializing_if = "f32_not_normal")]
+    pub temperature: f32,
+
+    #[serde(default = "default_freq")]
+    pub freq: f32,
+
+    /// Accelerometer range [g].
+    #[serde(default = "default_accel_range")]
+    pub accel_range: f32,
+
+    /// Gyro range [dps].
+    #[serde(default = "default_gyro_range")]
+    pub gyro_range: f32,
+
+    /// Start of the burst the samples belong to in ms, 0 when sampling continuously (added in
+    /// v7).
+    #[serde(default)]
+    pub burst_start: i64,
+
+    /// Encoding of the samples in the payload, see [`Codec`] (added in v8).
+    #[serde(default)]
+    pub codec: u32,
+
+    /// Number of values when the samples are compressed (added in v8).
+    #[serde(default)]
+    pub samples: u32,
+
+    /// How the samples were reduced, see [`Mode`]. This did not change the storage version,
+    /// packages without it are `Full`.
+    #[serde(default)]
+    pub mode: u32,
+
+    /// Length of base64 payload.
+    pub length: u32,
+}
+
+impl AxlPacketMeta {
+    /// Scaling of acceleration values [m/s^2].
+    pub fn accel_max(&self) -> f32 {
+        2. * self.accel_range * SENSORS_GRAVITY_STANDARD as f32
+    }
+
+    /// Scaling of gyro values [rad/s].
+    pub fn gyro_max(&self) -> f32 {
+        2. * self.gyro_range * SENSORS_DPS_TO_RADS as f32
+    }
+}
+
+/// Move an u16 on given -max to max range to its real value in f32.
+pub fn scale_u16_to_f32(max: f32, u: u16) -> f32 {
+    debug_assert!(max > 0.);
+    let max = max as f64;
+    let v = u as f64;
+    let v = v * (2. * max) / u16::MAX as f64;
+    let v = v - max;
+    return v as f32;
+}
@@ -0,0 +1,97 @@
+//! Decoding of delta and Rice coded samples (codec 1 in `axl.qo` notes since version 8), shared by
+//! the buoy and `sfy-data`.
+//!
+//! See `sfy-buoy/src/rice.rs` for the format and the encoder.
+//!
+//! > Keep in sync with `sfy-buoy/src/rice.rs` and `sfy-processing/sfy/rice.py`.
+
+/// Number of values in each sample (x, y, z).
+pub const SAMPLE_SZ: usize = 3;
+
+/// Number of residuals sharing a Rice parameter.
+pub const BLOCK: usize = 64;
+
+/// Length of the unary part that escapes to a 16 bit residual.
+pub const ESCAPE: u32 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The stream ended before all values were decoded.
+    Truncated,
+
+    /// The number of values is not a multiple of `SAMPLE_SZ`.
+    Length,
+}
+
+pub fn unzigzag(u: u16) -> i16 {
+    ((u >> 1) as i16) ^ -((u & 1) as i16)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn bit(&mut self) -> Result<u32, DecodeError> {
+        let b = self.data.get(self.pos / 8).ok_or(DecodeError::Truncated)?;
+        let bit = (b >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+
+        Ok(bit as u32)
+    }
+
+    fn get(&mut self, n: u32) -> Result<u32, DecodeError> {
+        let mut v = 0;
+        for _ in 0..n {
+            v = (v << 1) | self.bit()?;
+        }
+
+        Ok(v)
+    }
+}
+
+/// Decompress `out.len()` interleaved values from `data` into `out`.
+pub fn decode(data: &[u8], out: &mut [u16]) -> Result<(), DecodeError> {
+    if out.len() % SAMPLE_SZ != 0 {
+        return Err(DecodeError::Length);
+    }
+
+    let n = out.len() / SAMPLE_SZ;
+    let mut r = BitReader { data, pos: 0 };
+
+    for axis in 0..SAMPLE_SZ {
+        if n == 0 {
+            break;
+        }
+
+        let mut v = r.get(16)? as u16;
+        out[axis] = v;
+
+        let mut i = 1;
+        while i < n {
+            let end = (i + BLOCK).min(n);
+            let k = r.get(4)?;
+
+            for j in i..end {
+                let mut q = 0;
+                while q < ESCAPE && r.bit()? == 1 {
+                    q += 1;
+                }
+
+                let u = if q == ESCAPE {
+                    r.get(16)?
+                } else {
+                    (q << k) | r.get(k)?
+                };
+
+                v = v.wrapping_add(unzigzag(u as u16) as u16);
+                out[j * SAMPLE_SZ + axis] = v;
+            }
+
+            i = end;
+        }
+    }
+
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use defmt::{write, Format, Formatter};
 use heapless::{String, Vec};
 
+use crate::rice;
+
 #[cfg(feature = "raw")]
 pub const SAMPLE_NO: usize = 1024;
 
@@ -9,7 +11,10 @@ pub const SAMPLE_NO: usize = 1024;
 
 pub const SAMPLE_SZ: usize = 3;
 pub const AXL_SZ: usize = SAMPLE_SZ * SAMPLE_NO;
-pub const VERSION: u32 = 6;
+pub const VERSION: u32 = 9;
+
+/// Only every `DECIMATION`th sample is sent in decimated packages (see [`Mode`]).
+pub const DECIMATION: usize = 4;
 
 /// Maximum length of base64 string from [f16; AXL_SZ]
 pub const AXL_OUTN: usize = { AXL_SZ * 2 } * 4 / 3 + 4;
@@ -48,39 +53,56 @@ pub struct AxlPacket {
     pub accel_range: f32,
     pub gyro_range: f32,
 
+    /// Start of the burst the samples belong to in ms, 0 when sampling continuously.
+    pub burst_start: i64,
+
     /// IMU data. This is moved to the payload when transmitting.
     pub data: Vec<u16, { AXL_SZ }>,
 }
 
-fn f32_not_normal(f: &f32) -> bool {
-    !f32::is_subnormal(*f)
-}
-
-#[derive(serde::Serialize, Default)]
-pub struct AxlPacketMeta {
+/// `AxlPacket` as stored on the SD-card before version 7, without `burst_start`.
+#[derive(serde::Deserialize)]
+pub struct AxlPacketV6 {
     pub timestamp: i64,
-    pub offset: u32,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: u16,
     pub storage_id: Option<u32>,
     pub storage_version: u32,
-
     pub position_time: u32,
     pub lon: f64,
     pub lat: f64,
-
-    #[serde(skip_serializing_if = "f32_not_normal")]
     pub temperature: f32,
-
     pub freq: f32,
-    pub accel_range: f32, // g
-    pub gyro_range: f32,  // dps
-    pub length: u32,
+    pub accel_range: f32,
+    pub gyro_range: f32,
+    pub data: Vec<u16, { AXL_SZ }>,
+}
+
+impl From<AxlPacketV6> for AxlPacket {
+    /// The burst is not known, so every package is its own burst.
+    fn from(p: AxlPacketV6) -> AxlPacket {
+        AxlPacket {
+            timestamp: p.timestamp,
+            offset: p.offset,
+            storage_id: p.storage_id,
+            storage_version: p.storage_version,
+            position_time: p.position_time,
+            lon: p.lon,
+            lat: p.lat,
+            temperature: p.temperature,
+            freq: p.freq,
+            accel_range: p.accel_range,
+            gyro_range: p.gyro_range,
+            burst_start: p.timestamp,
+            data: p.data,
+        }
+    }
 }
 
+pub use sfy_wire::{AxlPacketMeta, Codec, Mode};
+
 impl core::fmt::Debug for AxlPacket {
     fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        core::write!(fmt, "AxlPacket(timestamp: {}, offset: {}, storage_id: {:?} (v: {:?}), position_time: {}, lon: {}, lat: {}, temp: {}, freq: {}, accel_range: {}, gyro_range: {}, data (length): {}))",
+        core::write!(fmt, "AxlPacket(timestamp: {}, offset: {}, storage_id: {:?} (v: {:?}), position_time: {}, lon: {}, lat: {}, temp: {}, freq: {}, accel_range: {}, gyro_range: {}, burst_start: {}, data (length): {}))",
             self.timestamp,
             self.offset,
             self.storage_id,
@@ -92,6 +114,7 @@ impl core::fmt::Debug for AxlPacket {
             self.freq,
             self.accel_range,
             self.gyro_range,
+            self.burst_start,
             self.data.len()
             )
     }
@@ -99,7 +122,7 @@ impl core::fmt::Debug for AxlPacket {
 
 impl Format for AxlPacket {
     fn format(&self, fmt: Formatter) {
-        write!(fmt, "AxlPacket(timestamp: {}, offset: {}, storage_id: {:?}, position_time: {}, lon: {}, lat: {}, temp: {}, freq: {}, accel_range: {}, gyro_range: {}, data (length): {}))",
+        write!(fmt, "AxlPacket(timestamp: {}, offset: {}, storage_id: {:?}, position_time: {}, lon: {}, lat: {}, temp: {}, freq: {}, accel_range: {}, gyro_range: {}, burst_start: {}, data (length): {}))",
             self.timestamp,
             self.offset,
             self.storage_id,
@@ -110,38 +133,112 @@ impl Format for AxlPacket {
             self.freq,
             self.accel_range,
             self.gyro_range,
+            self.burst_start,
             self.data.len()
             );
     }
 }
 
+fn encode_base64(data: &[u8]) -> Vec<u8, AXL_OUTN> {
+    let mut b64: Vec<_, AXL_OUTN> = Vec::new();
+    b64.resize_default(AXL_OUTN).unwrap();
+
+    let written = base64::encode_config_slice(data, base64::STANDARD, &mut b64);
+    b64.truncate(written);
+
+    b64
+}
+
+/// The compressed samples, or the uncompressed samples if they do not get smaller.
+fn encode(data: &[u16]) -> (Codec, Vec<u8, AXL_OUTN>) {
+    let mut c: Vec<u8, { AXL_SZ * 2 }> = Vec::new();
+
+    match rice::encode(data, &mut c) {
+        Ok(()) => (Codec::Rice, encode_base64(&c)),
+        Err(e) => {
+            defmt::debug!("not compressing samples: {}", e);
+            (Codec::Raw, encode_base64(bytemuck::cast_slice(data)))
+        }
+    }
+}
+
 impl AxlPacket {
-    pub fn base64(&self) -> Vec<u8, AXL_OUTN> {
-        let mut b64: Vec<_, AXL_OUTN> = Vec::new();
-        b64.resize_default(AXL_OUTN).unwrap();
+    /// Deserialize a COBS encoded package stored with storage `version` (the file extension of the
+    /// collection).
+    pub fn from_bytes_cobs(version: u32, buf: &mut [u8]) -> Result<AxlPacket, postcard::Error> {
+        if version < 7 {
+            postcard::from_bytes_cobs::<AxlPacketV6>(buf).map(AxlPacket::from)
+        } else {
+            postcard::from_bytes_cobs(buf)
+        }
+    }
 
+    /// Scaling of the samples [m/s^2], given by the accelerometer range of the package.
+    pub fn accel_max(&self) -> f32 {
+        crate::waves::wire::accel_max(self.accel_range)
+    }
+
+    /// The uncompressed samples.
+    pub fn base64(&self) -> Vec<u8, AXL_OUTN> {
         // Check endianness (TODO:  swap order if compiled for big endian machine).
         #[cfg(target_endian = "big")]
         compile_error!("serializied samples are assumed to be in little endian, target platform is big endian and no conversion is implemented.");
 
-        let data = bytemuck::cast_slice(&self.data);
-        let written = base64::encode_config_slice(data, base64::STANDARD, &mut b64);
-        b64.truncate(written);
+        encode_base64(bytemuck::cast_slice(&self.data))
+    }
 
-        b64
+    /// The compressed samples, or the uncompressed samples if they do not get smaller.
+    pub fn payload(&self) -> (Codec, Vec<u8, AXL_OUTN>) {
+        encode(&self.data)
     }
 
     /// Split package into metadata and payload.
     pub fn split(&self) -> (AxlPacketMeta, Vec<u8, AXL_OUTN>) {
-        let b64 = self.base64();
+        self.split_mode(Mode::Full)
+    }
+
+    /// Split package into metadata and payload, with the samples reduced according to `mode`.
+    pub fn split_mode(&self, mode: Mode) -> (AxlPacketMeta, Vec<u8, AXL_OUTN>) {
+        let (codec, b64, samples, offset, freq) = match mode {
+            Mode::Full => {
+                let (codec, b64) = self.payload();
+                (codec, b64, self.data.len(), self.offset, self.freq)
+            }
+            Mode::Decimated => {
+                // Start at the sample that puts `offset` on a remaining sample, so that the
+                // timestamp is still exact.
+                let start = self.offset as usize % DECIMATION;
+                let data: Vec<u16, { AXL_SZ / DECIMATION }> = self
+                    .data
+                    .chunks_exact(SAMPLE_SZ)
+                    .skip(start)
+                    .step_by(DECIMATION)
+                    .flatten()
+                    .copied()
+                    .collect();
+
+                let (codec, b64) = encode(&data);
+                (
+                    codec,
+                    b64,
+                    data.len(),
+                    self.offset / DECIMATION as u16,
+                    self.freq / DECIMATION as f32,
+                )
+            }
+        };
 
         let meta = AxlPacketMeta {
             timestamp: self.timestamp,
-            offset: self.offset as u32,
+            offset: offset as u32,
+            codec: codec as u32,
+            samples: samples as u32,
+            mode: mode as u32,
             length: b64.len() as u32,
-            freq: self.freq,
+            freq,
             accel_range: self.accel_range,
             gyro_range: self.gyro_range,
+            burst_start: self.burst_start,
             storage_id: self.storage_id,
             storage_version: self.storage_version,
             position_time: self.position_time,
@@ -207,6 +304,7 @@ mod tests {
             storage_id: Some(0),
             storage_version: VERSION,
             temperature: 0.0,
+            burst_start: 0,
             data: (0..AXL_SZ)
                 .map(|v| v as u16)
                 .collect::<Vec<_, { AXL_SZ }>>(),
@@ -216,6 +314,90 @@ mod tests {
         println!("{}", core::str::from_utf8(&b64).unwrap());
     }
 
+    #[test]
+    fn split_compressed() {
+        let mut p = AxlPacket {
+            timestamp: 0,
+            position_time: 0,
+            lat: 0.0,
+            lon: 0.0,
+            freq: 52.0,
+            accel_range: 4.,
+            gyro_range: 500.,
+            offset: 0,
+            storage_id: None,
+            storage_version: VERSION,
+            temperature: 0.0,
+            burst_start: 0,
+            data: (0..AXL_SZ)
+                .map(|v| (v / 3) as u16)
+                .collect::<Vec<_, { AXL_SZ }>>(),
+        };
+
+        let (meta, b64) = p.split();
+        assert_eq!(meta.codec, Codec::Rice as u32);
+        assert_eq!(meta.samples, AXL_SZ as u32);
+        assert_eq!(meta.length, b64.len() as u32);
+        assert!(b64.len() < p.base64().len() / 4);
+
+        let mut c = std::vec![0u8; AXL_SZ * 2 + 2];
+        let sz = base64::decode_config_slice(&b64, base64::STANDARD, &mut c).unwrap();
+        let mut data = Vec::<u16, AXL_SZ>::new();
+        rice::decode(&c[..sz], meta.samples as usize, &mut data).unwrap();
+        assert_eq!(data, p.data);
+
+        // Does not compress.
+        p.data = (0..AXL_SZ)
+            .map(|i| if (i / 3) % 2 == 0 { 0 } else { u16::MAX / 2 })
+            .collect();
+        let (meta, b64) = p.split();
+        assert_eq!(meta.codec, Codec::Raw as u32);
+        assert_eq!(b64, p.base64());
+    }
+
+    #[test]
+    fn split_decimated() {
+        let p = AxlPacket {
+            timestamp: 1_000_000,
+            position_time: 0,
+            lat: 0.0,
+            lon: 0.0,
+            freq: 52.0,
+            accel_range: 4.,
+            gyro_range: 500.,
+            offset: 6,
+            storage_id: Some(3),
+            storage_version: VERSION,
+            temperature: 0.0,
+            burst_start: 0,
+            data: (0..AXL_SZ)
+                .map(|v| (v / 3) as u16)
+                .collect::<Vec<_, { AXL_SZ }>>(),
+        };
+
+        let (meta, _) = p.split();
+        assert_eq!(meta.mode, Mode::Full as u32);
+
+        let (meta, b64) = p.split_mode(Mode::Decimated);
+        assert_eq!(meta.mode, Mode::Decimated as u32);
+        assert_eq!(meta.freq, 13.);
+        assert_eq!(meta.offset, 1);
+        assert_eq!(meta.samples as usize, AXL_SZ / DECIMATION);
+        assert_eq!(meta.storage_id, Some(3));
+
+        let mut c = std::vec![0u8; AXL_SZ * 2 + 2];
+        let sz = base64::decode_config_slice(&b64, base64::STANDARD, &mut c).unwrap();
+        let mut data = Vec::<u16, AXL_SZ>::new();
+        rice::decode(&c[..sz], meta.samples as usize, &mut data).unwrap();
+
+        // The sample at the offset is kept, so the timestamp still belongs to it.
+        assert_eq!(data[meta.offset as usize * SAMPLE_SZ], p.offset);
+        assert!(data
+            .chunks_exact(SAMPLE_SZ)
+            .enumerate()
+            .all(|(i, s)| s.iter().all(|v| *v as usize == 2 + i * DECIMATION)));
+    }
+
     #[cfg(feature = "continuous-post")]
     #[test]
     fn post_package() {
@@ -233,6 +415,7 @@ mod tests {
             storage_id: None,
             storage_version: VERSION,
             temperature: 23.695312,
+            burst_start: 0,
             data: (0..AXL_SZ)
                 .map(|v| v as u16)
                 .collect::<Vec<_, { AXL_SZ }>>(),
@@ -257,6 +440,7 @@ mod tests {
             storage_id: Some(1489),
             storage_version: VERSION,
             temperature: 0.0,
+            burst_start: 0,
             data: (0..AXL_SZ)
                 .map(|v| v as u16)
                 .collect::<Vec<_, { AXL_SZ }>>(),
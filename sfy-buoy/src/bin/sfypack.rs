@@ -5,8 +5,10 @@ use std::ops::Deref;
 use std::path::{Path, PathBuf};
 
 use sfy::axl;
-use sfy::storage::PACKAGE_SZ as RAW_PACKAGE_SZ;
-use sfy::waves::VecRawAxl;
+#[cfg(feature = "ext-gps")]
+use sfy::gps;
+use sfy::storage::header::{Header, HEADER_SZ};
+use sfy::waves::{VecRawAxl, RAW_AXL_BYTE_SZ};
 
 #[derive(FromArgs)]
 /// Load and print Axl package from binary collection.
@@ -25,10 +27,44 @@ struct SfyPack {
 
     #[argh(switch, description = "input file with raw-data")]
     raw: bool,
+
+    #[argh(
+        switch,
+        description = "decode the samples of axl.qo notes (JSON, e.g. from --note)"
+    )]
+    decode: bool,
+
+    #[argh(switch, description = "check the packages in the collection")]
+    verify: bool,
 }
 
 fn main() -> anyhow::Result<()> {
     let pck: SfyPack = argh::from_env();
+
+    if pck.decode {
+        eprintln!("Decoding notes from: {:?}", pck.file);
+        let notes = DecodedNote::from_file(&pck.file)?;
+        eprintln!("Decoded {} notes.", notes.len());
+        println!("{}", json::to_string_pretty(&notes).unwrap());
+        return Ok(());
+    }
+
+    if is_gps(&pck.file) {
+        return gps(&pck);
+    }
+
+    if pck.verify {
+        eprintln!("Verifying collection: {:?}", pck.file);
+        let (ok, corrupt) = Collection::verify(&pck.file, pck.raw)?;
+        println!("{} packages ok, {} corrupt.", ok, corrupt);
+
+        if corrupt > 0 {
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
     eprintln!("Loading collection from: {:?}", pck.file);
 
     let c = match pck.raw {
@@ -79,6 +115,72 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Whether the file is a collection of GPS packages, given by the file extension (e.g. `3.G1`).
+fn is_gps(p: &Path) -> bool {
+    p.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| e.starts_with('G'))
+}
+
+#[cfg(feature = "ext-gps")]
+fn gps(pck: &SfyPack) -> anyhow::Result<()> {
+    if pck.raw {
+        anyhow::bail!("GPS collections have no raw-data");
+    }
+
+    if pck.verify {
+        eprintln!("Verifying GPS collection: {:?}", pck.file);
+        let (ok, corrupt) = GpsCollection::verify(&pck.file)?;
+        println!("{} packages ok, {} corrupt.", ok, corrupt);
+
+        if corrupt > 0 {
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    eprintln!("Loading GPS collection from: {:?}", pck.file);
+    let c = GpsCollection::from_file(&pck.file)?;
+    eprintln!("Loaded {} packages.", c.pcks.len());
+
+    if pck.list {
+        for p in &c.pcks {
+            let ts = NaiveDateTime::from_timestamp(
+                p.timestamp / 1000,
+                (p.timestamp % 1000 * 1_000_000).try_into().unwrap(),
+            );
+            eprintln!(
+                "{:?}: storage id: {:?}, samples: {}, lon: {}, lat: {}",
+                ts,
+                p.storage_id,
+                p.len(),
+                p.lon,
+                p.lat
+            );
+        }
+
+        eprintln!("Listed {} packages.", c.pcks.len());
+    }
+
+    match (pck.json, pck.note) {
+        (true, false) => println!("{}", json::to_string_pretty(&c).unwrap()),
+        (false, true) => {
+            let pcks = c.pcks.iter().map(GpsNote::from).collect::<Vec<GpsNote>>();
+            println!("{}", json::to_string_pretty(&pcks).unwrap());
+        }
+        (false, false) => (),
+        _ => eprintln!("only one of --json and --note may be specified at the same time"),
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "ext-gps"))]
+fn gps(_pck: &SfyPack) -> anyhow::Result<()> {
+    anyhow::bail!("reading GPS collections requires building with `--features ext-gps`")
+}
+
 /// Simulated note event
 #[derive(serde::Serialize)]
 pub struct AxlNote {
@@ -97,6 +199,62 @@ impl AxlNote {
     }
 }
 
+/// A note with the samples decoded from the payload.
+#[derive(serde::Serialize)]
+pub struct DecodedNote {
+    body: json::Value,
+    data: Vec<u16>,
+}
+
+impl DecodedNote {
+    /// Decode a note, or an array of notes.
+    pub fn from_file(p: impl AsRef<Path>) -> anyhow::Result<Vec<DecodedNote>> {
+        let notes: json::Value = json::from_slice(&std::fs::read(p)?)?;
+
+        match notes {
+            json::Value::Array(notes) => notes.into_iter().map(DecodedNote::from_note).collect(),
+            note => Ok(vec![DecodedNote::from_note(note)?]),
+        }
+    }
+
+    pub fn from_note(mut note: json::Value) -> anyhow::Result<DecodedNote> {
+        let body = note["body"].take();
+        let payload = note["payload"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("note has no payload"))?;
+
+        let length = body["length"]
+            .as_u64()
+            .map_or(payload.len(), |l| l as usize)
+            .min(payload.len());
+
+        let mut bytes = vec![0u8; length * 3 / 4 + 3];
+        let sz = base64::decode_config_slice(&payload[..length], base64::STANDARD, &mut bytes)
+            .map_err(|e| anyhow::anyhow!("failed to decode payload: {:?}", e))?;
+        bytes.truncate(sz);
+
+        let data = match body["codec"].as_u64().unwrap_or(axl::Codec::Raw as u64) {
+            c if c == axl::Codec::Raw as u64 => bytes
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect(),
+            c if c == axl::Codec::Rice as u64 => {
+                let samples = body["samples"]
+                    .as_u64()
+                    .ok_or_else(|| anyhow::anyhow!("compressed note has no samples"))?;
+
+                let mut data = heapless::Vec::<u16, { axl::AXL_SZ }>::new();
+                sfy::rice::decode(&bytes, samples as usize, &mut data)
+                    .map_err(|e| anyhow::anyhow!("failed to decode samples: {:?}", e))?;
+                data.to_vec()
+            }
+            c => anyhow::bail!("unknown codec: {}", c),
+        };
+
+        Ok(DecodedNote { body, data })
+    }
+}
+
 #[derive(serde::Serialize)]
 struct Collection {
     pub pcks: Vec<axl::AxlPacket>,
@@ -105,72 +263,138 @@ struct Collection {
 
 impl Collection {
     pub fn from_file(p: impl AsRef<Path>) -> anyhow::Result<Collection> {
-        let p = p.as_ref();
-        let mut b = std::fs::read(p)?;
+        Self::load(p.as_ref(), false)
+    }
 
-        if (b.len() % axl::AXL_POSTCARD_SZ) != 0 {
-            eprintln!("Warning, collection consists of non-integer number of packages.");
+    pub fn from_file_raw(p: impl AsRef<Path>) -> anyhow::Result<Collection> {
+        Self::load(p.as_ref(), true)
+    }
+
+    /// Storage version of the collection, given by the file extension (e.g. `73.9`).
+    fn version(p: &Path) -> Option<u32> {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| e.parse::<u32>().ok())
+    }
+
+    /// Read the collection and split it into packages, returns the storage version and whether the
+    /// packages have a header (since version 9).
+    fn read(p: &Path, raw: bool) -> anyhow::Result<(Vec<u8>, usize, u32, bool)> {
+        let b = std::fs::read(p)?;
+        let version = Self::version(p);
+        let header = version.map_or(false, |v| v >= 9);
+        let version = version.unwrap_or(axl::VERSION);
+
+        let mut sz = axl::AXL_POSTCARD_SZ;
+        if header {
+            sz += HEADER_SZ;
+        }
+        if raw {
+            sz += RAW_AXL_BYTE_SZ;
         }
 
-        let n = b.len() / axl::AXL_POSTCARD_SZ;
+        if (b.len() % sz) != 0 {
+            eprintln!("Warning, collection consists of non-integer number of packages.");
+        }
 
         eprintln!(
             "Parsing {} bytes of packages into {} packages..",
             b.len(),
-            n
+            b.len() / sz
         );
-        let pcks = b
-            .chunks_exact_mut(axl::AXL_POSTCARD_SZ)
-            .filter_map(|p| match postcard::from_bytes_cobs(p) {
-                Ok(p) => Some(p),
-                Err(e) => {
-                    eprintln!("failed to parse package: {:?}", e);
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
 
-        Ok(Collection { pcks, raw: None })
+        Ok((b, sz, version, header))
     }
 
-    pub fn from_file_raw(p: impl AsRef<Path>) -> anyhow::Result<Collection> {
-        let p = p.as_ref();
-        let mut b = std::fs::read(p)?;
+    /// Parse a package, and check it against its header.
+    fn parse(
+        p: &mut [u8],
+        version: u32,
+        header: bool,
+        raw: bool,
+    ) -> anyhow::Result<(axl::AxlPacket, Option<Vec<f32>>)> {
+        let n = if raw {
+            p.len() - RAW_AXL_BYTE_SZ
+        } else {
+            p.len()
+        };
+        let (p, r) = p.split_at_mut(n);
+
+        let p = if header {
+            let (h, p) = p.split_at_mut(HEADER_SZ);
+            let h = Header::from_bytes(h).map_err(|e| anyhow::anyhow!("bad header: {:?}", e))?;
+
+            if raw {
+                h.check_raw(r).map_err(|e| {
+                    anyhow::anyhow!("corrupt raw values in package {}: {:?}", h.storage_id, e)
+                })?;
+            }
 
-        if (b.len() % RAW_PACKAGE_SZ) != 0 {
-            eprintln!("Warning, collection consists of non-integer number of packages.");
-        }
+            h.package(p)
+                .map_err(|e| anyhow::anyhow!("corrupt package {}: {:?}", h.storage_id, e))?
+        } else {
+            p
+        };
 
-        let n = b.len() / RAW_PACKAGE_SZ;
+        let pck = axl::AxlPacket::from_bytes_cobs(version, p)
+            .map_err(|e| anyhow::anyhow!("failed to parse package: {:?}", e))?;
 
-        eprintln!(
-            "Parsing {} bytes of packages into {} packages..",
-            b.len(),
-            n
-        );
-        let (pcks, raw) = b
-            .chunks_exact_mut(RAW_PACKAGE_SZ)
-            .filter_map(|p| {
-                let (p, raw) = p.split_at_mut(axl::AXL_POSTCARD_SZ);
+        let raw = raw.then(|| {
+            let raw = VecRawAxl::from_slice(bytemuck::cast_slice(r)).unwrap();
+            raw.iter().map(|v| (*v).into()).collect::<Vec<f32>>()
+        });
 
-                let raw = VecRawAxl::from_slice(bytemuck::cast_slice(raw)).unwrap();
-                let raw = raw.iter().map(|v| (*v).into()).collect::<Vec<f32>>();
+        Ok((pck, raw))
+    }
 
-                match postcard::from_bytes_cobs(p) {
-                    Ok(p) => Some((p, raw)),
-                    Err(e) => {
-                        eprintln!("failed to parse package: {:?}", e);
-                        None
-                    }
+    /// Load the packages of the collection, corrupt packages are skipped.
+    fn load(p: &Path, raw: bool) -> anyhow::Result<Collection> {
+        let (mut b, sz, version, header) = Self::read(p, raw)?;
+
+        let (pcks, raws): (Vec<_>, Vec<_>) = b
+            .chunks_exact_mut(sz)
+            .filter_map(|p| match Self::parse(p, version, header, raw) {
+                Ok(p) => Some(p),
+                Err(e) => {
+                    eprintln!("skipping package: {}", e);
+                    None
                 }
             })
             .unzip();
 
         Ok(Collection {
             pcks,
-            raw: Some(raw),
+            raw: raw.then(|| raws.into_iter().flatten().collect()),
         })
     }
+
+    /// Check every package in the collection, returns the number of good and corrupt packages.
+    pub fn verify(p: impl AsRef<Path>, raw: bool) -> anyhow::Result<(usize, usize)> {
+        let p = p.as_ref();
+        let (mut b, sz, version, header) = Self::read(p, raw)?;
+
+        if !header {
+            eprintln!("Warning, collection has no package headers, only parsing packages.");
+        }
+
+        let mut ok = 0;
+        let mut corrupt = 0;
+
+        for (i, p) in b.chunks_exact_mut(sz).enumerate() {
+            match Self::parse(p, version, header, raw) {
+                Ok((pck, _)) => {
+                    eprintln!("{}: ok (storage id: {:?})", i, pck.storage_id);
+                    ok += 1;
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", i, e);
+                    corrupt += 1;
+                }
+            }
+        }
+
+        Ok((ok, corrupt))
+    }
 }
 
 impl Deref for Collection {
@@ -181,6 +405,99 @@ impl Deref for Collection {
     }
 }
 
+/// Simulated egps.qo note event
+#[cfg(feature = "ext-gps")]
+#[derive(serde::Serialize)]
+pub struct GpsNote {
+    body: gps::GpsPacketMeta,
+    payload: String,
+}
+
+#[cfg(feature = "ext-gps")]
+impl GpsNote {
+    pub fn from(pck: &gps::GpsPacket) -> GpsNote {
+        let (body, b64) = pck.split();
+
+        let payload = String::from_utf8(b64.as_slice().to_vec()).unwrap();
+
+        GpsNote { body, payload }
+    }
+}
+
+/// A collection of GPS packages, every package has a header.
+#[cfg(feature = "ext-gps")]
+#[derive(serde::Serialize)]
+struct GpsCollection {
+    pub pcks: Vec<gps::GpsPacket>,
+}
+
+#[cfg(feature = "ext-gps")]
+impl GpsCollection {
+    const SZ: usize = HEADER_SZ + gps::GPS_POSTCARD_SZ;
+
+    fn read(p: &Path) -> anyhow::Result<Vec<u8>> {
+        let b = std::fs::read(p)?;
+
+        if (b.len() % Self::SZ) != 0 {
+            eprintln!("Warning, collection consists of non-integer number of packages.");
+        }
+
+        Ok(b)
+    }
+
+    /// Parse a package, and check it against its header.
+    fn parse(p: &mut [u8]) -> anyhow::Result<gps::GpsPacket> {
+        let (h, p) = p.split_at_mut(HEADER_SZ);
+        let h = Header::from_bytes(h).map_err(|e| anyhow::anyhow!("bad header: {:?}", e))?;
+
+        let p = h
+            .package(p)
+            .map_err(|e| anyhow::anyhow!("corrupt package {}: {:?}", h.storage_id, e))?;
+
+        postcard::from_bytes_cobs(p)
+            .map_err(|e| anyhow::anyhow!("failed to parse package: {:?}", e))
+    }
+
+    /// Load the packages of the collection, corrupt packages are skipped.
+    pub fn from_file(p: impl AsRef<Path>) -> anyhow::Result<GpsCollection> {
+        let mut b = Self::read(p.as_ref())?;
+
+        let pcks = b
+            .chunks_exact_mut(Self::SZ)
+            .filter_map(|p| {
+                Self::parse(p)
+                    .inspect_err(|e| eprintln!("skipping package: {}", e))
+                    .ok()
+            })
+            .collect();
+
+        Ok(GpsCollection { pcks })
+    }
+
+    /// Check every package in the collection, returns the number of good and corrupt packages.
+    pub fn verify(p: impl AsRef<Path>) -> anyhow::Result<(usize, usize)> {
+        let mut b = Self::read(p.as_ref())?;
+
+        let mut ok = 0;
+        let mut corrupt = 0;
+
+        for (i, p) in b.chunks_exact_mut(Self::SZ).enumerate() {
+            match Self::parse(p) {
+                Ok(pck) => {
+                    eprintln!("{}: ok (storage id: {:?})", i, pck.storage_id);
+                    ok += 1;
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", i, e);
+                    corrupt += 1;
+                }
+            }
+        }
+
+        Ok((ok, corrupt))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,6 +515,146 @@ mod tests {
         // }
     }
 
+    #[test]
+    fn verify_collection() {
+        let dir = std::env::temp_dir().join(format!("sfypack-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let f = dir.join("0.9");
+
+        let mut b = Vec::new();
+        for id in 0..3 {
+            let p = axl::AxlPacket {
+                timestamp: id as i64 * 20_000,
+                position_time: 0,
+                lat: 0.0,
+                lon: 0.0,
+                freq: 52.0,
+                accel_range: 4.,
+                gyro_range: 500.,
+                offset: 0,
+                storage_id: Some(id),
+                storage_version: axl::VERSION,
+                temperature: 0.0,
+                burst_start: 0,
+                data: (0..axl::AXL_SZ).map(|v| v as u16).collect(),
+            };
+
+            let mut buf = postcard::to_vec_cobs::<_, { axl::AXL_POSTCARD_SZ }>(&p)
+                .unwrap()
+                .to_vec();
+            let raw = vec![id as u8; RAW_AXL_BYTE_SZ];
+            let header = Header::new(id, &buf, &raw);
+            buf.resize(axl::AXL_POSTCARD_SZ, 0);
+
+            b.extend(header.to_bytes());
+            b.extend(buf);
+            b.extend(raw);
+        }
+
+        let sz = b.len() / 3;
+        std::fs::write(&f, &b).unwrap();
+        assert_eq!(Collection::verify(&f, true).unwrap(), (3, 0));
+
+        // Flip a byte in the second package, and in the raw values of the third.
+        b[sz + HEADER_SZ + 100] ^= 0xff;
+        b[3 * sz - 1] ^= 0xff;
+        std::fs::write(&f, &b).unwrap();
+        assert_eq!(Collection::verify(&f, true).unwrap(), (1, 2));
+
+        let c = Collection::from_file_raw(&f).unwrap();
+        assert_eq!(c.pcks.len(), 1);
+        assert_eq!(c.pcks[0].storage_id, Some(0));
+        assert_eq!(c.raw.unwrap().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "ext-gps")]
+    #[test]
+    fn gps_collection() {
+        assert!(is_gps(Path::new("3.G1")));
+        assert!(!is_gps(Path::new("3.9")));
+
+        let dir = std::env::temp_dir().join(format!("sfypack-gps-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let f = dir.join("0.G1");
+
+        let mut b = Vec::new();
+        for id in 0..3 {
+            let p = gps::GpsPacket {
+                timestamp: id as i64 * 20_000,
+                freq: 5.0,
+                version: gps::GPS_PACKET_V,
+                storage_id: Some(id),
+                lon: 5,
+                lat: 60,
+                msl: 0,
+                data: (0..60).collect(),
+                ha_min: 0.,
+                ha_max: 0.,
+                ha_mean: 0.,
+                va_min: 0.,
+                va_max: 0.,
+                va_mean: 0.,
+                fix: [0; 8],
+                soln: [0; 8],
+            };
+
+            let mut buf = postcard::to_vec_cobs::<_, { gps::GPS_POSTCARD_SZ }>(&p)
+                .unwrap()
+                .to_vec();
+            let header = Header::new(id, &buf, &[]);
+            buf.resize(gps::GPS_POSTCARD_SZ, 0);
+
+            b.extend(header.to_bytes());
+            b.extend(buf);
+        }
+
+        std::fs::write(&f, &b).unwrap();
+        assert_eq!(GpsCollection::verify(&f).unwrap(), (3, 0));
+
+        b[GpsCollection::SZ + HEADER_SZ + 10] ^= 0xff;
+        std::fs::write(&f, &b).unwrap();
+        assert_eq!(GpsCollection::verify(&f).unwrap(), (2, 1));
+
+        let c = GpsCollection::from_file(&f).unwrap();
+        let ids: Vec<_> = c.pcks.iter().map(|p| p.storage_id).collect();
+        assert_eq!(ids, [Some(0), Some(2)]);
+        assert_eq!(c.pcks[1].data.len(), 60);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn decode_note() {
+        let mut p = axl::AxlPacket {
+            timestamp: 0,
+            position_time: 0,
+            lat: 0.0,
+            lon: 0.0,
+            freq: 52.0,
+            accel_range: 4.,
+            gyro_range: 500.,
+            offset: 0,
+            storage_id: None,
+            storage_version: axl::VERSION,
+            temperature: 0.0,
+            burst_start: 0,
+            data: (0..axl::AXL_SZ).map(|v| (v / 3) as u16).collect(),
+        };
+
+        let note = json::to_value(AxlNote::from(&p, None)).unwrap();
+        assert_eq!(note["body"]["codec"], axl::Codec::Rice as u32);
+        let d = DecodedNote::from_note(note).unwrap();
+        assert_eq!(d.data, p.data.as_slice());
+
+        p.data = (0..axl::AXL_SZ).map(|v| (v * 7919) as u16).collect();
+        let note = json::to_value(AxlNote::from(&p, None)).unwrap();
+        assert_eq!(note["body"]["codec"], axl::Codec::Raw as u32);
+        let d = DecodedNote::from_note(note).unwrap();
+        assert_eq!(d.data, p.data.as_slice());
+    }
+
     #[test]
     fn open_raw() {
         let c = Collection::from_file_raw("tests/data/14.3").unwrap();
@@ -220,6 +677,15 @@ mod tests {
 
         assert!(c.raw.is_none());
         assert_eq!(c.pcks.len(), 4);
+        assert_eq!(c.pcks[0].storage_id, Some(3000));
+        assert_eq!(c.pcks[0].timestamp, 1704967608000);
+        assert_eq!(c.pcks[0].freq, 52.);
+        assert_eq!(c.pcks[0].data.len(), axl::AXL_SZ);
+
+        for p in c.iter() {
+            assert_eq!(p.storage_version, 6);
+            assert_eq!(p.burst_start, p.timestamp);
+        }
     }
 
     #[ignore]
@@ -0,0 +1,353 @@
+//! Crash dumps that survive a reset.
+//!
+//! The `HardFault` and panic handlers try to send the log to the Notecard before resetting, but
+//! if the Notecard is wedged the reason for the crash is lost. The exception frame or panic
+//! message is therefore also saved together with the most recent `log::log` messages to a RAM
+//! section that is not initialized at boot. After the reset the dump is taken with `init`, written
+//! to the SD-card (`CRASH.LOG`) and sent as `crash.qo`.
+//!
+//! The RAM is garbage after a power-on reset, so the dump is only used if the magic and checksum
+//! are valid.
+
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+use heapless::{String, Vec};
+
+#[cfg(test)]
+use crate::sim::free;
+#[cfg(not(test))]
+use cortex_m::interrupt::free;
+
+use crate::watchdog::ResetReason;
+
+/// Number of recent log messages kept in the dump.
+pub const LOG_SZ: usize = 4;
+
+/// Maximum length of each log message in the dump, longer messages are truncated.
+pub const LOG_MSG_SZ: usize = 128;
+
+/// Maximum length of the panic message.
+pub const MESSAGE_SZ: usize = 256;
+
+const MAGIC: u32 = 0xc4a5_4d0f;
+
+/// Registers stacked by the exception: r0, r1, r2, r3, r12, lr, pc, xpsr.
+pub type Frame = [u32; 8];
+
+#[repr(C)]
+pub struct CrashDump {
+    magic: u32,
+    checksum: u32,
+    kind: u8,
+    frame: Frame,
+    message_len: u16,
+    message: [u8; MESSAGE_SZ],
+
+    /// Ring of recent log messages, `log_next` is the next slot to be written.
+    log_next: u8,
+    log_len: [u8; LOG_SZ],
+    log: [[u8; LOG_MSG_SZ]; LOG_SZ],
+}
+
+/// Longest prefix of `s` that fits in `n` bytes without splitting a character.
+fn truncate(s: &str, n: usize) -> &str {
+    let mut n = n.min(s.len());
+    while !s.is_char_boundary(n) {
+        n -= 1;
+    }
+    &s[..n]
+}
+
+/// FNV-1a.
+fn hash(h: u32, bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(h, |h, b| (h ^ *b as u32).wrapping_mul(0x0100_0193))
+}
+
+impl Default for CrashDump {
+    fn default() -> CrashDump {
+        CrashDump::new()
+    }
+}
+
+impl CrashDump {
+    pub const fn new() -> CrashDump {
+        CrashDump {
+            magic: 0,
+            checksum: 0,
+            kind: 0,
+            frame: [0; 8],
+            message_len: 0,
+            message: [0; MESSAGE_SZ],
+            log_next: 0,
+            log_len: [0; LOG_SZ],
+            log: [[0; LOG_MSG_SZ]; LOG_SZ],
+        }
+    }
+
+    /// Clear the dump and the log ring.
+    pub fn clear(&mut self) {
+        self.magic = 0;
+        self.kind = 0;
+        self.frame = [0; 8];
+        self.message_len = 0;
+        self.log_next = 0;
+        self.log_len = [0; LOG_SZ];
+    }
+
+    fn checksum(&self) -> u32 {
+        let mut h = 0x811c_9dc5;
+        h = hash(h, &[self.kind, self.log_next]);
+
+        for r in self.frame {
+            h = hash(h, &r.to_le_bytes());
+        }
+
+        h = hash(
+            h,
+            &self.message[..(self.message_len as usize).min(MESSAGE_SZ)],
+        );
+
+        for (msg, len) in self.log.iter().zip(self.log_len) {
+            h = hash(h, &msg[..(len as usize).min(LOG_MSG_SZ)]);
+        }
+
+        h
+    }
+
+    /// Add a log message to the ring, overwriting the oldest.
+    pub fn record_log(&mut self, msg: &str) {
+        let i = self.log_next as usize % LOG_SZ;
+        let msg = truncate(msg, LOG_MSG_SZ);
+
+        self.log[i][..msg.len()].copy_from_slice(msg.as_bytes());
+        self.log_len[i] = msg.len() as u8;
+        self.log_next = ((i + 1) % LOG_SZ) as u8;
+    }
+
+    /// Save the crash, it is valid until `clear` is called.
+    pub fn commit(&mut self, kind: ResetReason, frame: Option<Frame>, message: &str) {
+        let message = truncate(message, MESSAGE_SZ);
+
+        self.kind = kind as u8;
+        self.frame = frame.unwrap_or([0; 8]);
+        self.message[..message.len()].copy_from_slice(message.as_bytes());
+        self.message_len = message.len() as u16;
+        self.checksum = self.checksum();
+        self.magic = MAGIC;
+    }
+
+    /// The saved crash, if the dump is valid.
+    pub fn note(&self) -> Option<CrashNote> {
+        if self.magic != MAGIC || self.checksum != self.checksum() {
+            return None;
+        }
+
+        let kind = if self.kind == ResetReason::HardFault as u8 {
+            ResetReason::HardFault
+        } else {
+            ResetReason::Panic
+        };
+
+        let message =
+            core::str::from_utf8(&self.message[..(self.message_len as usize).min(MESSAGE_SZ)])
+                .map(String::from)
+                .unwrap_or_default();
+
+        // Oldest first.
+        let mut log = Vec::new();
+        for i in 0..LOG_SZ {
+            let i = (self.log_next as usize + i) % LOG_SZ;
+            let len = (self.log_len[i] as usize).min(LOG_MSG_SZ);
+
+            if len > 0 {
+                if let Ok(msg) = core::str::from_utf8(&self.log[i][..len]) {
+                    log.push(String::from(msg)).ok();
+                }
+            }
+        }
+
+        Some(CrashNote {
+            kind: kind.as_str(),
+            frame: if kind == ResetReason::HardFault {
+                let [r0, r1, r2, r3, r12, lr, pc, xpsr] = self.frame;
+                Some(CrashFrame {
+                    r0,
+                    r1,
+                    r2,
+                    r3,
+                    r12,
+                    lr,
+                    pc,
+                    xpsr,
+                })
+            } else {
+                None
+            },
+            message,
+            log,
+        })
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq, defmt::Format)]
+pub struct CrashFrame {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32,
+}
+
+/// The crash as sent in `crash.qo`.
+#[derive(serde::Serialize, Debug, Clone, PartialEq, defmt::Format)]
+pub struct CrashNote {
+    pub kind: &'static str,
+    pub frame: Option<CrashFrame>,
+    pub message: String<MESSAGE_SZ>,
+    pub log: Vec<String<LOG_MSG_SZ>, LOG_SZ>,
+}
+
+impl CrashNote {
+    /// Write the crash as text, used for the SD-card.
+    pub fn write_text(&self, w: &mut impl Write) -> core::fmt::Result {
+        writeln!(w, "crash: {}", self.kind)?;
+
+        if let Some(f) = &self.frame {
+            writeln!(
+                w,
+                "r0: {:#010x} r1: {:#010x} r2: {:#010x} r3: {:#010x}",
+                f.r0, f.r1, f.r2, f.r3
+            )?;
+            writeln!(
+                w,
+                "r12: {:#010x} lr: {:#010x} pc: {:#010x} xpsr: {:#010x}",
+                f.r12, f.lr, f.pc, f.xpsr
+            )?;
+        }
+
+        if !self.message.is_empty() {
+            writeln!(w, "message: {}", self.message)?;
+        }
+
+        for msg in &self.log {
+            writeln!(w, "log: {}", msg)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(not(test), link_section = ".uninit.SFY_CRASH_DUMP")]
+static mut DUMP: MaybeUninit<CrashDump> = MaybeUninit::uninit();
+
+/// Log messages are only recorded after `init`, before that the dump may be garbage.
+static INIT: AtomicBool = AtomicBool::new(false);
+
+fn dump() -> *mut CrashDump {
+    core::ptr::addr_of_mut!(DUMP).cast()
+}
+
+/// Take the crash dump from before the reset (if any), and start recording log messages. Must be
+/// called before `record_log` or `save`.
+pub fn init() -> Option<CrashNote> {
+    free(|_| {
+        let dump = unsafe { &mut *dump() };
+        let note = dump.note();
+        dump.clear();
+        INIT.store(true, Ordering::SeqCst);
+
+        note
+    })
+}
+
+/// Record a log message in the dump.
+pub fn record_log(msg: &str) {
+    if INIT.load(Ordering::SeqCst) {
+        free(|_| unsafe { (*dump()).record_log(msg) });
+    }
+}
+
+/// Save a crash dump before resetting.
+pub fn save(kind: ResetReason, frame: Option<Frame>, message: &str) {
+    if INIT.load(Ordering::SeqCst) {
+        free(|_| unsafe { (*dump()).commit(kind, frame, message) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_crash() {
+        let mut d = CrashDump::new();
+        assert!(d.note().is_none());
+
+        d.record_log("hello");
+        assert!(d.note().is_none());
+
+        // Garbage.
+        d.magic = MAGIC;
+        d.checksum = 1;
+        assert!(d.note().is_none());
+    }
+
+    #[test]
+    fn panic_dump() {
+        let mut d = CrashDump::new();
+
+        for i in 0..6 {
+            let mut msg = String::<16>::new();
+            write!(msg, "msg {}", i).unwrap();
+            d.record_log(&msg);
+        }
+
+        d.commit(ResetReason::Panic, None, "panicked at 'oops'");
+
+        let n = d.note().unwrap();
+        assert_eq!(n.kind, "panic");
+        assert_eq!(n.frame, None);
+        assert_eq!(n.message, "panicked at 'oops'");
+        assert_eq!(
+            n.log
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<std::vec::Vec<_>>(),
+            ["msg 2", "msg 3", "msg 4", "msg 5"]
+        );
+
+        d.clear();
+        assert!(d.note().is_none());
+    }
+
+    #[test]
+    fn hardfault_dump() {
+        let mut d = CrashDump::new();
+        let long = "ø".repeat(200);
+        d.record_log(&long);
+        d.commit(
+            ResetReason::HardFault,
+            Some([0, 1, 2, 3, 12, 14, 15, 16]),
+            "",
+        );
+
+        let n = d.note().unwrap();
+        assert_eq!(n.kind, "hardfault");
+        assert_eq!(n.frame.as_ref().unwrap().pc, 15);
+        assert_eq!(n.log[0].len(), LOG_MSG_SZ);
+
+        let mut s = std::string::String::new();
+        n.write_text(&mut s).unwrap();
+        assert!(s.starts_with("crash: hardfault\n"));
+        assert!(s.contains("pc: 0x0000000f"));
+
+        let json = serde_json::to_string(&n).unwrap();
+        assert!(json.contains(r#""kind":"hardfault""#));
+    }
+}
@@ -0,0 +1,418 @@
+//! Runtime configuration of the buoy from the Notecard environment variables.
+//!
+//! The defaults are set at compile time through the environment variables with the same names
+//! (see `build.rs`), and can be overridden by setting the variables on Notehub for the device,
+//! fleet or project. The Notecard syncs the variables, they are read at boot and every
+//! `CONFIG_PERIOD` after that. Invalid values are ignored, and a variable that is removed falls
+//! back to the compile time default.
+//!
+//! | variable             | unit    | range                        |
+//! |----------------------|---------|------------------------------|
+//! | `BUOYSN`             |         |                              |
+//! | `BUOYPR`             |         |                              |
+//! | `SFY_EXT_SIM_APN`    |         |                              |
+//! | `SYNC_PERIOD`        | minutes | 1 - 1440                     |
+//! | `GPS_PERIOD`         | seconds | 10 - 86400                   |
+//! | `GPS_HEARTBEAT`      | hours   | 1 - 168 (or -1440 - -1 min.) |
+//! | `BURST_PERIOD`       | minutes | 0 (continuous) or 2 - 1440   |
+//! | `BURST_LENGTH`       | minutes | 1 - 1440                     |
+//! | `BURST_POSITION_AGE` | minutes | 0 (disabled) - 10080         |
+//! | `OUTPUT_FREQ`        | Hz      | one of `waves::RATES`        |
+//! | `UNSENT_ORDER`       |         | `oldest` or `newest`         |
+
+use heapless::String;
+
+use crate::note::{
+    BUOYPR, BUOYSN, BURST_LENGTH, BURST_PERIOD, BURST_POSITION_AGE, EXT_APN, GPS_HEARTBEAT,
+    GPS_PERIOD, OUTPUT_FREQ, SYNC_PERIOD, UNSENT_ORDER,
+};
+use crate::schedule::Schedule;
+use crate::waves::{Rate, RATES};
+
+/// Interval between checking the environment variables for changes [ms].
+pub const CONFIG_PERIOD: i64 = 30 * 60 * 1000;
+
+/// The environment variables that are read.
+pub const ENV_NAMES: [&str; 11] = [
+    "BUOYSN",
+    "BUOYPR",
+    "SFY_EXT_SIM_APN",
+    "SYNC_PERIOD",
+    "GPS_PERIOD",
+    "GPS_HEARTBEAT",
+    "BURST_PERIOD",
+    "BURST_LENGTH",
+    "BURST_POSITION_AGE",
+    "OUTPUT_FREQ",
+    "UNSENT_ORDER",
+];
+
+/// The order the packages that could not be sent right away are sent in, once the Notecard has
+/// room again (see `storage::unsent`).
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum UnsentOrder {
+    /// The oldest package first, new packages are sent after the backlog.
+    Oldest,
+
+    /// The newest package first, new packages are sent right away when there is room.
+    Newest,
+}
+
+impl core::str::FromStr for UnsentOrder {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<UnsentOrder, ()> {
+        match s {
+            "oldest" => Ok(UnsentOrder::Oldest),
+            "newest" => Ok(UnsentOrder::Newest),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Environment variables as returned by `env.get`, the values are always strings. A value that
+/// is too long is ignored, so that the other variables can still be read.
+#[derive(serde::Deserialize, Default, Debug, defmt::Format, PartialEq)]
+pub struct Env {
+    #[serde(rename = "BUOYSN", default, deserialize_with = "tolerant")]
+    pub name: Option<String<64>>,
+
+    #[serde(rename = "BUOYPR", default, deserialize_with = "tolerant")]
+    pub product: Option<String<64>>,
+
+    #[serde(rename = "SFY_EXT_SIM_APN", default, deserialize_with = "tolerant")]
+    pub ext_apn: Option<String<64>>,
+
+    #[serde(rename = "SYNC_PERIOD", default, deserialize_with = "tolerant")]
+    pub sync_period: Option<String<12>>,
+
+    #[serde(rename = "GPS_PERIOD", default, deserialize_with = "tolerant")]
+    pub gps_period: Option<String<12>>,
+
+    #[serde(rename = "GPS_HEARTBEAT", default, deserialize_with = "tolerant")]
+    pub gps_heartbeat: Option<String<12>>,
+
+    #[serde(rename = "BURST_PERIOD", default, deserialize_with = "tolerant")]
+    pub burst_period: Option<String<12>>,
+
+    #[serde(rename = "BURST_LENGTH", default, deserialize_with = "tolerant")]
+    pub burst_length: Option<String<12>>,
+
+    #[serde(rename = "BURST_POSITION_AGE", default, deserialize_with = "tolerant")]
+    pub burst_position_age: Option<String<12>>,
+
+    #[serde(rename = "OUTPUT_FREQ", default, deserialize_with = "tolerant")]
+    pub output_freq: Option<String<12>>,
+
+    #[serde(rename = "UNSENT_ORDER", default, deserialize_with = "tolerant")]
+    pub unsent_order: Option<String<12>>,
+}
+
+/// Deserialize a value, a value that does not fit is ignored with a warning rather than failing
+/// to deserialize the whole `Env`.
+fn tolerant<'de, D, const N: usize>(d: D) -> Result<Option<String<N>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let v: Option<&str> = serde::Deserialize::deserialize(d)?;
+
+    Ok(v.and_then(|v| {
+        let mut s = String::new();
+        match s.push_str(v) {
+            Ok(()) => Some(s),
+            Err(()) => {
+                defmt::warn!(
+                    "env: value is too long ({} > {} bytes), ignoring.",
+                    v.len(),
+                    N
+                );
+                None
+            }
+        }
+    }))
+}
+
+/// The configuration the buoy is running with, sent as `config.qo` when it changes.
+#[derive(serde::Serialize, Debug, Clone, PartialEq, defmt::Format)]
+pub struct Config {
+    pub name: Option<String<64>>,
+    pub product: Option<String<64>>,
+    pub ext_apn: Option<String<64>>,
+
+    /// Maximum time between outbound syncs [minutes].
+    pub sync_period: u32,
+
+    /// Interval between GPS fixes when moving [s].
+    pub gps_period: u32,
+
+    /// Interval of location heartbeat [hours], negative for minutes.
+    pub gps_heartbeat: i32,
+
+    /// Interval between the start of each burst of sampling [minutes], 0 for continuous.
+    pub burst_period: u32,
+
+    /// Length of each burst [minutes].
+    pub burst_length: u32,
+
+    /// Only sample when the position is younger than this [minutes], 0 to disable.
+    pub burst_position_age: u32,
+
+    /// Output frequency of the packages [Hz].
+    pub output_freq: u32,
+
+    /// The order the packages that are spilled to the SD-card are sent in.
+    pub unsent_order: UnsentOrder,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            name: BUOYSN.map(String::from),
+            product: BUOYPR.map(String::from),
+            ext_apn: EXT_APN.map(String::from),
+            sync_period: SYNC_PERIOD,
+            gps_period: GPS_PERIOD,
+            gps_heartbeat: GPS_HEARTBEAT,
+            burst_period: BURST_PERIOD,
+            burst_length: BURST_LENGTH,
+            burst_position_age: BURST_POSITION_AGE,
+            output_freq: OUTPUT_FREQ,
+            // unwrap: checked by build.rs.
+            unsent_order: UNSENT_ORDER.parse().unwrap(),
+        }
+    }
+}
+
+fn text(name: &str, v: &Option<String<64>>, default: &Option<String<64>>) -> Option<String<64>> {
+    match v {
+        Some(v) if !v.trim().is_empty() => Some(String::from(v.trim())),
+        Some(_) => {
+            defmt::warn!("env: {} is empty, ignoring.", name);
+            default.clone()
+        }
+        None => default.clone(),
+    }
+}
+
+fn number<T: core::str::FromStr + defmt::Format>(
+    name: &str,
+    v: &Option<String<12>>,
+    valid: impl Fn(&T) -> bool,
+    default: T,
+) -> T {
+    match v.as_ref().map(|v| v.trim().parse::<T>()) {
+        Some(Ok(v)) if valid(&v) => v,
+        Some(Ok(v)) => {
+            defmt::warn!("env: {} is out of range: {}, ignoring.", name, v);
+            default
+        }
+        Some(Err(_)) => {
+            defmt::warn!("env: {} is not a number, ignoring.", name);
+            default
+        }
+        None => default,
+    }
+}
+
+fn choice<T: core::str::FromStr>(name: &str, v: &Option<String<12>>, default: T) -> T {
+    match v.as_ref().map(|v| v.trim().parse::<T>()) {
+        Some(Ok(v)) => v,
+        Some(Err(_)) => {
+            defmt::warn!("env: {} is not a valid choice, ignoring.", name);
+            default
+        }
+        None => default,
+    }
+}
+
+impl Config {
+    /// The compile time defaults overridden by the valid environment variables.
+    pub fn from_env(env: &Env) -> Config {
+        let default = Config::default();
+
+        Config {
+            name: text("BUOYSN", &env.name, &default.name),
+            product: text("BUOYPR", &env.product, &default.product),
+            ext_apn: text("SFY_EXT_SIM_APN", &env.ext_apn, &default.ext_apn),
+            sync_period: number(
+                "SYNC_PERIOD",
+                &env.sync_period,
+                |v| (1..=24 * 60).contains(v),
+                default.sync_period,
+            ),
+            gps_period: number(
+                "GPS_PERIOD",
+                &env.gps_period,
+                |v| (10..=24 * 3600).contains(v),
+                default.gps_period,
+            ),
+            gps_heartbeat: number(
+                "GPS_HEARTBEAT",
+                &env.gps_heartbeat,
+                |v| (1..=7 * 24).contains(v) || (-24 * 60..=-1).contains(v),
+                default.gps_heartbeat,
+            ),
+            burst_period: number(
+                "BURST_PERIOD",
+                &env.burst_period,
+                |v| *v == 0 || (2..=24 * 60).contains(v),
+                default.burst_period,
+            ),
+            burst_length: number(
+                "BURST_LENGTH",
+                &env.burst_length,
+                |v| (1..=24 * 60).contains(v),
+                default.burst_length,
+            ),
+            burst_position_age: number(
+                "BURST_POSITION_AGE",
+                &env.burst_position_age,
+                |v| (0..=7 * 24 * 60).contains(v),
+                default.burst_position_age,
+            ),
+            output_freq: number(
+                "OUTPUT_FREQ",
+                &env.output_freq,
+                |v| Rate::from_hz(*v).is_some(),
+                default.output_freq,
+            ),
+            unsent_order: choice("UNSENT_ORDER", &env.unsent_order, default.unsent_order),
+        }
+    }
+
+    /// The sampling schedule.
+    pub fn schedule(&self) -> Schedule {
+        Schedule::new(
+            self.burst_period,
+            self.burst_length,
+            self.burst_position_age,
+        )
+    }
+
+    /// The output rate, the default rate if the compile time default is not supported.
+    pub fn rate(&self) -> Rate {
+        Rate::from_hz(self.output_freq).unwrap_or_else(|| {
+            defmt::warn!(
+                "env: OUTPUT_FREQ: {} Hz is not supported, using: {} Hz.",
+                self.output_freq,
+                RATES[0].output_freq()
+            );
+            RATES[0]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        assert_eq!(Config::from_env(&Env::default()), Config::default());
+    }
+
+    #[test]
+    fn parse_env() {
+        let env: Env = serde_json_core::from_str(
+            r#"{"SYNC_PERIOD":"60","GPS_PERIOD":"300","GPS_HEARTBEAT":"-30","BUOYSN":" WAVEBUG01 "}"#,
+        )
+        .unwrap()
+        .0;
+
+        let c = Config::from_env(&env);
+        assert_eq!(c.sync_period, 60);
+        assert_eq!(c.gps_period, 300);
+        assert_eq!(c.gps_heartbeat, -30);
+        assert_eq!(c.name.as_deref(), Some("WAVEBUG01"));
+        assert_eq!(c.product, Config::default().product);
+    }
+
+    #[test]
+    fn too_long_env() {
+        let env: Env = serde_json_core::from_str(
+            r#"{"BUOYSN":"WAVEBUG01 WAVEBUG01 WAVEBUG01 WAVEBUG01 WAVEBUG01 WAVEBUG01 WAVEBUG01","SYNC_PERIOD":"1234567890123","GPS_PERIOD":"300"}"#,
+        )
+        .unwrap()
+        .0;
+
+        assert_eq!(env.name, None);
+        assert_eq!(env.sync_period, None);
+
+        let c = Config::from_env(&env);
+        assert_eq!(c.name, Config::default().name);
+        assert_eq!(c.sync_period, Config::default().sync_period);
+        assert_eq!(c.gps_period, 300);
+    }
+
+    #[test]
+    fn burst_env() {
+        let env: Env = serde_json_core::from_str(
+            r#"{"BURST_PERIOD":"60","BURST_LENGTH":"20","BURST_POSITION_AGE":"-5"}"#,
+        )
+        .unwrap()
+        .0;
+
+        let c = Config::from_env(&env);
+        assert_eq!(c.burst_period, 60);
+        assert_eq!(c.burst_length, 20);
+        assert_eq!(c.burst_position_age, Config::default().burst_position_age);
+        assert!(!c.schedule().is_continuous());
+
+        let env = Env {
+            burst_period: Some(String::from("1")),
+            ..Default::default()
+        };
+        assert_eq!(Config::from_env(&env).burst_period, BURST_PERIOD);
+    }
+
+    #[test]
+    fn output_freq_env() {
+        for rate in RATES {
+            let env = Env {
+                output_freq: Some(String::from(rate.output_freq() as u32)),
+                ..Default::default()
+            };
+            assert_eq!(Config::from_env(&env).rate(), rate);
+        }
+
+        let env = Env {
+            output_freq: Some(String::from("20")),
+            ..Default::default()
+        };
+        let c = Config::from_env(&env);
+        assert_eq!(c.output_freq, OUTPUT_FREQ);
+        assert_eq!(Some(c.rate()), Rate::from_hz(OUTPUT_FREQ));
+    }
+
+    #[test]
+    fn unsent_order_env() {
+        let env = Env {
+            unsent_order: Some(String::from(" newest")),
+            ..Default::default()
+        };
+        assert_eq!(Config::from_env(&env).unsent_order, UnsentOrder::Newest);
+
+        let env = Env {
+            unsent_order: Some(String::from("latest")),
+            ..Default::default()
+        };
+        assert_eq!(
+            Config::from_env(&env).unsent_order,
+            Config::default().unsent_order
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        let env = Env {
+            name: Some(String::from("  ")),
+            sync_period: Some(String::from("0")),
+            gps_period: Some(String::from("ten")),
+            gps_heartbeat: Some(String::from("0")),
+            ..Default::default()
+        };
+
+        assert_eq!(Config::from_env(&env), Config::default());
+    }
+}
@@ -1,5 +1,6 @@
 use core::simd::{f32x4, num::SimdFloat};
 use heapless::Deque;
+use static_assertions as sa;
 
 /// Sample rate.
 pub const FREQ: f32 = 208.0;
@@ -34,17 +35,52 @@ pub mod hz20 {
     // pub const TRUE_CUTOFF: f32 = 8.0;
 }
 
-#[cfg(feature = "20Hz")]
-pub use hz20::*;
+/// Filter order, length or number of taps. The same for all filters.
+pub const NTAP: usize = hz50::NTAP;
 
-#[cfg(not(feature = "20Hz"))]
-pub use hz50::*;
+sa::const_assert_eq!(hz50::NTAP, hz20::NTAP);
 
-/// Maximum decimation given `CUTOFF` and sample rate (`FREQ`).
-pub const DECIMATE: u8 = (FREQ / CUTOFF / 2.) as u8;
+/// The compiled in filters, selected at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Filter {
+    /// Cut-off at 26 Hz, decimated to 52 Hz.
+    Hz50,
 
-/// Output frequency after decimation.
-pub const OUT_FREQ: f32 = FREQ / DECIMATE as f32;
+    /// Cut-off at 13 Hz, decimated to 26 Hz.
+    Hz20,
+}
+
+impl Filter {
+    pub fn coeffs(&self) -> &'static [f32; NTAP] {
+        match self {
+            Filter::Hz50 => &hz50::COEFFS,
+            Filter::Hz20 => &hz20::COEFFS,
+        }
+    }
+
+    /// Cut-off frequency of filter.
+    pub const fn cutoff(&self) -> f32 {
+        match self {
+            Filter::Hz50 => hz50::CUTOFF,
+            Filter::Hz20 => hz20::CUTOFF,
+        }
+    }
+
+    /// Maximum decimation given `cutoff` and sample rate (`FREQ`).
+    pub const fn decimate(&self) -> u8 {
+        (FREQ / self.cutoff() / 2.) as u8
+    }
+
+    /// Output frequency after decimation.
+    pub const fn out_freq(&self) -> f32 {
+        FREQ / self.decimate() as f32
+    }
+}
+
+/// The largest decimation of any of the filters.
+pub const MAX_DECIMATE: u8 = Filter::Hz20.decimate();
+
+sa::const_assert!(MAX_DECIMATE >= Filter::Hz50.decimate());
 
 /// The delay (in seconds) introduced by the filter: half the length of the filter.
 pub const DELAY: f32 = (NTAP / 2) as f32 / FREQ;
@@ -52,15 +88,19 @@ pub const DELAY: f32 = (NTAP / 2) as f32 / FREQ;
 /// A running FIR filter with pre-computed coefficients.
 pub struct FIR {
     samples: Deque<f32, NTAP>,
+    coeffs: &'static [f32; NTAP],
 }
 
 impl FIR {
-    pub fn new() -> FIR {
+    pub fn new(filter: Filter) -> FIR {
         let mut samples = Deque::new();
 
         while samples.push_back(0.0).is_ok() {}
 
-        FIR { samples }
+        FIR {
+            samples,
+            coeffs: filter.coeffs(),
+        }
     }
 
     /// Update filter with new sample value, apply filter and output current filtered value.
@@ -79,15 +119,15 @@ impl FIR {
 
         // self.samples
         //     .iter()
-        //     .zip(&COEFFS)
+        //     .zip(self.coeffs)
         //     .fold(0.0, |a, (s, c)| a + (s * c))
 
         // debug_assert_eq!(self.samples.len() % 4, 0);
-        // debug_assert_eq!(COEFFS.len() % 4, 0);
-        debug_assert_eq!(COEFFS.len(), self.samples.len());
+        // debug_assert_eq!(self.coeffs.len() % 4, 0);
+        debug_assert_eq!(self.coeffs.len(), self.samples.len());
 
         let (f, b) = self.samples.as_slices();
-        let (cf, cb) = COEFFS.split_at(f.len());
+        let (cf, cb) = self.coeffs.split_at(f.len());
 
         debug_assert_eq!(f.len(), cf.len());
         debug_assert_eq!(b.len(), cb.len());
@@ -139,8 +179,12 @@ impl FIR {
         while self.samples.push_back(0.0).is_ok() {}
     }
 
-    pub fn into_decimator(self) -> Decimator {
-        Decimator { fir: self, m: 0 }
+    pub fn into_decimator(self, decimate: u8) -> Decimator {
+        Decimator {
+            fir: self,
+            m: 0,
+            decimate,
+        }
     }
 }
 
@@ -149,15 +193,21 @@ impl FIR {
 pub struct Decimator {
     fir: FIR,
     m: u8,
+    decimate: u8,
 }
 
 impl Decimator {
+    /// Filter with the maximum decimation for the filter.
+    pub fn new(filter: Filter) -> Decimator {
+        FIR::new(filter).into_decimator(filter.decimate())
+    }
+
     /// Update filter with new sample. A filtered output value is calculated and returned
-    /// _if_ `DECIMATE` samples has passed. Otherwise `None` is returned.
+    /// _if_ `decimate` samples has passed. Otherwise `None` is returned.
     pub fn decimate(&mut self, v: f32) -> Option<f32> {
         self.fir.put(v);
 
-        if self.m % DECIMATE == 0 {
+        if self.m % self.decimate == 0 {
             self.m = 1;
 
             Some(self.fir.value())
@@ -180,13 +230,13 @@ mod tests {
 
     #[test]
     fn setup_filter() {
-        let f = FIR::new();
+        let f = FIR::new(Filter::Hz50);
         assert_eq!(f.samples.len(), NTAP);
     }
 
     #[test]
     fn add_some_filter() {
-        let mut f = FIR::new();
+        let mut f = FIR::new(Filter::Hz50);
 
         for v in 0..256 {
             f.filter(v as f32);
@@ -197,7 +247,7 @@ mod tests {
 
     #[test]
     fn zero() {
-        let mut f = FIR::new();
+        let mut f = FIR::new(Filter::Hz50);
 
         for _ in 0..256 {
             let o = f.filter(0.0);
@@ -209,7 +259,7 @@ mod tests {
 
     #[test]
     fn reset() {
-        let mut f = FIR::new();
+        let mut f = FIR::new(Filter::Hz50);
         assert_eq!(f.samples.len(), NTAP);
 
         for _ in 0..256 {
@@ -227,7 +277,7 @@ mod tests {
 
     #[test]
     fn sin_within_cutoff() {
-        let mut f = FIR::new();
+        let mut f = FIR::new(Filter::Hz50);
 
         let fs = FREQ;
         let dt = 1. / fs;
@@ -250,7 +300,7 @@ mod tests {
 
     #[test]
     fn sin_outside_cutoff() {
-        let mut f = FIR::new();
+        let mut f = FIR::new(Filter::Hz50);
 
         let fs = 208.;
         let dt = 1. / fs;
@@ -273,34 +323,45 @@ mod tests {
 
     #[test]
     fn decimate() {
-        let mut f = FIR::new();
-        let mut d = FIR::new().into_decimator();
-
-        let fs = FREQ;
-        let dt = 1. / fs;
-
-        let t = (0..4096).map(|i| i as f32 * dt).collect::<Vec<_>>();
-        let s = t
-            .iter()
-            .map(|t| 2. * (2. * t * 2. * std::f32::consts::PI).sin())
-            .collect::<Vec<_>>();
-
-        println!("decimate: {}", DECIMATE);
-        println!("out_freq: {}", OUT_FREQ);
+        for filter in [Filter::Hz50, Filter::Hz20] {
+            let mut f = FIR::new(filter);
+            let mut d = Decimator::new(filter);
+
+            let fs = FREQ;
+            let dt = 1. / fs;
+
+            let t = (0..4096).map(|i| i as f32 * dt).collect::<Vec<_>>();
+            let s = t
+                .iter()
+                .map(|t| 2. * (2. * t * 2. * std::f32::consts::PI).sin())
+                .collect::<Vec<_>>();
+
+            println!("decimate: {}", filter.decimate());
+            println!("out_freq: {}", filter.out_freq());
+
+            let sf = s
+                .iter()
+                .map(|s| f.filter(*s))
+                .step_by(filter.decimate() as usize)
+                .collect::<Vec<_>>();
+            let df = s.iter().filter_map(|s| d.decimate(*s)).collect::<Vec<_>>();
+            assert_eq!(sf, df);
+            assert_eq!(df.len(), 4096 / filter.decimate() as usize);
+        }
+    }
 
-        let sf = s
-            .iter()
-            .map(|s| f.filter(*s))
-            .step_by(DECIMATE as usize)
-            .collect::<Vec<_>>();
-        let df = s.iter().filter_map(|s| d.decimate(*s)).collect::<Vec<_>>();
-        assert_eq!(sf, df);
-        assert_eq!(df.len(), 4096 / DECIMATE as usize);
+    #[test]
+    fn filters() {
+        assert_eq!(Filter::Hz50.decimate(), 4);
+        assert_eq!(Filter::Hz50.out_freq(), 52.);
+        assert_eq!(Filter::Hz20.decimate(), 8);
+        assert_eq!(Filter::Hz20.out_freq(), 26.);
+        assert_eq!(MAX_DECIMATE, 8);
     }
 
     #[bench]
     fn decimate_cycle(b: &mut Bencher) {
-        let mut d = FIR::new().into_decimator();
+        let mut d = Decimator::new(Filter::Hz50);
         let fs = FREQ;
         let dt = 1. / fs;
 
@@ -319,7 +380,7 @@ mod tests {
 
     #[bench]
     fn decimate_many(b: &mut Bencher) {
-        let mut d = FIR::new().into_decimator();
+        let mut d = Decimator::new(Filter::Hz50);
         let fs = FREQ;
         let dt = 1. / fs;
 
@@ -338,7 +399,7 @@ mod tests {
 
     #[bench]
     fn fir_cycle(b: &mut Bencher) {
-        let mut f = FIR::new();
+        let mut f = FIR::new(Filter::Hz50);
         let fs = FREQ;
         let dt = 1. / fs;
 
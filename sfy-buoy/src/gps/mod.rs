@@ -9,20 +9,29 @@ use heapless::{
     Vec,
 };
 
-pub const GPS_PACKET_V: u8 = 3;
+/// Version of the packet, 4 adds the storage ID.
+pub const GPS_PACKET_V: u8 = 4;
 pub const GPS_PACKET_SZ: usize = 124;
 /// Maximum length of base64 string from
 pub const GPS_OUTN: usize = { 6 * GPS_PACKET_SZ * 2 } * 4 / 3 + 4;
 
+/// Space for a serialized packet on the SD-card, postcard messages are not fixed size.
+pub const GPS_POSTCARD_SZ: usize = 1024 * 3;
+
+pub mod ubx;
 mod wire;
 pub use wire::*;
 
 use crate::waves::wire::ScaledF32;
 use crate::EPGS_SZ;
 
-/// Queue from GPS to Notecard
+/// Queue from GPS to Notecard (to storage with the `storage` feature).
 pub static mut EGPSQ: Queue<GpsPacket, { crate::EPGS_SZ }> = Queue::new();
 
+/// Queue from storage to Notecard.
+#[cfg(feature = "storage")]
+pub static mut EGPS_NOTEQ: Queue<GpsPacket, { crate::EPGS_SZ }> = Queue::new();
+
 #[derive(serde::Deserialize, PartialEq, Clone, defmt::Format)]
 pub struct EgpsTime {
     pub time: i64,     // The time received from the GPS (milliseconds).
@@ -91,7 +100,7 @@ impl Sample {
 }
 
 /// A packet of GPS samples
-#[derive(serde::Serialize, serde::Deserialize, PartialEq)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct GpsPacket {
     /// Timestamp of first sample
     pub timestamp: i64,
@@ -100,6 +109,10 @@ pub struct GpsPacket {
 
     pub version: u8,
 
+    /// ID on SD-card, in the series of GPS collections. Will not be set before the packet has
+    /// been written to the SD-card.
+    pub storage_id: Option<u32>,
+
     /// Reference position for which the data is relative to. Mean of all samples.
     pub lon: i32,
     pub lat: i32,
@@ -130,6 +143,9 @@ pub struct GpsPacketMeta {
 
     pub version: u8,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_id: Option<u32>,
+
     /// Reference position for which the data is relative to. Mean of all samples.
     pub lon: i32,
     pub lat: i32,
@@ -184,6 +200,7 @@ impl GpsPacket {
             msl_range: wire::MSL_RANGE,
             vel_range: wire::VEL_RANGE,
             version: GPS_PACKET_V,
+            storage_id: self.storage_id,
             lon: self.lon,
             lat: self.lat,
             msl: self.msl,
@@ -208,8 +225,20 @@ where
     gps: U,
     queue: Producer<'static, GpsPacket, EPGS_SZ>,
     buf: Vec<Sample, { GPS_PACKET_SZ }>,
+
+    #[cfg(feature = "ubx")]
+    ubx: ubx::Parser,
+
+    /// Latest navigation status from the receiver.
+    #[cfg(feature = "ubx")]
+    pub status: Option<ubx::NavStatus>,
+
+    /// Latest time pulse data from the receiver.
+    #[cfg(feature = "ubx")]
+    pub time_pulse: Option<ubx::TimTp>,
 }
 
+#[cfg(not(feature = "ubx"))]
 enum ParseState {
     StartBracket,
     Body,
@@ -225,6 +254,12 @@ where
             gps,
             queue,
             buf: Vec::new(),
+            #[cfg(feature = "ubx")]
+            ubx: ubx::Parser::new(),
+            #[cfg(feature = "ubx")]
+            status: None,
+            #[cfg(feature = "ubx")]
+            time_pulse: None,
         }
     }
 
@@ -308,6 +343,7 @@ where
             timestamp,
             freq,
             version: GPS_PACKET_V,
+            storage_id: None,
             lon,
             lat,
             msl,
@@ -337,6 +373,7 @@ where
             .inspect_err(|_| defmt::error!("could not enque GpsPacket.."));
     }
 
+    #[cfg(not(feature = "ubx"))]
     pub fn sample(&mut self) -> Option<&Sample> {
         let mut buf = heapless::Vec::<u8, 1024>::new(); // reduce?
 
@@ -433,6 +470,74 @@ where
         // TODO: Not really handling extra data.
         self.buf.last()
     }
+
+    /// Read from the u-blox receiver until a `UBX-NAV-PVT` frame is parsed, and add it as a
+    /// sample. `UBX-NAV-STATUS` and `UBX-TIM-TP` frames on the way are kept.
+    #[cfg(feature = "ubx")]
+    pub fn sample(&mut self) -> Option<&Sample> {
+        let mut timeout = 0_u32;
+
+        let pvt = 'read: loop {
+            if timeout > 5_000_000 {
+                defmt::error!("gps: uart timed out.");
+                break None;
+            }
+
+            match self.gps.read() {
+                Ok(w) => {
+                    timeout = 0;
+                    self.ubx.push(w);
+                }
+                Err(nb::Error::WouldBlock) => {
+                    timeout += 1;
+                    continue;
+                }
+                Err(nb::Error::Other(_)) => {
+                    defmt::error!("ext-gps: error reading from uart");
+                    timeout += 1;
+                    continue;
+                }
+            }
+
+            for m in &mut self.ubx {
+                match m {
+                    Ok(ubx::Message::NavPvt(pvt)) => break 'read Some(pvt),
+                    Ok(ubx::Message::NavStatus(status)) => self.status = Some(status),
+                    Ok(ubx::Message::TimTp(tp)) => self.time_pulse = Some(tp),
+                    Ok(ubx::Message::Other(class, id)) => {
+                        trace!("gps: skipping UBX message: {:x} {:x}", class, id)
+                    }
+                    Err(e) => warn!("gps: bad UBX frame: {}", e),
+                }
+            }
+        };
+
+        let sample = pvt
+            .filter(|pvt| pvt.valid_time())
+            .map(|pvt| pvt.sample())
+            .filter(|sample| sample.timestamp().is_some());
+
+        match sample {
+            Some(sample) => {
+                self.buf
+                    .push(sample)
+                    .inspect_err(|_| {
+                        defmt::error!("GPS sample buffer is full! Discarding latest sample.")
+                    })
+                    .ok();
+            }
+            None => {
+                error!("Failed to read GPS sample from UBX.");
+
+                // collecting package to avoid getting mis-timed samples
+                warn!("collecting egps package, to avoid mis-timed samples.");
+                self.collect();
+                return None;
+            }
+        }
+
+        self.buf.last()
+    }
 }
 
 #[cfg(test)]
@@ -498,6 +603,7 @@ mod tests {
             msl: 20,
             freq: 100.0,
             version: super::GPS_PACKET_V,
+            storage_id: None,
             data: (0..(6 * GPS_PACKET_SZ))
                 .map(|v| v as u16)
                 .collect::<heapless::Vec<_, { 6 * GPS_PACKET_SZ }>>(),
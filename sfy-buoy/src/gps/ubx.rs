@@ -0,0 +1,617 @@
+//! Parser for the u-blox UBX binary protocol.
+//!
+//! A frame is (little endian):
+//!
+//! | bytes | field                                             |
+//! |-------|---------------------------------------------------|
+//! | 2     | sync chars (`0xb5 0x62`)                          |
+//! | 1     | class                                             |
+//! | 1     | id                                                |
+//! | 2     | length of payload (n)                             |
+//! | n     | payload                                           |
+//! | 2     | checksum (8-bit Fletcher over class to payload)   |
+//!
+//! Bytes are pushed to the [`Parser`] as they are read from the UART, and the frames are taken
+//! out by iterating over it. Anything between frames (e.g. NMEA sentences) is skipped. On a bad
+//! checksum or length the parser starts over from the byte after the sync chars, so that a
+//! frame hidden in a corrupt one is not lost.
+//!
+//! Only `NAV-PVT`, `NAV-STATUS` and `TIM-TP` are decoded, other valid frames are returned as
+//! [`Message::Other`].
+
+use heapless::Vec;
+
+use super::Sample;
+
+pub const SYNC: [u8; 2] = [0xb5, 0x62];
+
+/// Largest payload that is accepted, frames with longer payloads are dropped.
+pub const MAX_PAYLOAD: usize = 128;
+
+/// Sync chars, class, id, length, payload and checksum.
+pub const MAX_FRAME: usize = 6 + MAX_PAYLOAD + 2;
+
+pub const CLASS_NAV: u8 = 0x01;
+pub const CLASS_TIM: u8 = 0x0d;
+
+pub const ID_NAV_PVT: u8 = 0x07;
+pub const ID_NAV_STATUS: u8 = 0x03;
+pub const ID_TIM_TP: u8 = 0x01;
+
+pub const NAV_PVT_SZ: usize = 92;
+pub const NAV_STATUS_SZ: usize = 16;
+pub const TIM_TP_SZ: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum UbxError {
+    /// The checksum of the frame does not match.
+    Checksum,
+    /// The payload is longer than `MAX_PAYLOAD`.
+    Length(u16),
+    /// The payload has the wrong length for the message (class, id, length).
+    Payload(u8, u8, u16),
+}
+
+#[derive(Debug, Clone, PartialEq, defmt::Format)]
+pub enum Message {
+    NavPvt(NavPvt),
+    NavStatus(NavStatus),
+    TimTp(TimTp),
+    /// A valid frame of a message that is not decoded (class, id).
+    Other(u8, u8),
+}
+
+/// Navigation position, velocity and time solution (`UBX-NAV-PVT`).
+#[derive(Debug, Clone, PartialEq, defmt::Format)]
+pub struct NavPvt {
+    /// GPS time of week of the navigation epoch [ms].
+    pub itow: u32,
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub min: u8,
+    pub sec: u8,
+    /// Validity flags: bit 0 is valid date, bit 1 is valid time.
+    pub valid: u8,
+    /// Time accuracy estimate [ns].
+    pub t_acc: u32,
+    /// Fraction of second, may be negative [ns].
+    pub nano: i32,
+    /// 0: no fix, 1: dead reckoning, 2: 2D, 3: 3D, 4: GNSS + dead reckoning, 5: time only.
+    pub fix_type: u8,
+    /// Fix status flags: bit 0 is fix ok, bits 6-7 is the carrier phase solution.
+    pub flags: u8,
+    pub num_sv: u8,
+    pub lon: i32,     // deg * 1e7
+    pub lat: i32,     // deg * 1e7
+    pub height: i32,  // mm, above ellipsoid
+    pub h_msl: i32,   // mm
+    pub h_acc: u32,   // mm
+    pub v_acc: u32,   // mm
+    pub vel_n: i32,   // mm/s
+    pub vel_e: i32,   // mm/s
+    pub vel_d: i32,   // mm/s
+    pub g_speed: i32, // mm/s
+    pub s_acc: u32,   // mm/s
+}
+
+/// Receiver navigation status (`UBX-NAV-STATUS`).
+#[derive(Debug, Clone, PartialEq, defmt::Format)]
+pub struct NavStatus {
+    pub itow: u32,
+    pub gps_fix: u8,
+    pub flags: u8,
+    pub fix_stat: u8,
+    pub flags2: u8,
+    /// Time to first fix [ms].
+    pub ttff: u32,
+    /// Time since startup [ms].
+    pub msss: u32,
+}
+
+/// Time of the next time pulse (`UBX-TIM-TP`).
+#[derive(Debug, Clone, PartialEq, defmt::Format)]
+pub struct TimTp {
+    /// Time of week of the time pulse [ms].
+    pub tow_ms: u32,
+    /// Submillisecond part of `tow_ms` [ms * 2^-32].
+    pub tow_sub_ms: u32,
+    /// Quantization error of the time pulse [ps].
+    pub q_err: i32,
+    pub week: u16,
+    pub flags: u8,
+    pub ref_info: u8,
+}
+
+/// Little endian fields of a payload.
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    fn u8(&self, i: usize) -> u8 {
+        self.0[i]
+    }
+
+    fn u16(&self, i: usize) -> u16 {
+        u16::from_le_bytes([self.0[i], self.0[i + 1]])
+    }
+
+    fn u32(&self, i: usize) -> u32 {
+        u32::from_le_bytes([self.0[i], self.0[i + 1], self.0[i + 2], self.0[i + 3]])
+    }
+
+    fn i32(&self, i: usize) -> i32 {
+        self.u32(i) as i32
+    }
+}
+
+impl NavPvt {
+    fn from_payload(p: &[u8]) -> NavPvt {
+        let p = Fields(p);
+
+        NavPvt {
+            itow: p.u32(0),
+            year: p.u16(4),
+            month: p.u8(6),
+            day: p.u8(7),
+            hour: p.u8(8),
+            min: p.u8(9),
+            sec: p.u8(10),
+            valid: p.u8(11),
+            t_acc: p.u32(12),
+            nano: p.i32(16),
+            fix_type: p.u8(20),
+            flags: p.u8(21),
+            num_sv: p.u8(23),
+            lon: p.i32(24),
+            lat: p.i32(28),
+            height: p.i32(32),
+            h_msl: p.i32(36),
+            h_acc: p.u32(40),
+            v_acc: p.u32(44),
+            vel_n: p.i32(48),
+            vel_e: p.i32(52),
+            vel_d: p.i32(56),
+            g_speed: p.i32(60),
+            s_acc: p.u32(68),
+        }
+    }
+
+    /// Date and time are valid.
+    pub fn valid_time(&self) -> bool {
+        self.valid & 0b11 == 0b11
+    }
+
+    /// Carrier phase range solution: 0: none, 1: float, 2: fixed.
+    pub fn carr_soln(&self) -> u8 {
+        self.flags >> 6
+    }
+
+    /// The sample as it was read from the co-processor.
+    pub fn sample(&self) -> Sample {
+        Sample {
+            year: self.year,
+            month: self.month,
+            day: self.day,
+            hour: self.hour,
+            minute: self.min,
+            sec: self.sec,
+            nano: self.nano,
+            time_acc: self.t_acc,
+            lon: self.lon,
+            lat: self.lat,
+            msl: self.h_msl,
+            hor_acc: self.h_acc,
+            vert_acc: self.v_acc,
+            velN: self.vel_n,
+            velE: self.vel_e,
+            velD: self.vel_d,
+            sAcc: self.s_acc as i32,
+            fix: self.fix_type,
+            soln: self.carr_soln(),
+        }
+    }
+}
+
+impl NavStatus {
+    fn from_payload(p: &[u8]) -> NavStatus {
+        let p = Fields(p);
+
+        NavStatus {
+            itow: p.u32(0),
+            gps_fix: p.u8(4),
+            flags: p.u8(5),
+            fix_stat: p.u8(6),
+            flags2: p.u8(7),
+            ttff: p.u32(8),
+            msss: p.u32(12),
+        }
+    }
+}
+
+impl TimTp {
+    fn from_payload(p: &[u8]) -> TimTp {
+        let p = Fields(p);
+
+        TimTp {
+            tow_ms: p.u32(0),
+            tow_sub_ms: p.u32(4),
+            q_err: p.i32(8),
+            week: p.u16(12),
+            flags: p.u8(14),
+            ref_info: p.u8(15),
+        }
+    }
+}
+
+impl Message {
+    fn parse(class: u8, id: u8, p: &[u8]) -> Result<Message, UbxError> {
+        let expect = |sz: usize| {
+            if p.len() == sz {
+                Ok(())
+            } else {
+                Err(UbxError::Payload(class, id, p.len() as u16))
+            }
+        };
+
+        match (class, id) {
+            (CLASS_NAV, ID_NAV_PVT) => {
+                expect(NAV_PVT_SZ)?;
+                Ok(Message::NavPvt(NavPvt::from_payload(p)))
+            }
+            (CLASS_NAV, ID_NAV_STATUS) => {
+                expect(NAV_STATUS_SZ)?;
+                Ok(Message::NavStatus(NavStatus::from_payload(p)))
+            }
+            (CLASS_TIM, ID_TIM_TP) => {
+                expect(TIM_TP_SZ)?;
+                Ok(Message::TimTp(TimTp::from_payload(p)))
+            }
+            _ => Ok(Message::Other(class, id)),
+        }
+    }
+}
+
+/// 8-bit Fletcher checksum over class, id, length and payload.
+pub fn checksum(b: &[u8]) -> [u8; 2] {
+    let (mut a, mut b_) = (0u8, 0u8);
+
+    for v in b {
+        a = a.wrapping_add(*v);
+        b_ = b_.wrapping_add(a);
+    }
+
+    [a, b_]
+}
+
+#[derive(Default)]
+pub struct Parser {
+    /// Bytes from the start of the next possible frame.
+    buf: Vec<u8, MAX_FRAME>,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    pub fn push(&mut self, b: u8) {
+        if self.buf.is_full() {
+            // The frames have not been taken out, the first frame can not be complete.
+            self.consume(1);
+        }
+
+        // unwrap: checked that there is room above.
+        self.buf.push(b).unwrap();
+    }
+
+    /// Remove `n` bytes from the start of the buffer.
+    fn consume(&mut self, n: usize) {
+        let n = n.min(self.buf.len());
+        self.buf.copy_within(n.., 0);
+        self.buf.truncate(self.buf.len() - n);
+    }
+}
+
+/// The frames in the pushed bytes, ends when more bytes are needed. Should be drained after
+/// every push.
+impl Iterator for Parser {
+    type Item = Result<Message, UbxError>;
+
+    fn next(&mut self) -> Option<Result<Message, UbxError>> {
+        loop {
+            // Skip to the next sync char.
+            let start = self
+                .buf
+                .iter()
+                .position(|b| *b == SYNC[0])
+                .unwrap_or(self.buf.len());
+            self.consume(start);
+
+            if self.buf.len() < 2 {
+                return None;
+            }
+
+            if self.buf[1] != SYNC[1] {
+                self.consume(1);
+                continue;
+            }
+
+            if self.buf.len() < 6 {
+                return None;
+            }
+
+            let length = u16::from_le_bytes([self.buf[4], self.buf[5]]);
+            if length as usize > MAX_PAYLOAD {
+                self.consume(1);
+                return Some(Err(UbxError::Length(length)));
+            }
+
+            let end = 6 + length as usize;
+            if self.buf.len() < end + 2 {
+                return None;
+            }
+
+            if checksum(&self.buf[2..end]) != self.buf[end..end + 2] {
+                self.consume(1);
+                return Some(Err(UbxError::Checksum));
+            }
+
+            let m = Message::parse(self.buf[2], self.buf[3], &self.buf[6..end]);
+            self.consume(end + 2);
+
+            return Some(m);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frame the payload.
+    fn frame(class: u8, id: u8, payload: &[u8]) -> std::vec::Vec<u8> {
+        let mut f = std::vec::Vec::from(SYNC);
+        f.extend([class, id]);
+        f.extend((payload.len() as u16).to_le_bytes());
+        f.extend(payload);
+        let ck = checksum(&f[2..]);
+        f.extend(ck);
+        f
+    }
+
+    fn nav_pvt() -> std::vec::Vec<u8> {
+        let mut p = std::vec::Vec::new();
+        p.extend(123_456_000u32.to_le_bytes()); // itow
+        p.extend(2024u16.to_le_bytes());
+        p.extend([10, 26, 7, 16, 34]); // month, day, hour, min, sec
+        p.push(0b111); // valid
+        p.extend(47u32.to_le_bytes()); // t_acc
+        p.extend((-1_000i32).to_le_bytes()); // nano
+        p.extend([3, 0b1000_0001, 0, 12]); // fix type, flags, flags2, num sv
+        p.extend(53677011i32.to_le_bytes()); // lon
+        p.extend(603283447i32.to_le_bytes()); // lat
+        p.extend(130_000i32.to_le_bytes()); // height
+        p.extend(91506i32.to_le_bytes()); // h_msl
+        p.extend(11058u32.to_le_bytes()); // h_acc
+        p.extend(14322u32.to_le_bytes()); // v_acc
+        p.extend((-41i32).to_le_bytes()); // vel_n
+        p.extend(53i32.to_le_bytes()); // vel_e
+        p.extend(0i32.to_le_bytes()); // vel_d
+        p.extend(67i32.to_le_bytes()); // g_speed
+        p.extend(0i32.to_le_bytes()); // head_mot
+        p.extend(455u32.to_le_bytes()); // s_acc
+        p.resize(NAV_PVT_SZ, 0);
+
+        frame(CLASS_NAV, ID_NAV_PVT, &p)
+    }
+
+    fn parse_all(b: &[u8]) -> std::vec::Vec<Result<Message, UbxError>> {
+        let mut parser = Parser::new();
+        let mut msgs = std::vec::Vec::new();
+
+        for v in b {
+            parser.push(*v);
+            msgs.extend(&mut parser);
+        }
+
+        msgs
+    }
+
+    #[test]
+    fn checksum_poll() {
+        // Poll messages from the u-blox protocol description.
+        assert_eq!(
+            frame(0x06, 0x00, &[]),
+            [0xb5, 0x62, 0x06, 0x00, 0, 0, 0x06, 0x18]
+        );
+        assert_eq!(
+            frame(0x0a, 0x04, &[]),
+            [0xb5, 0x62, 0x0a, 0x04, 0, 0, 0x0e, 0x34]
+        );
+    }
+
+    #[test]
+    fn parse_nav_pvt() {
+        let msgs = parse_all(&nav_pvt());
+        assert_eq!(msgs.len(), 1);
+
+        let Ok(Message::NavPvt(pvt)) = &msgs[0] else {
+            panic!("not a NAV-PVT: {:?}", msgs[0]);
+        };
+
+        assert!(pvt.valid_time());
+        assert_eq!(pvt.carr_soln(), 2);
+        assert_eq!(pvt.num_sv, 12);
+        assert_eq!(pvt.s_acc, 455);
+
+        let s = pvt.sample();
+        assert_eq!((s.lon, s.lat, s.msl), (53677011, 603283447, 91506));
+        assert_eq!((s.velN, s.velE, s.velD), (-41, 53, 0));
+        assert_eq!((s.fix, s.soln), (3, 2));
+
+        // Negative nano-seconds are taken from the second.
+        assert_eq!(
+            s.timestamp().unwrap().and_utc().timestamp_millis() % 60_000,
+            33_999
+        );
+    }
+
+    #[test]
+    fn parse_status_and_time_pulse() {
+        let mut status = std::vec::Vec::new();
+        status.extend(1000u32.to_le_bytes());
+        status.extend([3, 0b1101, 0, 0]);
+        status.extend(25_000u32.to_le_bytes());
+        status.extend(60_000u32.to_le_bytes());
+
+        let mut tp = std::vec::Vec::new();
+        tp.extend(123_457_000u32.to_le_bytes());
+        tp.extend(0x8000_0000u32.to_le_bytes());
+        tp.extend((-1200i32).to_le_bytes());
+        tp.extend(2338u16.to_le_bytes());
+        tp.extend([0b11, 0]);
+
+        let mut b = frame(CLASS_NAV, ID_NAV_STATUS, &status);
+        b.extend(frame(CLASS_TIM, ID_TIM_TP, &tp));
+
+        let msgs = parse_all(&b);
+        assert_eq!(
+            msgs,
+            [
+                Ok(Message::NavStatus(NavStatus {
+                    itow: 1000,
+                    gps_fix: 3,
+                    flags: 0b1101,
+                    fix_stat: 0,
+                    flags2: 0,
+                    ttff: 25_000,
+                    msss: 60_000,
+                })),
+                Ok(Message::TimTp(TimTp {
+                    tow_ms: 123_457_000,
+                    tow_sub_ms: 0x8000_0000,
+                    q_err: -1200,
+                    week: 2338,
+                    flags: 0b11,
+                    ref_info: 0,
+                })),
+            ]
+        );
+    }
+
+    #[test]
+    fn resynchronize() {
+        // NMEA and stray sync chars between frames, a poll (other message), a corrupt frame, a
+        // frame with a bad length and a frame cut off by the next.
+        let mut b = std::vec::Vec::new();
+        b.extend(b"$GNGGA,071634.40,6019.70068,N,00522.06207,E,1,12,0.58,91.5,M,,*4B\r\n");
+        b.extend([0xb5, 0x00, 0xb5]);
+        b.extend(nav_pvt());
+        b.extend(frame(0x0a, 0x04, &[]));
+
+        let mut corrupt = nav_pvt();
+        corrupt[30] ^= 0x10;
+        b.extend(corrupt);
+
+        b.extend([0xb5, 0x62, 0x01, 0x07, 0xff, 0xff]);
+
+        let cut = nav_pvt();
+        b.extend(&cut[..50]);
+        b.extend(nav_pvt());
+
+        b.extend(frame(CLASS_NAV, ID_NAV_PVT, &[0; 10]));
+
+        let msgs = parse_all(&b);
+        let pvts = msgs
+            .iter()
+            .filter(|m| matches!(m, Ok(Message::NavPvt(_))))
+            .count();
+        assert_eq!(pvts, 2);
+
+        assert!(msgs.contains(&Ok(Message::Other(0x0a, 0x04))));
+        assert!(msgs.contains(&Err(UbxError::Checksum)));
+        assert!(msgs.contains(&Err(UbxError::Length(0xffff))));
+        assert_eq!(
+            msgs.last(),
+            Some(&Err(UbxError::Payload(CLASS_NAV, ID_NAV_PVT, 10)))
+        );
+    }
+
+    #[test]
+    fn frame_in_corrupt_frame() {
+        // The length of the first frame is corrupt and covers the next frame, which is found
+        // after the checksum fails.
+        let mut b = frame(0x0a, 0x04, &[]);
+        b[4] = 20;
+        b.extend(nav_pvt());
+        b.extend([0; 20]);
+
+        let msgs = parse_all(&b);
+        assert_eq!(msgs[0], Err(UbxError::Checksum));
+        assert!(matches!(msgs[1], Ok(Message::NavPvt(_))));
+        assert_eq!(msgs.len(), 2);
+    }
+
+    #[test]
+    fn uart_stream() {
+        // Constructed, not recorded: see `tests/data/ubx/README.md`.
+        let b = std::fs::read("tests/data/ubx/fixture.bin").unwrap();
+        let msgs = parse_all(&b);
+
+        // The tail of the frame at the start is skipped with the NMEA sentences.
+        assert_eq!(msgs.len(), 10);
+        assert_eq!(msgs[0], Ok(Message::Other(0x05, 0x01)));
+
+        let pvts: std::vec::Vec<&NavPvt> = msgs
+            .iter()
+            .filter_map(|m| match m {
+                Ok(Message::NavPvt(pvt)) => Some(pvt),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pvts.len(), 3);
+
+        let pvt = pvts[0];
+        assert_eq!(pvt.itow, 200_194_000);
+        assert_eq!(
+            (pvt.year, pvt.month, pvt.day, pvt.hour, pvt.min, pvt.sec),
+            (2024, 10, 26, 7, 16, 34)
+        );
+        assert_eq!((pvt.nano, pvt.t_acc), (-2_216, 21));
+        assert_eq!((pvt.fix_type, pvt.carr_soln(), pvt.num_sv), (3, 1, 27));
+        assert_eq!((pvt.lon, pvt.lat), (53_200_147, 603_945_271));
+        assert_eq!((pvt.height, pvt.h_msl), (44_762, 3_412));
+        assert_eq!((pvt.h_acc, pvt.v_acc, pvt.s_acc), (14, 10, 61));
+        assert_eq!(
+            (pvt.vel_n, pvt.vel_e, pvt.vel_d, pvt.g_speed),
+            (12, -31, 4, 43)
+        );
+        assert!(pvt.valid_time());
+
+        let s = pvts[2].sample();
+        assert_eq!((s.lon, s.lat, s.msl), (53_200_146, 603_945_268, 3_421));
+        assert_eq!((s.fix, s.soln), (3, 2));
+
+        let t: std::vec::Vec<i64> = pvts
+            .iter()
+            .map(|pvt| {
+                pvt.sample()
+                    .timestamp()
+                    .unwrap()
+                    .and_utc()
+                    .timestamp_millis()
+            })
+            .collect();
+        assert_eq!(t, [1729926993999, 1729926994999, 1729926995999]);
+
+        assert!(msgs.contains(&Ok(Message::TimTp(TimTp {
+            tow_ms: 200_195_000,
+            tow_sub_ms: 0,
+            q_err: -1_173,
+            week: 2338,
+            flags: 0b11,
+            ref_info: 0,
+        }))));
+    }
+}
@@ -0,0 +1,243 @@
+//! Iridium SBD transport through a RockBLOCK 9603 modem on the UART.
+//!
+//! Used when there is no cellular coverage. The messages are at most 340 bytes, so only the
+//! spectra (with the `spectrum` feature) or the position is sent. The packages remain on the
+//! SD-card. The Notecard is still used for GPS and time.
+//!
+//! The modem is controlled with AT commands: the message is written to the modem with
+//! `AT+SBDWB`, and sent with `AT+SBDIX` which blocks until the session is completed.
+
+use core::fmt::Write as _;
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::serial::{Read, Write};
+use heapless::{String, Vec};
+
+use crate::axl::AxlPacket;
+use crate::transport::Transport;
+
+pub mod wire;
+
+/// Minimum time between position messages [ms].
+#[cfg(not(feature = "spectrum"))]
+pub const SBD_POSITION_PERIOD: i64 = 30 * 60 * 1000;
+
+/// Timeout for regular commands [ms].
+const TIMEOUT: u32 = 2_000;
+
+/// Timeout for an SBD session, the modem gives up after about 60 seconds [ms].
+const SBDIX_TIMEOUT: u32 = 90_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum IridiumError {
+    Serial,
+    Timeout,
+    /// Unexpected or `ERROR` response from modem.
+    Response,
+    /// Writing the message to the modem failed with status.
+    Write(u8),
+    /// The SBD session failed with MO status.
+    Session(u8),
+}
+
+pub struct Iridium<U> {
+    uart: U,
+
+    /// Time of last position message [ms].
+    #[cfg(not(feature = "spectrum"))]
+    last_position: i64,
+
+    #[cfg(feature = "spectrum")]
+    spec: crate::spec::Spectrum,
+}
+
+impl<U: Read<u8> + Write<u8>> Iridium<U> {
+    pub fn new(uart: U, delay: &mut impl DelayMs<u16>) -> Result<Iridium<U>, IridiumError> {
+        let mut iridium = Iridium {
+            uart,
+            #[cfg(not(feature = "spectrum"))]
+            last_position: 0,
+            #[cfg(feature = "spectrum")]
+            spec: crate::spec::Spectrum::new(),
+        };
+
+        iridium.command("AT", TIMEOUT, delay)?;
+        iridium.command("ATE0", TIMEOUT, delay)?; // no echo
+        iridium.command("AT&K0", TIMEOUT, delay)?; // no flow control
+
+        defmt::info!("iridium: modem ready.");
+
+        Ok(iridium)
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), IridiumError> {
+        for b in bytes {
+            nb::block!(self.uart.write(*b)).map_err(|_| IridiumError::Serial)?;
+        }
+
+        nb::block!(self.uart.flush()).map_err(|_| IridiumError::Serial)
+    }
+
+    /// Read a non-empty line from the modem.
+    fn read_line(
+        &mut self,
+        timeout: u32,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<Vec<u8, 64>, IridiumError> {
+        let mut line = Vec::new();
+        let mut waited = 0;
+
+        loop {
+            match self.uart.read() {
+                Ok(b'\n') if !line.is_empty() => return Ok(line),
+                Ok(b'\r' | b'\n') => {}
+                Ok(b) => {
+                    line.push(b).map_err(|_| IridiumError::Response)?;
+                }
+                Err(nb::Error::WouldBlock) => {
+                    if waited > timeout {
+                        defmt::error!("iridium: timed out waiting for response.");
+                        return Err(IridiumError::Timeout);
+                    }
+
+                    delay.delay_ms(1);
+                    waited += 1;
+                }
+                Err(nb::Error::Other(_)) => return Err(IridiumError::Serial),
+            }
+        }
+    }
+
+    /// Run command and wait for `OK`, returns the last line of the response before `OK`.
+    fn command(
+        &mut self,
+        cmd: &str,
+        timeout: u32,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<Vec<u8, 64>, IridiumError> {
+        defmt::trace!("iridium: {}", cmd);
+
+        self.write_all(cmd.as_bytes())?;
+        self.write_all(b"\r")?;
+
+        let mut response = Vec::new();
+
+        loop {
+            let line = self.read_line(timeout, delay)?;
+
+            match line.as_slice() {
+                b"OK" => return Ok(response),
+                b"ERROR" => {
+                    defmt::error!("iridium: {} failed.", cmd);
+                    return Err(IridiumError::Response);
+                }
+                _ => response = line,
+            }
+        }
+    }
+
+    /// Send message, returns the MO message sequence number.
+    pub fn send_message(
+        &mut self,
+        msg: &[u8],
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<u16, IridiumError> {
+        defmt::debug!("iridium: sending message of {} bytes..", msg.len());
+
+        let mut cmd = String::<16>::new();
+        write!(&mut cmd, "AT+SBDWB={}\r", msg.len()).map_err(|_| IridiumError::Response)?;
+        self.write_all(cmd.as_bytes())?;
+
+        if self.read_line(TIMEOUT, delay)?.as_slice() != b"READY" {
+            return Err(IridiumError::Response);
+        }
+
+        let checksum = msg.iter().fold(0u16, |c, b| c.wrapping_add(*b as u16));
+        self.write_all(msg)?;
+        self.write_all(&checksum.to_be_bytes())?;
+
+        // 0: ok, 1: timeout, 2: checksum, 3: wrong size.
+        match self.read_line(TIMEOUT, delay)?.as_slice() {
+            b"0" => {}
+            [s] if s.is_ascii_digit() => return Err(IridiumError::Write(s - b'0')),
+            _ => return Err(IridiumError::Response),
+        }
+
+        if self.read_line(TIMEOUT, delay)?.as_slice() != b"OK" {
+            return Err(IridiumError::Response);
+        }
+
+        // +SBDIX: <MO status>, <MOMSN>, <MT status>, <MTMSN>, <MT length>, <MT queued>
+        let response = self.command("AT+SBDIX", SBDIX_TIMEOUT, delay)?;
+        let response = core::str::from_utf8(&response).map_err(|_| IridiumError::Response)?;
+
+        let mut fields = response
+            .strip_prefix("+SBDIX:")
+            .ok_or(IridiumError::Response)?
+            .split(',')
+            .map(|f| f.trim().parse::<u16>());
+
+        let status = fields
+            .next()
+            .and_then(Result::ok)
+            .ok_or(IridiumError::Response)?;
+        let momsn = fields
+            .next()
+            .and_then(Result::ok)
+            .ok_or(IridiumError::Response)?;
+
+        // MO status 0 to 4 means that the message was transferred.
+        if status > 4 {
+            defmt::warn!("iridium: session failed, status: {}", status);
+            return Err(IridiumError::Session(status as u8));
+        }
+
+        defmt::info!("iridium: message sent: {} ({} bytes)", momsn, msg.len());
+
+        Ok(momsn)
+    }
+}
+
+impl<U: Read<u8> + Write<u8>> Transport for Iridium<U> {
+    type Error = IridiumError;
+
+    fn ready(&mut self, _delay: &mut impl DelayMs<u16>) -> Result<bool, IridiumError> {
+        Ok(true)
+    }
+
+    /// The packages are too large for SBD, only the position is sent (at most every
+    /// `SBD_POSITION_PERIOD`). With the `spectrum` feature only the spectra are sent.
+    #[allow(unused)]
+    fn send(
+        &mut self,
+        pck: &AxlPacket,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<usize, IridiumError> {
+        #[cfg(not(feature = "spectrum"))]
+        if (pck.timestamp - self.last_position) > SBD_POSITION_PERIOD {
+            let msg = wire::position(pck.timestamp, pck.position_time, pck.lat, pck.lon);
+            self.send_message(&msg, delay)?;
+            self.last_position = pck.timestamp;
+
+            return Ok(msg.len());
+        }
+
+        Ok(0)
+    }
+
+    #[cfg(feature = "spectrum")]
+    fn spectrum(&mut self) -> &mut crate::spec::Spectrum {
+        &mut self.spec
+    }
+
+    #[cfg(feature = "spectrum")]
+    fn send_spec(
+        &mut self,
+        spec: &crate::spec::SpecPacket,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), IridiumError> {
+        let msg = wire::spectrum(spec);
+        self.send_message(&msg, delay)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+//! Binary messages sent over Iridium SBD.
+//!
+//! All messages start with the same header, values are little endian:
+//!
+//! | bytes | type  | field                            |
+//! |-------|-------|----------------------------------|
+//! | 1     | u8    | message type (`MessageType`)     |
+//! | 1     | u8    | version (`SBD_VERSION`)          |
+//! | 4     | u32   | timestamp [s]                    |
+//! | 4     | u32   | position time [s]                |
+//! | 4     | i32   | latitude [1e-7 deg]              |
+//! | 4     | i32   | longitude [1e-7 deg]             |
+//!
+//! The position message is only the header. The spectrum message continues with:
+//!
+//! | bytes | type  | field                            |
+//! |-------|-------|----------------------------------|
+//! | 2     | u16   | packages                         |
+//! | 4     | f32   | df [Hz]                          |
+//! | 2     | u16   | hs [mm]                          |
+//! | 2     | u16   | tp [cs]                          |
+//! | 2     | u16   | tm01 [cs]                        |
+//! | 2     | u16   | tm02 [cs]                        |
+//! | 2     | i16   | mdir [0.1 deg]                   |
+//! | 2     | u16   | spread [0.1 deg]                 |
+//! | 1     | u8    | number of bins (n)               |
+//! | 2 n   | u16   | spectrum [m^2/Hz], log10 scaled  |
+//! | 4 n   | i8    | a1, b1, a2, b2 for each bin      |
+//!
+//! The spectrum is scaled as `1 + (log10(S) - SPEC_LOG_MIN) / (SPEC_LOG_MAX - SPEC_LOG_MIN) *
+//! (u16::MAX - 1)`, and is 0 when `S` is zero. The directional moments are scaled by 127. Missing
+//! values are `u16::MAX`, `i16::MIN` and `i8::MIN`.
+//!
+//! > Keep in sync with `sfy-data/src/sbd.rs`.
+
+use heapless::Vec;
+
+/// Maximum size of mobile originated SBD messages on the 9603.
+pub const SBD_MAX: usize = 340;
+
+pub const SBD_VERSION: u8 = 1;
+
+/// Range of the log10 scaled spectrum.
+pub const SPEC_LOG_MIN: f32 = -10.;
+pub const SPEC_LOG_MAX: f32 = 4.;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum MessageType {
+    Position = 1,
+    Spectrum = 2,
+}
+
+pub type Message = Vec<u8, SBD_MAX>;
+
+fn header(tp: MessageType, timestamp: i64, position_time: u32, lat: f64, lon: f64) -> Message {
+    let mut msg = Message::new();
+
+    // unwrap: header is smaller than the message.
+    msg.push(tp as u8).unwrap();
+    msg.push(SBD_VERSION).unwrap();
+    msg.extend_from_slice(&((timestamp / 1000) as u32).to_le_bytes())
+        .unwrap();
+    msg.extend_from_slice(&position_time.to_le_bytes()).unwrap();
+    msg.extend_from_slice(&((lat * 1e7) as i32).to_le_bytes())
+        .unwrap();
+    msg.extend_from_slice(&((lon * 1e7) as i32).to_le_bytes())
+        .unwrap();
+
+    msg
+}
+
+/// Position message, `timestamp` in ms.
+pub fn position(timestamp: i64, position_time: u32, lat: f64, lon: f64) -> Message {
+    header(MessageType::Position, timestamp, position_time, lat, lon)
+}
+
+/// Scale positive value to u16, `u16::MAX` if missing.
+fn scale_u16(v: f32, scale: f32) -> u16 {
+    if v.is_finite() && v >= 0. {
+        libm::roundf(v * scale).min((u16::MAX - 1) as f32) as u16
+    } else {
+        u16::MAX
+    }
+}
+
+/// Log10 scaled spectral density, 0 is zero (or below `SPEC_LOG_MIN`).
+fn log16(v: f32) -> u16 {
+    if v.is_finite() && v > 0. {
+        let l = (libm::log10f(v) - SPEC_LOG_MIN) / (SPEC_LOG_MAX - SPEC_LOG_MIN);
+        (libm::roundf(l * (u16::MAX - 1) as f32) + 1.).clamp(1., u16::MAX as f32) as u16
+    } else {
+        0
+    }
+}
+
+/// Directional moment on [-1, 1], `i8::MIN` if missing.
+fn moment8(v: f32) -> i8 {
+    if v.is_finite() {
+        libm::roundf(v.clamp(-1., 1.) * i8::MAX as f32) as i8
+    } else {
+        i8::MIN
+    }
+}
+
+/// Spectrum message.
+#[cfg(feature = "spectrum")]
+pub fn spectrum(spec: &crate::spec::SpecPacket) -> Message {
+    let mut msg = header(
+        MessageType::Spectrum,
+        spec.timestamp,
+        spec.position_time,
+        spec.lat,
+        spec.lon,
+    );
+
+    let mdir = if spec.mdir.is_finite() {
+        libm::roundf(spec.mdir * 10.) as i16
+    } else {
+        i16::MIN
+    };
+
+    // unwrap: the spectrum is checked to fit in the message.
+    msg.extend_from_slice(&(spec.packages as u16).to_le_bytes())
+        .unwrap();
+    msg.extend_from_slice(&spec.df.to_le_bytes()).unwrap();
+    msg.extend_from_slice(&scale_u16(spec.hs, 1000.).to_le_bytes())
+        .unwrap();
+    msg.extend_from_slice(&scale_u16(spec.tp, 100.).to_le_bytes())
+        .unwrap();
+    msg.extend_from_slice(&scale_u16(spec.tm01, 100.).to_le_bytes())
+        .unwrap();
+    msg.extend_from_slice(&scale_u16(spec.tm02, 100.).to_le_bytes())
+        .unwrap();
+    msg.extend_from_slice(&mdir.to_le_bytes()).unwrap();
+    msg.extend_from_slice(&scale_u16(spec.spread, 10.).to_le_bytes())
+        .unwrap();
+
+    msg.push(spec.spec.len() as u8).unwrap();
+    for s in &spec.spec {
+        msg.extend_from_slice(&log16(*s).to_le_bytes()).unwrap();
+    }
+
+    for i in 0..spec.spec.len() {
+        for m in [&spec.a1, &spec.b1, &spec.a2, &spec.b2] {
+            msg.push(moment8(m[i]) as u8).unwrap();
+        }
+    }
+
+    msg
+}
+
+#[cfg(feature = "spectrum")]
+static_assertions::const_assert!(37 + 6 * crate::spec::SPEC_BINS <= SBD_MAX);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_message() {
+        let msg = position(1_700_000_000_123, 1_699_999_990, 60.39, -5.32);
+        assert_eq!(msg.len(), 18);
+        assert_eq!(msg[0], MessageType::Position as u8);
+        assert_eq!(msg[1], SBD_VERSION);
+        assert_eq!(&msg[2..6], &1_700_000_000u32.to_le_bytes());
+        assert_eq!(&msg[6..10], &1_699_999_990u32.to_le_bytes());
+        assert_eq!(&msg[10..14], &603_900_000i32.to_le_bytes());
+        assert_eq!(&msg[14..18], &(-53_200_000i32).to_le_bytes());
+    }
+
+    #[test]
+    fn scaling() {
+        assert_eq!(scale_u16(1.234, 1000.), 1234);
+        assert_eq!(scale_u16(f32::NAN, 1000.), u16::MAX);
+        assert_eq!(scale_u16(1e9, 1000.), u16::MAX - 1);
+
+        assert_eq!(log16(0.), 0);
+        assert_eq!(log16(1e-10), 1);
+        assert_eq!(log16(1e4), u16::MAX);
+
+        let l = log16(0.5);
+        let v = libm::powf(
+            10.,
+            SPEC_LOG_MIN + (l - 1) as f32 / (u16::MAX - 1) as f32 * (SPEC_LOG_MAX - SPEC_LOG_MIN),
+        );
+        assert!((v - 0.5).abs() < 0.5 * 1e-3);
+
+        assert_eq!(moment8(1.), 127);
+        assert_eq!(moment8(-2.), -127);
+        assert_eq!(moment8(f32::NAN), i8::MIN);
+    }
+
+    #[cfg(feature = "spectrum")]
+    #[test]
+    fn spectrum_message() {
+        use crate::spec::{SpecPacket, SPEC_BINS};
+
+        let mut spec = SpecPacket {
+            timestamp: 1_700_000_000_000,
+            packages: 60,
+            df: 0.05,
+            hs: 1.5,
+            tp: 8.,
+            tm01: 6.5,
+            tm02: 6.,
+            mdir: -45.,
+            spread: 30.,
+            ..Default::default()
+        };
+
+        for i in 0..SPEC_BINS {
+            spec.spec.push(i as f32).unwrap();
+            spec.a1.push(0.5).unwrap();
+            spec.b1.push(-0.5).unwrap();
+            spec.a2.push(f32::NAN).unwrap();
+            spec.b2.push(0.).unwrap();
+        }
+
+        let msg = spectrum(&spec);
+        assert_eq!(msg.len(), 37 + 6 * SPEC_BINS);
+        assert_eq!(msg[0], MessageType::Spectrum as u8);
+        assert_eq!(&msg[18..20], &60u16.to_le_bytes());
+        assert_eq!(&msg[24..26], &1500u16.to_le_bytes());
+        assert_eq!(&msg[32..34], &(-450i16).to_le_bytes());
+        assert_eq!(msg[36] as usize, SPEC_BINS);
+
+        // First bin is zero.
+        assert_eq!(&msg[37..39], &[0, 0]);
+
+        let m = 37 + 2 * SPEC_BINS;
+        assert_eq!(&msg[m..m + 4], &[64, (-64i8) as u8, i8::MIN as u8, 0]);
+    }
+}
@@ -16,11 +16,15 @@ use chrono::NaiveDateTime;
 use core::cell::RefCell;
 use core::fmt::Debug;
 use core::ops::DerefMut;
-use cortex_m::interrupt::{free, Mutex};
+#[cfg(not(test))]
+use cortex_m::interrupt::free;
+use cortex_m::interrupt::Mutex;
 use embedded_hal::blocking::{
     delay::DelayMs,
     i2c::{Read, Write, WriteRead},
 };
+#[cfg(test)]
+use sim::free;
 
 #[cfg(feature = "storage")]
 use embedded_hal::{
@@ -32,20 +36,34 @@ use embedded_hal::{
 use rtcc::DateTimeAccess;
 
 pub mod axl;
+pub mod crash;
+pub mod env;
 #[cfg(feature = "fir")]
 pub mod fir;
 pub mod log;
 pub mod note;
+pub mod rice;
+pub mod schedule;
+#[cfg(test)]
+mod sim;
+#[cfg(feature = "spectrum")]
+pub mod spec;
+pub mod status;
 #[cfg(feature = "storage")]
 pub mod storage;
+pub mod transport;
+pub mod watchdog;
 pub mod waves;
 
 #[cfg(feature = "ext-gps")]
 pub mod gps;
 
+#[cfg(feature = "iridium")]
+pub mod iridium;
+
 use axl::AxlPacket;
 #[cfg(feature = "storage")]
-use storage::Storage;
+use storage::{unsent::Unsent, Storage};
 #[cfg(feature = "storage")]
 use waves::AxlPacketT;
 
@@ -324,6 +342,7 @@ pub struct Imu<E: Debug + defmt::Format, I: Write<Error = E> + WriteRead<Error =
     pub queue: heapless::spsc::Producer<'static, ImuAxlPacketT, IMUQ_SZ>,
     waves: waves::Waves<I>,
     last_read: i64,
+    sampling: bool,
 }
 
 impl<E: Debug + defmt::Format, I: Write<Error = E> + WriteRead<Error = E>> Imu<E, I> {
@@ -335,6 +354,7 @@ impl<E: Debug + defmt::Format, I: Write<Error = E> + WriteRead<Error = E>> Imu<E
             queue,
             waves,
             last_read: 0,
+            sampling: true,
         }
     }
 
@@ -345,6 +365,17 @@ impl<E: Debug + defmt::Format, I: Write<Error = E> + WriteRead<Error = E>> Imu<E
         position_time: u32,
         lon: f64,
         lat: f64,
+    ) -> Result<u32, waves::ImuError<E>> {
+        self.retrieve(now, position_time, lon, lat)
+            .inspect_err(|e| status::COUNTERS.imu_error(e))
+    }
+
+    fn retrieve(
+        &mut self,
+        now: i64,
+        position_time: u32,
+        lon: f64,
+        lat: f64,
     ) -> Result<u32, waves::ImuError<E>> {
         trace!("Polling IMU.. (now: {})", now,);
 
@@ -353,6 +384,7 @@ impl<E: Debug + defmt::Format, I: Write<Error = E> + WriteRead<Error = E>> Imu<E
         if self.waves.is_full() {
             trace!("waves buffer is full, pushing to queue..");
             let pck = self.waves.take_buf(now, position_time, lon, lat)?;
+            status::COUNTERS.set_imu_temperature(self.waves.temperature);
 
             #[cfg(not(feature = "storage"))]
             let pck = pck.0;
@@ -400,6 +432,199 @@ impl<E: Debug + defmt::Format, I: Write<Error = E> + WriteRead<Error = E>> Imu<E
 
         Ok(())
     }
+
+    /// Whether the FIFO is enabled and samples are being collected.
+    pub fn sampling(&self) -> bool {
+        self.sampling
+    }
+
+    /// Stop sampling at the end of a burst. The FIFO is disabled, and samples that do not fill
+    /// up a package are discarded.
+    pub fn sleep(&mut self) -> Result<(), waves::ImuError<E>> {
+        info!("IMU: stopping sampling.");
+        self.sampling = false;
+        self.waves.disable_fifo()?;
+
+        Ok(())
+    }
+
+    /// Start sampling a new burst. The packages are marked with the start of the burst.
+    pub fn wake(
+        &mut self,
+        now: i64,
+        position_time: u32,
+        lon: f64,
+        lat: f64,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), waves::ImuError<E>> {
+        info!("IMU: starting burst at: {}", now);
+        self.waves.burst_start = now;
+        self.sampling = true;
+        self.reset(now, position_time, lon, lat, delay)
+    }
+
+    pub fn rate(&self) -> waves::Rate {
+        self.waves.rate()
+    }
+
+    /// Change the output rate. The samples that do not fill up a package are discarded. Between
+    /// bursts the new rate takes effect when sampling starts again.
+    pub fn set_rate(
+        &mut self,
+        rate: waves::Rate,
+        now: i64,
+        position_time: u32,
+        lon: f64,
+        lat: f64,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), waves::ImuError<E>> {
+        self.waves.set_rate(rate);
+
+        if self.sampling {
+            self.reset(now, position_time, lon, lat, delay)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Maximum number of requested or unsent packages from the SD-card in the note queue at a time,
+/// the rest of the queue is left for the live packages.
+#[cfg(feature = "storage")]
+pub const REQUEST_BATCH: usize = if NOTEQ_SZ / 4 > 1 { NOTEQ_SZ / 4 } else { 1 };
+
+/// A request for packages from the SD-card through `storage.db` on the Notecard.
+#[cfg(feature = "storage")]
+#[derive(Default)]
+struct Request {
+    /// Last requested package in the note queue, and the number of packages queued behind it.
+    pending: Option<(u32, usize)>,
+
+    /// The range of requested packages that have not been sent yet, these are not removed to
+    /// free space.
+    requested: Option<(u32, u32)>,
+}
+
+#[cfg(feature = "storage")]
+impl Request {
+    /// Whether requested packages are still in the note queue.
+    fn waiting(&self, queued: usize) -> bool {
+        self.pending.is_some_and(|(_, behind)| queued > behind)
+    }
+
+    /// A live package was queued behind the requested packages.
+    fn behind(&mut self) {
+        if let Some((_, behind)) = &mut self.pending {
+            *behind += 1;
+        }
+    }
+
+    /// Queue the next batch of the request with `queue`, which returns false when the note queue
+    /// has no room. Returns the progress to write to the Notecard and whether the request should
+    /// be cleared, or none if there is no request.
+    fn queue(
+        &mut self,
+        next_id: u32,
+        info: Option<note::StorageIdInfo>,
+        request: Option<note::RequestData>,
+        mut queue: impl FnMut(u32) -> Result<bool, storage::StorageErr>,
+    ) -> Option<(note::StorageIdInfo, Result<(), storage::StorageErr>)> {
+        let (request_start, request_end) = match request {
+            Some(note::RequestData {
+                request_start: Some(request_start),
+                request_end: Some(request_end),
+            }) => (request_start, request_end),
+            _ => {
+                self.pending = None;
+                self.requested = None;
+                return None;
+            }
+        };
+
+        let mut info = info.unwrap_or_default();
+
+        // The progress belongs to a different request.
+        if (info.request_start, info.request_end) != (Some(request_start), Some(request_end)) {
+            defmt::info!("New request: {} -> {}", request_start, request_end);
+            info = note::StorageIdInfo {
+                sent_id: None,
+                request_start: Some(request_start),
+                request_end: Some(request_end),
+            };
+            self.pending = None;
+        }
+
+        if let Some((id, _)) = self.pending.take() {
+            info.sent_id = Some(id);
+        }
+
+        let start = info.sent_id.map(|id| id + 1).unwrap_or(request_start);
+        let end = request_end.min(next_id.saturating_sub(1));
+
+        self.requested = Some((start, request_end));
+
+        if next_id == 0 || start > end {
+            defmt::info!("Request complete, deleting request.");
+            self.requested = None;
+            return Some((note::StorageIdInfo::default(), Ok(())));
+        }
+
+        defmt::info!("Request, sending range: {} -> {}", start, end);
+
+        let mut r = Ok(());
+
+        for id in (start..=end).take(REQUEST_BATCH) {
+            match queue(id) {
+                Ok(true) => {
+                    defmt::debug!("Queued stored package: {}", id);
+                    self.pending = Some((id, 0));
+                }
+                Ok(false) => {
+                    defmt::trace!("Notecard queue is full, not adding more packages.");
+                    break;
+                }
+                Err(storage::StorageErr::GenericSdMmmcErr(embedded_sdmmc::Error::FileNotFound)) => {
+                    let new_id = ((id / storage::COLLECTION_SIZE) + 1) * storage::COLLECTION_SIZE;
+
+                    defmt::debug!(
+                        "File does not exist, advancing range by full collection: {} -> {}.",
+                        id,
+                        new_id
+                    );
+
+                    // Nothing has been queued, so the progress can be written right away.
+                    if self.pending.is_none() {
+                        info.sent_id = Some(new_id - 1);
+                    }
+
+                    break;
+                }
+                Err(storage::StorageErr::CorruptPackage(e)) => {
+                    defmt::warn!("Skipping corrupt package: {} ({:?})", id, e);
+
+                    // Nothing is queued for the package, it is done as soon as the packages
+                    // before it are.
+                    match &mut self.pending {
+                        Some((pending, _)) => *pending = id,
+                        None => info.sent_id = Some(id),
+                    }
+                }
+                Err(e) => {
+                    defmt::error!("Failed to read from SD-card: {:?}, clearing request.", e);
+                    self.pending = None;
+                    r = Err(e);
+                    break;
+                }
+            }
+        }
+
+        if r.is_err() {
+            info = note::StorageIdInfo::default();
+            self.requested = None;
+        }
+
+        Some((info, r))
+    }
 }
 
 #[cfg(feature = "storage")]
@@ -411,6 +636,31 @@ where
     storage: Storage<Spi, CS, DL>,
     pub storage_queue: heapless::spsc::Consumer<'static, AxlPacketT, STORAGEQ_SZ>,
     pub note_queue: heapless::spsc::Producer<'static, AxlPacket, NOTEQ_SZ>,
+
+    /// Packages requested through `request-data`.
+    request: Request,
+
+    /// Packages on the SD-card that have not been queued for the Notecard, read from the SD-card
+    /// when first needed.
+    unsent: Option<Unsent>,
+
+    /// Storage IDs of the packages in the note queue, in the same order. These are kept in
+    /// `UNSENT_FILE` until they have been taken off the note queue, so that they are sent again
+    /// after a reset. Requested packages have been sent before and are not kept (`None`).
+    queued: heapless::Deque<Option<u32>, NOTEQ_SZ>,
+
+    /// Stored packages have been spilled or queued since `UNSENT_FILE` was last written.
+    unsent_changed: bool,
+
+    /// Queue from the GPS, and to the Notecard.
+    #[cfg(feature = "ext-gps")]
+    pub gps_queue: heapless::spsc::Consumer<'static, gps::GpsPacket, EPGS_SZ>,
+    #[cfg(feature = "ext-gps")]
+    pub gps_note_queue: heapless::spsc::Producer<'static, gps::GpsPacket, EPGS_SZ>,
+
+    /// GPS packages requested through `request-gps`.
+    #[cfg(feature = "ext-gps")]
+    gps_request: Request,
 }
 
 #[cfg(feature = "storage")]
@@ -424,20 +674,49 @@ where
         storage: Storage<Spi, CS, DL>,
         storage_queue: heapless::spsc::Consumer<'static, AxlPacketT, STORAGEQ_SZ>,
         note_queue: heapless::spsc::Producer<'static, AxlPacket, NOTEQ_SZ>,
+        #[cfg(feature = "ext-gps")] gps_queue: heapless::spsc::Consumer<
+            'static,
+            gps::GpsPacket,
+            EPGS_SZ,
+        >,
+        #[cfg(feature = "ext-gps")] gps_note_queue: heapless::spsc::Producer<
+            'static,
+            gps::GpsPacket,
+            EPGS_SZ,
+        >,
     ) -> StorageManager<Spi, CS, DL> {
         StorageManager {
             storage,
             storage_queue,
             note_queue,
+            request: Request::default(),
+            unsent: None,
+            queued: heapless::Deque::new(),
+            unsent_changed: false,
+            #[cfg(feature = "ext-gps")]
+            gps_queue,
+            #[cfg(feature = "ext-gps")]
+            gps_note_queue,
+            #[cfg(feature = "ext-gps")]
+            gps_request: Request::default(),
         }
     }
 
     /// Drain data queue from IMU to SD card and queue the processed data for the notecard.
     ///
+    /// Stored packages are kept in `UNSENT_FILE` until they have been taken off the note queue.
+    /// The package is spilled to the SD-card instead of queued when the Notecard is full or the
+    /// note queue has no room, or when it should be sent after older unsent packages (see
+    /// `queue_unsent`).
+    ///
+    /// To spare the SD-card `UNSENT_FILE` is not written here, but once per drain of the note
+    /// queue by `queue_unsent`. Packages stored since then are not sent again after a reset, but
+    /// can still be requested.
+    ///
     /// > NOTE: This function is called very frequently and should not communicate with the Notecard.
     pub fn drain_queue<I2C: Read + Write>(
         &mut self,
-        _note: &mut note::Notecarrier<I2C>,
+        note: &mut note::Notecarrier<I2C>,
         _delay: &mut impl DelayMs<u16>,
     ) -> Result<Option<u32>, storage::StorageErr> {
         let mut e: Result<Option<u32>, storage::StorageErr> = Ok(None);
@@ -453,6 +732,9 @@ where
                 pck.0,
                 self.storage_queue.len()
             );
+
+            self.evict();
+
             e = self
                 .storage
                 .store(&mut pck)
@@ -461,113 +743,387 @@ where
                 })
                 .map(|id| Some(id));
 
-            self.note_queue
-                .enqueue(pck.0)
-                .inspect_err(|pck| {
-                    defmt::error!("queue is full, discarding data: {}", pck.data.len());
-                })
-                .ok();
+            let id = e.as_ref().ok().copied().flatten();
+
+            if id.is_some() && self.spill(note) {
+                defmt::debug!("Spilling package to SD-card: {:?}", id);
+
+                // unwrap: loaded by `spill`.
+                self.unsent.as_mut().unwrap().insert(id.unwrap());
+            } else {
+                match self.enqueue(pck.0, id) {
+                    Ok(_) => self.request.behind(),
+                    Err(pck) => {
+                        defmt::error!("queue is full, discarding data: {}", pck.data.len());
+                    }
+                }
+            }
+
+            self.unsent_changed |= id.is_some();
         }
 
         e
     }
 
-    /// XXX: Currently disabled.
+    /// Drain the queue from the GPS to the SD-card, and queue the packages for the Notecard.
+    ///
+    /// > NOTE: This function is called very frequently and should not communicate with the Notecard.
+    #[cfg(feature = "ext-gps")]
+    pub fn drain_gps_queue(&mut self) -> Result<Option<u32>, storage::StorageErr> {
+        let Some(mut pck) = self.gps_queue.dequeue() else {
+            return Ok(None);
+        };
+
+        defmt::info!(
+            "Storing GPS package: {} (gps queue length: {})",
+            pck.timestamp,
+            self.gps_queue.len()
+        );
+
+        self.evict();
+
+        let e = self
+            .storage
+            .store_gps(&mut pck)
+            .inspect_err(|err| defmt::error!("Failed to save GPS package: {}", err))
+            .map(Some);
+
+        match self.gps_note_queue.enqueue(pck) {
+            Ok(_) => self.gps_request.behind(),
+            Err(_) => defmt::error!("egps queue is full, discarding GPS package."),
+        }
+
+        e
+    }
+
+    /// Free space on the SD-card, keeping the requested packages.
+    fn evict(&mut self) {
+        #[cfg(feature = "ext-gps")]
+        let gps_requested = self.gps_request.requested;
+
+        #[cfg(not(feature = "ext-gps"))]
+        let gps_requested = None;
+
+        self.storage
+            .evict(self.request.requested, gps_requested)
+            .inspect_err(|err| defmt::error!("Failed to free space on SD-card: {}", err))
+            .ok();
+    }
+
+    /// Whether a stored package should be spilled to the SD-card rather than queued.
+    fn spill<I2C: Read + Write>(&mut self, note: &note::Notecarrier<I2C>) -> bool {
+        // Iridium only sends the positions or spectra of the live packages.
+        if cfg!(feature = "iridium") {
+            return false;
+        }
+
+        let full = note.is_full() || !self.note_queue.ready();
+
+        // The spectra are computed from the packages in the note queue, and unsent packages are
+        // not sent while only the spectra are sent.
+        let behind = note.config().unsent_order == env::UnsentOrder::Oldest
+            && note.link_mode() != note::LinkMode::Spectra;
+
+        match self.unsent() {
+            Ok(unsent) => full || (behind && !unsent.is_empty()),
+            Err(e) => {
+                defmt::error!("Failed to read unsent packages: {}", e);
+                false
+            }
+        }
+    }
+
+    /// The unsent packages, read from the SD-card the first time.
+    fn unsent(&mut self) -> Result<&mut Unsent, storage::StorageErr> {
+        if self.unsent.is_none() {
+            let unsent = self.storage.load_unsent()?;
+            if !unsent.is_empty() {
+                defmt::info!("Unsent packages on SD-card: {}", unsent.len());
+            }
+            self.unsent = Some(unsent);
+        }
+
+        // unwrap: loaded above.
+        Ok(self.unsent.as_mut().unwrap())
+    }
+
+    /// Write the unsent packages to the SD-card, including the ones still in the note queue.
+    fn save_unsent(&mut self) -> Result<(), storage::StorageErr> {
+        self.handed_off();
+
+        let mut unsent = self.unsent()?.clone();
+        for id in self.queued.iter().flatten() {
+            unsent.insert(*id);
+        }
+
+        self.storage.save_unsent(&unsent)?;
+        self.unsent_changed = false;
+
+        Ok(())
+    }
+
+    /// Forget the packages that have been taken off the note queue, returns true if any of them
+    /// were kept in `UNSENT_FILE`.
+    fn handed_off(&mut self) -> bool {
+        let mut any = false;
+
+        while self.queued.len() > self.note_queue.len() {
+            any |= self.queued.pop_front().flatten().is_some();
+        }
+
+        any
+    }
+
+    /// Queue a package for the Notecard, see `queued`.
+    fn enqueue(&mut self, pck: AxlPacket, id: Option<u32>) -> Result<(), AxlPacket> {
+        self.handed_off();
+        self.note_queue.enqueue(pck)?;
+
+        // unwrap: the note queue has room for one less than `queued`.
+        self.queued.push_back(id).unwrap();
+
+        Ok(())
+    }
+
+    /// Keep the packages that were not sent by the Notecard as unsent, and write `UNSENT_FILE`
+    /// if packages have been stored, or taken off the note queue, since it was last written.
+    fn commit_unsent<I2C: Read + Write>(&mut self, note: &mut note::Notecarrier<I2C>) {
+        let skipped = note.take_skipped();
+        if !skipped.is_empty() {
+            match self.unsent() {
+                Ok(unsent) => {
+                    unsent.extend(&skipped);
+                    self.unsent_changed = true;
+                }
+                Err(e) => defmt::error!("Failed to read unsent packages: {}", e),
+            }
+        }
+
+        if !self.handed_off() && !self.unsent_changed {
+            return;
+        }
+
+        self.save_unsent()
+            .inspect_err(|e| defmt::error!("Failed to write unsent packages: {}", e))
+            .ok();
+
+        if self.queued.is_empty() && self.unsent.as_ref().is_some_and(|u| u.is_empty()) {
+            defmt::debug!("All stored packages have been sent.");
+        }
+    }
+
+    /// Fill in the state of the SD-card.
+    pub fn status(&self, status: &mut status::StatusNote) {
+        status.next_id = self.storage.next_id();
+        status.sd_free = self.storage.free_space();
+    }
+
+    /// Queue packages requested through `request-data` in `storage.db` on the Notecard from the
+    /// SD-card.
+    ///
+    /// At most `REQUEST_BATCH` packages are queued at a time, and no more are queued before those
+    /// have been taken off the note queue. The progress (`StorageIdInfo`) is only written to the
+    /// Notecard at that point, so that requested packages that were still in the queue at a
+    /// reset are queued again.
+    ///
+    /// Should be called after the note queue has been drained.
     pub fn queue_requested_packages<I2C: Read + Write>(
         &mut self,
         note: &mut note::Notecarrier<I2C>,
         delay: &mut impl DelayMs<u16>,
     ) -> Result<(), storage::StorageErr> {
-        // Send additional requested packages from SD-card.
-        if let Some(next_id) = self.storage.next_id() {
-            if let Ok((
-                Some(note::StorageIdInfo { sent_id }),
-                Some(note::RequestData {
-                    request_start: Some(request_start),
-                    request_end: Some(request_end),
-                }),
-            )) = note.read_storage_info(delay)
-            {
-                let sent_id = sent_id.unwrap_or(request_start);
-                let request_end = request_end.min(next_id.saturating_sub(1));
-
-                if sent_id < request_end {
-                    defmt::info!("Request, sending range: {} -> {}", sent_id, request_end);
-                    for id in (sent_id..=request_end).take(100) {
-                        let pck = self.storage.get(id);
-
-                        defmt::debug!("Sending stored package: {:?}", pck);
-
-                        match pck {
-                            Ok(pck) => {
-                                match self.note_queue.enqueue(pck) {
-                                    Ok(_) => {
-                                        // Update range of sent packages.
-                                        note.write_storage_info(
-                                            delay,
-                                            Some(id),
-                                            if id >= request_end { true } else { false },
-                                        )
-                                        .inspect_err(|e| {
-                                            defmt::error!("Failed to set storageinfo: {:?}", e)
-                                        })
-                                        .ok();
-                                    }
-                                    Err(_) => {
-                                        defmt::trace!(
-                                            "Notecard queue is full, not adding more packages."
-                                        );
-                                        break;
-                                    } // queue is full.
-                                }
-                            }
-                            Err(storage::StorageErr::GenericSdMmmcErr(
-                                embedded_sdmmc::Error::FileNotFound,
-                            )) => {
-                                let new_id = ((id / storage::COLLECTION_SIZE) + 1)
-                                    * storage::COLLECTION_SIZE;
-
-                                defmt::debug!(
-                                    "File does not exist, advancing range by full collection: {} -> {}.",
-                                    id, new_id
-                                );
+        if self.request.waiting(self.note_queue.len()) {
+            defmt::debug!("Requested packages are still in the note queue, waiting.");
+            return Ok(());
+        }
 
-                                note.write_storage_info(
-                                    delay,
-                                    Some(new_id),
-                                    if new_id >= request_end { true } else { false },
-                                )
-                                .inspect_err(|e| {
-                                    defmt::error!("Failed to set storageinfo: {:?}", e)
-                                })
-                                .ok();
-
-                                break;
-                            }
-                            Err(e) => {
-                                defmt::error!(
-                                    "Failed to read from SD-card: {:?}, clearing request.",
-                                    e
-                                );
-                                note.write_storage_info(delay, None, true)
-                                    .inspect_err(|e| {
-                                        defmt::error!("Failed to set storageinfo: {:?}", e)
-                                    })
-                                    .ok();
-                                return Err(e);
-                            }
-                        }
-                    }
-                } else {
-                    // Request done, clearing.
-                    defmt::info!("Request complete, deleting request.");
-                    note.write_storage_info(delay, None, true)
-                        .inspect_err(|e| defmt::error!("Failed to set storageinfo: {:?}", e))
-                        .ok();
+        let next_id = match self.storage.next_id() {
+            Some(next_id) => next_id,
+            None => return Ok(()),
+        };
+
+        let (info, request) = match note.read_storage_info(delay) {
+            Ok(r) => r,
+            Err(e) => {
+                defmt::error!("Failed to read storage-info: {:?}", e);
+                return Ok(());
+            }
+        };
+
+        let mut req = core::mem::take(&mut self.request);
+        let r = req.queue(next_id, info, request, |id| {
+            if !self.note_queue.ready() {
+                return Ok(false);
+            }
+
+            let pck = self.storage.get(id)?;
+
+            // unwrap: checked that the queue is ready above.
+            self.enqueue(pck, None).ok().unwrap();
+
+            Ok(true)
+        });
+        self.request = req;
+
+        let Some((info, r)) = r else {
+            return Ok(());
+        };
+
+        note.write_storage_info(delay, info, r.is_err() || self.request.requested.is_none())
+            .inspect_err(|e| defmt::error!("Failed to set storageinfo: {:?}", e))
+            .ok();
+
+        r
+    }
+
+    /// Queue GPS packages requested through `request-gps` in `storage.db` on the Notecard from the
+    /// SD-card, in the same way as `queue_requested_packages`.
+    ///
+    /// Should be called after the egps queue has been drained.
+    #[cfg(feature = "ext-gps")]
+    pub fn queue_requested_gps<I2C: Read + Write>(
+        &mut self,
+        note: &mut note::Notecarrier<I2C>,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), storage::StorageErr> {
+        if self.gps_request.waiting(self.gps_note_queue.len()) {
+            defmt::debug!("Requested GPS packages are still in the egps queue, waiting.");
+            return Ok(());
+        }
+
+        let next_id = match self.storage.next_gps_id() {
+            Some(next_id) => next_id,
+            None => return Ok(()),
+        };
+
+        let (info, request) = match note.read_gps_storage_info(delay) {
+            Ok(r) => r,
+            Err(e) => {
+                defmt::error!("Failed to read gps-storage-info: {:?}", e);
+                return Ok(());
+            }
+        };
+
+        let mut req = core::mem::take(&mut self.gps_request);
+        let r = req.queue(next_id, info, request, |id| {
+            if !self.gps_note_queue.ready() {
+                return Ok(false);
+            }
+
+            let pck = self.storage.get_gps(id)?;
+
+            // unwrap: checked that the queue is ready above.
+            self.gps_note_queue.enqueue(pck).ok().unwrap();
+
+            Ok(true)
+        });
+        self.gps_request = req;
+
+        let Some((info, r)) = r else {
+            return Ok(());
+        };
+
+        note.write_gps_storage_info(
+            delay,
+            info,
+            r.is_err() || self.gps_request.requested.is_none(),
+        )
+        .inspect_err(|e| defmt::error!("Failed to set gps-storage-info: {:?}", e))
+        .ok();
+
+        r
+    }
+
+    /// Queue the packages that were spilled to the SD-card while the Notecard was full or the
+    /// note queue had no room, or that were not sent while only the spectra were sent, in the
+    /// order set by `UNSENT_ORDER`.
+    ///
+    /// Packages are only queued while there are less than `REQUEST_BATCH` packages in the note
+    /// queue, the Notecard has room, and more than the spectra are sent. Requested packages go
+    /// first. The packages are kept in
+    /// `UNSENT_FILE` until they have been taken off the note queue, so after a reset this also
+    /// sends the packages that were stored but never taken off the note queue.
+    ///
+    /// Should be called after the note queue has been drained.
+    pub fn queue_unsent<I2C: Read + Write>(
+        &mut self,
+        note: &mut note::Notecarrier<I2C>,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), storage::StorageErr> {
+        use transport::Transport;
+
+        self.commit_unsent(note);
+
+        if self.request.pending.is_some() || self.request.requested.is_some() {
+            return Ok(());
+        }
+
+        if self.unsent()?.is_empty() || self.note_queue.len() >= REQUEST_BATCH {
+            return Ok(());
+        }
+
+        match note.ready(delay) {
+            Ok(true) => (),
+            Ok(false) => return Ok(()),
+            Err(e) => {
+                defmt::error!("Failed to check if the Notecard is ready: {:?}", e);
+                return Ok(());
+            }
+        }
+
+        // They would only be skipped again.
+        if note.link_mode() == note::LinkMode::Spectra {
+            return Ok(());
+        }
+
+        let order = note.config().unsent_order;
+        let mut skipped = false;
+        let mut r = Ok(());
+
+        // The rest of the note queue is left for the live packages.
+        while self.note_queue.len() < REQUEST_BATCH {
+            // unwrap: loaded above.
+            let Some(id) = self.unsent.as_ref().unwrap().next(order) else {
+                break;
+            };
+
+            match self.storage.get(id) {
+                Ok(pck) => {
+                    defmt::debug!("Queuing unsent package: {}", id);
+
+                    // unwrap: checked that there is room in the queue above.
+                    self.enqueue(pck, Some(id)).ok().unwrap();
+                    self.unsent.as_mut().unwrap().remove(id, id);
+                }
+                Err(storage::StorageErr::GenericSdMmmcErr(embedded_sdmmc::Error::FileNotFound)) => {
+                    // The collection has been removed to free space.
+                    let c = id / storage::COLLECTION_SIZE;
+                    defmt::warn!("Unsent package does not exist, skipping collection: {}", c);
+                    self.unsent.as_mut().unwrap().remove(
+                        c * storage::COLLECTION_SIZE,
+                        (c + 1) * storage::COLLECTION_SIZE - 1,
+                    );
+                    skipped = true;
+                }
+                Err(storage::StorageErr::CorruptPackage(e)) => {
+                    defmt::warn!("Skipping corrupt unsent package: {} ({:?})", id, e);
+                    self.unsent.as_mut().unwrap().remove(id, id);
+                    skipped = true;
+                }
+                Err(e) => {
+                    defmt::error!("Failed to read unsent package from SD-card: {:?}", e);
+                    r = Err(e);
+                    break;
                 }
             }
         }
 
-        Ok(())
+        if skipped {
+            self.save_unsent()
+                .inspect_err(|e| defmt::error!("Failed to write unsent packages: {}", e))
+                .ok();
+        }
+
+        r
     }
 }
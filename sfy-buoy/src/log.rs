@@ -14,6 +14,8 @@ static LOGQ: Queue<String<256>> = Queue::new();
 pub fn log(msg: &str) {
     #[cfg(not(test))]
     defmt::debug!("logq: {}", msg);
+    crate::crash::record_log(msg);
+
     let mut s = String::new();
     s.push_str(msg).ok();
 
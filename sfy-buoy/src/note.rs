@@ -1,9 +1,11 @@
-use crate::axl::{AxlPacket, AXL_OUTN};
+use crate::axl::{AxlPacket, Mode, AXL_OUTN};
 use blues_notecard::{self as notecard, NoteError, Notecard, NotecardConfig};
 use core::ops::{Deref, DerefMut};
 use embedded_hal::blocking::delay::DelayMs;
 use embedded_hal::blocking::i2c::{Read, Write};
 
+use crate::env::{Config, Env};
+use crate::transport::Transport;
 use crate::NOTEQ_SZ;
 
 pub const BUOYSN: Option<&str> = option_env!("BUOYSN");
@@ -20,16 +22,104 @@ include!(concat!(env!("OUT_DIR"), "/config.rs"));
 /// Initialize sync when storage use is above this percentage.
 pub const NOTECARD_STORAGE_INIT_SYNC: u32 = 65;
 
+/// Notecard storage use [%] where the packages are decimated, and where only the spectra are sent
+/// (with the `spectrum` feature). No more packages are added above 75%.
+pub const DECIMATE_STORAGE: usize = 50;
+pub const SPECTRA_STORAGE: usize = 65;
+
+/// Length of the note queue left after draining it, averaged over `BACKLOG_DRAINS`, where the
+/// packages are decimated, and where only the spectra are sent. The length before draining is
+/// given by how often the main loop runs, not by how well the uplink keeps up.
+pub const DECIMATE_QUEUE: usize = NOTEQ_SZ / 2;
+pub const SPECTRA_QUEUE: usize = NOTEQ_SZ - 2;
+
+/// Number of drains of the note queue the backlog is averaged over.
+pub const BACKLOG_DRAINS: usize = 4;
+
+/// The storage use must drop this much [%] below a level, and the note queue to half of it,
+/// before stepping down from it.
+pub const LINK_HYSTERESIS: usize = 10;
+
+/// How much of the packages is sent, depending on the pressure on the uplink. The full packages
+/// are always stored on the SD-card, and can be requested later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum LinkMode {
+    Full,
+
+    /// The packages are decimated, see [`Mode::Decimated`].
+    Decimated,
+
+    /// Only the spectra are sent (with the `spectrum` feature).
+    Spectra,
+}
+
+/// The link mode for the Notecard storage use [%] and the backlog of the note queue (see
+/// `DECIMATE_QUEUE`). The mode steps up right away, but only steps down when the pressure is well
+/// below the level.
+pub fn link_mode(current: LinkMode, storage: usize, queue: usize) -> LinkMode {
+    let level = |storage: usize, queue: usize| {
+        if cfg!(feature = "spectrum") && (storage >= SPECTRA_STORAGE || queue >= SPECTRA_QUEUE) {
+            LinkMode::Spectra
+        } else if storage >= DECIMATE_STORAGE || queue >= DECIMATE_QUEUE {
+            LinkMode::Decimated
+        } else {
+            LinkMode::Full
+        }
+    };
+
+    let mode = level(storage, queue);
+
+    if mode >= current {
+        mode
+    } else {
+        level(storage + LINK_HYSTERESIS, queue * 2).min(current)
+    }
+}
+
 pub struct Notecarrier<I2C: Read + Write> {
     note: Notecard<I2C>,
     device: Option<heapless::String<40>>,
     sn: Option<heapless::String<120>>,
+
+    /// The configuration in use, and the time it was last checked [ms].
+    config: Config,
+    last_config: i64,
+
+    /// The Notecard is in low-power hub mode between bursts of sampling.
+    low_power: bool,
+
+    /// The Notecard was more than 75% full the last time it was checked.
+    full: bool,
+
+    /// How much of the packages is sent, from the storage use of the Notecard [%] the last time it
+    /// was checked and the average length of the note queue left after draining it.
+    link: LinkMode,
+    storage: usize,
+    queue: usize,
+    backlog: heapless::HistoryBuffer<usize, BACKLOG_DRAINS>,
+
+    /// Storage IDs of the packages that were not sent in `LinkMode::Spectra`, they are kept as
+    /// unsent by the `StorageManager`.
+    #[cfg(feature = "storage")]
+    skipped: crate::storage::unsent::Unsent,
+
+    #[cfg(feature = "spectrum")]
+    spec: crate::spec::Spectrum,
 }
 
+/// Progress of sending the requested packages.
 #[derive(serde::Serialize, serde::Deserialize, Default, defmt::Format, PartialEq)]
 pub struct StorageIdInfo {
+    /// Last package of the request that has been sent.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sent_id: Option<u32>,
+
+    /// The request the progress belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_start: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_end: Option<u32>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Default, defmt::Format, PartialEq)]
@@ -53,40 +143,126 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
         );
         note.initialize(delay)?;
 
-        // Use extrnal SIM first
-        if let Some(apn) = EXT_APN {
-            defmt::info!("Configuring for external SIM..");
-            let w = note
-                .card()
-                .wireless(
-                    delay,
-                    None,
-                    Some(apn),
-                    Some("dual-secondary-primary"),
-                    Some(1),
-                )?
-                .wait(delay)?;
-            defmt::info!("Wireless status: {:#?}", w);
-        }
-
         // Location mode is not supported when in continuous mode.
         #[cfg(feature = "continuous")]
         note.card()
             .location_mode(delay, Some("off"), None, None, None, None, None, None, None)?
             .wait(delay)?;
 
+        // The environment variables are the ones from the last sync.
+        let config = Self::read_env(&mut note, delay)
+            .inspect_err(|e| defmt::error!("Failed to read environment variables: {:?}", e))
+            .map(|env| Config::from_env(&env))
+            .unwrap_or_default();
+        defmt::info!("Configuration: {}", config);
+
+        Self::apply_config(&mut note, &config, None, delay)?;
+
+        let version = note.card().version(delay)?.wait(delay)?;
+        defmt::info!("Notecard version: {:?}", version);
+
+        let dev = note.hub().get(delay)?.wait(delay)?;
+        defmt::info!("device: {}, sn: {}", dev.device, dev.sn);
+
+        let mut n = Notecarrier {
+            note,
+            device: dev.device,
+            sn: dev.sn,
+            config,
+            last_config: 0,
+            low_power: false,
+            full: false,
+            link: LinkMode::Full,
+            storage: 0,
+            queue: 0,
+            backlog: heapless::HistoryBuffer::new(),
+            #[cfg(feature = "storage")]
+            skipped: crate::storage::unsent::Unsent::new(),
+            #[cfg(feature = "spectrum")]
+            spec: crate::spec::Spectrum::new(),
+        };
+        n.setup_templates(delay)?;
+        n.send_config(delay)?;
+
+        defmt::info!("initializing initial sync ..");
+        n.note.hub().sync(delay, false)?.wait(delay)?;
+
+        Ok(n)
+    }
+
+    /// Read the environment variables used for the configuration.
+    fn read_env(note: &mut Notecard<I2C>, delay: &mut impl DelayMs<u16>) -> Result<Env, NoteError> {
+        #[derive(serde::Serialize)]
+        struct EnvGet {
+            req: &'static str,
+            names: &'static [&'static str],
+        }
+
+        #[derive(serde::Deserialize, Default)]
+        struct EnvGetResponse {
+            body: Option<Env>,
+        }
+
+        let r: EnvGetResponse = note
+            .request(
+                delay,
+                EnvGet {
+                    req: "env.get",
+                    names: &crate::env::ENV_NAMES,
+                },
+            )?
+            .wait(delay)?;
+
+        Ok(r.body.unwrap_or_default())
+    }
+
+    /// Configure the Notecard. The SIM is only configured if the APN has changed from the
+    /// `previous` configuration.
+    fn apply_config(
+        note: &mut Notecard<I2C>,
+        config: &Config,
+        previous: Option<&Config>,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), NoteError> {
+        // Use extrnal SIM first
+        match (&config.ext_apn, previous.map(|p| &p.ext_apn)) {
+            (Some(apn), previous) if previous != Some(&config.ext_apn) => {
+                defmt::info!("Configuring for external SIM..");
+                let w = note
+                    .card()
+                    .wireless(
+                        delay,
+                        None,
+                        Some(apn),
+                        Some("dual-secondary-primary"),
+                        Some(1),
+                    )?
+                    .wait(delay)?;
+                defmt::info!("Wireless status: {:#?}", w);
+            }
+            (None, Some(Some(_))) => {
+                defmt::info!("Configuring for internal SIM..");
+                let w = note
+                    .card()
+                    .wireless(delay, None, Some("-"), Some("primary"), None)?
+                    .wait(delay)?;
+                defmt::info!("Wireless status: {:#?}", w);
+            }
+            _ => {}
+        }
+
         note.hub()
             .set(
                 delay,
-                BUOYPR,
+                config.product.as_deref(),
                 None,
                 if cfg!(feature = "continuous") {
                     Some(notecard::hub::req::HubMode::Continuous)
                 } else {
                     Some(notecard::hub::req::HubMode::Periodic)
                 },
-                BUOYSN,
-                Some(SYNC_PERIOD), // max time between out-going sync in minutes.
+                config.name.as_deref(),
+                Some(config.sync_period), // max time between out-going sync in minutes.
                 None,
                 None,
                 None,
@@ -101,7 +277,7 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
             .location_mode(
                 delay,
                 Some("periodic"),
-                Some(GPS_PERIOD), // seconds between each GPS fix. the position is only logged if
+                Some(config.gps_period), // seconds between each GPS fix. the position is only logged if
                 // the accelerometer detects movement. otherwise the heartbeat
                 // configured to the most frequent (1 hour) is set below.
                 None,
@@ -114,26 +290,215 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
             .wait(delay)?;
 
         note.card()
-            .location_track(delay, true, true, false, Some(GPS_HEARTBEAT), None)?
+            .location_track(delay, true, true, false, Some(config.gps_heartbeat), None)?
             .wait(delay)?;
 
-        let version = note.card().version(delay)?.wait(delay)?;
-        defmt::info!("Notecard version: {:?}", version);
+        Ok(())
+    }
 
-        let dev = note.hub().get(delay)?.wait(delay)?;
-        defmt::info!("device: {}, sn: {}", dev.device, dev.sn);
+    /// The configuration the buoy is running with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
 
-        let mut n = Notecarrier {
-            note,
-            device: dev.device,
-            sn: dev.sn,
+    /// The Notecard was more than 75% full the last time it was checked (see `Transport::ready`).
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    pub fn link_mode(&self) -> LinkMode {
+        self.link
+    }
+
+    /// Take the storage IDs of the packages that were not sent in `LinkMode::Spectra`.
+    #[cfg(feature = "storage")]
+    pub fn take_skipped(&mut self) -> crate::storage::unsent::Unsent {
+        core::mem::take(&mut self.skipped)
+    }
+
+    /// Update the link mode from the pressure on the uplink, changes are logged.
+    fn update_link(&mut self) {
+        use core::fmt::Write;
+
+        let link = link_mode(self.link, self.storage, self.queue);
+
+        if link != self.link {
+            let mut msg = heapless::String::<128>::new();
+            write!(
+                msg,
+                "Uplink mode: {:?} -> {:?} (notecard storage: {}%, note queue backlog: {}).",
+                self.link, link, self.storage, self.queue
+            )
+            .ok();
+            defmt::warn!("{}", msg);
+            crate::log::log(&msg);
+
+            self.link = link;
+        }
+    }
+
+    /// Send the configuration in use as `config.qo`.
+    pub fn send_config(&mut self, delay: &mut impl DelayMs<u16>) -> Result<(), NoteError> {
+        self.note
+            .note()
+            .add(
+                delay,
+                Some("config.qo"),
+                None,
+                Some(&self.config),
+                None,
+                false,
+            )?
+            .wait(delay)?;
+
+        Ok(())
+    }
+
+    /// Send a crash dump from before the last reset as `crash.qo`.
+    pub fn send_crash(
+        &mut self,
+        crash: &crate::crash::CrashNote,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), NoteError> {
+        self.note
+            .note()
+            .add(delay, Some("crash.qo"), None, Some(crash), None, true)?
+            .wait(delay)?;
+
+        Ok(())
+    }
+
+    /// Read the supply voltage with `card.voltage` [V].
+    pub fn voltage(&mut self, delay: &mut impl DelayMs<u16>) -> Result<f32, NoteError> {
+        #[derive(serde::Serialize)]
+        struct CardVoltage {
+            req: &'static str,
+        }
+
+        #[derive(serde::Deserialize, Default)]
+        struct CardVoltageResponse {
+            value: Option<f32>,
+        }
+
+        let r: CardVoltageResponse = self
+            .note
+            .request(
+                delay,
+                CardVoltage {
+                    req: "card.voltage",
+                },
+            )?
+            .wait(delay)?;
+
+        Ok(r.value.unwrap_or(0.0))
+    }
+
+    /// Send the status as `status.qo`, the voltage is read from the Notecard.
+    pub fn send_status(
+        &mut self,
+        mut status: crate::status::StatusNote,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), NoteError> {
+        status.voltage = self
+            .voltage(delay)
+            .inspect_err(|e| defmt::error!("Failed to read voltage: {:?}", e))
+            .unwrap_or(0.0);
+
+        defmt::info!("Sending status: {}", status);
+
+        self.note
+            .note()
+            .add(delay, Some("status.qo"), None, Some(&status), None, false)?
+            .wait(delay)?;
+
+        Ok(())
+    }
+
+    /// Whether the Notecard is in low-power hub mode.
+    pub fn low_power(&self) -> bool {
+        self.low_power
+    }
+
+    /// Put the Notecard in the `minimum` hub mode between bursts of sampling, or restore the
+    /// normal mode. The queued notes are synced before entering low-power mode, notes added while
+    /// in low-power mode are synced when the normal mode is restored.
+    pub fn set_low_power(
+        &mut self,
+        low_power: bool,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), NoteError> {
+        #[derive(serde::Serialize)]
+        struct HubSetMode {
+            req: &'static str,
+            mode: &'static str,
+        }
+
+        #[derive(serde::Deserialize, Default)]
+        struct Empty {}
+
+        let mode = match (low_power, cfg!(feature = "continuous")) {
+            (true, _) => "minimum",
+            (false, true) => "continuous",
+            (false, false) => "periodic",
         };
-        n.setup_templates(delay)?;
 
-        defmt::info!("initializing initial sync ..");
-        n.note.hub().sync(delay, false)?.wait(delay)?;
+        defmt::info!("Setting hub mode: {}", mode);
 
-        Ok(n)
+        if low_power {
+            self.note.hub().sync(delay, true)?.wait(delay)?;
+        }
+
+        let _: Empty = self
+            .note
+            .request(
+                delay,
+                HubSetMode {
+                    req: "hub.set",
+                    mode,
+                },
+            )?
+            .wait(delay)?;
+
+        if !low_power {
+            self.note.hub().sync(delay, false)?.wait(delay)?;
+        }
+
+        self.low_power = low_power;
+
+        Ok(())
+    }
+
+    /// Check the environment variables every `CONFIG_PERIOD` and apply the configuration if it
+    /// has changed. Returns whether the configuration was changed.
+    pub fn check_config(
+        &mut self,
+        now: i64,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<bool, NoteError> {
+        if (now - self.last_config) < crate::env::CONFIG_PERIOD {
+            return Ok(false);
+        }
+
+        self.last_config = now;
+
+        let env = Self::read_env(&mut self.note, delay)?;
+        let config = Config::from_env(&env);
+
+        if config == self.config {
+            return Ok(false);
+        }
+
+        defmt::info!("Configuration changed: {} -> {}", self.config, config);
+        Self::apply_config(&mut self.note, &config, Some(&self.config), delay)?;
+        self.config = config;
+        self.send_config(delay)?;
+
+        // `apply_config` sets the normal hub mode.
+        if self.low_power {
+            self.set_low_power(true, delay)?;
+        }
+
+        Ok(true)
     }
 
     /// Initiate sync and wait for it to complete (or time out).
@@ -183,6 +548,10 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
             freq: f32,
             accel_range: f32,
             gyro_range: f32,
+            burst_start: u32,
+            codec: u32,
+            samples: u32,
+            mode: u32,
             length: u32,
         }
 
@@ -201,6 +570,10 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
             freq: 14.1,
             accel_range: 14.1,
             gyro_range: 14.1,
+            burst_start: 18,
+            codec: 14,
+            samples: 14,
+            mode: 14,
             length: 14,
         };
 
@@ -214,6 +587,42 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
             )?
             .wait(delay)?;
 
+        #[derive(serde::Serialize)]
+        struct StatusTemplate {
+            uptime: u32,
+            resets: u32,
+            reset_reason: &'static str,
+            voltage: f32,
+            imu_temperature: f32,
+            next_id: u32,
+            sd_free: u32,
+            storage_queue: u32,
+            note_queue: u32,
+            egps_queue: u32,
+            fifo_overrun: u32,
+            too_few_samples: u32,
+        }
+
+        let status_template = StatusTemplate {
+            uptime: 14,
+            resets: 14,
+            reset_reason: "text",
+            voltage: 14.1,
+            imu_temperature: 14.1,
+            next_id: 14,
+            sd_free: 14,
+            storage_queue: 12,
+            note_queue: 12,
+            egps_queue: 12,
+            fifo_overrun: 14,
+            too_few_samples: 14,
+        };
+
+        defmt::debug!("setting up template for StatusNote");
+        self.note()
+            .template(delay, Some("status.qo"), Some(status_template), None)?
+            .wait(delay)?;
+
         #[cfg(feature = "ext-gps")]
         {
             defmt::debug!("setting up egps templates..");
@@ -224,6 +633,7 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
 
                 freq: f32,
                 version: u32,
+                storage_id: u32,
 
                 lon: f32,
                 lat: f32,
@@ -249,6 +659,7 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
                 timestamp: 18,
                 freq: 14.1,
                 version: 14,
+                storage_id: 14,
 
                 lon: 18.1,
                 lat: 18.1,
@@ -281,6 +692,69 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
                 .wait(delay)?;
         }
 
+        #[cfg(feature = "spectrum")]
+        {
+            use crate::spec::SPEC_BINS;
+
+            #[derive(serde::Serialize, Default)]
+            struct SpecPacketTemplate {
+                timestamp: u32,
+                packages: u32,
+
+                position_time: u32,
+                lon: f32,
+                lat: f32,
+
+                freq: f32,
+                df: f32,
+
+                hs: f32,
+                tp: f32,
+                tm01: f32,
+                tm02: f32,
+                mdir: f32,
+                spread: f32,
+
+                spec: heapless::Vec<f32, SPEC_BINS>,
+                a1: heapless::Vec<f32, SPEC_BINS>,
+                b1: heapless::Vec<f32, SPEC_BINS>,
+                a2: heapless::Vec<f32, SPEC_BINS>,
+                b2: heapless::Vec<f32, SPEC_BINS>,
+            }
+
+            let bins = || heapless::Vec::from_slice(&[14.1; SPEC_BINS]).unwrap();
+
+            let spec_template = SpecPacketTemplate {
+                timestamp: 18,
+                packages: 14,
+
+                position_time: 14,
+                lon: 18.1,
+                lat: 18.1,
+
+                freq: 14.1,
+                df: 14.1,
+
+                hs: 14.1,
+                tp: 14.1,
+                tm01: 14.1,
+                tm02: 14.1,
+                mdir: 14.1,
+                spread: 14.1,
+
+                spec: bins(),
+                a1: bins(),
+                b1: bins(),
+                a2: bins(),
+                b2: bins(),
+            };
+
+            defmt::debug!("setting up template for SpecPacket");
+            self.note()
+                .template(delay, Some("spec.qo"), Some(spec_template), None)?
+                .wait(delay)?;
+        }
+
         Ok(())
     }
 
@@ -325,7 +799,23 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
 
         #[cfg(not(feature = "continuous-post"))]
         let len = {
-            let (meta, b64) = pck.split();
+            let (meta, b64) = match self.link {
+                LinkMode::Full => pck.split(),
+                LinkMode::Decimated => pck.split_mode(Mode::Decimated),
+                LinkMode::Spectra => {
+                    defmt::debug!(
+                        "Only sending spectra, not sending package: {}",
+                        pck.storage_id
+                    );
+
+                    #[cfg(feature = "storage")]
+                    if let Some(id) = pck.storage_id {
+                        self.skipped.insert(id);
+                    }
+
+                    return Ok(0);
+                }
+            };
             let r = self
                 .note
                 .note()
@@ -410,6 +900,40 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
         Ok(b64.len())
     }
 
+    #[cfg(feature = "spectrum")]
+    pub fn send_spec(
+        &mut self,
+        spec: &crate::spec::SpecPacket,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), NoteError> {
+        let r = self
+            .note
+            .note()
+            .add(
+                delay,
+                Some("spec.qo"),
+                None,
+                Some(spec),
+                None,
+                if cfg!(feature = "continuous") {
+                    true
+                } else {
+                    false
+                },
+            )?
+            .wait(delay)?;
+
+        defmt::info!(
+            "Sent spectrum: {}, hs: {}, tp: {} (note: {:?})",
+            spec.timestamp,
+            spec.hs,
+            spec.tp,
+            r
+        );
+
+        Ok(())
+    }
+
     /// Send log messages
     pub fn drain_log(
         &mut self,
@@ -427,14 +951,59 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
         Ok(())
     }
 
+    /// The progress and the request for packages from the SD-card (`request-data`).
     pub fn read_storage_info(
         &mut self,
         delay: &mut impl DelayMs<u16>,
+    ) -> Result<(Option<StorageIdInfo>, Option<RequestData>), NoteError> {
+        self.read_request(delay, "storage-info", "request-data")
+    }
+
+    pub fn write_storage_info(
+        &mut self,
+        delay: &mut impl DelayMs<u16>,
+        info: StorageIdInfo,
+        clear_request: bool,
+    ) -> Result<(), NoteError> {
+        self.write_request(delay, "storage-info", "request-data", info, clear_request)
+    }
+
+    /// The progress and the request for GPS packages from the SD-card (`request-gps`).
+    #[cfg(feature = "ext-gps")]
+    pub fn read_gps_storage_info(
+        &mut self,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(Option<StorageIdInfo>, Option<RequestData>), NoteError> {
+        self.read_request(delay, "gps-storage-info", "request-gps")
+    }
+
+    #[cfg(feature = "ext-gps")]
+    pub fn write_gps_storage_info(
+        &mut self,
+        delay: &mut impl DelayMs<u16>,
+        info: StorageIdInfo,
+        clear_request: bool,
+    ) -> Result<(), NoteError> {
+        self.write_request(
+            delay,
+            "gps-storage-info",
+            "request-gps",
+            info,
+            clear_request,
+        )
+    }
+
+    /// Read the progress (`info` in `storage.dbx`) and the request (`request` in `storage.db`).
+    fn read_request(
+        &mut self,
+        delay: &mut impl DelayMs<u16>,
+        info: &str,
+        request: &str,
     ) -> Result<(Option<StorageIdInfo>, Option<RequestData>), NoteError> {
         let r = self
             .note
             .note()
-            .get(delay, "storage.dbx", "storage-info", false, false)?
+            .get(delay, "storage.dbx", info, false, false)?
             .wait(delay)
             .map(|r| r.body)
             .unwrap_or(None);
@@ -442,7 +1011,7 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
         let d: Option<RequestData> = self
             .note
             .note()
-            .get(delay, "storage.db", "request-data", false, false)?
+            .get(delay, "storage.db", request, false, false)?
             .wait(delay)
             .map(|r| r.body)
             .unwrap_or(None);
@@ -450,109 +1019,70 @@ impl<I2C: Read + Write> Notecarrier<I2C> {
         Ok((r, d))
     }
 
-    pub fn write_storage_info(
+    fn write_request(
         &mut self,
         delay: &mut impl DelayMs<u16>,
-        mut sent_id: Option<u32>,
+        info_note: &str,
+        request_note: &str,
+        mut info: StorageIdInfo,
         clear_request: bool,
     ) -> Result<(), NoteError> {
         if clear_request {
-            defmt::info!("Clearing data-request..");
+            defmt::info!("Clearing data-request: {}..", request_note);
             self.note
                 .note()
-                .delete(delay, "storage.db", "request-data")
+                .delete(delay, "storage.db", request_note)
                 .and_then(|r| r.wait(delay))
-                .inspect_err(|e| defmt::error!("Failed to delete request-data: {:?}", e))
+                .inspect_err(|e| defmt::error!("Failed to delete {}: {:?}", request_note, e))
                 .ok();
 
-            sent_id = None;
+            info = StorageIdInfo::default();
         }
 
-        let current_info = self.read_storage_info(delay).ok().map(|(c, _)| c).flatten();
-
-        let info = StorageIdInfo { sent_id };
+        let current_info = self
+            .read_request(delay, info_note, request_note)
+            .ok()
+            .map(|(c, _)| c)
+            .flatten();
 
         if Some(&info) != current_info.as_ref() {
             defmt::trace!(
-                "Updating sent_id: {}, clear request: {}",
-                sent_id,
+                "Updating {}: {}, clear request: {}",
+                info_note,
+                info,
                 clear_request,
             );
             self.note
                 .note()
-                .delete(delay, "storage.dbx", "storage-info")
+                .delete(delay, "storage.dbx", info_note)
                 .and_then(|r| r.wait(delay))
-                .inspect_err(|e| defmt::error!("Failed to delete storage-info: {:?}", e))
+                .inspect_err(|e| defmt::error!("Failed to delete {}: {:?}", info_note, e))
                 .ok();
 
             self.note
                 .note()
-                .update(
-                    delay,
-                    "storage.dbx",
-                    "storage-info",
-                    Some(info),
-                    None,
-                    false,
-                )?
+                .update(delay, "storage.dbx", info_note, Some(info), None, false)?
                 .wait(delay)?;
         }
 
         Ok(())
     }
 
-    /// Send queued packages to the notecard.
+    /// Send queued packages to the notecard. The packages left in the queue afterwards are the
+    /// backlog of the uplink (see `link_mode`).
     pub fn drain_queue(
         &mut self,
         queue: &mut heapless::spsc::Consumer<'static, AxlPacket, NOTEQ_SZ>,
         delay: &mut impl DelayMs<u16>,
     ) -> Result<usize, NoteError> {
-        // Sending packages takes a long time (16-17 seconds). Only 1 package is sent at a time
-        // before running main-loop again and letting other tasks run. The main-loop will keep
-        // going immediately again if there are more data in the queue.
-
-        // defmt::debug!("draining imu queue: {}", queue.len());
-
-        let mut tsz = 0;
+        let r = crate::transport::drain_queue(self, queue, delay);
 
-        while let Some(pck) = queue.dequeue() {
-            // TODO: if status was over 75 last time, don't spam notecard with status requests.
-            let status = self.note.card().status(delay)?.wait(delay)?;
-
-            if status.storage > 75 {
-                // wait until notecard has synced.
-                defmt::warn!("notecard is more than 75% full, not adding more notes until sync is done: queue sz: {}", queue.len());
-                return Ok(0);
-            }
+        self.backlog.write(queue.len());
+        let n = self.backlog.len();
+        self.queue = (self.backlog.as_slice().iter().sum::<usize>() + n / 2) / n;
+        self.update_link();
 
-            defmt::info!(
-                "sending package: note queue sz (after dequeue): {}",
-                queue.len()
-            );
-            match self.send(&pck, delay) {
-                Ok(sz) => {
-                    tsz += sz;
-                }
-                Err(e) => {
-                    defmt::error!(
-                        "Error while sending package to notecard: {:?}, retrying..",
-                        e
-                    );
-                    match self.send(&pck, delay) {
-                        Ok(sz) => {
-                            tsz += sz;
-                        }
-                        Err(e) => {
-                            defmt::error!("Error while sending package to notecard: {:?}, discarding package.", e);
-                            return Err(e);
-                        }
-                    }
-                }
-            }
-        }
-
-        // defmt::debug!("done draining imu queue: {}", queue.len());
-        Ok(tsz)
+        r
     }
 
     /// Send queued ext-gps packages to the notecard.
@@ -656,6 +1186,46 @@ impl<I2C: Read + Write> DerefMut for Notecarrier<I2C> {
     }
 }
 
+impl<I2C: Read + Write> Transport for Notecarrier<I2C> {
+    type Error = NoteError;
+
+    fn ready(&mut self, delay: &mut impl DelayMs<u16>) -> Result<bool, NoteError> {
+        // TODO: if status was over 75 last time, don't spam notecard with status requests.
+        let status = self.note.card().status(delay)?.wait(delay)?;
+        self.full = status.storage > 75;
+        self.storage = status.storage;
+        self.update_link();
+
+        if self.full {
+            // wait until notecard has synced.
+            defmt::warn!(
+                "notecard is more than 75% full, not adding more notes until sync is done."
+            );
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    fn send(&mut self, pck: &AxlPacket, delay: &mut impl DelayMs<u16>) -> Result<usize, NoteError> {
+        Notecarrier::send(self, pck, delay)
+    }
+
+    #[cfg(feature = "spectrum")]
+    fn spectrum(&mut self) -> &mut crate::spec::Spectrum {
+        &mut self.spec
+    }
+
+    #[cfg(feature = "spectrum")]
+    fn send_spec(
+        &mut self,
+        spec: &crate::spec::SpecPacket,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), NoteError> {
+        Notecarrier::send_spec(self, spec, delay)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::axl::AXL_SZ;
@@ -683,4 +1253,33 @@ mod tests {
 
         assert_eq!(sent_data, data_values);
     }
+
+    #[test]
+    fn link_mode_hysteresis() {
+        use super::*;
+        use LinkMode::*;
+
+        assert_eq!(link_mode(Full, 0, 0), Full);
+        assert_eq!(link_mode(Full, DECIMATE_STORAGE, 0), Decimated);
+        assert_eq!(link_mode(Full, 0, DECIMATE_QUEUE), Decimated);
+
+        // Stays until well below.
+        assert_eq!(link_mode(Decimated, DECIMATE_STORAGE - 1, 0), Decimated);
+        assert_eq!(link_mode(Decimated, 0, DECIMATE_QUEUE - 1), Decimated);
+        assert_eq!(
+            link_mode(Decimated, DECIMATE_STORAGE - LINK_HYSTERESIS - 1, 0),
+            Full
+        );
+
+        if cfg!(feature = "spectrum") {
+            assert_eq!(link_mode(Full, SPECTRA_STORAGE, 0), Spectra);
+            assert_eq!(link_mode(Spectra, SPECTRA_STORAGE - 1, 0), Spectra);
+            assert_eq!(
+                link_mode(Spectra, SPECTRA_STORAGE - LINK_HYSTERESIS - 1, 0),
+                Decimated
+            );
+        } else {
+            assert_eq!(link_mode(Full, 100, NOTEQ_SZ), Decimated);
+        }
+    }
 }
@@ -0,0 +1,262 @@
+//! Lossless compression of the samples with delta and Rice coding.
+//!
+//! The acceleration changes little from one sample to the next, so the difference between
+//! consecutive samples of each axis (the residual) is small. The residuals are zig-zag mapped to
+//! unsigned values and Rice coded in blocks of [`BLOCK`] residuals, each block with the Rice
+//! parameter `k` that gives the fewest bits (as in FLAC).
+//!
+//! The bits are written most significant bit first, and the axes one after the other:
+//!
+//! * the first value of the axis (16 bits),
+//! * for each block: `k` (4 bits) followed by the residuals of the block. A residual `u` is
+//!   written as `u >> k` in unary (one bits terminated by a zero bit) followed by the lowest `k`
+//!   bits of `u`. When `u >> k` is [`ESCAPE`] or more, [`ESCAPE`] one bits are followed by `u`
+//!   (16 bits).
+//!
+//! The last byte is padded with zero bits. The number of values is not part of the stream.
+//!
+//! The decoder is in `sfy-wire` ([`sfy_wire::rice`]), so that it is shared with `sfy-data`.
+//!
+//! > Keep in sync with `sfy-wire/src/rice.rs` and `sfy-processing/sfy/rice.py`.
+
+use heapless::Vec;
+
+use crate::axl::SAMPLE_SZ;
+
+pub use sfy_wire::rice::{DecodeError, BLOCK, ESCAPE};
+
+/// Largest Rice parameter (4 bits).
+const K_MAX: u32 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Error {
+    /// The compressed values do not fit in the output buffer.
+    BufferFull,
+
+    /// The stream ended before all values were decoded.
+    Truncated,
+
+    /// The number of values is not a multiple of `SAMPLE_SZ`, or does not fit in the output.
+    Length,
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Error {
+        match e {
+            DecodeError::Truncated => Error::Truncated,
+            DecodeError::Length => Error::Length,
+        }
+    }
+}
+
+fn zigzag(d: i16) -> u16 {
+    ((d << 1) ^ (d >> 15)) as u16
+}
+
+/// Residual of value `i` (after the first) of `axis`.
+fn residual(data: &[u16], axis: usize, i: usize) -> u16 {
+    let v = data[i * SAMPLE_SZ + axis];
+    let p = data[(i - 1) * SAMPLE_SZ + axis];
+
+    zigzag(v.wrapping_sub(p) as i16)
+}
+
+/// Number of bits used for residual `u` with Rice parameter `k`.
+fn bits(u: u16, k: u32) -> u32 {
+    let q = (u as u32) >> k;
+
+    if q >= ESCAPE {
+        ESCAPE + 16
+    } else {
+        q + 1 + k
+    }
+}
+
+struct BitWriter<'a, const N: usize> {
+    out: &'a mut Vec<u8, N>,
+    acc: u32,
+    n: u32,
+}
+
+impl<'a, const N: usize> BitWriter<'a, N> {
+    /// Write the lowest `n` (at most 24) bits of `v`.
+    fn put(&mut self, v: u32, n: u32) -> Result<(), Error> {
+        debug_assert!(n <= 24);
+
+        self.acc = (self.acc << n) | (v & ((1 << n) - 1));
+        self.n += n;
+
+        while self.n >= 8 {
+            self.n -= 8;
+            self.out
+                .push((self.acc >> self.n) as u8)
+                .map_err(|_| Error::BufferFull)?;
+        }
+
+        Ok(())
+    }
+
+    fn ones(&mut self, mut n: u32) -> Result<(), Error> {
+        while n > 0 {
+            let m = n.min(24);
+            self.put(u32::MAX, m)?;
+            n -= m;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if self.n > 0 {
+            self.put(0, 8 - self.n)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compress the interleaved samples in `data` into `out`. Fails if the compressed values do not
+/// fit, `out` must then be discarded.
+pub fn encode<const N: usize>(data: &[u16], out: &mut Vec<u8, N>) -> Result<(), Error> {
+    if data.len() % SAMPLE_SZ != 0 {
+        return Err(Error::Length);
+    }
+
+    let n = data.len() / SAMPLE_SZ;
+    let mut w = BitWriter { out, acc: 0, n: 0 };
+
+    for axis in 0..SAMPLE_SZ {
+        if n == 0 {
+            break;
+        }
+
+        w.put(data[axis] as u32, 16)?;
+
+        let mut i = 1;
+        while i < n {
+            let end = (i + BLOCK).min(n);
+
+            let k = (0..=K_MAX)
+                .min_by_key(|k| {
+                    (i..end)
+                        .map(|j| bits(residual(data, axis, j), *k))
+                        .sum::<u32>()
+                })
+                .unwrap();
+
+            w.put(k, 4)?;
+
+            for j in i..end {
+                let u = residual(data, axis, j) as u32;
+                let q = u >> k;
+
+                if q >= ESCAPE {
+                    w.ones(ESCAPE)?;
+                    w.put(u, 16)?;
+                } else {
+                    w.ones(q)?;
+                    w.put(0, 1)?;
+                    w.put(u, k)?;
+                }
+            }
+
+            i = end;
+        }
+    }
+
+    w.flush()
+}
+
+/// Decompress `len` interleaved values from `data` into `out`.
+pub fn decode<const N: usize>(data: &[u8], len: usize, out: &mut Vec<u16, N>) -> Result<(), Error> {
+    if len > out.capacity() {
+        return Err(Error::Length);
+    }
+
+    out.clear();
+    out.resize_default(len).unwrap();
+
+    Ok(sfy_wire::rice::decode(data, out)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::axl::AXL_SZ;
+    use crate::waves::wire::{ScaledF32, A16};
+    use sfy_wire::rice::unzigzag;
+
+    fn roundtrip(data: &[u16]) -> usize {
+        let mut c = Vec::<u8, { AXL_SZ * 3 }>::new();
+        encode(data, &mut c).unwrap();
+
+        let mut d = Vec::<u16, AXL_SZ>::new();
+        decode(&c, data.len(), &mut d).unwrap();
+        assert_eq!(d.as_slice(), data);
+
+        c.len()
+    }
+
+    #[test]
+    fn zigzag_values() {
+        for d in [0i16, 1, -1, 2, -2, i16::MAX, i16::MIN] {
+            assert_eq!(unzigzag(zigzag(d)), d);
+        }
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+    }
+
+    #[test]
+    fn waves() {
+        let data: std::vec::Vec<u16> = (0..AXL_SZ / SAMPLE_SZ)
+            .flat_map(|i| {
+                let t = i as f32 / 52.;
+                let w = 2. * core::f32::consts::PI * 0.1;
+                let z = 0.5 * libm::sinf(w * t) + 0.02 * libm::sinf(13. * t);
+
+                [0.3 * z, -0.2 * z, z].map(|v| A16::from_f32(v).to_u16())
+            })
+            .collect();
+
+        let sz = roundtrip(&data);
+        println!("compressed: {} -> {} bytes", data.len() * 2, sz);
+        assert!(sz < data.len() * 2 / 2);
+    }
+
+    #[test]
+    fn extremes() {
+        let data: std::vec::Vec<u16> = (0..AXL_SZ)
+            .map(|i| if (i / 3) % 2 == 0 { 0 } else { u16::MAX })
+            .collect();
+        roundtrip(&data);
+
+        let data: std::vec::Vec<u16> = (0..AXL_SZ).map(|i| (i * 7919) as u16).collect();
+        roundtrip(&data);
+
+        roundtrip(&[]);
+        roundtrip(&[1, 2, 3]);
+        roundtrip(&[1, 2, 3, 400, 500, 60000]);
+    }
+
+    #[test]
+    fn buffer_full() {
+        let data: std::vec::Vec<u16> = (0..AXL_SZ)
+            .map(|i| if (i / 3) % 2 == 0 { 0 } else { u16::MAX / 2 })
+            .collect();
+
+        let mut c = Vec::<u8, { AXL_SZ * 2 }>::new();
+        assert_eq!(encode(&data, &mut c), Err(Error::BufferFull));
+    }
+
+    #[test]
+    fn bad_length() {
+        let mut c = Vec::<u8, 16>::new();
+        assert_eq!(encode(&[1, 2], &mut c), Err(Error::Length));
+
+        let mut d = Vec::<u16, 16>::new();
+        assert_eq!(decode(&[0; 8], 4, &mut d), Err(Error::Length));
+        assert_eq!(decode(&[0; 8], 18, &mut d), Err(Error::Length));
+        assert_eq!(decode(&[0; 2], 6, &mut d), Err(Error::Truncated));
+    }
+}
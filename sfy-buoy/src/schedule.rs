@@ -0,0 +1,165 @@
+//! Duty-cycled sampling in bursts for long deployments.
+//!
+//! The IMU samples in bursts of `length` every `period`, optionally only while the position is
+//! fresh. The bursts start at multiples of `period` since midnight UTC, so that buoys with the same
+//! schedule sample at the same time. The main loop decides when to sample (`Burst::update`) and
+//! requests the IMU interrupt to start or stop sampling through `request`. Between bursts the
+//! FIFO of the IMU is disabled, the Notecard is in low-power mode and the MCU in deep sleep.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+const DAY: i64 = 24 * 3600 * 1000;
+
+/// When to sample.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Schedule {
+    /// Interval between the start of each burst [ms], 0 for continuous.
+    period: i64,
+
+    /// Length of each burst [ms].
+    length: i64,
+
+    /// Maximum age of position [ms], 0 to disable.
+    position_age: i64,
+}
+
+impl Schedule {
+    /// All arguments are in minutes. A `period` of 0 samples continuously, and a `position_age` of
+    /// 0 samples regardless of the position.
+    pub fn new(period: u32, length: u32, position_age: u32) -> Schedule {
+        Schedule {
+            period: period as i64 * 60_000,
+            length: length as i64 * 60_000,
+            position_age: position_age as i64 * 60_000,
+        }
+    }
+
+    pub fn continuous() -> Schedule {
+        Schedule::new(0, 0, 0)
+    }
+
+    pub fn is_continuous(&self) -> bool {
+        (self.period == 0 || self.length >= self.period) && self.position_age == 0
+    }
+
+    /// Whether the IMU should be sampling at `now` [ms], `position_time` [s] is the time of the
+    /// last position.
+    pub fn active(&self, now: i64, position_time: u32) -> bool {
+        let window = self.period == 0
+            || self.length >= self.period
+            || now.rem_euclid(DAY) % self.period < self.length;
+
+        let fresh =
+            self.position_age == 0 || (now - position_time as i64 * 1000) <= self.position_age;
+
+        window && fresh
+    }
+}
+
+/// Keeps track of whether a burst is in progress.
+pub struct Burst {
+    sampling: bool,
+}
+
+impl Burst {
+    /// The IMU is sampling at start-up.
+    pub fn new() -> Burst {
+        Burst { sampling: true }
+    }
+
+    pub fn sampling(&self) -> bool {
+        self.sampling
+    }
+
+    /// Returns `Some(true)` when a burst should start and `Some(false)` when it should end.
+    pub fn update(&mut self, schedule: &Schedule, now: i64, position_time: u32) -> Option<bool> {
+        let active = schedule.active(now, position_time);
+
+        if active != self.sampling {
+            self.sampling = active;
+            Some(active)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Burst {
+    fn default() -> Burst {
+        Burst::new()
+    }
+}
+
+/// Whether the IMU interrupt should be sampling.
+static SAMPLING: AtomicBool = AtomicBool::new(true);
+
+/// Request the IMU interrupt to start or stop sampling.
+pub fn request(sampling: bool) {
+    SAMPLING.store(sampling, Ordering::SeqCst);
+}
+
+pub fn requested() -> bool {
+    SAMPLING.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: i64 = 60_000;
+
+    #[test]
+    fn continuous() {
+        let s = Schedule::continuous();
+        assert!(s.is_continuous());
+        assert!(s.active(0, 0));
+        assert!(s.active(1234 * MIN, 0));
+
+        assert!(Schedule::new(20, 20, 0).is_continuous());
+        assert!(!Schedule::new(0, 20, 10).is_continuous());
+    }
+
+    #[test]
+    fn windows() {
+        let s = Schedule::new(60, 20, 0);
+        let day = 19000 * DAY;
+
+        assert!(s.active(day, 0));
+        assert!(s.active(day + 19 * MIN, 0));
+        assert!(!s.active(day + 20 * MIN, 0));
+        assert!(!s.active(day + 59 * MIN, 0));
+        assert!(s.active(day + 60 * MIN, 0));
+
+        // Aligned to midnight when the period does not divide a day.
+        let s = Schedule::new(7 * 60, 60, 0);
+        assert!(s.active(day + 21 * 60 * MIN, 0));
+        assert!(!s.active(day + 22 * 60 * MIN, 0));
+        assert!(s.active(day + 24 * 60 * MIN, 0));
+    }
+
+    #[test]
+    fn position_age() {
+        let s = Schedule::new(0, 0, 10);
+        let now = 19000 * DAY;
+        let pt = (now / 1000) as u32;
+
+        assert!(s.active(now, pt));
+        assert!(s.active(now + 10 * MIN, pt));
+        assert!(!s.active(now + 11 * MIN, pt));
+        assert!(!s.active(now, 0));
+    }
+
+    #[test]
+    fn burst() {
+        let s = Schedule::new(60, 20, 0);
+        let mut b = Burst::new();
+        let day = 19000 * DAY;
+
+        assert_eq!(b.update(&s, day, 0), None);
+        assert_eq!(b.update(&s, day + 20 * MIN, 0), Some(false));
+        assert!(!b.sampling());
+        assert_eq!(b.update(&s, day + 40 * MIN, 0), None);
+        assert_eq!(b.update(&s, day + 60 * MIN, 0), Some(true));
+        assert!(b.sampling());
+    }
+}
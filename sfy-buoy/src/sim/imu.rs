@@ -0,0 +1,302 @@
+//! Simulated ISM330DHCX.
+//!
+//! The IMU is modelled as a register file on the I2C bus, with auto-increment of the register
+//! address. The FIFO is filled with recorded samples by [`MockImu::tick`], and popped when the
+//! `FIFO_DATA_OUT_TAG` register is read. The FIFO status registers are updated from the state of
+//! the FIFO, so overruns are detected the same way as on the hardware.
+
+use core::cell::RefCell;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use super::SimError;
+use crate::waves::{ACCEL_RANGE, GYRO_RANGE};
+
+pub const ADDRESS: u8 = 0x6a;
+
+const WHO_AM_I: u8 = 0x0f;
+const FIFO_CTRL4: u8 = 0x0a;
+const CTRL3_C: u8 = 0x12;
+const OUT_TEMP_L: u8 = 0x20;
+const FIFO_STATUS1: u8 = 0x3a;
+const FIFO_STATUS2: u8 = 0x3b;
+const FIFO_DATA_OUT_TAG: u8 = 0x78;
+
+const FIFO_STATUS2_OVR_LATCHED: u8 = 1 << 3;
+const FIFO_STATUS2_FULL: u8 = 1 << 5;
+const FIFO_STATUS2_OVR: u8 = 1 << 6;
+
+const TAG_GYRO: u8 = 0x01;
+const TAG_ACCEL: u8 = 0x02;
+
+/// Number of words (gyro or accel samples) the FIFO can hold.
+pub const FIFO_CAPACITY: usize = 1022;
+
+/// A sample of gyro [dps] and acceleration [m/s^2].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sample {
+    pub gyro: [f32; 3],
+    pub accel: [f32; 3],
+}
+
+pub struct ImuState {
+    regs: [u8; 256],
+    fifo: VecDeque<[u8; 7]>,
+    overrun: bool,
+
+    samples: Vec<Sample>,
+    next: usize,
+    tag_cnt: u8,
+
+    /// Temperature [C].
+    pub temperature: f32,
+}
+
+/// Handle to the simulated IMU.
+#[derive(Clone)]
+pub struct MockImu(Rc<RefCell<ImuState>>);
+
+impl MockImu {
+    /// IMU replaying `samples`, starting over when they run out.
+    pub fn new(samples: Vec<Sample>) -> MockImu {
+        assert!(!samples.is_empty());
+
+        let mut regs = [0u8; 256];
+        regs[WHO_AM_I as usize] = 0x6b;
+
+        MockImu(Rc::new(RefCell::new(ImuState {
+            regs,
+            fifo: VecDeque::new(),
+            overrun: false,
+            samples,
+            next: 0,
+            tag_cnt: 0,
+            temperature: 12.,
+        })))
+    }
+
+    /// IMU replaying the acceleration recorded in a table of comma separated x, y, z values
+    /// [m/s^2], like `tests/data/ism_data_table.txt`. The gyro is still.
+    pub fn from_table(path: &str) -> MockImu {
+        let table = std::fs::read_to_string(path).unwrap();
+        let values = table
+            .split(',')
+            .map(|v| v.trim().parse::<f32>().unwrap())
+            .collect::<Vec<_>>();
+
+        let samples = values
+            .chunks_exact(3)
+            .map(|a| Sample {
+                gyro: [0.; 3],
+                accel: [a[0], a[1], a[2]],
+            })
+            .collect();
+
+        MockImu::new(samples)
+    }
+
+    pub fn state(&self) -> std::cell::RefMut<'_, ImuState> {
+        self.0.borrow_mut()
+    }
+
+    /// Let `n` samples pass. If the FIFO is enabled a gyro and an accel word is batched for each
+    /// sample.
+    pub fn tick(&self, n: usize) {
+        self.0.borrow_mut().tick(n)
+    }
+
+    /// Number of words in the FIFO.
+    pub fn fifo_len(&self) -> usize {
+        self.0.borrow().fifo.len()
+    }
+}
+
+impl ImuState {
+    fn fifo_enabled(&self) -> bool {
+        self.regs[FIFO_CTRL4 as usize] & 0x07 != 0
+    }
+
+    fn tick(&mut self, n: usize) {
+        if !self.fifo_enabled() {
+            return;
+        }
+
+        for _ in 0..n {
+            let s = self.samples[self.next];
+            self.next = (self.next + 1) % self.samples.len();
+            self.tag_cnt = (self.tag_cnt + 1) % 4;
+
+            // Gyro sensitivity is 4.375 mdps / LSB at 125 dps, and accel sensitivity is
+            // 0.061 mg / LSB at 2 g.
+            let gyro_lsb = 4.375e-3 * GYRO_RANGE / 125.;
+            let accel_lsb = 0.061e-3 * 9.80665 * ACCEL_RANGE / 2.;
+
+            for (tag, v, lsb) in [
+                (TAG_GYRO, s.gyro, gyro_lsb),
+                (TAG_ACCEL, s.accel, accel_lsb),
+            ] {
+                if self.fifo.len() >= FIFO_CAPACITY {
+                    self.overrun = true;
+                    self.regs[FIFO_STATUS2 as usize] |= FIFO_STATUS2_OVR_LATCHED;
+                    return;
+                }
+
+                let tag = tag << 3 | self.tag_cnt << 1;
+                let tag = tag | (tag.count_ones() as u8 & 1);
+
+                let mut word = [tag, 0, 0, 0, 0, 0, 0];
+                for (i, v) in v.iter().enumerate() {
+                    let raw = (v / lsb).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+                    word[1 + 2 * i..3 + 2 * i].copy_from_slice(&raw.to_le_bytes());
+                }
+
+                self.fifo.push_back(word);
+            }
+        }
+    }
+
+    fn update_status(&mut self) {
+        let n = self.fifo.len();
+
+        self.regs[FIFO_STATUS1 as usize] = (n & 0xff) as u8;
+
+        let mut status2 = self.regs[FIFO_STATUS2 as usize] & FIFO_STATUS2_OVR_LATCHED;
+        status2 |= ((n >> 8) & 0x03) as u8;
+        if n >= FIFO_CAPACITY {
+            status2 |= FIFO_STATUS2_FULL;
+        }
+        if self.overrun {
+            status2 |= FIFO_STATUS2_OVR;
+        }
+        self.regs[FIFO_STATUS2 as usize] = status2;
+
+        let temp = ((self.temperature - 25.) * 256.) as i16;
+        self.regs[OUT_TEMP_L as usize..OUT_TEMP_L as usize + 2]
+            .copy_from_slice(&temp.to_le_bytes());
+    }
+
+    fn write_reg(&mut self, reg: u8, v: u8) {
+        match reg {
+            // BOOT and SW_RESET are cleared when done, which is immediately.
+            CTRL3_C => self.regs[reg as usize] = v & !0x81,
+            FIFO_CTRL4 => {
+                self.regs[reg as usize] = v;
+
+                // Bypass mode empties the FIFO and resets the overrun.
+                if v & 0x07 == 0 {
+                    self.fifo.clear();
+                    self.overrun = false;
+                }
+            }
+            _ => self.regs[reg as usize] = v,
+        }
+    }
+
+    fn read_regs(&mut self, reg: u8, buf: &mut [u8]) {
+        if reg == FIFO_DATA_OUT_TAG {
+            let word = self.fifo.pop_front().unwrap_or_default();
+            self.regs[FIFO_DATA_OUT_TAG as usize..FIFO_DATA_OUT_TAG as usize + 7]
+                .copy_from_slice(&word);
+        }
+
+        self.update_status();
+
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = self.regs[(reg as usize + i) % 256];
+        }
+
+        // The latched overrun flag is reset when the status register is read.
+        if (reg..reg.saturating_add(buf.len() as u8)).contains(&FIFO_STATUS2) {
+            self.regs[FIFO_STATUS2 as usize] &= !FIFO_STATUS2_OVR_LATCHED;
+        }
+    }
+}
+
+impl Write for MockImu {
+    type Error = SimError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), SimError> {
+        if address != ADDRESS {
+            return Err(SimError::Nack);
+        }
+
+        let mut s = self.0.borrow_mut();
+        if let Some((reg, values)) = bytes.split_first() {
+            for (i, v) in values.iter().enumerate() {
+                s.write_reg(reg.wrapping_add(i as u8), *v);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteRead for MockImu {
+    type Error = SimError;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), SimError> {
+        if address != ADDRESS {
+            return Err(SimError::Nack);
+        }
+
+        let mut s = self.0.borrow_mut();
+        match bytes {
+            [reg] => s.read_regs(*reg, buffer),
+            [reg, values @ ..] => {
+                for (i, v) in values.iter().enumerate() {
+                    s.write_reg(reg.wrapping_add(i as u8), *v);
+                }
+                s.read_regs(*reg, buffer);
+            }
+            [] => buffer.fill(0),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimDelay;
+    use crate::waves::{ImuError, Waves, RATES};
+
+    #[test]
+    fn read_fifo() {
+        let imu = MockImu::from_table("tests/data/ism_data_table.txt");
+        let mut waves = Waves::new(imu.clone(), RATES[0]).unwrap();
+
+        // FIFO is not running.
+        imu.tick(10);
+        assert_eq!(imu.fifo_len(), 0);
+
+        waves.enable_fifo(&mut SimDelay).unwrap();
+        imu.tick(10);
+        assert_eq!(imu.fifo_len(), 20);
+
+        assert_eq!(waves.read_and_filter().unwrap(), 10);
+        assert_eq!(imu.fifo_len(), 0);
+
+        let t = waves.get_temperature().unwrap();
+        assert!((t - imu.state().temperature).abs() < 0.01);
+    }
+
+    #[test]
+    fn fifo_overrun() {
+        let imu = MockImu::from_table("tests/data/ism_data_table.txt");
+        let mut waves = Waves::new(imu.clone(), RATES[0]).unwrap();
+        waves.enable_fifo(&mut SimDelay).unwrap();
+
+        imu.tick(FIFO_CAPACITY);
+        assert!(matches!(
+            waves.read_and_filter(),
+            Err(ImuError::FifoOverrun { overrun: true, .. })
+        ));
+
+        // Resetting the FIFO clears the overrun.
+        waves.enable_fifo(&mut SimDelay).unwrap();
+        imu.tick(10);
+        assert_eq!(waves.read_and_filter().unwrap(), 10);
+    }
+}
@@ -0,0 +1,238 @@
+//! Simulated RockBLOCK 9603 on the UART.
+//!
+//! Answers the AT commands used by the driver. The message written with `AT+SBDWB` is checked
+//! against the checksum, and is sent by `AT+SBDIX` if there is signal.
+
+use core::cell::RefCell;
+use embedded_hal::serial::{Read, Write};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use super::SimError;
+
+pub struct ModemState {
+    input: Vec<u8>,
+    output: VecDeque<u8>,
+    echo: bool,
+
+    /// Length of binary message expected after `AT+SBDWB`.
+    binary: Option<usize>,
+    mo: Vec<u8>,
+    momsn: u16,
+
+    /// Messages that have been sent.
+    pub messages: Vec<Vec<u8>>,
+
+    /// Whether the sessions succeed.
+    pub signal: bool,
+}
+
+/// Handle to the simulated modem.
+#[derive(Clone)]
+pub struct FakeModem(Rc<RefCell<ModemState>>);
+
+impl FakeModem {
+    pub fn new() -> FakeModem {
+        FakeModem(Rc::new(RefCell::new(ModemState {
+            input: Vec::new(),
+            output: VecDeque::new(),
+            echo: true,
+            binary: None,
+            mo: Vec::new(),
+            momsn: 0,
+            messages: Vec::new(),
+            signal: true,
+        })))
+    }
+
+    pub fn state(&self) -> std::cell::RefMut<'_, ModemState> {
+        self.0.borrow_mut()
+    }
+
+    pub fn messages(&self) -> Vec<Vec<u8>> {
+        self.0.borrow().messages.clone()
+    }
+}
+
+impl ModemState {
+    fn respond(&mut self, r: &str) {
+        self.output.extend(r.as_bytes());
+    }
+
+    fn command(&mut self, cmd: &str) {
+        if self.echo {
+            self.respond(cmd);
+            self.respond("\r");
+        }
+
+        match cmd {
+            "AT" | "AT&K0" => self.respond("\r\nOK\r\n"),
+            "ATE0" => {
+                self.echo = false;
+                self.respond("\r\nOK\r\n");
+            }
+            "AT+SBDIX" => {
+                if self.signal {
+                    self.messages.push(self.mo.clone());
+                    self.respond(&format!("\r\n+SBDIX: 0, {}, 0, 0, 0, 0\r\n", self.momsn));
+                    self.momsn += 1;
+                } else {
+                    self.respond(&format!("\r\n+SBDIX: 32, {}, 2, 0, 0, 0\r\n", self.momsn));
+                }
+                self.respond("\r\nOK\r\n");
+            }
+            _ => match cmd.strip_prefix("AT+SBDWB=").map(str::parse::<usize>) {
+                Some(Ok(n)) if n >= 1 && n <= 340 => {
+                    self.binary = Some(n);
+                    self.respond("READY\r\n");
+                }
+                Some(_) => self.respond("3\r\n\r\nOK\r\n"),
+                None => self.respond("\r\nERROR\r\n"),
+            },
+        }
+    }
+}
+
+impl Write<u8> for FakeModem {
+    type Error = SimError;
+
+    fn write(&mut self, b: u8) -> nb::Result<(), SimError> {
+        let mut s = self.0.borrow_mut();
+        s.input.push(b);
+
+        match s.binary {
+            Some(n) => {
+                if s.input.len() == n + 2 {
+                    let input = std::mem::take(&mut s.input);
+                    s.binary = None;
+
+                    let checksum = input[..n]
+                        .iter()
+                        .fold(0u16, |c, b| c.wrapping_add(*b as u16));
+
+                    if checksum.to_be_bytes() == input[n..] {
+                        s.mo = input[..n].to_vec();
+                        s.respond("0\r\n\r\nOK\r\n");
+                    } else {
+                        s.respond("2\r\n\r\nOK\r\n");
+                    }
+                }
+            }
+            None => {
+                if b == b'\r' {
+                    let input = std::mem::take(&mut s.input);
+                    let cmd = String::from_utf8_lossy(&input[..input.len() - 1]).into_owned();
+                    s.command(cmd.trim());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), SimError> {
+        Ok(())
+    }
+}
+
+impl Read<u8> for FakeModem {
+    type Error = SimError;
+
+    fn read(&mut self) -> nb::Result<u8, SimError> {
+        self.0
+            .borrow_mut()
+            .output
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::axl::{AxlPacket, AXL_SZ};
+    use crate::iridium::{wire, Iridium, IridiumError};
+    use crate::sim::SimDelay;
+    use crate::transport;
+    use crate::waves::wire::{ScaledF32, A16};
+    use crate::NOTEQ_SZ;
+    use heapless::spsc::Queue;
+
+    fn package(timestamp: i64) -> AxlPacket {
+        AxlPacket {
+            timestamp,
+            offset: 0,
+            storage_id: None,
+            storage_version: 0,
+            position_time: 1_700_000_000,
+            lon: 5.32,
+            lat: 60.39,
+            temperature: 12.,
+            freq: crate::waves::OUTPUT_FREQ,
+            accel_range: crate::waves::ACCEL_RANGE,
+            gyro_range: 0.,
+            burst_start: 0,
+            data: (0..AXL_SZ).map(|_| A16::from_f32(0.).to_u16()).collect(),
+        }
+    }
+
+    #[test]
+    fn send_message() {
+        let modem = FakeModem::new();
+        let mut sbd = Iridium::new(modem.clone(), &mut SimDelay).unwrap();
+
+        let msg = wire::position(1_700_000_000_000, 1_700_000_000, 60.39, 5.32);
+        assert_eq!(sbd.send_message(&msg, &mut SimDelay), Ok(0));
+        assert_eq!(sbd.send_message(&msg, &mut SimDelay), Ok(1));
+        assert_eq!(modem.messages(), [msg.to_vec(), msg.to_vec()]);
+
+        modem.state().signal = false;
+        assert_eq!(
+            sbd.send_message(&msg, &mut SimDelay),
+            Err(IridiumError::Session(32))
+        );
+        assert_eq!(modem.messages().len(), 2);
+    }
+
+    #[test]
+    fn drain_queue() {
+        let modem = FakeModem::new();
+        let mut sbd = Iridium::new(modem.clone(), &mut SimDelay).unwrap();
+
+        let queue: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let (mut p, mut c) = queue.split();
+
+        let mut now = 1_700_000_000_000;
+        let mut sent = 0;
+
+        for _ in 0..(40 * 60 / 20) {
+            p.enqueue(package(now)).ok().unwrap();
+            sent += transport::drain_queue(&mut sbd, &mut c, &mut SimDelay).unwrap();
+            now += 20_000;
+        }
+
+        let messages = modem.messages();
+
+        // Only spectra are sent with the spectrum feature, otherwise the position every
+        // `SBD_POSITION_PERIOD`.
+        #[cfg(feature = "spectrum")]
+        {
+            use crate::spec::SPEC_PACKAGES;
+
+            assert_eq!(sent, 0);
+            assert_eq!(messages.len(), 120 / SPEC_PACKAGES);
+            assert!(messages
+                .iter()
+                .all(|m| m[0] == wire::MessageType::Spectrum as u8));
+        }
+
+        #[cfg(not(feature = "spectrum"))]
+        {
+            assert_eq!(sent, messages.iter().map(Vec::len).sum::<usize>());
+            assert_eq!(messages.len(), 2);
+            assert!(messages
+                .iter()
+                .all(|m| m[0] == wire::MessageType::Position as u8));
+        }
+    }
+}
@@ -0,0 +1,784 @@
+//! Host simulation of the peripherals of the buoy.
+//!
+//! The main loops in `sfy-artemis` and `sfy-ext-gps` can otherwise only be exercised on hardware
+//! through `target-test`. The simulated peripherals are:
+//!
+//! * [`imu::MockImu`]: an ISM330DHCX on the I2C bus that replays recorded samples through its
+//!   FIFO.
+//! * [`sd::MockSd`]: an SD-card on the SPI bus, backed by memory and formatted with FAT16.
+//! * [`notecard::FakeNotecard`]: a Notecard on the I2C bus that answers the JSON requests and
+//!   keeps the notes that are added.
+//! * [`iridium::FakeModem`]: a RockBLOCK 9603 on the UART that keeps the SBD messages that are
+//!   sent (with the `iridium` feature).
+//!
+//! The peripherals are handles to shared state, so that the test can keep a clone to inspect or
+//! drive the peripheral after it has been moved into the drivers.
+
+use cortex_m::interrupt::CriticalSection;
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+pub mod imu;
+#[cfg(feature = "iridium")]
+pub mod iridium;
+pub mod notecard;
+pub mod sd;
+
+/// Error returned by the simulated buses.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum SimError {
+    /// No device on the address.
+    Nack,
+}
+
+/// `cortex_m::interrupt::free` is not implemented for the host. Interrupts are not simulated,
+/// so the closure is run with a made-up critical section.
+pub fn free<F, R>(f: F) -> R
+where
+    F: FnOnce(&CriticalSection) -> R,
+{
+    f(unsafe { &CriticalSection::new() })
+}
+
+/// Delays return immediately, time is driven by the test.
+#[derive(Default, Clone, Copy)]
+pub struct SimDelay;
+
+impl DelayUs<u8> for SimDelay {
+    fn delay_us(&mut self, _us: u8) {}
+}
+
+impl DelayMs<u16> for SimDelay {
+    fn delay_ms(&mut self, _ms: u16) {}
+}
+
+/// The defmt messages are discarded on the host.
+#[defmt::global_logger]
+struct SimLogger;
+
+unsafe impl defmt::Logger for SimLogger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("{=u64}", 0);
+
+#[defmt::panic_handler]
+fn defmt_panic() -> ! {
+    panic!("defmt panic")
+}
+
+#[cfg(test)]
+mod tests {
+    /// A storage manager for the simulated SD-card. With `ext-gps` the GPS queues are not used.
+    #[cfg(feature = "storage")]
+    fn storage_manager(
+        storage: crate::storage::Storage<super::sd::MockSd, super::sd::MockCs, super::SimDelay>,
+        storage_c: heapless::spsc::Consumer<
+            'static,
+            crate::waves::AxlPacketT,
+            { crate::STORAGEQ_SZ },
+        >,
+        note_p: heapless::spsc::Producer<'static, crate::axl::AxlPacket, { crate::NOTEQ_SZ }>,
+    ) -> crate::StorageManager<super::sd::MockSd, super::sd::MockCs, super::SimDelay> {
+        #[cfg(feature = "ext-gps")]
+        return crate::StorageManager::new(
+            storage,
+            storage_c,
+            note_p,
+            Box::leak(Box::new(heapless::spsc::Queue::new())).split().1,
+            Box::leak(Box::new(heapless::spsc::Queue::new())).split().0,
+        );
+
+        #[cfg(not(feature = "ext-gps"))]
+        return crate::StorageManager::new(storage, storage_c, note_p);
+    }
+
+    #[cfg(feature = "storage")]
+    #[test]
+    fn imu_to_storage_to_notecard() {
+        use super::imu::MockImu;
+        use super::notecard::FakeNotecard;
+        use super::sd::{MockCs, MockSd};
+        use super::*;
+        use crate::axl::{AxlPacket, AXL_SZ};
+        use crate::note::Notecarrier;
+        use crate::storage::{clock::CountClock, Storage};
+        use crate::waves::{AxlPacketT, Waves, RATES};
+        use crate::{Imu, NOTEQ_SZ, STORAGEQ_SZ};
+        use core::sync::atomic::AtomicI32;
+        use heapless::spsc::Queue;
+
+        static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+        let mut delay = SimDelay;
+
+        let mock_imu = MockImu::from_table("tests/data/ism_data_table.txt");
+        let sd = MockSd::formatted();
+        let nc = FakeNotecard::new();
+
+        let mut note = Notecarrier::new(nc.clone(), &mut delay).unwrap();
+
+        let storageq: &'static mut Queue<AxlPacketT, STORAGEQ_SZ> =
+            Box::leak(Box::new(Queue::new()));
+        let noteq: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let (imu_p, storage_c) = storageq.split();
+        let (note_p, mut note_c) = noteq.split();
+
+        let mut now = 1_700_000_000_000i64;
+
+        let mut waves = Waves::new(mock_imu.clone(), RATES[0]).unwrap();
+        waves.take_buf(now, 0, 5.32, 60.39).unwrap();
+        waves.enable_fifo(&mut delay).unwrap();
+        let mut imu = Imu::new(waves, imu_p);
+
+        let storage = Storage::open(sd.clone(), MockCs, CountClock(&COUNT), |_, _| {}, delay);
+        let mut storage = storage_manager(storage, storage_c, note_p);
+
+        let mut stored = Vec::new();
+        let mut sent = 0;
+
+        for _ in 0..100 {
+            // One second of samples.
+            mock_imu.tick(208);
+            now += 1000;
+
+            imu.check_retrieve(now, 0, 5.32, 60.39).unwrap();
+
+            if let Some(id) = storage.drain_queue(&mut note, &mut delay).unwrap() {
+                stored.push(id);
+            }
+
+            sent += note.drain_queue(&mut note_c, &mut delay).unwrap();
+
+            if nc.notes("axl.qo").len() == 2 {
+                break;
+            }
+        }
+
+        assert_eq!(stored, [0, 1]);
+
+        let notes = nc.notes("axl.qo");
+        assert_eq!(notes.len(), 2);
+        assert_eq!(
+            sent,
+            notes
+                .iter()
+                .map(|n| n.payload.as_ref().unwrap().len())
+                .sum::<usize>()
+        );
+
+        for (id, n) in stored.iter().zip(&notes) {
+            assert_eq!(n.body["storage_id"], *id);
+            assert_eq!(n.body["lat"], 60.39);
+
+            let payload = n.payload.as_ref().unwrap();
+            let mut data = vec![0u8; AXL_SZ * 2 + 2];
+            let sz = base64::decode_config_slice(payload, base64::STANDARD, &mut data).unwrap();
+            assert!(sz <= AXL_SZ * 2);
+            assert_eq!(n.body["samples"], AXL_SZ);
+
+            // The package on the SD-card is the one that was sent.
+            let pck = storage.storage.get(*id).unwrap();
+            assert_eq!(pck.storage_id, Some(*id));
+            assert_eq!(pck.payload().1.as_slice(), payload.as_bytes());
+        }
+    }
+
+    #[cfg(feature = "storage")]
+    #[test]
+    fn requested_packages() {
+        use super::notecard::FakeNotecard;
+        use super::sd::{MockCs, MockSd};
+        use super::*;
+        use crate::axl::{AxlPacket, AXL_SZ};
+        use crate::note::{Notecarrier, StorageIdInfo};
+        use crate::storage::{clock::CountClock, Storage};
+        use crate::waves::AxlPacketT;
+        use crate::{NOTEQ_SZ, REQUEST_BATCH, STORAGEQ_SZ};
+        use core::sync::atomic::AtomicI32;
+        use heapless::spsc::Queue;
+        use serde_json::json;
+
+        static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+        let mut delay = SimDelay;
+
+        let sd = MockSd::formatted();
+        let nc = FakeNotecard::new();
+
+        let mut note = Notecarrier::new(nc.clone(), &mut delay).unwrap();
+
+        let storageq: &'static mut Queue<AxlPacketT, STORAGEQ_SZ> =
+            Box::leak(Box::new(Queue::new()));
+        let noteq: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let (mut storage_p, storage_c) = storageq.split();
+        let (note_p, mut note_c) = noteq.split();
+
+        let storage = Storage::open(sd.clone(), MockCs, CountClock(&COUNT), |_, _| {}, delay);
+        let mut storage = storage_manager(storage, storage_c, note_p);
+
+        // Store and send some live packages.
+        for i in 0..10 {
+            let pck = AxlPacket {
+                timestamp: 1_700_000_000_000 + i * 20_000,
+                offset: 0,
+                storage_id: None,
+                storage_version: 0,
+                position_time: 1_700_000_000,
+                lon: 5.32,
+                lat: 60.39,
+                temperature: 12.,
+                freq: crate::waves::OUTPUT_FREQ,
+                accel_range: crate::waves::ACCEL_RANGE,
+                gyro_range: 0.,
+                burst_start: 0,
+                data: (0..AXL_SZ).map(|_| 0).collect(),
+            };
+
+            #[cfg(feature = "raw")]
+            let pck: AxlPacketT = (pck, crate::waves::VecRawAxl::new());
+
+            #[cfg(not(feature = "raw"))]
+            let pck: AxlPacketT = (pck,);
+
+            storage_p.enqueue(pck).ok().unwrap();
+            storage.drain_queue(&mut note, &mut delay).unwrap();
+            note.drain_queue(&mut note_c, &mut delay).unwrap();
+        }
+
+        assert_eq!(nc.notes("axl.qo").len(), 10);
+
+        // No request.
+        storage
+            .queue_requested_packages(&mut note, &mut delay)
+            .unwrap();
+        assert_eq!(note_c.len(), 0);
+
+        // The end of the request is past the last stored package.
+        nc.state().db.insert(
+            ("storage.db".into(), "request-data".into()),
+            json!({ "request_start": 2, "request_end": 20 }),
+        );
+
+        // Nothing more is queued before the requested packages have been sent.
+        storage
+            .queue_requested_packages(&mut note, &mut delay)
+            .unwrap();
+        storage
+            .queue_requested_packages(&mut note, &mut delay)
+            .unwrap();
+        assert_eq!(note_c.len(), REQUEST_BATCH.min(8));
+
+        let (info, _) = note.read_storage_info(&mut delay).unwrap();
+        assert_eq!(info.unwrap().sent_id, None);
+
+        for _ in 0..20 {
+            note.drain_queue(&mut note_c, &mut delay).unwrap();
+            storage
+                .queue_requested_packages(&mut note, &mut delay)
+                .unwrap();
+
+            if !nc
+                .state()
+                .db
+                .contains_key(&("storage.db".into(), "request-data".into()))
+            {
+                break;
+            }
+        }
+
+        let resent = nc.notes("axl.qo")[10..]
+            .iter()
+            .map(|n| n.body["storage_id"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(resent, (2..10).collect::<Vec<_>>());
+
+        // The request and the progress is cleared when done.
+        let (info, request) = note.read_storage_info(&mut delay).unwrap();
+        assert!(request.is_none());
+        assert_eq!(info.unwrap_or_default(), StorageIdInfo::default());
+    }
+
+    #[cfg(feature = "storage")]
+    #[test]
+    fn corrupt_package() {
+        use super::notecard::FakeNotecard;
+        use super::sd::{MockCs, MockSd};
+        use super::*;
+        use crate::axl::{AxlPacket, AXL_SZ};
+        use crate::note::Notecarrier;
+        use crate::storage::header::{HeaderError, MAGIC};
+        use crate::storage::{clock::CountClock, Storage, StorageErr};
+        use crate::waves::AxlPacketT;
+        use crate::{NOTEQ_SZ, STORAGEQ_SZ};
+        use core::sync::atomic::AtomicI32;
+        use heapless::spsc::Queue;
+        use serde_json::json;
+
+        static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+        let mut delay = SimDelay;
+
+        let sd = MockSd::formatted();
+        let nc = FakeNotecard::new();
+
+        let mut note = Notecarrier::new(nc.clone(), &mut delay).unwrap();
+
+        let storageq: &'static mut Queue<AxlPacketT, STORAGEQ_SZ> =
+            Box::leak(Box::new(Queue::new()));
+        let noteq: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let (mut storage_p, storage_c) = storageq.split();
+        let (note_p, mut note_c) = noteq.split();
+
+        let storage = Storage::open(sd.clone(), MockCs, CountClock(&COUNT), |_, _| {}, delay);
+        let mut storage = storage_manager(storage, storage_c, note_p);
+
+        for i in 0..4 {
+            let pck = AxlPacket {
+                timestamp: 1_700_000_000_000 + i * 20_000,
+                offset: 0,
+                storage_id: None,
+                storage_version: 0,
+                position_time: 1_700_000_000,
+                lon: 5.32,
+                lat: 60.39,
+                temperature: 12.,
+                freq: crate::waves::OUTPUT_FREQ,
+                accel_range: crate::waves::ACCEL_RANGE,
+                gyro_range: 0.,
+                burst_start: 0,
+                data: (0..AXL_SZ).map(|v| v as u16).collect(),
+            };
+
+            #[cfg(feature = "raw")]
+            let pck: AxlPacketT = (pck, crate::waves::VecRawAxl::new());
+
+            #[cfg(not(feature = "raw"))]
+            let pck: AxlPacketT = (pck,);
+
+            storage_p.enqueue(pck).ok().unwrap();
+            storage.drain_queue(&mut note, &mut delay).unwrap();
+            note.drain_queue(&mut note_c, &mut delay).unwrap();
+        }
+
+        // Flip a byte of the package length in the header of package 1.
+        let mut header = MAGIC.to_le_bytes().to_vec();
+        header.extend(1u32.to_le_bytes());
+        assert!(sd.corrupt(&header, 8));
+
+        assert_eq!(storage.storage.get(0).unwrap().storage_id, Some(0));
+        assert!(matches!(
+            storage.storage.get(1),
+            Err(StorageErr::CorruptPackage(
+                HeaderError::Length | HeaderError::Crc
+            ))
+        ));
+        assert_eq!(storage.storage.get(2).unwrap().storage_id, Some(2));
+
+        // The corrupt package is skipped when the packages are requested.
+        nc.state().db.insert(
+            ("storage.db".into(), "request-data".into()),
+            json!({ "request_start": 0, "request_end": 3 }),
+        );
+
+        for _ in 0..10 {
+            storage
+                .queue_requested_packages(&mut note, &mut delay)
+                .unwrap();
+            note.drain_queue(&mut note_c, &mut delay).unwrap();
+        }
+
+        let resent = nc.notes("axl.qo")[4..]
+            .iter()
+            .map(|n| n.body["storage_id"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(resent, [0, 2, 3]);
+    }
+
+    #[cfg(all(feature = "storage", not(feature = "iridium")))]
+    #[test]
+    fn unsent_packages() {
+        use super::notecard::FakeNotecard;
+        use super::sd::{MockCs, MockSd};
+        use super::*;
+        use crate::axl::{AxlPacket, AXL_SZ};
+        use crate::note::Notecarrier;
+        use crate::storage::{clock::CountClock, Storage};
+        use crate::waves::AxlPacketT;
+        use crate::{NOTEQ_SZ, STORAGEQ_SZ};
+        use core::sync::atomic::AtomicI32;
+        use heapless::spsc::Queue;
+
+        static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+        let mut delay = SimDelay;
+
+        let sd = MockSd::formatted();
+        let nc = FakeNotecard::new();
+
+        let mut note = Notecarrier::new(nc.clone(), &mut delay).unwrap();
+
+        let storageq: &'static mut Queue<AxlPacketT, STORAGEQ_SZ> =
+            Box::leak(Box::new(Queue::new()));
+        let noteq: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let (mut storage_p, storage_c) = storageq.split();
+        let (note_p, mut note_c) = noteq.split();
+
+        let storage = Storage::open(sd.clone(), MockCs, CountClock(&COUNT), |_, _| {}, delay);
+        let mut storage = storage_manager(storage, storage_c, note_p);
+
+        let package = |i: i64| {
+            let pck = AxlPacket {
+                timestamp: 1_700_000_000_000 + i * 20_000,
+                offset: 0,
+                storage_id: None,
+                storage_version: 0,
+                position_time: 1_700_000_000,
+                lon: 5.32,
+                lat: 60.39,
+                temperature: 12.,
+                freq: crate::waves::OUTPUT_FREQ,
+                accel_range: crate::waves::ACCEL_RANGE,
+                gyro_range: 0.,
+                burst_start: 0,
+                data: (0..AXL_SZ).map(|_| 0).collect(),
+            };
+
+            #[cfg(feature = "raw")]
+            let pck: AxlPacketT = (pck, crate::waves::VecRawAxl::new());
+
+            #[cfg(not(feature = "raw"))]
+            let pck: AxlPacketT = (pck,);
+
+            pck
+        };
+
+        // The Notecard is full: the first package stays in the note queue, the rest are spilled
+        // to the SD-card.
+        nc.state().storage = 80;
+
+        for i in 0..6 {
+            storage_p.enqueue(package(i)).ok().unwrap();
+            storage.drain_queue(&mut note, &mut delay).unwrap();
+            note.drain_queue(&mut note_c, &mut delay).unwrap();
+            storage.queue_unsent(&mut note, &mut delay).unwrap();
+        }
+
+        assert!(nc.notes("axl.qo").is_empty());
+        assert_eq!(note_c.len(), 1);
+
+        // The unsent packages, and the package in the note queue, survive a reset.
+        let unsent = storage.storage.load_unsent().unwrap();
+        assert_eq!(unsent.len(), 6);
+        assert!((0..6).all(|id| unsent.contains(id)));
+
+        // The Notecard has synced, the backlog is sent before the new packages.
+        nc.state().storage = 10;
+
+        for i in 6..30 {
+            if i < 8 {
+                storage_p.enqueue(package(i)).ok().unwrap();
+                storage.drain_queue(&mut note, &mut delay).unwrap();
+            }
+
+            note.drain_queue(&mut note_c, &mut delay).unwrap();
+            storage.queue_unsent(&mut note, &mut delay).unwrap();
+        }
+
+        let sent = nc
+            .notes("axl.qo")
+            .iter()
+            .map(|n| n.body["storage_id"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sent, (0..8).collect::<Vec<_>>());
+
+        assert!(storage.storage.load_unsent().unwrap().is_empty());
+    }
+
+    #[cfg(all(feature = "storage", feature = "spectrum", not(feature = "iridium")))]
+    #[test]
+    fn spectra_skipped_packages() {
+        use super::notecard::FakeNotecard;
+        use super::sd::{MockCs, MockSd};
+        use super::*;
+        use crate::axl::{AxlPacket, AXL_SZ};
+        use crate::note::{LinkMode, Notecarrier};
+        use crate::storage::{clock::CountClock, Storage};
+        use crate::waves::AxlPacketT;
+        use crate::{NOTEQ_SZ, STORAGEQ_SZ};
+        use core::sync::atomic::AtomicI32;
+        use heapless::spsc::Queue;
+
+        static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+        let mut delay = SimDelay;
+
+        let sd = MockSd::formatted();
+        let nc = FakeNotecard::new();
+
+        let mut note = Notecarrier::new(nc.clone(), &mut delay).unwrap();
+
+        let storageq: &'static mut Queue<AxlPacketT, STORAGEQ_SZ> =
+            Box::leak(Box::new(Queue::new()));
+        let noteq: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let (mut storage_p, storage_c) = storageq.split();
+        let (note_p, mut note_c) = noteq.split();
+
+        let storage = Storage::open(sd.clone(), MockCs, CountClock(&COUNT), |_, _| {}, delay);
+        let mut storage = storage_manager(storage, storage_c, note_p);
+
+        let package = |i: i64| {
+            let pck = AxlPacket {
+                timestamp: 1_700_000_000_000 + i * 20_000,
+                offset: 0,
+                storage_id: None,
+                storage_version: 0,
+                position_time: 1_700_000_000,
+                lon: 5.32,
+                lat: 60.39,
+                temperature: 12.,
+                freq: crate::waves::OUTPUT_FREQ,
+                accel_range: crate::waves::ACCEL_RANGE,
+                gyro_range: 0.,
+                burst_start: 0,
+                data: (0..AXL_SZ).map(|_| 0).collect(),
+            };
+
+            #[cfg(feature = "raw")]
+            let pck: AxlPacketT = (pck, crate::waves::VecRawAxl::new());
+
+            #[cfg(not(feature = "raw"))]
+            let pck: AxlPacketT = (pck,);
+
+            pck
+        };
+
+        // Only the spectra are sent, the packages are kept as unsent.
+        nc.state().storage = crate::note::SPECTRA_STORAGE;
+
+        for i in 0..3 {
+            storage_p.enqueue(package(i)).ok().unwrap();
+            storage.drain_queue(&mut note, &mut delay).unwrap();
+            note.drain_queue(&mut note_c, &mut delay).unwrap();
+            storage.queue_unsent(&mut note, &mut delay).unwrap();
+        }
+
+        assert_eq!(note.link_mode(), LinkMode::Spectra);
+        assert!(nc.notes("axl.qo").is_empty());
+
+        let unsent = storage.storage.load_unsent().unwrap();
+        assert_eq!(unsent.len(), 3);
+        assert!((0..3).all(|id| unsent.contains(id)));
+
+        // The uplink has recovered, the skipped packages are sent.
+        nc.state().storage = 10;
+
+        for _ in 0..10 {
+            note.drain_queue(&mut note_c, &mut delay).unwrap();
+            storage.queue_unsent(&mut note, &mut delay).unwrap();
+        }
+
+        let sent = nc
+            .notes("axl.qo")
+            .iter()
+            .map(|n| n.body["storage_id"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sent, [0, 1, 2]);
+
+        assert!(storage.storage.load_unsent().unwrap().is_empty());
+    }
+
+    #[cfg(all(feature = "storage", not(feature = "iridium")))]
+    #[test]
+    fn note_queue_after_reset() {
+        use super::notecard::FakeNotecard;
+        use super::sd::{MockCs, MockSd};
+        use super::*;
+        use crate::axl::{AxlPacket, AXL_SZ};
+        use crate::note::Notecarrier;
+        use crate::storage::{clock::CountClock, Storage};
+        use crate::waves::AxlPacketT;
+        use crate::{NOTEQ_SZ, STORAGEQ_SZ};
+        use core::sync::atomic::AtomicI32;
+        use heapless::spsc::Queue;
+
+        static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+        let mut delay = SimDelay;
+
+        let sd = MockSd::formatted();
+        let nc = FakeNotecard::new();
+
+        let mut note = Notecarrier::new(nc.clone(), &mut delay).unwrap();
+
+        let storageq: &'static mut Queue<AxlPacketT, STORAGEQ_SZ> =
+            Box::leak(Box::new(Queue::new()));
+        let noteq: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let (mut storage_p, storage_c) = storageq.split();
+        let (note_p, mut note_c) = noteq.split();
+
+        let storage = Storage::open(sd.clone(), MockCs, CountClock(&COUNT), |_, _| {}, delay);
+        let mut storage = storage_manager(storage, storage_c, note_p);
+
+        for i in 0..3 {
+            let pck = AxlPacket {
+                timestamp: 1_700_000_000_000 + i * 20_000,
+                offset: 0,
+                storage_id: None,
+                storage_version: 0,
+                position_time: 1_700_000_000,
+                lon: 5.32,
+                lat: 60.39,
+                temperature: 12.,
+                freq: crate::waves::OUTPUT_FREQ,
+                accel_range: crate::waves::ACCEL_RANGE,
+                gyro_range: 0.,
+                burst_start: 0,
+                data: (0..AXL_SZ).map(|_| 0).collect(),
+            };
+
+            #[cfg(feature = "raw")]
+            let pck: AxlPacketT = (pck, crate::waves::VecRawAxl::new());
+
+            #[cfg(not(feature = "raw"))]
+            let pck: AxlPacketT = (pck,);
+
+            storage_p.enqueue(pck).ok().unwrap();
+            storage.drain_queue(&mut note, &mut delay).unwrap();
+        }
+
+        // `UNSENT_FILE` is only written once per drain of the note queue.
+        assert!(storage.storage.load_unsent().unwrap().is_empty());
+
+        // The first package is taken off the note queue, the others are lost in the reset.
+        assert_eq!(note_c.len(), 3);
+        note_c.dequeue().unwrap();
+        storage.queue_unsent(&mut note, &mut delay).unwrap();
+        assert_eq!(note_c.len(), 2);
+
+        let unsent = storage.storage.load_unsent().unwrap();
+        assert_eq!(unsent.len(), 2);
+        assert!(unsent.contains(1) && unsent.contains(2));
+
+        // Reset: the queues are lost, the packages are read from the SD-card again.
+        drop(storage);
+        drop(note_c);
+
+        let storageq: &'static mut Queue<AxlPacketT, STORAGEQ_SZ> =
+            Box::leak(Box::new(Queue::new()));
+        let noteq: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let (_, storage_c) = storageq.split();
+        let (note_p, mut note_c) = noteq.split();
+
+        let storage = Storage::open(sd.clone(), MockCs, CountClock(&COUNT), |_, _| {}, delay);
+        let mut storage = storage_manager(storage, storage_c, note_p);
+
+        for _ in 0..10 {
+            storage.queue_unsent(&mut note, &mut delay).unwrap();
+            note.drain_queue(&mut note_c, &mut delay).unwrap();
+        }
+
+        let sent = nc
+            .notes("axl.qo")
+            .iter()
+            .map(|n| n.body["storage_id"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sent, [1, 2]);
+
+        storage.queue_unsent(&mut note, &mut delay).unwrap();
+        assert!(storage.storage.load_unsent().unwrap().is_empty());
+    }
+
+    #[cfg(all(feature = "storage", feature = "ext-gps"))]
+    #[test]
+    fn gps_requested_packages() {
+        use super::notecard::FakeNotecard;
+        use super::sd::{MockCs, MockSd};
+        use super::*;
+        use crate::axl::AxlPacket;
+        use crate::gps::{GpsPacket, GPS_PACKET_SZ, GPS_PACKET_V};
+        use crate::note::{Notecarrier, StorageIdInfo};
+        use crate::storage::{clock::CountClock, Storage};
+        use crate::waves::AxlPacketT;
+        use crate::{StorageManager, EPGS_SZ, NOTEQ_SZ, STORAGEQ_SZ};
+        use core::sync::atomic::AtomicI32;
+        use heapless::spsc::Queue;
+        use serde_json::json;
+
+        static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+        let mut delay = SimDelay;
+
+        let sd = MockSd::formatted();
+        let nc = FakeNotecard::new();
+
+        let mut note = Notecarrier::new(nc.clone(), &mut delay).unwrap();
+
+        let storageq: &'static mut Queue<AxlPacketT, STORAGEQ_SZ> =
+            Box::leak(Box::new(Queue::new()));
+        let noteq: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let gpsq: &'static mut Queue<GpsPacket, EPGS_SZ> = Box::leak(Box::new(Queue::new()));
+        let gps_noteq: &'static mut Queue<GpsPacket, EPGS_SZ> = Box::leak(Box::new(Queue::new()));
+        let (_, storage_c) = storageq.split();
+        let (note_p, _) = noteq.split();
+        let (mut gps_p, gps_c) = gpsq.split();
+        let (gps_note_p, mut gps_note_c) = gps_noteq.split();
+
+        let storage = Storage::open(sd.clone(), MockCs, CountClock(&COUNT), |_, _| {}, delay);
+        let mut storage = StorageManager::new(storage, storage_c, note_p, gps_c, gps_note_p);
+
+        // Store and send some live GPS packages.
+        for i in 0..6 {
+            let pck = GpsPacket {
+                timestamp: 1_700_000_000_000 + i * 20_000,
+                freq: 20.0,
+                version: GPS_PACKET_V,
+                storage_id: None,
+                lon: 53677011,
+                lat: 603283447,
+                msl: 91506,
+                data: (0..6 * GPS_PACKET_SZ).map(|v| v as u16).collect(),
+                ha_min: 1.0,
+                ha_max: 2.0,
+                ha_mean: 1.5,
+                va_min: 1.0,
+                va_max: 2.0,
+                va_mean: 1.5,
+                fix: [0; 8],
+                soln: [0; 8],
+            };
+
+            gps_p.enqueue(pck).ok().unwrap();
+            assert_eq!(storage.drain_gps_queue().unwrap(), Some(i as u32));
+            note.drain_egps_queue(&mut gps_note_c, &mut delay).unwrap();
+        }
+
+        let notes = nc.notes("egps.qo");
+        assert_eq!(notes.len(), 6);
+        assert_eq!(notes[5].body["storage_id"], 5);
+
+        nc.state().db.insert(
+            ("storage.db".into(), "request-gps".into()),
+            json!({ "request_start": 1, "request_end": 3 }),
+        );
+
+        for _ in 0..10 {
+            storage.queue_requested_gps(&mut note, &mut delay).unwrap();
+            note.drain_egps_queue(&mut gps_note_c, &mut delay).unwrap();
+        }
+
+        let resent = nc.notes("egps.qo")[6..]
+            .iter()
+            .map(|n| n.body["storage_id"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(resent, [1, 2, 3]);
+
+        // The request and the progress is cleared when done.
+        let (info, request) = note.read_gps_storage_info(&mut delay).unwrap();
+        assert!(request.is_none());
+        assert_eq!(info.unwrap_or_default(), StorageIdInfo::default());
+        assert!(nc.notes("axl.qo").is_empty());
+    }
+}
@@ -0,0 +1,581 @@
+//! Simulated Notecard.
+//!
+//! The Notecard speaks the serial-over-I2C protocol: a write of `[len, bytes..]` adds to the
+//! request, and a write of `[0, n]` asks for up to `n` bytes of the response which are returned
+//! by the following read as `[available, sent, bytes..]`. Requests are terminated by a newline,
+//! and answered with a JSON response similar to what the Notecard would have returned.
+
+use core::cell::RefCell;
+use embedded_hal::blocking::i2c::{Read, Write};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use super::SimError;
+
+pub const ADDRESS: u8 = 0x17;
+
+/// A note added with `note.add`.
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub file: String,
+    pub body: Value,
+    pub payload: Option<String>,
+}
+
+pub struct NotecardState {
+    input: Vec<u8>,
+    output: VecDeque<u8>,
+
+    /// Number of bytes asked for by the last read request.
+    requested: usize,
+
+    /// Every request received.
+    pub requests: Vec<Value>,
+
+    /// Notes added to outbound queues.
+    pub notes: Vec<Note>,
+
+    /// Notes in databases, by file and note id.
+    pub db: HashMap<(String, String), Value>,
+
+    /// Environment variables.
+    pub env: HashMap<String, String>,
+
+    templates: Vec<String>,
+
+    /// Used storage [%].
+    pub storage: usize,
+
+    /// Unix time [s].
+    pub time: u64,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Handle to the simulated Notecard.
+#[derive(Clone)]
+pub struct FakeNotecard(Rc<RefCell<NotecardState>>);
+
+impl FakeNotecard {
+    pub fn new() -> FakeNotecard {
+        FakeNotecard(Rc::new(RefCell::new(NotecardState {
+            input: Vec::new(),
+            output: VecDeque::new(),
+            requested: 0,
+            requests: Vec::new(),
+            notes: Vec::new(),
+            db: HashMap::new(),
+            env: HashMap::new(),
+            templates: Vec::new(),
+            storage: 8,
+            time: 1_700_000_000,
+            lat: 60.39,
+            lon: 5.32,
+        })))
+    }
+
+    pub fn state(&self) -> std::cell::RefMut<'_, NotecardState> {
+        self.0.borrow_mut()
+    }
+
+    /// Notes added to `file`.
+    pub fn notes(&self, file: &str) -> Vec<Note> {
+        self.0
+            .borrow()
+            .notes
+            .iter()
+            .filter(|n| n.file == file)
+            .cloned()
+            .collect()
+    }
+
+    /// Names of the requests received.
+    pub fn requests(&self) -> Vec<String> {
+        self.0
+            .borrow()
+            .requests
+            .iter()
+            .filter_map(|r| r.get("req").or_else(|| r.get("cmd")))
+            .filter_map(|r| r.as_str().map(String::from))
+            .collect()
+    }
+}
+
+impl NotecardState {
+    fn request(&mut self, req: Value) -> Option<Value> {
+        self.requests.push(req.clone());
+
+        // Commands are not answered.
+        if let Some(cmd) = req.get("cmd").and_then(Value::as_str) {
+            self.response(cmd, &req);
+            return None;
+        }
+
+        match req.get("req").and_then(Value::as_str) {
+            Some(r) => Some(self.response(r, &req)),
+            None => Some(json!({ "err": "no request or command specified {io}" })),
+        }
+    }
+
+    fn response(&mut self, r: &str, req: &Value) -> Value {
+        let file = req.get("file").and_then(Value::as_str).map(String::from);
+        let note = req.get("note").and_then(Value::as_str).map(String::from);
+
+        match r {
+            "card.status" => json!({
+                "status": "{normal}",
+                "usb": true,
+                "storage": self.storage,
+                "time": self.time,
+                "connected": true,
+            }),
+            "card.version" => json!({
+                "body": {
+                    "org": "Blues Wireless",
+                    "product": "Notecard",
+                    "version": "notecard-5.3.1",
+                    "ver_major": 5,
+                    "ver_minor": 3,
+                    "ver_patch": 1,
+                    "ver_build": 16347,
+                    "built": "Jan 25 2023 16:51:03",
+                },
+                "version": "notecard-5.3.1.16347",
+                "device": "dev:864475040000000",
+                "name": "Blues Wireless Notecard",
+                "board": "1.11",
+                "sku": "NOTE-WBEX-500",
+                "api": 5,
+            }),
+            "card.time" => json!({
+                "time": self.time,
+                "area": "Bergen Vestland",
+                "zone": "CET,Europe/Oslo",
+                "minutes": 60,
+                "lat": self.lat,
+                "lon": self.lon,
+                "country": "NO",
+            }),
+            "card.location" => json!({
+                "status": "GPS updated (58 sec, 41dB SNR, 9 sats) {gps-active} {gps-signal} {gps-sats} {gps}",
+                "mode": "periodic",
+                "lat": self.lat,
+                "lon": self.lon,
+                "time": self.time,
+            }),
+            "card.location.mode" => json!({
+                "mode": req.get("mode").cloned().unwrap_or(json!("periodic")),
+                "seconds": req.get("seconds").cloned().unwrap_or(json!(3600)),
+            }),
+            "card.location.track" => json!({
+                "start": true,
+                "heartbeat": true,
+                "hours": req.get("hours").cloned().unwrap_or(json!(1)),
+            }),
+            "card.voltage" => json!({
+                "mode": "usb",
+                "value": 5.12,
+                "usb": true,
+            }),
+            "card.wireless" => json!({
+                "status": "{modem-on}",
+                "mode": "auto",
+                "count": 3,
+                "net": {},
+            }),
+            "hub.get" => json!({
+                "device": "dev:864475040000000",
+                "product": req.get("product").cloned().unwrap_or(json!("no.met.sfy")),
+                "mode": "periodic",
+                "outbound": 20,
+                "sn": "sfy-sim",
+            }),
+            "hub.sync.status" => json!({
+                "status": "completed {sync-end}",
+                "time": self.time,
+                "completed": 5,
+            }),
+            "hub.set" | "hub.sync" | "hub.log" => json!({}),
+            "env.get" => {
+                let names = req
+                    .get("names")
+                    .and_then(Value::as_array)
+                    .cloned()
+                    .unwrap_or_default();
+                let body = names
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .filter_map(|n| self.env.get(n).map(|v| (n.to_string(), json!(v))))
+                    .collect::<serde_json::Map<_, _>>();
+
+                json!({ "body": body, "time": self.time })
+            }
+            "note.template" => {
+                if let Some(file) = file {
+                    self.templates.push(file);
+                }
+                json!({ "bytes": 89 })
+            }
+            "note.add" => {
+                let file = file.unwrap_or_else(|| "data.qo".into());
+                let template = self.templates.contains(&file);
+
+                self.notes.push(Note {
+                    file,
+                    body: req.get("body").cloned().unwrap_or(json!({})),
+                    payload: req.get("payload").and_then(Value::as_str).map(String::from),
+                });
+
+                if template {
+                    json!({ "template": true })
+                } else {
+                    json!({ "total": self.notes.len() })
+                }
+            }
+            "note.get" | "note.update" | "note.delete" => {
+                let (file, note) = match (file, note) {
+                    (Some(file), Some(note)) => (file, note),
+                    _ => return json!({ "err": "no notefile or note specified {io}" }),
+                };
+
+                match r {
+                    "note.update" => {
+                        let body = req.get("body").cloned().unwrap_or(json!({}));
+                        self.db.insert((file, note), body);
+                        json!({})
+                    }
+                    "note.get" => match self.db.get(&(file, note)) {
+                        Some(body) => json!({ "body": body, "time": self.time }),
+                        None => json!({ "err": "note not found {note-noexist}" }),
+                    },
+                    _ => match self.db.remove(&(file, note)) {
+                        Some(_) => json!({}),
+                        None => json!({ "err": "note not found {note-noexist}" }),
+                    },
+                }
+            }
+            _ => json!({ "err": format!("unknown request: {} {{io}}", r) }),
+        }
+    }
+}
+
+impl Write for FakeNotecard {
+    type Error = SimError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), SimError> {
+        if address != ADDRESS {
+            return Err(SimError::Nack);
+        }
+
+        let mut s = self.0.borrow_mut();
+
+        match bytes {
+            // Read request.
+            [0, n] => s.requested = *n as usize,
+            [len, data @ ..] => {
+                let len = (*len as usize).min(data.len());
+                s.input.extend_from_slice(&data[..len]);
+
+                while let Some(i) = s.input.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = s.input.drain(..=i).collect();
+
+                    // An empty line resets the request.
+                    if line.iter().all(u8::is_ascii_whitespace) {
+                        continue;
+                    }
+
+                    let response = match serde_json::from_slice::<Value>(&line) {
+                        Ok(req) => s.request(req),
+                        Err(_) => Some(json!({ "err": "unrecognized request {io}" })),
+                    };
+
+                    if let Some(response) = response {
+                        let response = serde_json::to_vec(&response).unwrap();
+                        s.output.extend(response);
+                        s.output.extend(b"\r\n");
+                    }
+                }
+            }
+            // Ping.
+            [] => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for FakeNotecard {
+    type Error = SimError;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), SimError> {
+        if address != ADDRESS {
+            return Err(SimError::Nack);
+        }
+
+        let mut s = self.0.borrow_mut();
+
+        let n = s
+            .requested
+            .min(s.output.len())
+            .min(buffer.len().saturating_sub(2));
+        s.requested = 0;
+
+        buffer.fill(0);
+        for b in buffer.iter_mut().skip(2).take(n) {
+            *b = s.output.pop_front().unwrap();
+        }
+
+        if let [available, sent, ..] = buffer {
+            *available = s.output.len().min(u8::MAX as usize) as u8;
+            *sent = n as u8;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::Config;
+    use crate::note::{Notecarrier, StorageIdInfo};
+    use crate::sim::SimDelay;
+
+    #[test]
+    fn setup_notecarrier() {
+        let nc = FakeNotecard::new();
+        let mut note = Notecarrier::new(nc.clone(), &mut SimDelay).unwrap();
+
+        let requests = nc.requests();
+        assert!(requests.iter().any(|r| r == "hub.set"));
+        assert!(requests.iter().any(|r| r == "hub.sync"));
+        assert!(nc.state().templates.iter().any(|t| t == "axl.qo"));
+
+        // Notecard is filling up.
+        nc.state().storage = 70;
+        note.check_and_sync(&mut SimDelay).unwrap();
+        assert_eq!(requests.len() + 4, nc.requests().len());
+        assert_eq!(nc.requests().last().unwrap(), "hub.sync");
+    }
+
+    #[test]
+    fn storage_info() {
+        let nc = FakeNotecard::new();
+        let mut note = Notecarrier::new(nc.clone(), &mut SimDelay).unwrap();
+
+        let (info, request) = note.read_storage_info(&mut SimDelay).unwrap();
+        assert!(info.is_none());
+        assert!(request.is_none());
+
+        nc.state().db.insert(
+            ("storage.db".into(), "request-data".into()),
+            json!({ "request_start": 10, "request_end": 20 }),
+        );
+
+        let info = StorageIdInfo {
+            sent_id: Some(12),
+            request_start: Some(10),
+            request_end: Some(20),
+        };
+        note.write_storage_info(&mut SimDelay, info, false).unwrap();
+
+        let (info, request) = note.read_storage_info(&mut SimDelay).unwrap();
+        let info = info.unwrap();
+        assert_eq!(info.sent_id, Some(12));
+        assert_eq!(info.request_start, Some(10));
+        assert_eq!(request.unwrap().request_end, Some(20));
+
+        note.write_storage_info(&mut SimDelay, StorageIdInfo::default(), true)
+            .unwrap();
+        let (info, request) = note.read_storage_info(&mut SimDelay).unwrap();
+        assert!(request.is_none());
+        assert_eq!(info.unwrap_or_default(), StorageIdInfo::default());
+    }
+
+    #[test]
+    fn status_note() {
+        use crate::status::{Queues, Status};
+        use crate::watchdog::ResetReason;
+
+        let nc = FakeNotecard::new();
+        let mut note = Notecarrier::new(nc.clone(), &mut SimDelay).unwrap();
+        assert!(nc.state().templates.iter().any(|t| t == "status.qo"));
+
+        let status = Status::new(0, 1, ResetReason::Watchdog);
+        note.send_status(status.note(60_000, Queues::default()), &mut SimDelay)
+            .unwrap();
+
+        let notes = nc.notes("status.qo");
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].body["voltage"], 5.12);
+        assert_eq!(notes[0].body["uptime"], 60);
+        assert_eq!(notes[0].body["reset_reason"], "watchdog");
+    }
+
+    #[test]
+    fn low_power() {
+        let nc = FakeNotecard::new();
+        let mut note = Notecarrier::new(nc.clone(), &mut SimDelay).unwrap();
+
+        let hub_mode = |nc: &FakeNotecard| {
+            nc.state()
+                .requests
+                .iter()
+                .filter(|r| r["req"] == "hub.set")
+                .last()
+                .map(|r| r["mode"].clone())
+                .unwrap()
+        };
+
+        assert!(!note.low_power());
+        note.set_low_power(true, &mut SimDelay).unwrap();
+        assert!(note.low_power());
+        assert_eq!(hub_mode(&nc), "minimum");
+
+        // Changing the configuration keeps the low-power mode.
+        nc.state().env.insert("SYNC_PERIOD".into(), "5".into());
+        assert!(note.check_config(1_700_000_000_000, &mut SimDelay).unwrap());
+        assert_eq!(hub_mode(&nc), "minimum");
+
+        note.set_low_power(false, &mut SimDelay).unwrap();
+        assert!(!note.low_power());
+        assert_eq!(hub_mode(&nc), "periodic");
+        assert_eq!(nc.requests().last().unwrap(), "hub.sync");
+    }
+
+    #[test]
+    fn env_config() {
+        let nc = FakeNotecard::new();
+        nc.state().env.insert("SYNC_PERIOD".into(), "60".into());
+        nc.state().env.insert("GPS_PERIOD".into(), "ten".into());
+
+        let mut note = Notecarrier::new(nc.clone(), &mut SimDelay).unwrap();
+
+        assert_eq!(note.config().sync_period, 60);
+        assert_eq!(note.config().gps_period, Config::default().gps_period);
+
+        let hub_set = |nc: &FakeNotecard| {
+            nc.state()
+                .requests
+                .iter()
+                .filter(|r| r["req"] == "hub.set")
+                .last()
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(hub_set(&nc)["outbound"], 60);
+
+        let configs = nc.notes("config.qo");
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].body["sync_period"], 60);
+
+        // Changed on Notehub.
+        nc.state().env.insert("SYNC_PERIOD".into(), "5".into());
+
+        let now = 1_700_000_000_000;
+        assert!(note.check_config(now, &mut SimDelay).unwrap());
+        assert_eq!(note.config().sync_period, 5);
+        assert_eq!(hub_set(&nc)["outbound"], 5);
+        assert_eq!(nc.notes("config.qo").len(), 2);
+
+        // Not checked again before `CONFIG_PERIOD`.
+        nc.state().env.remove("SYNC_PERIOD");
+        assert!(!note.check_config(now + 1000, &mut SimDelay).unwrap());
+
+        assert!(note
+            .check_config(now + crate::env::CONFIG_PERIOD, &mut SimDelay)
+            .unwrap());
+        assert_eq!(note.config(), &Config::default());
+
+        // Unchanged.
+        assert!(!note
+            .check_config(now + 2 * crate::env::CONFIG_PERIOD, &mut SimDelay)
+            .unwrap());
+        assert_eq!(nc.notes("config.qo").len(), 3);
+    }
+
+    /// The main loop drains the note queue every 280 s, or when it is nearly full, and a package
+    /// is made every 19.7 s (52 Hz). After being congested a healthy uplink returns to
+    /// `LinkMode::Full`, even though the note queue is never short when it is drained.
+    #[test]
+    fn link_mode_at_loop_cadence() {
+        use crate::axl::{AxlPacket, AXL_SZ};
+        use crate::note::{LinkMode, BACKLOG_DRAINS, DECIMATE_QUEUE};
+        use crate::NOTEQ_SZ;
+        use heapless::spsc::Queue;
+
+        const LOOP_DELAY: i64 = 14 * 20_000;
+        const SHORT_LOOP_DELAY: i64 = 30_000;
+        const PACKAGE: i64 = 19_700;
+        const SYNCED: i64 = 30 * 60_000;
+
+        let nc = FakeNotecard::new();
+        let mut note = Notecarrier::new(nc.clone(), &mut SimDelay).unwrap();
+
+        let noteq: &'static mut Queue<AxlPacket, NOTEQ_SZ> = Box::leak(Box::new(Queue::new()));
+        let (mut note_p, mut note_c) = noteq.split();
+
+        let package = |t: i64| AxlPacket {
+            timestamp: 1_700_000_000_000 + t,
+            offset: 0,
+            storage_id: None,
+            storage_version: crate::axl::VERSION,
+            position_time: 1_700_000_000,
+            lon: 5.32,
+            lat: 60.39,
+            temperature: 12.,
+            freq: 52.,
+            accel_range: crate::waves::ACCEL_RANGE,
+            gyro_range: crate::waves::GYRO_RANGE,
+            burst_start: 0,
+            data: (0..AXL_SZ).map(|_| 0).collect(),
+        };
+
+        // The Notecard is full for half an hour, and then keeps up after it has synced.
+        nc.state().storage = 80;
+
+        let mut next = 0;
+        let mut last = 0;
+        let mut drained = Vec::new();
+
+        for t in (0..2 * SYNCED).step_by(1_000) {
+            if t == SYNCED {
+                assert_ne!(note.link_mode(), LinkMode::Full);
+                nc.state().storage = 8;
+            }
+
+            if t >= next {
+                note_p.enqueue(package(t)).ok();
+                next += PACKAGE;
+            }
+
+            if (t - last) > LOOP_DELAY
+                || ((note_c.capacity() - note_c.len()) < 3 && (t - last) > SHORT_LOOP_DELAY)
+            {
+                last = t;
+                let queue = note_c.len();
+                note.drain_queue(&mut note_c, &mut SimDelay).unwrap();
+
+                if t >= SYNCED {
+                    drained.push((queue, note.link_mode()));
+                }
+            }
+        }
+
+        // The note queue is never short when it is drained, but it is always emptied.
+        assert!(drained.iter().all(|(queue, _)| *queue >= DECIMATE_QUEUE));
+        assert!(note_c.len() < DECIMATE_QUEUE);
+
+        let full = drained
+            .iter()
+            .position(|(_, link)| *link == LinkMode::Full)
+            .unwrap();
+        assert!(full < BACKLOG_DRAINS);
+        assert!(drained[full..]
+            .iter()
+            .all(|(_, link)| *link == LinkMode::Full));
+
+        assert_eq!(nc.notes("axl.qo").last().unwrap().body["mode"], 0);
+    }
+}
@@ -0,0 +1,444 @@
+//! Simulated SD-card.
+//!
+//! The card speaks the SPI mode protocol byte by byte, enough for `embedded-sdmmc` to initialize
+//! it as an SDHC card and to read and write single and multiple blocks. The blocks are kept in
+//! memory, blocks that have not been written read as zeros.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use embedded_hal::{
+    blocking::spi::{Transfer, Write},
+    digital::v2::OutputPin,
+};
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+pub const BLOCK_SZ: usize = 512;
+
+/// First block of the FAT16 partition made by [`MockSd::formatted`].
+pub const PARTITION_START: u32 = 2048;
+
+/// Number of blocks in the FAT16 partition (64 MB).
+pub const PARTITION_BLOCKS: u32 = 131072;
+
+const DATA_START_BLOCK: u8 = 0xfe;
+const WRITE_MULTIPLE_TOKEN: u8 = 0xfc;
+const STOP_TRAN_TOKEN: u8 = 0xfd;
+const DATA_RES_ACCEPTED: u8 = 0x05;
+
+const R1_READY_STATE: u8 = 0x00;
+const R1_IDLE_STATE: u8 = 0x01;
+const R1_ILLEGAL_COMMAND: u8 = 0x04;
+
+type Block = [u8; BLOCK_SZ];
+
+enum Mode {
+    Idle,
+    ReadMultiple(u32),
+    WriteToken { block: u32, multiple: bool },
+    WriteData { block: u32, multiple: bool },
+}
+
+pub struct SdState {
+    blocks: HashMap<u32, Block>,
+    num_blocks: u32,
+
+    mode: Mode,
+    idle: bool,
+    app_cmd: bool,
+    cmd: Vec<u8>,
+    data: Vec<u8>,
+    out: VecDeque<u8>,
+}
+
+/// Handle to the simulated SD-card.
+#[derive(Clone)]
+pub struct MockSd(Rc<RefCell<SdState>>);
+
+impl MockSd {
+    /// A blank card.
+    pub fn new(num_blocks: u32) -> MockSd {
+        MockSd(Rc::new(RefCell::new(SdState {
+            blocks: HashMap::new(),
+            num_blocks,
+            mode: Mode::Idle,
+            idle: true,
+            app_cmd: false,
+            cmd: Vec::new(),
+            data: Vec::new(),
+            out: VecDeque::new(),
+        })))
+    }
+
+    /// A card with a single FAT16 partition.
+    pub fn formatted() -> MockSd {
+        let sd = MockSd::new(PARTITION_START + PARTITION_BLOCKS);
+
+        let reserved = 1;
+        let fats = 2;
+        let fat_blocks = 128;
+        let root_entries = 512u16;
+
+        let mut mbr = [0u8; BLOCK_SZ];
+        let p = &mut mbr[446..462];
+        p[4] = 0x06; // FAT16
+        p[8..12].copy_from_slice(&PARTITION_START.to_le_bytes());
+        p[12..16].copy_from_slice(&PARTITION_BLOCKS.to_le_bytes());
+        mbr[510..512].copy_from_slice(&[0x55, 0xaa]);
+        sd.write_block(0, &mbr);
+
+        let mut bpb = [0u8; BLOCK_SZ];
+        bpb[0..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+        bpb[3..11].copy_from_slice(b"SFYSIM  ");
+        bpb[11..13].copy_from_slice(&(BLOCK_SZ as u16).to_le_bytes());
+        bpb[13] = 4; // blocks per cluster
+        bpb[14..16].copy_from_slice(&(reserved as u16).to_le_bytes());
+        bpb[16] = fats as u8;
+        bpb[17..19].copy_from_slice(&root_entries.to_le_bytes());
+        bpb[21] = 0xf8; // fixed disk
+        bpb[22..24].copy_from_slice(&(fat_blocks as u16).to_le_bytes());
+        bpb[24..26].copy_from_slice(&63u16.to_le_bytes());
+        bpb[26..28].copy_from_slice(&255u16.to_le_bytes());
+        bpb[28..32].copy_from_slice(&PARTITION_START.to_le_bytes());
+        bpb[32..36].copy_from_slice(&PARTITION_BLOCKS.to_le_bytes());
+        bpb[36] = 0x80;
+        bpb[38] = 0x29;
+        bpb[39..43].copy_from_slice(&0x5f5fu32.to_le_bytes());
+        bpb[43..54].copy_from_slice(b"SFY SIM    ");
+        bpb[54..62].copy_from_slice(b"FAT16   ");
+        bpb[510..512].copy_from_slice(&[0x55, 0xaa]);
+        sd.write_block(PARTITION_START, &bpb);
+
+        // The first two entries of the FAT are reserved.
+        let mut fat = [0u8; BLOCK_SZ];
+        fat[0..4].copy_from_slice(&[0xf8, 0xff, 0xff, 0xff]);
+        for f in 0..fats {
+            sd.write_block(PARTITION_START + reserved + f * fat_blocks, &fat);
+        }
+
+        sd
+    }
+
+    pub fn write_block(&self, block: u32, data: &Block) {
+        self.0.borrow_mut().blocks.insert(block, *data);
+    }
+
+    pub fn read_block(&self, block: u32) -> Block {
+        self.0.borrow().read_block(block)
+    }
+
+    /// Number of blocks that have been written.
+    pub fn written_blocks(&self) -> usize {
+        self.0.borrow().blocks.len()
+    }
+
+    /// Flip the bits of the byte `offset` bytes after the first occurrence of `pattern` in a
+    /// block. Returns `false` if the pattern was not found.
+    pub fn corrupt(&self, pattern: &[u8], offset: usize) -> bool {
+        let mut state = self.0.borrow_mut();
+
+        for block in state.blocks.values_mut() {
+            if let Some(i) = block.windows(pattern.len()).position(|w| w == pattern) {
+                if let Some(b) = block.get_mut(i + offset) {
+                    *b ^= 0xff;
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+/// CRC16-CCITT (XMODEM) used for data blocks.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for b in data {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+impl SdState {
+    fn read_block(&self, block: u32) -> Block {
+        self.blocks.get(&block).copied().unwrap_or([0u8; BLOCK_SZ])
+    }
+
+    fn queue_data(&mut self, data: &[u8]) {
+        self.out.push_back(DATA_START_BLOCK);
+        self.out.extend(data);
+        self.out.extend(crc16(data).to_be_bytes());
+    }
+
+    /// Card specific data (version 2).
+    fn csd(&self) -> [u8; 16] {
+        let c_size = self.num_blocks / 1024 - 1;
+
+        let mut csd = [0u8; 16];
+        csd[0] = 0x40;
+        csd[7] = ((c_size >> 16) & 0x3f) as u8;
+        csd[8] = (c_size >> 8) as u8;
+        csd[9] = c_size as u8;
+        csd[15] = 0x01;
+        csd
+    }
+
+    fn command(&mut self, cmd: u8, arg: u32) {
+        let r1 = if self.idle {
+            R1_IDLE_STATE
+        } else {
+            R1_READY_STATE
+        };
+
+        if self.app_cmd {
+            self.app_cmd = false;
+
+            match cmd {
+                // SD_SEND_OP_COND
+                41 => {
+                    self.idle = false;
+                    self.out.push_back(R1_READY_STATE);
+                }
+                // SET_WR_BLK_ERASE_COUNT
+                23 => self.out.push_back(r1),
+                _ => self.out.push_back(r1 | R1_ILLEGAL_COMMAND),
+            }
+
+            return;
+        }
+
+        match cmd {
+            // GO_IDLE_STATE
+            0 => {
+                self.idle = true;
+                self.out.push_back(R1_IDLE_STATE);
+            }
+            // SEND_IF_COND
+            8 => {
+                self.out.push_back(r1);
+                self.out.extend([0x00, 0x00, 0x01, (arg & 0xff) as u8]);
+            }
+            // SEND_CSD
+            9 => {
+                self.out.push_back(r1);
+                let csd = self.csd();
+                self.queue_data(&csd);
+            }
+            // STOP_TRANSMISSION: stuff byte first.
+            12 => self.out.extend([0xff, r1]),
+            // SEND_STATUS
+            13 => self.out.extend([r1, 0x00]),
+            // SET_BLOCKLEN
+            16 => self.out.push_back(r1),
+            // READ_SINGLE_BLOCK
+            17 => {
+                self.out.push_back(r1);
+                let block = self.read_block(arg);
+                self.queue_data(&block);
+            }
+            // READ_MULTIPLE_BLOCK
+            18 => {
+                self.out.push_back(r1);
+                self.mode = Mode::ReadMultiple(arg);
+            }
+            // WRITE_BLOCK
+            24 => {
+                self.out.push_back(r1);
+                self.mode = Mode::WriteToken {
+                    block: arg,
+                    multiple: false,
+                };
+            }
+            // WRITE_MULTIPLE_BLOCK
+            25 => {
+                self.out.push_back(r1);
+                self.mode = Mode::WriteToken {
+                    block: arg,
+                    multiple: true,
+                };
+            }
+            // APP_CMD
+            55 => {
+                self.app_cmd = true;
+                self.out.push_back(r1);
+            }
+            // READ_OCR: powered up, high capacity.
+            58 => {
+                self.out.push_back(r1);
+                self.out.extend([0xc0, 0xff, 0x80, 0x00]);
+            }
+            // CRC_ON_OFF
+            59 => self.out.push_back(r1),
+            _ => self.out.push_back(r1 | R1_ILLEGAL_COMMAND),
+        }
+    }
+
+    fn input(&mut self, b: u8) {
+        match self.mode {
+            Mode::Idle | Mode::ReadMultiple(_) => {
+                if !self.cmd.is_empty() {
+                    self.cmd.push(b);
+
+                    if self.cmd.len() == 6 {
+                        let cmd = self.cmd[0] & 0x3f;
+                        let arg = u32::from_be_bytes([
+                            self.cmd[1],
+                            self.cmd[2],
+                            self.cmd[3],
+                            self.cmd[4],
+                        ]);
+                        self.cmd.clear();
+                        self.command(cmd, arg);
+                    }
+                } else if b & 0xc0 == 0x40 {
+                    // A new command ends a multiple block read.
+                    if let Mode::ReadMultiple(_) = self.mode {
+                        self.mode = Mode::Idle;
+                        self.out.clear();
+                    }
+
+                    self.cmd.push(b);
+                }
+            }
+            Mode::WriteToken { block, multiple } => match b {
+                DATA_START_BLOCK | WRITE_MULTIPLE_TOKEN => {
+                    self.data.clear();
+                    self.mode = Mode::WriteData { block, multiple };
+                }
+                STOP_TRAN_TOKEN if multiple => self.mode = Mode::Idle,
+                _ => {}
+            },
+            Mode::WriteData { block, multiple } => {
+                self.data.push(b);
+
+                if self.data.len() == BLOCK_SZ + 2 {
+                    let mut data = [0u8; BLOCK_SZ];
+                    data.copy_from_slice(&self.data[..BLOCK_SZ]);
+
+                    let crc = u16::from_be_bytes([self.data[BLOCK_SZ], self.data[BLOCK_SZ + 1]]);
+                    assert_eq!(crc, crc16(&data), "bad crc in written block: {}", block);
+
+                    assert!(block < self.num_blocks, "block out of range: {}", block);
+                    self.blocks.insert(block, data);
+                    self.out.push_back(DATA_RES_ACCEPTED);
+
+                    self.mode = if multiple {
+                        Mode::WriteToken {
+                            block: block + 1,
+                            multiple,
+                        }
+                    } else {
+                        Mode::Idle
+                    };
+                }
+            }
+        }
+    }
+
+    /// Exchange a byte. The response to a command is available from the next exchange.
+    fn exchange(&mut self, b: u8) -> u8 {
+        if self.out.is_empty() {
+            if let Mode::ReadMultiple(block) = self.mode {
+                let data = self.read_block(block);
+                self.queue_data(&data);
+                self.mode = Mode::ReadMultiple(block + 1);
+            }
+        }
+
+        let o = self.out.pop_front().unwrap_or(0xff);
+        self.input(b);
+        o
+    }
+}
+
+impl Transfer<u8> for MockSd {
+    type Error = Infallible;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Infallible> {
+        let mut s = self.0.borrow_mut();
+        for w in words.iter_mut() {
+            *w = s.exchange(*w);
+        }
+
+        Ok(words)
+    }
+}
+
+impl Write<u8> for MockSd {
+    type Error = Infallible;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+        let mut s = self.0.borrow_mut();
+        for w in words {
+            s.exchange(*w);
+        }
+
+        Ok(())
+    }
+}
+
+/// Chip select of the card, the card is always selected.
+pub struct MockCs;
+
+impl OutputPin for MockCs {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "storage"))]
+mod tests {
+    use super::*;
+    use crate::sim::SimDelay;
+    use crate::storage::{clock::CountClock, collection_fname, Storage, COLLECTION_SIZE};
+    use core::sync::atomic::AtomicI32;
+
+    static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc16(&[0u8; 512]), 0x0000);
+        assert_eq!(crc16(&[0xffu8; 512]), 0x7fa1);
+    }
+
+    #[test]
+    fn new_collection_on_restart() {
+        let sd = MockSd::formatted();
+
+        let open = |sd: &MockSd| {
+            let mut storage =
+                Storage::open(sd.clone(), MockCs, CountClock(&COUNT), |_, _| {}, SimDelay);
+            storage.acquire().unwrap();
+            storage
+        };
+
+        let mut storage = open(&sd);
+        assert_eq!(storage.next_id(), Some(0));
+
+        storage
+            .acquire()
+            .unwrap()
+            .write(&collection_fname(0), &[1, 2, 3], &[])
+            .unwrap();
+        drop(storage);
+
+        assert!(sd.written_blocks() > 4);
+
+        // Every start-up uses a new collection.
+        let storage = open(&sd);
+        assert_eq!(storage.next_id(), Some(COLLECTION_SIZE));
+    }
+}
@@ -0,0 +1,551 @@
+//! Wave spectrum and bulk wave parameters computed on the buoy.
+//!
+//! The filtered and decimated acceleration of each [`AxlPacket`] (rotated to the earth frame by
+//! the AHRS) is windowed and Fourier transformed for the frequencies up to [`F_MAX`]. The auto-
+//! and cross-spectra are averaged over about [`SPEC_PERIOD`] seconds of packages before the
+//! displacement spectrum and the bulk parameters are returned as a [`SpecPacket`], which is sent
+//! as the much smaller `spec.qo` note. The number of packages and bins depend on the output
+//! frequency of the packages, a change in output frequency starts a new spectrum.
+//!
+//! The directional moments `a1`, `b1`, `a2` and `b2` (the first five Fourier coefficients of the
+//! directional distribution, together with the spectrum) are computed for each bin from the
+//! co- and quad-spectra of the vertical and horizontal accelerations, as for a heave-pitch-roll
+//! buoy (Longuet-Higgins et al., 1963; Kuik et al., 1988).
+//!
+//! There is no magnetometer, so the horizontal axes are in the frame of the AHRS: the mean
+//! direction and the directional moments are relative to the x-axis of this frame, not to north.
+//! The directional spreading does not depend on the orientation of the frame.
+
+use core::f32::consts::PI;
+use heapless::Vec;
+
+use crate::axl::{AxlPacket, SAMPLE_NO, SAMPLE_SZ};
+use crate::waves::wire::scale_u16_to_f32;
+use crate::waves::{MIN_OUTPUT_FREQ, OUTPUT_FREQ};
+
+/// Length of time the spectrum is averaged over [s].
+pub const SPEC_PERIOD: f32 = 20. * 60.;
+
+/// Number of packages averaged in each spectrum at the default output frequency.
+pub const SPEC_PACKAGES: usize = (SPEC_PERIOD * OUTPUT_FREQ / SAMPLE_NO as f32) as usize;
+
+/// Highest frequency in the spectrum [Hz].
+pub const F_MAX: f32 = 1.0;
+
+/// Lowest frequency used for the bulk parameters [Hz]. Below this the displacement is dominated
+/// by noise in the acceleration.
+pub const F_MIN: f32 = 0.05;
+
+/// Maximum number of frequency bins in the spectrum, at the lowest output frequency. The first bin
+/// is at `df = freq / SAMPLE_NO`.
+pub const SPEC_BINS: usize = (F_MAX * SAMPLE_NO as f32 / MIN_OUTPUT_FREQ) as usize;
+
+/// Number of packages averaged in each spectrum at output frequency `freq`.
+pub fn packages(freq: f32) -> usize {
+    (SPEC_PERIOD * freq / SAMPLE_NO as f32) as usize
+}
+
+/// Number of frequency bins up to [`F_MAX`] at output frequency `freq`.
+pub fn bins(freq: f32) -> usize {
+    ((F_MAX * SAMPLE_NO as f32 / freq) as usize).min(SPEC_BINS)
+}
+
+/// Spectrum and bulk parameters sent in the `spec.qo` note.
+#[derive(serde::Serialize, Default, Debug, PartialEq)]
+pub struct SpecPacket {
+    /// Timestamp of the first package in the spectrum [ms].
+    pub timestamp: i64,
+
+    /// Number of packages averaged.
+    pub packages: u32,
+
+    /// Time of position in seconds.
+    pub position_time: u32,
+    pub lon: f64,
+    pub lat: f64,
+
+    /// Sample frequency of the acceleration [Hz].
+    pub freq: f32,
+
+    /// Frequency resolution, bin `i` of `spec` is at `(i + 1) * df` [Hz].
+    pub df: f32,
+
+    /// Significant wave height [m].
+    pub hs: f32,
+
+    /// Peak period [s].
+    pub tp: f32,
+
+    /// Mean periods [s].
+    pub tm01: f32,
+    pub tm02: f32,
+
+    /// Mean direction the waves are propagating towards, counter-clockwise from the x-axis of the
+    /// AHRS frame [deg].
+    pub mdir: f32,
+
+    /// Mean directional spreading [deg].
+    pub spread: f32,
+
+    /// Vertical displacement spectrum [m^2/Hz].
+    pub spec: Vec<f32, SPEC_BINS>,
+
+    /// Directional moments for each bin in `spec`.
+    pub a1: Vec<f32, SPEC_BINS>,
+    pub b1: Vec<f32, SPEC_BINS>,
+    pub a2: Vec<f32, SPEC_BINS>,
+    pub b2: Vec<f32, SPEC_BINS>,
+}
+
+/// Accumulates the spectra of the packages until enough packages have been collected.
+pub struct Spectrum {
+    /// `cos(2 pi i / N)` for the first quarter of the period.
+    cos: [f32; SAMPLE_NO / 4 + 1],
+
+    packages: u32,
+    timestamp: i64,
+
+    /// Output frequency of the accumulated packages.
+    freq: f32,
+
+    czz: [f32; SPEC_BINS],
+    cxx: [f32; SPEC_BINS],
+    cyy: [f32; SPEC_BINS],
+    cxy: [f32; SPEC_BINS],
+    qzx: [f32; SPEC_BINS],
+    qzy: [f32; SPEC_BINS],
+}
+
+impl Spectrum {
+    pub fn new() -> Spectrum {
+        let mut cos = [0f32; SAMPLE_NO / 4 + 1];
+        for (i, c) in cos.iter_mut().enumerate() {
+            *c = libm::cosf(2. * PI * i as f32 / SAMPLE_NO as f32);
+        }
+
+        Spectrum {
+            cos,
+            packages: 0,
+            timestamp: 0,
+            freq: OUTPUT_FREQ,
+            czz: [0.; SPEC_BINS],
+            cxx: [0.; SPEC_BINS],
+            cyy: [0.; SPEC_BINS],
+            cxy: [0.; SPEC_BINS],
+            qzx: [0.; SPEC_BINS],
+            qzy: [0.; SPEC_BINS],
+        }
+    }
+
+    /// Number of packages accumulated in the current spectrum.
+    pub fn len(&self) -> usize {
+        self.packages as usize
+    }
+
+    pub fn reset(&mut self) {
+        self.packages = 0;
+        self.czz = [0.; SPEC_BINS];
+        self.cxx = [0.; SPEC_BINS];
+        self.cyy = [0.; SPEC_BINS];
+        self.cxy = [0.; SPEC_BINS];
+        self.qzx = [0.; SPEC_BINS];
+        self.qzy = [0.; SPEC_BINS];
+    }
+
+    /// `(cos, sin)` of `2 pi i / N`.
+    fn cos_sin(&self, i: usize) -> (f32, f32) {
+        const Q: usize = SAMPLE_NO / 4;
+
+        let i = i % SAMPLE_NO;
+        match i / Q {
+            0 => (self.cos[i], self.cos[Q - i]),
+            1 => (-self.cos[2 * Q - i], self.cos[i - Q]),
+            2 => (-self.cos[i - 2 * Q], -self.cos[3 * Q - i]),
+            _ => (self.cos[4 * Q - i], -self.cos[i - 3 * Q]),
+        }
+    }
+
+    /// Add the spectrum of a package. Returns the averaged spectrum when enough packages (see
+    /// [`packages`]) have been accumulated, and starts over on a new spectrum.
+    pub fn sample(&mut self, pck: &AxlPacket) -> Option<SpecPacket> {
+        if pck.data.len() != SAMPLE_NO * SAMPLE_SZ {
+            defmt::warn!(
+                "spectrum: package has {} values, expected {}: skipping.",
+                pck.data.len(),
+                SAMPLE_NO * SAMPLE_SZ
+            );
+            return None;
+        }
+
+        if self.packages > 0 && pck.freq != self.freq {
+            defmt::warn!(
+                "spectrum: output frequency changed: {} -> {}, starting over.",
+                self.freq,
+                pck.freq
+            );
+            self.reset();
+        }
+        self.freq = pck.freq;
+        let bins = bins(pck.freq);
+
+        let max = pck.accel_max();
+        let value = |i: usize| scale_u16_to_f32(max, pck.data[i]);
+
+        // The mean leaks into the lowest bins through the window.
+        let mut mean = [0f32; SAMPLE_SZ];
+        for (i, m) in mean.iter_mut().enumerate() {
+            *m = (0..SAMPLE_NO)
+                .map(|n| value(n * SAMPLE_SZ + i))
+                .sum::<f32>()
+                / SAMPLE_NO as f32;
+        }
+
+        // Fourier coefficients (re, im) of x, y and z for each bin.
+        let mut fx = [(0f32, 0f32); SPEC_BINS];
+        let mut fy = [(0f32, 0f32); SPEC_BINS];
+        let mut fz = [(0f32, 0f32); SPEC_BINS];
+
+        for n in 0..SAMPLE_NO {
+            // Hann window.
+            let w = 0.5 - 0.5 * self.cos_sin(n).0;
+
+            let x = (value(n * SAMPLE_SZ) - mean[0]) * w;
+            let y = (value(n * SAMPLE_SZ + 1) - mean[1]) * w;
+            let z = (value(n * SAMPLE_SZ + 2) - mean[2]) * w;
+
+            for b in 0..bins {
+                let (c, s) = self.cos_sin((b + 1) * n);
+
+                fx[b].0 += x * c;
+                fx[b].1 -= x * s;
+                fy[b].0 += y * c;
+                fy[b].1 -= y * s;
+                fz[b].0 += z * c;
+                fz[b].1 -= z * s;
+            }
+        }
+
+        for b in 0..bins {
+            let (xr, xi) = fx[b];
+            let (yr, yi) = fy[b];
+            let (zr, zi) = fz[b];
+
+            self.czz[b] += zr * zr + zi * zi;
+            self.cxx[b] += xr * xr + xi * xi;
+            self.cyy[b] += yr * yr + yi * yi;
+            self.cxy[b] += xr * yr + xi * yi;
+            self.qzx[b] += zi * xr - zr * xi;
+            self.qzy[b] += zi * yr - zr * yi;
+        }
+
+        if self.packages == 0 {
+            self.timestamp = pck.timestamp;
+        }
+        self.packages += 1;
+
+        if self.packages as usize >= packages(pck.freq) {
+            let spec = self.spectrum(pck);
+            self.reset();
+            Some(spec)
+        } else {
+            None
+        }
+    }
+
+    /// Compute the displacement spectrum and bulk parameters from the accumulated spectra.
+    fn spectrum(&self, pck: &AxlPacket) -> SpecPacket {
+        let fs = pck.freq;
+        let df = fs / SAMPLE_NO as f32;
+
+        // One-sided spectral density of the Hann-windowed (sum of w^2 is 3N/8) series, averaged
+        // over the packages.
+        let scale = 2. / (fs * 3. * SAMPLE_NO as f32 / 8. * self.packages as f32);
+
+        let mut spec = Vec::new();
+        let (mut a1, mut b1, mut a2, mut b2) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let (mut m0, mut m1, mut m2) = (0f32, 0f32, 0f32);
+        let (mut qzx, mut qzy) = (0f32, 0f32);
+        let (mut ma1, mut mb1) = (0f32, 0f32);
+        let mut peak = (0f32, 0f32);
+
+        for b in 0..bins(fs) {
+            let f = (b + 1) as f32 * df;
+
+            // Acceleration to displacement.
+            let w4 = libm::powf(2. * PI * f, 4.);
+            let s = self.czz[b] * scale / w4;
+            spec.push(s).unwrap();
+
+            let m = self.moments(b);
+            a1.push(m[0]).unwrap();
+            b1.push(m[1]).unwrap();
+            a2.push(m[2]).unwrap();
+            b2.push(m[3]).unwrap();
+
+            if f < F_MIN {
+                continue;
+            }
+
+            m0 += s * df;
+            m1 += f * s * df;
+            m2 += f * f * s * df;
+
+            qzx += self.qzx[b];
+            qzy += self.qzy[b];
+
+            if !m[0].is_nan() {
+                ma1 += m[0] * s * df;
+                mb1 += m[1] * s * df;
+            }
+
+            if s > peak.1 {
+                peak = (f, s);
+            }
+        }
+
+        let period = |m: f32| if m > 0. { m } else { f32::NAN };
+
+        // Circular spreading from the energy-weighted mean of a1 and b1.
+        let r1 = libm::sqrtf(ma1 * ma1 + mb1 * mb1) / m0;
+        let spread = libm::sqrtf(2. * (1. - r1.min(1.))).to_degrees();
+
+        SpecPacket {
+            timestamp: self.timestamp,
+            packages: self.packages,
+            position_time: pck.position_time,
+            lon: pck.lon,
+            lat: pck.lat,
+            freq: fs,
+            df,
+            hs: 4. * libm::sqrtf(m0),
+            tp: 1. / period(peak.0),
+            tm01: m0 / period(m1),
+            tm02: libm::sqrtf(m0 / period(m2)),
+            mdir: libm::atan2f(qzy, qzx).to_degrees(),
+            spread,
+            spec,
+            a1,
+            b1,
+            a2,
+            b2,
+        }
+    }
+
+    /// Directional moments `[a1, b1, a2, b2]` of bin `b`. These are NaN when there is no energy
+    /// in the bin.
+    fn moments(&self, b: usize) -> [f32; 4] {
+        let h = self.cxx[b] + self.cyy[b];
+        let d = libm::sqrtf(self.czz[b] * h);
+
+        if h > 0. && d > 0. {
+            [
+                self.qzx[b] / d,
+                self.qzy[b] / d,
+                (self.cxx[b] - self.cyy[b]) / h,
+                2. * self.cxy[b] / h,
+            ]
+        } else {
+            [f32::NAN; 4]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waves::wire::{ScaledF32, A16};
+    use crate::waves::ACCEL_RANGE;
+
+    /// Packages with a wave of amplitude `a` [m] at frequency bin `k`, propagating towards
+    /// `dir` [deg].
+    fn wave(k: usize, a: f32, dir: f32) -> impl Iterator<Item = AxlPacket> {
+        wave_at(OUTPUT_FREQ, k, a, dir)
+    }
+
+    /// Like `wave`, with output frequency `fs`.
+    fn wave_at(fs: f32, k: usize, a: f32, dir: f32) -> impl Iterator<Item = AxlPacket> {
+        let f = k as f32 * fs / SAMPLE_NO as f32;
+        let w = 2. * PI * f;
+        let dir = dir.to_radians();
+
+        (0..).map(move |p| {
+            let data = (0..SAMPLE_NO)
+                .flat_map(|n| {
+                    let t = (p * SAMPLE_NO + n) as f32 / fs;
+
+                    // Vertical displacement is `a cos(wt)`, and the horizontal displacement is
+                    // `a sin(wt)` in the direction of propagation.
+                    let z = -w * w * a * libm::cosf(w * t);
+                    let h = -w * w * a * libm::sinf(w * t);
+
+                    [h * libm::cosf(dir), h * libm::sinf(dir), z]
+                })
+                .map(|v| A16::from_f32(v).to_u16())
+                .collect();
+
+            AxlPacket {
+                timestamp: 1_700_000_000_000 + (p * 20_000) as i64,
+                offset: 0,
+                storage_id: None,
+                storage_version: 0,
+                position_time: 1_700_000_000,
+                lon: 5.32,
+                lat: 60.39,
+                temperature: 12.,
+                freq: fs,
+                accel_range: ACCEL_RANGE,
+                gyro_range: 0.,
+                burst_start: 0,
+                data,
+            }
+        })
+    }
+
+    #[test]
+    fn cos_sin() {
+        let s = Spectrum::new();
+
+        for i in 0..(2 * SAMPLE_NO) {
+            let (c, sn) = s.cos_sin(i);
+            let a = 2. * PI * i as f32 / SAMPLE_NO as f32;
+            assert!((c - libm::cosf(a)).abs() < 1e-5, "cos: {i}");
+            assert!((sn - libm::sinf(a)).abs() < 1e-5, "sin: {i}");
+        }
+    }
+
+    #[test]
+    fn averaged_over_packages() {
+        let mut s = Spectrum::new();
+        let mut w = wave(10, 1., 0.);
+
+        for _ in 0..(SPEC_PACKAGES - 1) {
+            assert!(s.sample(&w.next().unwrap()).is_none());
+        }
+        assert_eq!(s.len(), SPEC_PACKAGES - 1);
+
+        let spec = s.sample(&w.next().unwrap()).unwrap();
+        assert_eq!(spec.packages as usize, SPEC_PACKAGES);
+        assert_eq!(spec.timestamp, 1_700_000_000_000);
+        assert_eq!(spec.spec.len(), bins(OUTPUT_FREQ));
+        assert_eq!(s.len(), 0);
+    }
+
+    #[test]
+    fn lowest_output_freq() {
+        let k = 20;
+        let f = k as f32 * MIN_OUTPUT_FREQ / SAMPLE_NO as f32;
+
+        let mut s = Spectrum::new();
+        let spec = wave_at(MIN_OUTPUT_FREQ, k, 1., 0.)
+            .find_map(|p| s.sample(&p))
+            .unwrap();
+
+        assert_eq!(spec.packages as usize, packages(MIN_OUTPUT_FREQ));
+        assert_eq!(spec.spec.len(), SPEC_BINS);
+        assert_eq!(spec.freq, MIN_OUTPUT_FREQ);
+        assert!((spec.tp - 1. / f).abs() < 1e-3, "tp: {}", spec.tp);
+    }
+
+    #[test]
+    fn output_freq_changed() {
+        let mut s = Spectrum::new();
+        let mut w = wave(10, 1., 0.);
+
+        for _ in 0..10 {
+            assert!(s.sample(&w.next().unwrap()).is_none());
+        }
+
+        let mut w = wave_at(MIN_OUTPUT_FREQ, 10, 1., 0.);
+        assert!(s.sample(&w.next().unwrap()).is_none());
+        assert_eq!(s.len(), 1);
+    }
+
+    #[test]
+    fn monochromatic_wave() {
+        let k = 10;
+        let a = 1.;
+        let f = k as f32 * OUTPUT_FREQ / SAMPLE_NO as f32;
+
+        let mut s = Spectrum::new();
+        let spec = wave(k, a, 90.).find_map(|p| s.sample(&p)).unwrap();
+
+        // Hs = 4 sqrt(a^2 / 2), the energy leaks into the neighbouring bins through the window.
+        let hs = 4. * libm::sqrtf(a * a / 2.);
+        assert!((spec.hs - hs).abs() / hs < 0.05, "hs: {}", spec.hs);
+
+        assert!((spec.tp - 1. / f).abs() < 1e-3, "tp: {}", spec.tp);
+        assert!((spec.tm01 - 1. / f).abs() / (1. / f) < 0.05);
+        assert!((spec.tm02 - 1. / f).abs() / (1. / f) < 0.05);
+        assert!((spec.mdir - 90.).abs() < 2., "mdir: {}", spec.mdir);
+
+        let peak = spec
+            .spec
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        assert_eq!(peak.0 + 1, k);
+
+        // Waves from a single direction have no spreading.
+        assert!(spec.spread < 5., "spread: {}", spec.spread);
+    }
+
+    #[test]
+    fn directional_moments() {
+        let k = 10;
+
+        for dir in [0f32, 30., 90., -150.] {
+            let mut s = Spectrum::new();
+            let spec = wave(k, 1., dir).find_map(|p| s.sample(&p)).unwrap();
+
+            let t = dir.to_radians();
+            let expected = [
+                libm::cosf(t),
+                libm::sinf(t),
+                libm::cosf(2. * t),
+                libm::sinf(2. * t),
+            ];
+            let m = [
+                spec.a1[k - 1],
+                spec.b1[k - 1],
+                spec.a2[k - 1],
+                spec.b2[k - 1],
+            ];
+
+            for (m, e) in m.iter().zip(expected) {
+                assert!((m - e).abs() < 0.02, "{dir}: {m:?} != {expected:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn spreading() {
+        // Every other package has waves towards 0 and 90 degrees. Then a1 = b1 = 1/2, and the
+        // spreading is sqrt(2 (1 - sqrt(1/2))).
+        let mut s = Spectrum::new();
+        let spec = wave(10, 1., 0.)
+            .zip(wave(10, 1., 90.))
+            .enumerate()
+            .map(|(i, (p, q))| if i % 2 == 0 { p } else { q })
+            .find_map(|p| s.sample(&p))
+            .unwrap();
+
+        let spread = libm::sqrtf(2. * (1. - libm::sqrtf(0.5))).to_degrees();
+        assert!((spec.mdir - 45.).abs() < 2., "mdir: {}", spec.mdir);
+        assert!((spec.spread - spread).abs() < 2., "spread: {}", spec.spread);
+    }
+
+    #[test]
+    fn direction() {
+        for dir in [0., 45., 135., -120.] {
+            let mut s = Spectrum::new();
+            let spec = wave(8, 0.5, dir).find_map(|p| s.sample(&p)).unwrap();
+            assert!(
+                (spec.mdir - dir).abs() < 2.,
+                "mdir: {} != {}",
+                spec.mdir,
+                dir
+            );
+        }
+    }
+}
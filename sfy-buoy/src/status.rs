@@ -0,0 +1,304 @@
+//! Periodic health and status of the buoy, sent as `status.qo`.
+//!
+//! The counters are updated from the IMU interrupt, and the note is put together in the main loop
+//! every `STATUS_PERIOD` of RTC time.
+
+use core::fmt::Debug;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::watchdog::ResetReason;
+use crate::waves::ImuError;
+
+/// Interval between status notes [ms]. Checked once per iteration of the main loop, so a note may be
+/// late by up to one (possibly sleeping) loop delay.
+pub const STATUS_PERIOD: u32 = 60 * 60 * 1000;
+
+/// A longer step of the RTC between two iterations of the main loop [ms], or a step backwards, is
+/// taken to be the RTC being set (e.g. from 2020-01-01 at start-up to the time from the Notecard).
+pub const MAX_LOOP_STEP: u32 = STATUS_PERIOD;
+
+/// Counters updated by the IMU interrupt.
+pub struct Counters {
+    fifo_overrun: AtomicU32,
+    too_few_samples: AtomicU32,
+
+    /// Bits of the last IMU temperature (`f32`).
+    imu_temperature: AtomicU32,
+}
+
+impl Counters {
+    pub const fn new() -> Counters {
+        Counters {
+            fifo_overrun: AtomicU32::new(0),
+            too_few_samples: AtomicU32::new(0),
+            imu_temperature: AtomicU32::new(0),
+        }
+    }
+
+    pub fn imu_error<E: Debug>(&self, e: &ImuError<E>) {
+        match e {
+            ImuError::FifoOverrun { .. } => {
+                self.fifo_overrun.fetch_add(1, Ordering::Relaxed);
+            }
+            ImuError::TooFewSamples(_) => {
+                self.too_few_samples.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn set_imu_temperature(&self, t: f32) {
+        self.imu_temperature.store(t.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn fifo_overrun(&self) -> u32 {
+        self.fifo_overrun.load(Ordering::Relaxed)
+    }
+
+    pub fn too_few_samples(&self) -> u32 {
+        self.too_few_samples.load(Ordering::Relaxed)
+    }
+
+    pub fn imu_temperature(&self) -> f32 {
+        f32::from_bits(self.imu_temperature.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for Counters {
+    fn default() -> Counters {
+        Counters::new()
+    }
+}
+
+pub static COUNTERS: Counters = Counters::new();
+
+/// Fill levels of the queues.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Queues {
+    pub storage: usize,
+    pub note: usize,
+    pub egps: usize,
+}
+
+/// The body of `status.qo`. The Notecard drops fields that are zero in templated notes.
+#[derive(serde::Serialize, Default, Debug, Clone, PartialEq, defmt::Format)]
+pub struct StatusNote {
+    /// Time since start of main loop [s].
+    pub uptime: u32,
+
+    /// Resets since last power-on.
+    pub resets: u32,
+    pub reset_reason: &'static str,
+
+    /// Supply voltage from `card.voltage` [V].
+    pub voltage: f32,
+
+    /// Temperature of the IMU [C].
+    pub imu_temperature: f32,
+
+    /// Next storage ID on the SD-card, missing if the SD-card is not available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_id: Option<u32>,
+
+    /// Estimated free space on the SD-card [MB].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sd_free: Option<u32>,
+
+    pub storage_queue: u32,
+    pub note_queue: u32,
+    pub egps_queue: u32,
+
+    pub fifo_overrun: u32,
+    pub too_few_samples: u32,
+}
+
+/// Keeps track of when to send the status note.
+pub struct Status {
+    /// Start of the main loop [ms], moved along when the RTC is set.
+    started: i64,
+
+    /// Time of the last tick [ms].
+    seen: i64,
+
+    /// Time of the last status note [ms], `None` until the first note.
+    last: Option<i64>,
+    resets: u32,
+    reset_reason: ResetReason,
+}
+
+impl Status {
+    /// `now` is the time at the start of the main loop [ms].
+    pub fn new(now: i64, resets: u32, reset_reason: ResetReason) -> Status {
+        Status {
+            started: now,
+            seen: now,
+            last: None,
+            resets,
+            reset_reason,
+        }
+    }
+
+    /// Returns true if a status note should be sent at `now` [ms]: on the first call, and then when
+    /// `STATUS_PERIOD` has elapsed since the last note. If the RTC is not set (`None`) only the
+    /// first note is sent. A clock that jumps backwards (e.g. when the RTC is set from the GPS)
+    /// restarts the period.
+    ///
+    /// The uptime is counted from the start of the main loop, and is kept when the RTC is set
+    /// (see [`MAX_LOOP_STEP`]).
+    pub fn tick(&mut self, now: Option<i64>) -> bool {
+        if let Some(now) = now {
+            let step = now - self.seen;
+            if step < 0 || step > MAX_LOOP_STEP as i64 {
+                self.started += step;
+            }
+            self.seen = now;
+        }
+
+        let send = match (self.last, now) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(last), Some(now)) => now < last || now - last >= STATUS_PERIOD as i64,
+        };
+
+        if send {
+            self.last = Some(now.unwrap_or(0));
+        }
+
+        send
+    }
+
+    /// The status without voltage and storage, which are filled in by the `Notecarrier` and
+    /// `StorageManager`.
+    pub fn note(&self, now: i64, queues: Queues) -> StatusNote {
+        StatusNote {
+            uptime: ((now - self.started).max(0) / 1000) as u32,
+            resets: self.resets,
+            reset_reason: self.reset_reason.as_str(),
+            voltage: 0.0,
+            imu_temperature: COUNTERS.imu_temperature(),
+            next_id: None,
+            sd_free: None,
+            storage_queue: queues.storage as u32,
+            note_queue: queues.note as u32,
+            egps_queue: queues.egps as u32,
+            fifo_overrun: COUNTERS.fifo_overrun(),
+            too_few_samples: COUNTERS.too_few_samples(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick() {
+        let mut s = Status::new(0, 0, ResetReason::PowerOn);
+        let p = STATUS_PERIOD as i64;
+
+        // Main loop while bursting (280 s) and while sleeping between bursts (15 min).
+        let sent: std::vec::Vec<i64> = (0..40)
+            .map(|i| {
+                if i < 20 {
+                    i * 280_000
+                } else {
+                    20 * 280_000 + (i - 20) * 15 * 60_000
+                }
+            })
+            .filter(|t| s.tick(Some(*t)))
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                0,
+                13 * 280_000,
+                20 * 280_000 + 2 * 15 * 60_000,
+                20 * 280_000 + 6 * 15 * 60_000,
+                20 * 280_000 + 10 * 15 * 60_000,
+                20 * 280_000 + 14 * 15 * 60_000,
+                20 * 280_000 + 18 * 15 * 60_000
+            ]
+        );
+        assert!(sent.windows(2).all(|w| w[1] - w[0] >= p));
+
+        // No RTC.
+        assert!(!s.tick(None));
+
+        // Clock set backwards.
+        assert!(s.tick(Some(1_000)));
+        assert!(!s.tick(Some(1_000 + p - 1)));
+        assert!(s.tick(Some(1_000 + p)));
+
+        let mut s = Status::new(0, 0, ResetReason::PowerOn);
+        assert!(s.tick(None));
+        assert!(!s.tick(None));
+    }
+
+    #[test]
+    fn uptime_rtc_set() {
+        let q = Queues::default();
+
+        // The RTC starts at 2020-01-01 and is set from the Notecard after a minute.
+        let rtc = 1_577_836_800_000;
+        let mut s = Status::new(rtc, 0, ResetReason::PowerOn);
+        s.tick(Some(rtc + 60_000));
+        assert_eq!(s.note(rtc + 60_000, q).uptime, 60);
+
+        let now = 1_790_000_000_000;
+        s.tick(Some(now));
+        assert_eq!(s.note(now, q).uptime, 60);
+
+        s.tick(Some(now + 15 * 60_000));
+        assert_eq!(s.note(now + 15 * 60_000, q).uptime, 60 + 15 * 60);
+
+        // Set backwards.
+        s.tick(Some(now));
+        assert_eq!(s.note(now, q).uptime, 60 + 15 * 60);
+
+        // RTC not available.
+        s.tick(None);
+        s.tick(Some(now + 60_000));
+        assert_eq!(s.note(now + 60_000, q).uptime, 2 * 60 + 15 * 60);
+    }
+
+    #[test]
+    fn counters() {
+        let c = Counters::new();
+        c.imu_error::<()>(&ImuError::TooFewSamples(4000));
+        c.imu_error::<()>(&ImuError::FifoOverrun {
+            fifo_full: true,
+            overrun: false,
+            latched: false,
+            samples: 512,
+            buffer: 0,
+        });
+        c.imu_error::<()>(&ImuError::TooFewSamples(4000));
+        c.imu_error(&ImuError::I2C(()));
+        c.set_imu_temperature(21.5);
+
+        assert_eq!(c.too_few_samples(), 2);
+        assert_eq!(c.fifo_overrun(), 1);
+        assert_eq!(c.imu_temperature(), 21.5);
+    }
+
+    #[test]
+    fn status_note() {
+        let s = Status::new(1_000, 2, ResetReason::Watchdog);
+        let n = s.note(
+            3_601_000,
+            Queues {
+                storage: 1,
+                note: 4,
+                egps: 0,
+            },
+        );
+
+        assert_eq!(n.uptime, 3600);
+        assert_eq!(n.resets, 2);
+        assert_eq!(n.note_queue, 4);
+
+        let json = serde_json::to_string(&n).unwrap();
+        assert!(json.contains(r#""reset_reason":"watchdog""#));
+        assert!(!json.contains("next_id"));
+    }
+}
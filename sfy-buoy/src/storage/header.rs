@@ -0,0 +1,205 @@
+//! Header in front of every package in a collection file (since storage version 9).
+//!
+//! The header is [`HEADER_SZ`] bytes, all fields are little endian `u32`s:
+//!
+//! * magic ([`MAGIC`]),
+//! * storage ID of the package,
+//! * length of the COBS-framed package,
+//! * length of the raw values (0 without the `raw` feature),
+//! * CRC32 of the storage ID, the lengths and the package,
+//! * CRC32 of the raw values.
+//!
+//! The package (padded to `AXL_POSTCARD_SZ`) and the raw values follow the header. The raw values
+//! have their own checksum so that the package can be checked without reading them.
+
+use crate::axl::AXL_POSTCARD_SZ;
+
+/// Start of every package.
+pub const MAGIC: u32 = u32::from_le_bytes(*b"SFYP");
+
+pub const HEADER_SZ: usize = 6 * 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum HeaderError {
+    /// Not the start of a package.
+    Magic,
+
+    /// The lengths do not fit in a package.
+    Length,
+
+    /// The header belongs to a different package.
+    StorageId,
+
+    /// The package does not match its checksum.
+    Crc,
+
+    /// The raw values do not match their checksum.
+    RawCrc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Header {
+    pub storage_id: u32,
+    pub length: u32,
+    pub raw_length: u32,
+    pub crc: u32,
+    pub raw_crc: u32,
+}
+
+impl Header {
+    pub fn new(storage_id: u32, package: &[u8], raw: &[u8]) -> Header {
+        let length = package.len() as u32;
+        let raw_length = raw.len() as u32;
+
+        Header {
+            storage_id,
+            length,
+            raw_length,
+            crc: Self::package_crc(storage_id, length, raw_length, package),
+            raw_crc: crc32fast::hash(raw),
+        }
+    }
+
+    fn package_crc(storage_id: u32, length: u32, raw_length: u32, package: &[u8]) -> u32 {
+        let mut h = crc32fast::Hasher::new();
+        h.update(&storage_id.to_le_bytes());
+        h.update(&length.to_le_bytes());
+        h.update(&raw_length.to_le_bytes());
+        h.update(package);
+        h.finalize()
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SZ] {
+        let mut b = [0u8; HEADER_SZ];
+
+        for (c, v) in b.chunks_exact_mut(4).zip([
+            MAGIC,
+            self.storage_id,
+            self.length,
+            self.raw_length,
+            self.crc,
+            self.raw_crc,
+        ]) {
+            c.copy_from_slice(&v.to_le_bytes());
+        }
+
+        b
+    }
+
+    /// Parse the header at the start of `b`, the package is not checked.
+    pub fn from_bytes(b: &[u8]) -> Result<Header, HeaderError> {
+        if b.len() < HEADER_SZ {
+            return Err(HeaderError::Length);
+        }
+
+        let v = |i: usize| u32::from_le_bytes(b[i * 4..(i + 1) * 4].try_into().unwrap());
+
+        if v(0) != MAGIC {
+            return Err(HeaderError::Magic);
+        }
+
+        let header = Header {
+            storage_id: v(1),
+            length: v(2),
+            raw_length: v(3),
+            crc: v(4),
+            raw_crc: v(5),
+        };
+
+        if header.length as usize > AXL_POSTCARD_SZ {
+            return Err(HeaderError::Length);
+        }
+
+        Ok(header)
+    }
+
+    /// The package from the bytes following the header, if it matches the checksum.
+    pub fn package<'a>(&self, b: &'a mut [u8]) -> Result<&'a mut [u8], HeaderError> {
+        let package = b
+            .get_mut(..self.length as usize)
+            .ok_or(HeaderError::Length)?;
+
+        if Self::package_crc(self.storage_id, self.length, self.raw_length, package) != self.crc {
+            return Err(HeaderError::Crc);
+        }
+
+        Ok(package)
+    }
+
+    /// Check the raw values against the checksum.
+    pub fn check_raw(&self, raw: &[u8]) -> Result<(), HeaderError> {
+        let raw = raw
+            .get(..self.raw_length as usize)
+            .ok_or(HeaderError::Length)?;
+
+        if crc32fast::hash(raw) != self.raw_crc {
+            return Err(HeaderError::RawCrc);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let package = [1u8, 2, 3, 4, 5];
+        let raw = [6u8, 7, 8];
+        let h = Header::new(1234, &package, &raw);
+
+        let mut b = std::vec::Vec::new();
+        b.extend(h.to_bytes());
+        b.extend(package);
+        b.extend([0u8; 3]); // padding
+        b.extend(raw);
+
+        let h2 = Header::from_bytes(&b).unwrap();
+        assert_eq!(h, h2);
+        assert_eq!(h2.storage_id, 1234);
+
+        let (_, rest) = b.split_at_mut(HEADER_SZ);
+        let (p, raw2) = rest.split_at_mut(8);
+        assert_eq!(h2.package(p).unwrap(), &package);
+        assert_eq!(h2.check_raw(raw2), Ok(()));
+    }
+
+    #[test]
+    fn corrupt() {
+        let package = [1u8, 2, 3, 4, 5];
+        let raw = [6u8, 7, 8];
+        let h = Header::new(1234, &package, &raw);
+        let b = h.to_bytes();
+
+        let mut p = package;
+        p[2] ^= 0x10;
+        assert_eq!(h.package(&mut p), Err(HeaderError::Crc));
+        assert_eq!(h.check_raw(&[6, 7, 9]), Err(HeaderError::RawCrc));
+        assert_eq!(h.check_raw(&[6, 7]), Err(HeaderError::Length));
+        assert_eq!(h.package(&mut [1, 2]), Err(HeaderError::Length));
+
+        // The storage ID is part of the checksum.
+        let mut b2 = b;
+        b2[4] ^= 0x01;
+        let h2 = Header::from_bytes(&b2).unwrap();
+        assert_eq!(h2.package(&mut package.clone()), Err(HeaderError::Crc));
+
+        let mut b2 = b;
+        b2[0] ^= 0x01;
+        assert_eq!(Header::from_bytes(&b2), Err(HeaderError::Magic));
+
+        let mut b2 = b;
+        b2[11] = 0xff;
+        assert_eq!(Header::from_bytes(&b2), Err(HeaderError::Length));
+
+        assert_eq!(Header::from_bytes(&b[..10]), Err(HeaderError::Length));
+
+        // Zeroed (never written) bytes are not a package.
+        assert_eq!(
+            Header::from_bytes(&[0u8; HEADER_SZ]),
+            Err(HeaderError::Magic)
+        );
+    }
+}
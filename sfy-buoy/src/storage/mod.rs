@@ -9,10 +9,23 @@
 //! `COBS`es. The collection file is the full ID stripped of the last 2 digits. Each collection
 //! file holds 100 packages.
 //!
+//! Since version 9 every package starts with a [`header::Header`] with a checksum, so that a
+//! corrupted package can be detected and skipped.
+//!
+//! The GPS packets from the external GPS (`ext-gps`) are stored in a separate series of
+//! collections, with their own IDs and storage version ([`GPS_STORAGE_VERSION`]): `12345.G1`.
+//!
+//! The clusters used by the files on the card are tracked, and when the free space of the volume
+//! drops below [`MIN_FREE_SPACE`] (or [`MIN_FREE_FRACTION`] of the volume on large cards) the
+//! oldest collections are removed (see [`Storage::evict`]).
+//!
 //! At 52 Hz and 1024 length data-package, there is 4389 packages per day. That is about 44 collections per day. See tests for more details.
 
+#[cfg(test)]
+use crate::sim::free;
 use core::fmt::Debug;
 use core::sync::atomic::Ordering;
+#[cfg(not(test))]
 use cortex_m::interrupt::free;
 use embedded_hal::{
     blocking::delay::DelayUs,
@@ -20,39 +33,88 @@ use embedded_hal::{
     digital::v2::OutputPin,
 };
 use embedded_sdmmc::{
-    sdcard::AcquireOpts, Error as GenericSdMmcError, Mode, SdCard, SdCardError, VolumeIdx,
-    VolumeManager,
+    sdcard::AcquireOpts, Block, BlockDevice, BlockIdx, Error as GenericSdMmcError, Mode, SdCard,
+    SdCardError, VolumeIdx, VolumeManager,
 };
 use heapless::{String, Vec};
 
 use crate::axl::{self, AxlPacket, AXL_POSTCARD_SZ};
 use crate::waves::AxlPacketT;
 
+#[cfg(feature = "ext-gps")]
+use crate::gps::{GpsPacket, GPS_POSTCARD_SZ};
+
 #[cfg(feature = "raw")]
 use crate::waves::RAW_AXL_BYTE_SZ;
 
 #[cfg(feature = "raw")]
-pub const PACKAGE_SZ: usize = AXL_POSTCARD_SZ + RAW_AXL_BYTE_SZ;
+pub const PACKAGE_SZ: usize = HEADER_SZ + AXL_POSTCARD_SZ + RAW_AXL_BYTE_SZ;
 
 #[cfg(not(feature = "raw"))]
-pub const PACKAGE_SZ: usize = AXL_POSTCARD_SZ;
+pub const PACKAGE_SZ: usize = HEADER_SZ + AXL_POSTCARD_SZ;
+
+#[cfg(feature = "ext-gps")]
+pub const GPS_PACKAGE_SZ: usize = HEADER_SZ + GPS_POSTCARD_SZ;
 
 pub mod clock;
+pub mod header;
+pub mod unsent;
+pub mod volume;
 // mod handles;
 
 use clock::CountClock;
+use header::{Header, HEADER_SZ};
+use unsent::{Unsent, UNSENT_FILE, UNSENT_SZ};
+use volume::{Volume, VolumeErr};
 // use handles::*;
 
 /// Writing to a file seems to take longer time when it has more packages, this can cause timeouts
 /// in the interrupt that drains the IMU FIFO. See <https://github.com/gauteh/sfy/issues/77>.
 pub const COLLECTION_SIZE: u32 = 1000;
 pub const STORAGE_VERSION: u32 = axl::VERSION;
+
+/// The oldest collections are removed when the free space on the SD-card drops below this
+/// [bytes].
+pub const MIN_FREE_SPACE: u64 = 2 * COLLECTION_SIZE as u64 * PACKAGE_SZ as u64;
+
+/// On larger cards at least this fraction (1 / `MIN_FREE_FRACTION`) of the volume is kept free.
+pub const MIN_FREE_FRACTION: u64 = 100;
+
+/// Crash dumps are appended to this file as text.
+pub const CRASH_FILE: &'static str = "CRASH.LOG";
 #[cfg(not(feature = "target-test"))]
-pub const STORAGE_VERSION_STR: &'static str = "6";
+pub const STORAGE_VERSION_STR: &'static str = "9";
 
 #[cfg(feature = "target-test")]
 pub const STORAGE_VERSION_STR: &'static str = "t";
 
+/// Storage version of the GPS collections, the file extension is `G` followed by the version.
+pub const GPS_STORAGE_VERSION: u32 = 1;
+pub const GPS_STORAGE_VERSION_STR: &'static str = "G1";
+
+/// The series of collections on the SD-card, each with their own IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Series {
+    Axl,
+    Gps,
+}
+
+impl Series {
+    pub fn version(&self) -> u32 {
+        match self {
+            Series::Axl => STORAGE_VERSION,
+            Series::Gps => GPS_STORAGE_VERSION,
+        }
+    }
+
+    pub fn fname(&self, c: u32) -> String<32> {
+        match self {
+            Series::Axl => collection_fname(c),
+            Series::Gps => gps_collection_fname(c),
+        }
+    }
+}
+
 #[derive(Debug, defmt::Format)]
 pub enum StorageErr {
     SdMmcErr(SdCardError),
@@ -61,9 +123,17 @@ pub enum StorageErr {
     WriteIDFailure,
     WriteError,
     ReadPackageError,
+    CorruptPackage(header::HeaderError),
     SerializationError,
     DiskFull,
     Uninitialized,
+    VolumeErr(VolumeErr),
+}
+
+impl From<VolumeErr> for StorageErr {
+    fn from(e: VolumeErr) -> Self {
+        StorageErr::VolumeErr(e)
+    }
 }
 
 impl From<SdCardError> for StorageErr {
@@ -83,7 +153,7 @@ const SD_RETRY_DELAY: i32 = 10 * 60;
 enum SdState {
     Uninitialized,
     Retry { last_try: i32 },
-    Initialized { next_id: u32 },
+    Initialized { next_id: u32, next_gps_id: u32 },
 }
 
 pub enum SdSpiSpeed {
@@ -101,6 +171,16 @@ where
     reclock_cb: fn(&mut Spi, SdSpiSpeed) -> (),
     clock: CountClock,
     state: SdState,
+
+    /// Data area of the FAT volume on the SD-card.
+    volume: Option<Volume>,
+
+    /// Space used by the files on the SD-card (whole clusters) [bytes], scanned when initialized
+    /// and updated on writes and evictions.
+    used: u64,
+
+    /// Free space to keep on the SD-card [bytes], at least `1 / MIN_FREE_FRACTION` of the volume.
+    min_free: u64,
 }
 
 impl<Spi: Transfer<u8> + DefaultWrite<u8>, CS: OutputPin, DL: DelayUs<u8>> Storage<Spi, CS, DL>
@@ -134,6 +214,9 @@ where
             reclock_cb,
             clock,
             state: SdState::Uninitialized,
+            volume: None,
+            used: 0,
+            min_free: MIN_FREE_SPACE,
         }
     }
 
@@ -144,22 +227,118 @@ where
     /// Returns the next free ID.
     pub fn next_id(&self) -> Option<u32> {
         match self.state {
-            SdState::Initialized { next_id } => Some(next_id),
+            SdState::Initialized { next_id, .. } => Some(next_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the next free ID of the GPS packets.
+    pub fn next_gps_id(&self) -> Option<u32> {
+        match self.state {
+            SdState::Initialized { next_gps_id, .. } => Some(next_gps_id),
             _ => None,
         }
     }
 
+    /// Free space on the SD-card [MB], from the clusters used by the files on it.
+    pub fn free_space(&self) -> Option<u32> {
+        self.free_bytes()
+            .map(|free| (free / 1024_u64.pow(2)) as u32)
+    }
+
+    /// Free space on the SD-card [bytes]: the data area of the volume less the clusters used by
+    /// the files. The clusters of the directories are not counted.
+    fn free_bytes(&self) -> Option<u64> {
+        self.next_id()?;
+        self.volume.map(|v| v.capacity().saturating_sub(self.used))
+    }
+
+    /// Free space to keep on the SD-card [bytes].
+    fn min_free(&self) -> u64 {
+        let fraction = self.volume.map_or(0, |v| v.capacity() / MIN_FREE_FRACTION);
+        self.min_free.max(fraction)
+    }
+
+    /// Account for a file growing from `from` to `to` bytes.
+    fn grow(&mut self, from: u64, to: u64) {
+        if let Some(v) = self.volume {
+            self.used += v.allocated(to) - v.allocated(from);
+        }
+    }
+
+    /// Remove the oldest collections while the free space is below `MIN_FREE_SPACE`. Collections
+    /// of older storage versions are removed first, and the collection is taken from the series
+    /// that uses the most space. The current collections, and collections with packages in
+    /// `protect` and `protect_gps` (a range of storage IDs, e.g. a pending request), are kept.
+    /// Every removed collection is logged.
+    pub fn evict(
+        &mut self,
+        protect: Option<(u32, u32)>,
+        protect_gps: Option<(u32, u32)>,
+    ) -> Result<(), StorageErr> {
+        use core::fmt::Write;
+
+        while let Some(free) = self.free_bytes() {
+            if free >= self.min_free() {
+                break;
+            }
+
+            // unwrap: free space is only known when initialized.
+            let current = self.next_id().unwrap() / COLLECTION_SIZE;
+            let current_gps = self.next_gps_id().unwrap() / COLLECTION_SIZE;
+            let keep = |series: Series, version: u32, c: u32| {
+                let (current, protect) = match series {
+                    Series::Axl => (current, protect),
+                    Series::Gps => (current_gps, protect_gps),
+                };
+
+                version == series.version()
+                    && (c >= current
+                        || protect.is_some_and(|(start, end)| {
+                            c >= start / COLLECTION_SIZE && c <= end / COLLECTION_SIZE
+                        }))
+            };
+
+            let mut sd = self.acquire()?;
+            let Some((name, version, c, size)) = sd.oldest_collection(keep)? else {
+                defmt::warn!("SD-card is almost full, but there are no collections to remove.");
+                break;
+            };
+
+            sd.remove_file(&name)?;
+            let size = self.volume.map_or(0, |v| v.allocated(size as u64));
+            self.used = self.used.saturating_sub(size);
+
+            let mut msg = String::<256>::new();
+            write!(
+                msg,
+                "SD-card: {} MB free, removed collection: {} (storage version: {}, ids: {} -> {}).",
+                free / 1024_u64.pow(2),
+                name,
+                version,
+                c * COLLECTION_SIZE,
+                (c + 1) * COLLECTION_SIZE - 1
+            )
+            .ok();
+            defmt::warn!("{}", msg);
+            crate::log::log(&msg);
+        }
+
+        Ok(())
+    }
+
     pub fn deinit(&mut self) {
         self.state = SdState::Uninitialized;
     }
 
-    /// Deserialize and return AxlPacket.
+    /// Deserialize and return AxlPacket. A package that does not match its header is returned as
+    /// `CorruptPackage`.
     pub fn get(&mut self, id: u32) -> Result<AxlPacket, StorageErr> {
         defmt::debug!("Reading file: {}", id);
         let (collection, file, offset) = id_to_parts(id);
 
-        let mut buf: Vec<u8, { AXL_POSTCARD_SZ }> = Vec::new();
-        buf.resize_default(AXL_POSTCARD_SZ).unwrap();
+        let mut buf: Vec<u8, { HEADER_SZ + AXL_POSTCARD_SZ }> = Vec::new();
+        buf.resize_default(buf.capacity()).unwrap();
 
         defmt::debug!(
             "Reading package id: {} from collection: {}, fileid: {}, offset: {}",
@@ -169,17 +348,56 @@ where
             offset
         );
 
+        self.read_package(id, &collection, offset, &mut buf)
+    }
+
+    /// Deserialize and return a GpsPacket, see `get`.
+    #[cfg(feature = "ext-gps")]
+    pub fn get_gps(&mut self, id: u32) -> Result<GpsPacket, StorageErr> {
+        let (collection, file, offset) = gps_id_to_parts(id);
+
+        let mut buf: Vec<u8, { HEADER_SZ + GPS_POSTCARD_SZ }> = Vec::new();
+        buf.resize_default(buf.capacity()).unwrap();
+
+        defmt::debug!(
+            "Reading GPS package id: {} from collection: {}, fileid: {}, offset: {}",
+            id,
+            collection,
+            file,
+            offset
+        );
+
+        self.read_package(id, &collection, offset, &mut buf)
+    }
+
+    /// Read the package at `offset` into `buf` and check it against its header.
+    fn read_package<T: serde::de::DeserializeOwned>(
+        &mut self,
+        id: u32,
+        collection: &str,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<T, StorageErr> {
         let sz = self
             .acquire()
-            .and_then(|mut sd| sd.read(&collection, offset, &mut buf))?;
+            .and_then(|mut sd| sd.read(collection, offset, buf))?;
 
         defmt::trace!("Read {:?} bytes.", sz);
 
-        // De-serialize
-        let pck: AxlPacket =
-            postcard::from_bytes_cobs(&mut buf).map_err(|_| StorageErr::ReadPackageError)?;
+        let (header, package) = buf.split_at_mut(HEADER_SZ);
+        let package = Header::from_bytes(header)
+            .and_then(|header| {
+                if header.storage_id != id {
+                    return Err(header::HeaderError::StorageId);
+                }
 
-        Ok(pck)
+                header.package(package)
+            })
+            .inspect_err(|e| defmt::error!("Package {} is corrupt: {}", id, e))
+            .map_err(StorageErr::CorruptPackage)?;
+
+        // De-serialize
+        postcard::from_bytes_cobs(package).map_err(|_| StorageErr::ReadPackageError)
     }
 
     /// Store a new package.
@@ -194,7 +412,7 @@ where
 
         // If writing fails we will always start a new collection, so ID's should not get out of
         // sync within one collection file.
-        let id = sd.advance_id()?;
+        let id = sd.advance_id(Series::Axl)?;
         let (collection, fid, offset) = id_to_parts(id);
 
         // Package now has a storage ID.
@@ -204,6 +422,7 @@ where
         let mut buf: Vec<u8, { AXL_POSTCARD_SZ }> = postcard::to_vec_cobs(pck)
             .inspect_err(|e| defmt::error!("Serialization: {:?}", defmt::Debug2Format(e)))
             .map_err(|_| StorageErr::SerializationError)?;
+        let length = buf.len();
         buf.resize_default(buf.capacity()).unwrap();
 
         // Serialize raw bytes
@@ -221,6 +440,8 @@ where
         #[cfg(not(feature = "raw"))]
         let raw_bytes: &[u8] = &[];
 
+        let header = Header::new(id, &buf[..length], raw_bytes);
+
         // And write..
         defmt::info!(
             "Writing package to card id: {}, size: {} + {}, timestamp: {}, collection: {}, fileid: {}, offset: {}",
@@ -233,11 +454,88 @@ where
             offset
         );
 
-        sd.write(&collection, &buf, raw_bytes)?;
+        sd.write(&collection, &header.to_bytes(), &buf, raw_bytes)?;
+        self.grow(offset as u64, (offset + PACKAGE_SZ) as u64);
         defmt::debug!("Package written.");
 
         Ok(id)
     }
+
+    /// Store a new GPS package, in the series of GPS collections.
+    #[cfg(feature = "ext-gps")]
+    pub fn store_gps(&mut self, pck: &mut GpsPacket) -> Result<u32, StorageErr> {
+        let mut sd = self.acquire()?;
+
+        let id = sd.advance_id(Series::Gps)?;
+        let (collection, fid, offset) = gps_id_to_parts(id);
+
+        // Package now has a storage ID.
+        pck.storage_id = Some(id);
+
+        let mut buf: Vec<u8, { GPS_POSTCARD_SZ }> = postcard::to_vec_cobs(pck)
+            .inspect_err(|e| defmt::error!("Serialization: {:?}", defmt::Debug2Format(e)))
+            .map_err(|_| StorageErr::SerializationError)?;
+        let length = buf.len();
+        buf.resize_default(buf.capacity()).unwrap();
+
+        let header = Header::new(id, &buf[..length], &[]);
+
+        defmt::info!(
+            "Writing GPS package to card id: {}, size: {}, timestamp: {}, collection: {}, fileid: {}, offset: {}",
+            id,
+            buf.len(),
+            pck.timestamp,
+            collection,
+            fid,
+            offset
+        );
+
+        sd.write(&collection, &header.to_bytes(), &buf, &[])?;
+        self.grow(offset as u64, (offset + GPS_PACKAGE_SZ) as u64);
+        defmt::debug!("GPS package written.");
+
+        Ok(id)
+    }
+
+    /// Append a crash dump to `CRASH_FILE`.
+    pub fn store_crash(&mut self, crash: &crate::crash::CrashNote) -> Result<(), StorageErr> {
+        use core::fmt::Write;
+
+        let mut buf = String::<1024>::new();
+        crash
+            .write_text(&mut buf)
+            .inspect_err(|_| defmt::warn!("Crash dump truncated."))
+            .ok();
+        buf.push_str("\n").ok();
+
+        defmt::info!("Writing crash dump to: {}", CRASH_FILE);
+        self.acquire()?.append(CRASH_FILE, buf.as_bytes())?;
+        // The size of the crash log is not known, assume the dump starts a new cluster.
+        self.grow(0, buf.len() as u64);
+
+        Ok(())
+    }
+
+    /// The packages that have not been sent from `UNSENT_FILE`, none if the file does not exist
+    /// or is corrupt.
+    pub fn load_unsent(&mut self) -> Result<Unsent, StorageErr> {
+        let mut buf = [0u8; UNSENT_SZ];
+
+        match self.acquire()?.read_file(UNSENT_FILE, &mut buf) {
+            Ok(sz) => Ok(Unsent::from_bytes(&buf[..sz]).unwrap_or_else(|| {
+                defmt::error!("Corrupt {}, discarding unsent packages.", UNSENT_FILE);
+                Unsent::new()
+            })),
+            Err(StorageErr::GenericSdMmmcErr(GenericSdMmcError::FileNotFound)) => Ok(Unsent::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write the packages that have not been sent to `UNSENT_FILE`.
+    pub fn save_unsent(&mut self, unsent: &Unsent) -> Result<(), StorageErr> {
+        defmt::debug!("Writing unsent packages: {}", unsent.len());
+        self.acquire()?.write_file(UNSENT_FILE, &unsent.to_bytes())
+    }
 }
 
 pub struct SdHandle<'a, Spi: Transfer<u8> + DefaultWrite<u8>, CS: OutputPin, DL: DelayUs<u8>>
@@ -297,27 +595,54 @@ where
                 let sz = storage.sd.device().num_bytes()? / 1024_u64.pow(2);
                 defmt::info!("SD card size: {} mb", sz);
 
+                let volume = Self::read_volume(storage.sd.device())?;
+                defmt::info!(
+                    "SD card volume: {} mb, cluster size: {} b",
+                    volume.capacity() / 1024_u64.pow(2),
+                    volume.cluster_sz
+                );
+                storage.volume = Some(volume);
+
                 defmt::debug!("Increasing SPI speed.");
                 storage
                     .sd
                     .device()
                     .spi(|spi| (storage.reclock_cb)(spi, SdSpiSpeed::High));
 
+                // Start after the last collection, the collections before it may have been
+                // removed to free space.
+                let (used, last, last_gps) = Self::scan(&mut storage.sd, volume)?;
+                defmt::info!("SD card used: {} mb", used / 1024_u64.pow(2));
+                storage.used = used;
+
                 // XXX: This is a slow operation which is likely to cause trouble if it is done on
                 // every send to notecard loop. Hopefully we will fail above (quickly
                 // enough), otherwise this can only be attempted seldomly.
-                let next_id =
-                    Self::find_first_free_collection(&mut storage.sd, None)? * COLLECTION_SIZE;
+                let next_id = Self::find_first_free_collection(
+                    &mut storage.sd,
+                    Series::Axl,
+                    last.map(|c| c + 1),
+                )? * COLLECTION_SIZE;
                 defmt::info!("Next free ID: {}", next_id);
 
-                storage.state = SdState::Initialized { next_id };
+                let next_gps_id = Self::find_first_free_collection(
+                    &mut storage.sd,
+                    Series::Gps,
+                    last_gps.map(|c| c + 1),
+                )? * COLLECTION_SIZE;
+                defmt::info!("Next free GPS ID: {}", next_gps_id);
+
+                storage.state = SdState::Initialized {
+                    next_id,
+                    next_gps_id,
+                };
 
                 Ok(SdHandle {
                     sd: &mut storage.sd,
                     state: &mut storage.state,
                 })
             }
-            SdState::Initialized { next_id: _ } => Ok(SdHandle {
+            SdState::Initialized { .. } => Ok(SdHandle {
                 sd: &mut storage.sd,
                 state: &mut storage.state,
             }),
@@ -327,6 +652,7 @@ where
     pub fn write(
         &mut self,
         collection: &str,
+        header: &[u8],
         buf: &[u8],
         #[allow(unused)] buf_raw: &[u8],
     ) -> Result<(), StorageErr> {
@@ -338,6 +664,8 @@ where
                 .inspect_err(|e| defmt::error!("File seek error: {}", e))
                 .map_err(|_| StorageErr::WriteError)?; // We should already be at the
                                                        // end.
+            f.write(&header)?;
+
             #[cfg(feature = "raw")]
             {
                 f.write(&buf)?;
@@ -357,6 +685,55 @@ where
         sz
     }
 
+    /// Append to a file, creating it if it does not exist.
+    pub fn append(&mut self, file: &str, buf: &[u8]) -> Result<(), StorageErr> {
+        let r: Result<(), StorageErr> = try {
+            let mut v = self.sd.open_volume(VolumeIdx(0))?;
+            let mut root = v.open_root_dir()?;
+            let mut f = root.open_file_in_dir(file, Mode::ReadWriteCreateOrAppend)?;
+            f.write(buf)?;
+        };
+
+        if r.is_err() {
+            *self.state = SdState::Uninitialized;
+        }
+
+        r
+    }
+
+    /// Replace the content of a file, creating it if it does not exist.
+    pub fn write_file(&mut self, file: &str, buf: &[u8]) -> Result<(), StorageErr> {
+        let r: Result<(), StorageErr> = try {
+            let mut v = self.sd.open_volume(VolumeIdx(0))?;
+            let mut root = v.open_root_dir()?;
+            let mut f = root.open_file_in_dir(file, Mode::ReadWriteCreateOrTruncate)?;
+            f.write(buf)?;
+        };
+
+        if r.is_err() {
+            *self.state = SdState::Uninitialized;
+        }
+
+        r
+    }
+
+    /// Read a file from the start, returns the number of bytes read.
+    pub fn read_file(&mut self, file: &str, buf: &mut [u8]) -> Result<usize, StorageErr> {
+        let r: Result<usize, StorageErr> = try {
+            let mut v = self.sd.open_volume(VolumeIdx(0))?;
+            let mut root = v.open_root_dir()?;
+            let mut f = root.open_file_in_dir(file, Mode::ReadOnly)?;
+            free(|_| f.read(buf))?
+        };
+
+        match r {
+            Ok(_) | Err(StorageErr::GenericSdMmmcErr(GenericSdMmcError::FileNotFound)) => (),
+            Err(_) => *self.state = SdState::Uninitialized,
+        }
+
+        r
+    }
+
     pub fn read(
         &mut self,
         collection: &str,
@@ -368,7 +745,7 @@ where
             let mut root = v.open_root_dir()?;
             let mut f = root.open_file_in_dir(collection, Mode::ReadOnly)?;
 
-            if f.length() < (offset + AXL_POSTCARD_SZ) as u32 {
+            if f.length() < (offset + buf.len()) as u32 {
                 defmt::debug!("Collection is not long enough, no such file in it.");
                 return Err(GenericSdMmcError::FileNotFound.into());
             }
@@ -385,16 +762,24 @@ where
         sz
     }
 
-    /// Get the next free ID (and advance to new collection if necessary).
-    fn advance_id(&mut self) -> Result<u32, StorageErr> {
-        if let SdState::Initialized { next_id: id } = &mut self.state {
+    /// Get the next free ID in the series (and advance to new collection if necessary).
+    fn advance_id(&mut self, series: Series) -> Result<u32, StorageErr> {
+        if let SdState::Initialized {
+            next_id,
+            next_gps_id,
+        } = &mut self.state
+        {
+            let id = match series {
+                Series::Axl => next_id,
+                Series::Gps => next_gps_id,
+            };
             let current = *id;
             let mut next_id = *id + 1;
 
             // Check that the next collection is free, if rolling over.
             if next_id % COLLECTION_SIZE == 0 {
                 let c = next_id / COLLECTION_SIZE;
-                let nc = Self::find_first_free_collection(&mut self.sd, Some(c))?;
+                let nc = Self::find_first_free_collection(&mut self.sd, series, Some(c))?;
 
                 if nc > c {
                     defmt::info!("Starting new collection: {}", c);
@@ -414,13 +799,14 @@ where
     /// collection.
     pub fn find_first_free_collection<'a>(
         sd: &'a mut VolumeManager<SdCard<Spi, CS, DL>, CountClock>,
+        series: Series,
         start: Option<u32>,
     ) -> Result<u32, StorageErr> {
         let mut v = sd.open_volume(VolumeIdx(0))?;
         let mut root = v.open_root_dir()?;
 
         for c in start.unwrap_or(0)..65536u32 {
-            let f = collection_fname(c);
+            let f = series.fname(c);
             defmt::debug!("Searching for free collection, testing: {}", f);
             match root.find_directory_entry(f.as_str()) {
                 Ok(_) => continue,
@@ -435,14 +821,126 @@ where
     pub fn remove_collection(&mut self, collection: u32) -> Result<(), StorageErr> {
         defmt::info!("Removing collection: {}", collection);
 
-        let f = collection_fname(collection);
+        self.remove_file(collection_fname(collection).as_str())
+    }
 
+    pub fn remove_file(&mut self, file: &str) -> Result<(), StorageErr> {
         let mut v = self.sd.open_volume(VolumeIdx(0))?;
         let mut root = v.open_root_dir()?;
-        root.delete_file_in_dir(f.as_str())?;
+        root.delete_file_in_dir(file)?;
 
         Ok(())
     }
+
+    /// Read the data area of the first partition from the MBR and its boot sector.
+    fn read_volume(sd: &mut SdCard<Spi, CS, DL>) -> Result<Volume, StorageErr> {
+        let mut block = [Block::new()];
+
+        sd.read(&mut block, BlockIdx(0))?;
+        let start = Volume::partition_start(&block[0].contents)?;
+
+        sd.read(&mut block, BlockIdx(start))?;
+        Ok(Volume::from_boot_sector(&block[0].contents)?)
+    }
+
+    /// The space used by the files on the card (whole clusters) [bytes], and the last collection
+    /// of the current storage version of the packages and of the GPS packages.
+    fn scan<'a>(
+        sd: &'a mut VolumeManager<SdCard<Spi, CS, DL>, CountClock>,
+        volume: Volume,
+    ) -> Result<(u64, Option<u32>, Option<u32>), StorageErr> {
+        let mut v = sd.open_volume(VolumeIdx(0))?;
+        let mut root = v.open_root_dir()?;
+
+        let mut used = 0u64;
+        let mut last = None;
+        let mut last_gps = None;
+
+        root.iterate_dir(|e| {
+            used += volume.allocated(e.size as u64);
+
+            match parse_fname(&e.name) {
+                Some((Series::Axl, STORAGE_VERSION, c)) => last = last.max(Some(c)),
+                Some((Series::Gps, GPS_STORAGE_VERSION, c)) => last_gps = last_gps.max(Some(c)),
+                _ => (),
+            }
+        })?;
+
+        Ok((used, last, last_gps))
+    }
+
+    /// The oldest collection that should not be kept, from the series that uses the most space:
+    /// the file name, the storage version, the collection and the size [bytes].
+    fn oldest_collection(
+        &mut self,
+        keep: impl Fn(Series, u32, u32) -> bool,
+    ) -> Result<Option<(String<16>, u32, u32, u32)>, StorageErr> {
+        let mut v = self.sd.open_volume(VolumeIdx(0))?;
+        let mut root = v.open_root_dir()?;
+
+        // Oldest collection and the space used by each series.
+        let mut oldest: [(Option<(String<16>, u32, u32, u32)>, u64); 2] = [(None, 0), (None, 0)];
+
+        root.iterate_dir(|e| {
+            if let Some((series, version, c)) = parse_fname(&e.name) {
+                let (oldest, used) = &mut oldest[series as usize];
+                *used += e.size as u64;
+
+                if !keep(series, version, c)
+                    && oldest
+                        .as_ref()
+                        .map_or(true, |(_, ov, oc, _)| (version, c) < (*ov, *oc))
+                {
+                    let mut name = String::new();
+                    core::fmt::write(&mut name, format_args!("{}", e.name)).ok();
+                    *oldest = Some((name, version, c, e.size));
+                }
+            }
+        })?;
+
+        let [(axl, axl_used), (gps, gps_used)] = oldest;
+
+        Ok(match (axl, gps) {
+            (Some(axl), Some(gps)) => Some(if gps_used > axl_used { gps } else { axl }),
+            (axl, gps) => axl.or(gps),
+        })
+    }
+}
+
+/// Storage version and collection of a collection file name (e.g. `12.9`).
+fn parse_collection_fname(name: &impl core::fmt::Display) -> Option<(u32, u32)> {
+    let mut s = String::<16>::new();
+    core::fmt::write(&mut s, format_args!("{}", name)).ok()?;
+
+    let (c, version) = s.split_once('.')?;
+    let c = c.parse().ok()?;
+    let version = if version.eq_ignore_ascii_case(STORAGE_VERSION_STR) {
+        STORAGE_VERSION
+    } else {
+        version.parse().ok()?
+    };
+
+    Some((version, c))
+}
+
+/// Storage version and collection of a GPS collection file name (e.g. `12.G1`).
+fn parse_gps_collection_fname(name: &impl core::fmt::Display) -> Option<(u32, u32)> {
+    let mut s = String::<16>::new();
+    core::fmt::write(&mut s, format_args!("{}", name)).ok()?;
+
+    let (c, version) = s.split_once('.')?;
+    let version = version
+        .strip_prefix('G')
+        .or_else(|| version.strip_prefix('g'))?;
+
+    Some((version.parse().ok()?, c.parse().ok()?))
+}
+
+/// Series, storage version and collection of a collection file name.
+fn parse_fname(name: &impl core::fmt::Display) -> Option<(Series, u32, u32)> {
+    parse_collection_fname(name)
+        .map(|(version, c)| (Series::Axl, version, c))
+        .or_else(|| parse_gps_collection_fname(name).map(|(version, c)| (Series::Gps, version, c)))
 }
 
 pub fn collection_fname(c: u32) -> String<32> {
@@ -452,6 +950,13 @@ pub fn collection_fname(c: u32) -> String<32> {
     f
 }
 
+pub fn gps_collection_fname(c: u32) -> String<32> {
+    let mut f: String<32> = String::from(c);
+    f.push_str(".").unwrap();
+    f.push_str(GPS_STORAGE_VERSION_STR).unwrap();
+    f
+}
+
 /// Calculate collection file, file number in collection and byte offset of start of pacakge in
 /// collection file for a given ID.
 pub fn id_to_parts(id: u32) -> (String<32>, u32, usize) {
@@ -464,6 +969,17 @@ pub fn id_to_parts(id: u32) -> (String<32>, u32, usize) {
     (collection, fileid, offset)
 }
 
+/// Calculate collection file, file number in collection and byte offset of a GPS package, see
+/// `id_to_parts`.
+#[cfg(feature = "ext-gps")]
+pub fn gps_id_to_parts(id: u32) -> (String<32>, u32, usize) {
+    let collection = id / COLLECTION_SIZE;
+    let fileid = id % COLLECTION_SIZE;
+    let offset = fileid as usize * GPS_PACKAGE_SZ;
+
+    (gps_collection_fname(collection), fileid, offset)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -478,16 +994,214 @@ mod tests {
     #[test]
     fn test_id_to_parts() {
         let (c, file, o) = id_to_parts(0);
-        assert_eq!(c, "0.6");
+        assert_eq!(c, format!("0.{}", STORAGE_VERSION_STR).as_str());
         assert_eq!(file, 0);
         assert_eq!(o, 0);
 
         let (c, file, o) = id_to_parts(1231255);
-        assert_eq!(c, "1231.6");
+        assert_eq!(c, format!("1231.{}", STORAGE_VERSION_STR).as_str());
         assert_eq!(file, 255);
         assert_eq!(o, 255 * PACKAGE_SZ);
     }
 
+    #[test]
+    fn collection_fnames() {
+        let current = format!("12.{}", STORAGE_VERSION_STR);
+        assert_eq!(
+            parse_collection_fname(&current),
+            Some((STORAGE_VERSION, 12))
+        );
+        assert_eq!(parse_collection_fname(&"3.6"), Some((6, 3)));
+        assert_eq!(parse_collection_fname(&"CRASH.LOG"), None);
+        assert_eq!(parse_collection_fname(&unsent::UNSENT_FILE), None);
+        assert_eq!(parse_collection_fname(&"12"), None);
+
+        assert_eq!(parse_collection_fname(&"12.G1"), None);
+        assert_eq!(parse_gps_collection_fname(&"12.G1"), Some((1, 12)));
+        assert_eq!(parse_gps_collection_fname(&current), None);
+        assert_eq!(
+            parse_fname(&gps_collection_fname(7)),
+            Some((Series::Gps, GPS_STORAGE_VERSION, 7))
+        );
+        assert_eq!(parse_fname(&"3.6"), Some((Series::Axl, 6, 3)));
+    }
+
+    fn package() -> AxlPacketT {
+        let pck = AxlPacket {
+            timestamp: 1002330,
+            position_time: 123123,
+            temperature: 0.0,
+            lat: 34.52341,
+            lon: 54.012,
+            freq: 52.0,
+            accel_range: 4.,
+            gyro_range: 500.,
+            burst_start: 0,
+            offset: 15,
+            storage_id: None,
+            storage_version: STORAGE_VERSION,
+            data: (0..AXL_SZ).map(|v| v as u16).collect(),
+        };
+
+        #[cfg(feature = "raw")]
+        return (pck, crate::waves::VecRawAxl::new());
+
+        #[cfg(not(feature = "raw"))]
+        return (pck,);
+    }
+
+    #[test]
+    fn evict_oldest() {
+        use crate::sim::sd::{MockCs, MockSd};
+        use crate::sim::SimDelay;
+        use core::sync::atomic::AtomicI32;
+
+        static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+        let sd = MockSd::formatted();
+        let mut storage = Storage::open(sd, MockCs, CountClock(&COUNT), |_, _| {}, SimDelay);
+
+        // A package in each of the collections 0, 1, 2 and 3.
+        for c in 0..4 {
+            assert_eq!(storage.store(&mut package()).unwrap(), c * COLLECTION_SIZE);
+            storage.deinit();
+        }
+
+        storage.acquire().unwrap();
+        assert_eq!(storage.next_id(), Some(4 * COLLECTION_SIZE));
+        let free = storage.free_bytes().unwrap();
+
+        // The collections use whole clusters, and the partition offset and the FATs are not free.
+        let volume = storage.volume.unwrap();
+        let collection = volume.allocated(PACKAGE_SZ as u64);
+        assert!(collection > PACKAGE_SZ as u64);
+        assert_eq!(free, volume.capacity() - 4 * collection);
+        assert!(volume.capacity() < crate::sim::sd::PARTITION_BLOCKS as u64 * 512);
+
+        // Enough free space.
+        storage.min_free = free;
+        storage.evict(None, None).unwrap();
+        assert_eq!(storage.free_bytes(), Some(free));
+
+        // Collection 0 is still requested, so collection 1 is removed.
+        storage.min_free = free + 1;
+        storage.evict(Some((10, 20)), None).unwrap();
+        assert_eq!(storage.free_bytes(), Some(free + collection));
+
+        assert_eq!(storage.get(0).unwrap().storage_id, Some(0));
+        assert!(matches!(
+            storage.get(COLLECTION_SIZE),
+            Err(StorageErr::GenericSdMmmcErr(
+                GenericSdMmcError::FileNotFound
+            ))
+        ));
+
+        // The removed collection is not used again.
+        storage.deinit();
+        storage.acquire().unwrap();
+        assert_eq!(storage.next_id(), Some(4 * COLLECTION_SIZE));
+        assert_eq!(storage.free_bytes(), Some(free + collection));
+
+        // Remove everything but the current collection.
+        storage.min_free = u64::MAX;
+        storage.evict(None, None).unwrap();
+        for c in 0..4 {
+            assert!(storage.get(c * COLLECTION_SIZE).is_err());
+        }
+    }
+
+    #[cfg(feature = "ext-gps")]
+    fn gps_package(timestamp: i64) -> GpsPacket {
+        GpsPacket {
+            timestamp,
+            freq: 20.0,
+            version: crate::gps::GPS_PACKET_V,
+            storage_id: None,
+            lon: 53677011,
+            lat: 603283447,
+            msl: 91506,
+            data: (0..6 * crate::gps::GPS_PACKET_SZ)
+                .map(|v| v as u16)
+                .collect(),
+            ha_min: 1.0,
+            ha_max: 2.0,
+            ha_mean: 1.5,
+            va_min: 1.0,
+            va_max: 2.0,
+            va_mean: 1.5,
+            fix: [0, 0, 0, 10, 0, 0, 0, 0],
+            soln: [10, 0, 0, 0, 0, 0, 0, 0],
+        }
+    }
+
+    #[cfg(feature = "ext-gps")]
+    #[test]
+    fn gps_series() {
+        use crate::sim::sd::{MockCs, MockSd};
+        use crate::sim::SimDelay;
+        use core::sync::atomic::AtomicI32;
+
+        static COUNT: AtomicI32 = AtomicI32::new(1_700_000_000);
+
+        let sd = MockSd::formatted();
+        let mut storage = Storage::open(sd, MockCs, CountClock(&COUNT), |_, _| {}, SimDelay);
+
+        // The GPS packages have their own IDs.
+        assert_eq!(storage.store(&mut package()).unwrap(), 0);
+        for i in 0..3 {
+            let mut pck = gps_package(i * 1000);
+            assert_eq!(storage.store_gps(&mut pck).unwrap(), i as u32);
+            assert_eq!(pck.storage_id, Some(i as u32));
+        }
+        assert_eq!(storage.store(&mut package()).unwrap(), 1);
+
+        let pck = storage.get_gps(1).unwrap();
+        let mut expected = gps_package(1000);
+        expected.storage_id = Some(1);
+        assert_eq!(pck, expected);
+
+        // The IDs of one series are not packages in the other.
+        assert!(matches!(
+            storage.get_gps(3),
+            Err(StorageErr::GenericSdMmmcErr(
+                GenericSdMmcError::FileNotFound
+            ))
+        ));
+        assert!(matches!(
+            storage.get(2),
+            Err(StorageErr::GenericSdMmmcErr(
+                GenericSdMmcError::FileNotFound
+            ))
+        ));
+
+        // Both series start new collections after a restart.
+        storage.deinit();
+        storage.acquire().unwrap();
+        assert_eq!(storage.next_id(), Some(COLLECTION_SIZE));
+        assert_eq!(storage.next_gps_id(), Some(COLLECTION_SIZE));
+
+        storage.store_gps(&mut gps_package(0)).unwrap();
+        storage.deinit();
+        storage.acquire().unwrap();
+        assert_eq!(storage.next_id(), Some(COLLECTION_SIZE));
+        assert_eq!(storage.next_gps_id(), Some(2 * COLLECTION_SIZE));
+
+        // The packages use more space, so their oldest collection is removed first.
+        let free = storage.free_bytes().unwrap();
+        storage.min_free = free + 1;
+        storage.evict(None, None).unwrap();
+        assert!(storage.get(0).is_err());
+        assert!(storage.get_gps(0).is_ok());
+
+        // Then the GPS collections, unless requested.
+        storage.min_free = u64::MAX;
+        storage
+            .evict(None, Some((COLLECTION_SIZE, COLLECTION_SIZE)))
+            .unwrap();
+        assert!(storage.get_gps(0).is_err());
+        assert!(storage.get_gps(COLLECTION_SIZE).is_ok());
+    }
+
     #[test]
     fn test_fat32_limits() {
         let pcks_per_day = 52 * 60 * 60 * 24 / 1024;
@@ -540,6 +1254,7 @@ mod tests {
             freq: 53.0,
             accel_range: 8.,
             gyro_range: 500.,
+            burst_start: 0,
             offset: 15,
             storage_id: Some(0),
             storage_version: STORAGE_VERSION,
@@ -554,6 +1269,7 @@ mod tests {
             freq: 53.0,
             accel_range: 8.,
             gyro_range: 500.,
+            burst_start: 0,
             offset: 15,
             storage_id: Some(1),
             storage_version: STORAGE_VERSION,
@@ -568,6 +1284,7 @@ mod tests {
             freq: 53.0,
             accel_range: 8.,
             gyro_range: 500.,
+            burst_start: 0,
             offset: 15,
             storage_id: Some(2),
             storage_version: STORAGE_VERSION,
@@ -588,9 +1305,11 @@ mod tests {
 
         for p in 0..3 {
             let slice = &mut buf[(AXL_POSTCARD_SZ * p)..(AXL_POSTCARD_SZ * (p + 1))];
-            let pck: AxlPacket = postcard::from_bytes_cobs(slice).unwrap();
+            let pck = AxlPacket::from_bytes_cobs(6, slice).unwrap();
             println!("Deserialized data package: {:?}", pck);
             assert_eq!(pck.storage_id, Some(3000 + p as u32));
+            assert_eq!(pck.storage_version, 6);
+            assert_eq!(pck.burst_start, pck.timestamp);
         }
     }
 }
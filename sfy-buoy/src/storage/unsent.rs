@@ -0,0 +1,282 @@
+//! Packages that are stored on the SD-card, but have not been queued for the Notecard.
+//!
+//! Packages are spilled here when the Notecard is full or the note queue has no room, and they
+//! are sent when there is room again. The IDs are kept as a few ranges, and written to
+//! [`UNSENT_FILE`] together with the packages in the note queue, so that the backlog and the
+//! note queue survive a reset. The file is (little endian `u32`s): the
+//! storage version, the number of ranges, the ranges (first and last ID), and a CRC32 of the
+//! preceding bytes.
+
+use heapless::Vec;
+
+use super::STORAGE_VERSION;
+use crate::env::UnsentOrder;
+
+pub const UNSENT_FILE: &'static str = "UNSENT.DAT";
+
+/// Maximum number of separate ranges of unsent packages.
+pub const UNSENT_RANGES: usize = 8;
+
+pub const UNSENT_SZ: usize = 4 * (3 + 2 * UNSENT_RANGES);
+
+/// Sorted, non-overlapping and non-adjacent ranges of storage IDs (inclusive).
+#[derive(Debug, Clone, Default, PartialEq, Eq, defmt::Format)]
+pub struct Unsent {
+    ranges: Vec<(u32, u32), UNSENT_RANGES>,
+}
+
+impl Unsent {
+    pub fn new() -> Unsent {
+        Unsent::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Number of unsent packages.
+    pub fn len(&self) -> u32 {
+        self.ranges.iter().map(|(a, b)| b - a + 1).sum()
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.ranges.iter().any(|(a, b)| (*a..=*b).contains(&id))
+    }
+
+    /// The next package to send.
+    pub fn next(&self, order: UnsentOrder) -> Option<u32> {
+        match order {
+            UnsentOrder::Oldest => self.ranges.first().map(|(a, _)| *a),
+            UnsentOrder::Newest => self.ranges.last().map(|(_, b)| *b),
+        }
+    }
+
+    /// Add a package. When there are too many ranges the two closest ranges are joined, and the
+    /// packages between them will be sent again.
+    pub fn insert(&mut self, id: u32) {
+        if self.contains(id) {
+            return;
+        }
+
+        let i = self
+            .ranges
+            .iter()
+            .position(|(a, _)| *a > id)
+            .unwrap_or(self.ranges.len());
+
+        let before = i > 0 && self.ranges[i - 1].1 + 1 == id;
+        let after = i < self.ranges.len() && self.ranges[i].0 == id + 1;
+
+        match (before, after) {
+            (true, true) => {
+                self.ranges[i - 1].1 = self.ranges[i].1;
+                self.ranges.remove(i);
+            }
+            (true, false) => self.ranges[i - 1].1 = id,
+            (false, true) => self.ranges[i].0 = id,
+            (false, false) => {
+                if self.ranges.is_full() {
+                    self.join_closest();
+                    return self.insert(id);
+                }
+
+                // unwrap: checked that there is room above.
+                self.ranges.insert(i, (id, id)).unwrap();
+            }
+        }
+    }
+
+    /// Add the packages of `other`.
+    pub fn extend(&mut self, other: &Unsent) {
+        for (a, b) in other.ranges.iter().copied() {
+            for id in a..=b {
+                self.insert(id);
+            }
+        }
+    }
+
+    fn join_closest(&mut self) {
+        if let Some(i) =
+            (1..self.ranges.len()).min_by_key(|i| self.ranges[*i].0 - self.ranges[*i - 1].1)
+        {
+            defmt::warn!(
+                "Too many ranges of unsent packages, joining: {:?} and {:?}",
+                self.ranges[i - 1],
+                self.ranges[i]
+            );
+            self.ranges[i - 1].1 = self.ranges[i].1;
+            self.ranges.remove(i);
+        }
+    }
+
+    /// Remove the packages from `start` to `end` (inclusive).
+    pub fn remove(&mut self, start: u32, end: u32) {
+        let mut ranges = Vec::<(u32, u32), UNSENT_RANGES>::new();
+
+        for (a, b) in self.ranges.iter().copied() {
+            if b < start || a > end {
+                ranges.push((a, b)).ok();
+                continue;
+            }
+
+            // Splitting a range needs room for one more, rather keep the packages and send them
+            // again.
+            if a < start && b > end && self.ranges.is_full() {
+                defmt::warn!(
+                    "Too many ranges of unsent packages, keeping: {} -> {}",
+                    a,
+                    b
+                );
+                ranges.push((a, b)).ok();
+                continue;
+            }
+
+            if a < start {
+                ranges.push((a, start - 1)).ok();
+            }
+
+            if b > end {
+                ranges.push((end + 1, b)).ok();
+            }
+        }
+
+        self.ranges = ranges;
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8, UNSENT_SZ> {
+        let mut b = Vec::new();
+
+        let mut push = |v: u32| b.extend_from_slice(&v.to_le_bytes()).unwrap();
+        push(STORAGE_VERSION);
+        push(self.ranges.len() as u32);
+        for (a, e) in &self.ranges {
+            push(*a);
+            push(*e);
+        }
+
+        let crc = crc32fast::hash(&b);
+        b.extend_from_slice(&crc.to_le_bytes()).unwrap();
+
+        b
+    }
+
+    /// Parse the unsent packages, `None` if the file is corrupt or from a different storage
+    /// version.
+    pub fn from_bytes(b: &[u8]) -> Option<Unsent> {
+        let v = |i: usize| {
+            b.get(i * 4..(i + 1) * 4)
+                .map(|v| u32::from_le_bytes(v.try_into().unwrap()))
+        };
+
+        if v(0)? != STORAGE_VERSION {
+            return None;
+        }
+
+        let n = v(1)? as usize;
+        if n > UNSENT_RANGES {
+            return None;
+        }
+
+        let end = 4 * (2 + 2 * n);
+        if crc32fast::hash(b.get(..end)?) != v(2 + 2 * n)? {
+            return None;
+        }
+
+        let mut unsent = Unsent::new();
+        for i in 0..n {
+            let (a, e) = (v(2 + 2 * i)?, v(3 + 2 * i)?);
+
+            // Must be sorted and apart.
+            if a > e || unsent.ranges.last().is_some_and(|(_, pe)| a <= pe + 1) {
+                return None;
+            }
+
+            unsent.ranges.push((a, e)).ok()?;
+        }
+
+        Some(unsent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut u = Unsent::new();
+        assert_eq!(u.next(UnsentOrder::Oldest), None);
+
+        for id in (10..20).chain(30..33).chain([25, 5]) {
+            u.insert(id);
+        }
+        u.insert(12);
+        assert_eq!(u.ranges, [(5, 5), (10, 19), (25, 25), (30, 32)]);
+        assert_eq!(u.len(), 15);
+        assert_eq!(u.next(UnsentOrder::Oldest), Some(5));
+        assert_eq!(u.next(UnsentOrder::Newest), Some(32));
+
+        // Joins the neighbours.
+        u.insert(20);
+        u.insert(24);
+        (21..24).for_each(|id| u.insert(id));
+        assert_eq!(u.ranges, [(5, 5), (10, 25), (30, 32)]);
+
+        u.remove(5, 5);
+        u.remove(12, 13);
+        u.remove(32, 32);
+        u.remove(0, 1);
+        assert_eq!(u.ranges, [(10, 11), (14, 25), (30, 31)]);
+
+        u.remove(11, 30);
+        assert_eq!(u.ranges, [(10, 10), (31, 31)]);
+
+        u.remove(0, 100);
+        assert!(u.is_empty());
+    }
+
+    #[test]
+    fn too_many_ranges() {
+        let mut u = Unsent::new();
+        for i in 0..UNSENT_RANGES as u32 {
+            u.insert(i * 10);
+        }
+        u.insert(1000);
+        assert_eq!(u.ranges.len(), UNSENT_RANGES);
+        assert_eq!(u.ranges[0], (0, 10));
+        assert_eq!(u.ranges.last(), Some(&(1000, 1000)));
+
+        // Splitting keeps the packages.
+        let mut u = Unsent::new();
+        for i in 0..UNSENT_RANGES as u32 {
+            u.insert(i * 10);
+            u.insert(i * 10 + 1);
+            u.insert(i * 10 + 2);
+        }
+        u.remove(11, 11);
+        assert_eq!(u.ranges.len(), UNSENT_RANGES);
+        assert!(u.contains(11));
+        assert!(u.contains(UNSENT_RANGES as u32 * 10 - 8));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut u = Unsent::new();
+        for id in (10..20).chain([25, 40]) {
+            u.insert(id);
+        }
+
+        let b = u.to_bytes();
+        assert_eq!(Unsent::from_bytes(&b), Some(u.clone()));
+        assert_eq!(
+            Unsent::from_bytes(&Unsent::new().to_bytes()),
+            Some(Unsent::new())
+        );
+
+        let mut c = b.clone();
+        c[9] ^= 0x1;
+        assert_eq!(Unsent::from_bytes(&c), None);
+        assert_eq!(Unsent::from_bytes(&b[..b.len() - 1]), None);
+        assert_eq!(Unsent::from_bytes(&[]), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! Geometry of the FAT volume on the SD-card.
+//!
+//! The free space is the data area of the first partition less the clusters used by the files.
+//! The data area is read from the boot sector (BPB) of the partition, so that the partition
+//! offset, the reserved sectors, the FATs and the root directory are not counted as free space.
+
+/// Offset of the first partition entry in the MBR.
+const PARTITION_ENTRY: usize = 446;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum VolumeErr {
+    NoSignature,
+    BadBootSector,
+}
+
+/// Data area of a FAT volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Volume {
+    /// Number of data clusters.
+    pub clusters: u32,
+
+    /// Size of a cluster [bytes].
+    pub cluster_sz: u32,
+}
+
+impl Volume {
+    /// The first block of the first partition in the MBR.
+    pub fn partition_start(mbr: &[u8; 512]) -> Result<u32, VolumeErr> {
+        if mbr[510..512] != [0x55, 0xaa] {
+            return Err(VolumeErr::NoSignature);
+        }
+
+        let p = &mbr[PARTITION_ENTRY..PARTITION_ENTRY + 16];
+        Ok(u32::from_le_bytes([p[8], p[9], p[10], p[11]]))
+    }
+
+    /// Parse the boot sector (BPB) of a FAT12, FAT16 or FAT32 volume.
+    pub fn from_boot_sector(bpb: &[u8; 512]) -> Result<Volume, VolumeErr> {
+        if bpb[510..512] != [0x55, 0xaa] {
+            return Err(VolumeErr::NoSignature);
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes([bpb[i], bpb[i + 1]]) as u32;
+        let u32_at = |i: usize| u32::from_le_bytes([bpb[i], bpb[i + 1], bpb[i + 2], bpb[i + 3]]);
+
+        let bytes_per_sector = u16_at(11);
+        let sectors_per_cluster = bpb[13] as u32;
+        let reserved = u16_at(14);
+        let fats = bpb[16] as u32;
+        let root_entries = u16_at(17);
+
+        let total = match u16_at(19) {
+            0 => u32_at(32),
+            n => n,
+        };
+
+        let fat_sz = match u16_at(22) {
+            0 => u32_at(36),
+            n => n,
+        };
+
+        if bytes_per_sector == 0 || sectors_per_cluster == 0 {
+            return Err(VolumeErr::BadBootSector);
+        }
+
+        let root_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+        let data_sectors = total
+            .checked_sub(reserved + fats * fat_sz + root_sectors)
+            .ok_or(VolumeErr::BadBootSector)?;
+
+        Ok(Volume {
+            clusters: data_sectors / sectors_per_cluster,
+            cluster_sz: sectors_per_cluster * bytes_per_sector,
+        })
+    }
+
+    /// Size of the data area [bytes].
+    pub fn capacity(&self) -> u64 {
+        self.clusters as u64 * self.cluster_sz as u64
+    }
+
+    /// Space taken by a file of `size` bytes: a whole number of clusters [bytes].
+    pub fn allocated(&self, size: u64) -> u64 {
+        size.div_ceil(self.cluster_sz as u64) * self.cluster_sz as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Boot sector of a 32 GB card formatted with FAT32 and 32 kB clusters.
+    fn fat32() -> [u8; 512] {
+        let mut bpb = [0u8; 512];
+        bpb[11..13].copy_from_slice(&512u16.to_le_bytes());
+        bpb[13] = 64;
+        bpb[14..16].copy_from_slice(&32u16.to_le_bytes());
+        bpb[16] = 2;
+        bpb[32..36].copy_from_slice(&62_325_760u32.to_le_bytes());
+        bpb[36..40].copy_from_slice(&7_607u32.to_le_bytes());
+        bpb[510..512].copy_from_slice(&[0x55, 0xaa]);
+        bpb
+    }
+
+    #[test]
+    fn fat32_volume() {
+        let v = Volume::from_boot_sector(&fat32()).unwrap();
+        assert_eq!(v.cluster_sz, 32 * 1024);
+        assert_eq!(v.clusters, (62_325_760 - 32 - 2 * 7_607) / 64);
+        assert!(v.capacity() < 62_325_760 * 512);
+
+        assert_eq!(v.allocated(0), 0);
+        assert_eq!(v.allocated(1), 32 * 1024);
+        assert_eq!(v.allocated(32 * 1024 + 1), 64 * 1024);
+    }
+
+    #[test]
+    fn sim_volume() {
+        use crate::sim::sd::{MockSd, PARTITION_BLOCKS, PARTITION_START};
+
+        let sd = MockSd::formatted();
+        assert_eq!(
+            Volume::partition_start(&sd.read_block(0)),
+            Ok(PARTITION_START)
+        );
+
+        let v = Volume::from_boot_sector(&sd.read_block(PARTITION_START)).unwrap();
+        assert_eq!(v.cluster_sz, 4 * 512);
+        assert_eq!(v.clusters, (PARTITION_BLOCKS - 1 - 2 * 128 - 32) / 4);
+    }
+
+    #[test]
+    fn no_signature() {
+        assert_eq!(
+            Volume::from_boot_sector(&[0u8; 512]),
+            Err(VolumeErr::NoSignature)
+        );
+        assert_eq!(
+            Volume::partition_start(&[0u8; 512]),
+            Err(VolumeErr::NoSignature)
+        );
+    }
+}
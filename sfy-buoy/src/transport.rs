@@ -0,0 +1,98 @@
+//! Telemetry links that the packages can be sent over.
+//!
+//! The Notecard ([`crate::note::Notecarrier`]) sends the full packages over cellular, while the
+//! Iridium modem ([`crate::iridium::Iridium`]) only has room for the spectra or positions.
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use crate::axl::AxlPacket;
+use crate::NOTEQ_SZ;
+
+pub trait Transport {
+    type Error: defmt::Format;
+
+    /// Check whether the transport can take more packages.
+    fn ready(&mut self, delay: &mut impl DelayMs<u16>) -> Result<bool, Self::Error>;
+
+    /// Send package, returns the number of bytes queued for transmission.
+    fn send(
+        &mut self,
+        pck: &AxlPacket,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<usize, Self::Error>;
+
+    /// The spectrum accumulated from the packages passing through the transport.
+    #[cfg(feature = "spectrum")]
+    fn spectrum(&mut self) -> &mut crate::spec::Spectrum;
+
+    #[cfg(feature = "spectrum")]
+    fn send_spec(
+        &mut self,
+        spec: &crate::spec::SpecPacket,
+        delay: &mut impl DelayMs<u16>,
+    ) -> Result<(), Self::Error>;
+}
+
+/// Send queued packages over the transport.
+pub fn drain_queue<T: Transport>(
+    transport: &mut T,
+    queue: &mut heapless::spsc::Consumer<'static, AxlPacket, NOTEQ_SZ>,
+    delay: &mut impl DelayMs<u16>,
+) -> Result<usize, T::Error> {
+    // Sending packages takes a long time (16-17 seconds). Only 1 package is sent at a time
+    // before running main-loop again and letting other tasks run. The main-loop will keep
+    // going immediately again if there are more data in the queue.
+
+    // defmt::debug!("draining imu queue: {}", queue.len());
+
+    let mut tsz = 0;
+
+    while queue.peek().is_some() {
+        // The package is left in the queue when the transport is not ready, with storage the
+        // packages behind it are spilled to the SD-card until there is room again.
+        if !transport.ready(delay)? {
+            defmt::warn!(
+                "transport is not ready, not sending more packages: queue sz: {}",
+                queue.len()
+            );
+            return Ok(tsz);
+        }
+
+        // unwrap: peeked above.
+        let pck = queue.dequeue().unwrap();
+
+        // The spectrum is sent even if the package is not, it is much smaller.
+        #[cfg(feature = "spectrum")]
+        if let Some(spec) = transport.spectrum().sample(&pck) {
+            transport
+                .send_spec(&spec, delay)
+                .inspect_err(|e| defmt::error!("Error while sending spectrum: {:?}", e))
+                .ok();
+        }
+
+        defmt::info!(
+            "sending package: note queue sz (after dequeue): {}",
+            queue.len()
+        );
+        match transport.send(&pck, delay) {
+            Ok(sz) => {
+                tsz += sz;
+            }
+            Err(e) => {
+                defmt::error!("Error while sending package: {:?}, retrying..", e);
+                match transport.send(&pck, delay) {
+                    Ok(sz) => {
+                        tsz += sz;
+                    }
+                    Err(e) => {
+                        defmt::error!("Error while sending package: {:?}, discarding package.", e);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    // defmt::debug!("done draining imu queue: {}", queue.len());
+    Ok(tsz)
+}
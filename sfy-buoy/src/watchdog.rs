@@ -0,0 +1,261 @@
+//! Watchdog progress tracking and reset reasons.
+//!
+//! The hardware watchdog is set up in the binaries, and is only fed when all the tasks
+//! (`Progress`) have made progress since the last time it was fed. A task that is stuck will
+//! therefore reset the buoy after the watchdog timeout.
+//!
+//! The reason for the last reset is read from the reset status register at boot. Hard faults and
+//! panics both end in a software reset, so a marker is left in memory that is not initialized at
+//! boot to tell them apart.
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// The IMU interrupt has read samples from the IMU.
+pub const IMU: u8 = 1 << 0;
+
+/// The storage queue has been drained to the SD-card.
+pub const STORAGE: u8 = 1 << 1;
+
+/// An iteration of Notecard communication has completed.
+pub const NOTE: u8 = 1 << 2;
+
+/// Progress of the tasks since the watchdog was last fed.
+#[derive(Default)]
+pub struct Progress(AtomicU8);
+
+impl Progress {
+    pub const fn new() -> Progress {
+        Progress(AtomicU8::new(0))
+    }
+
+    /// Mark that `task` has made progress.
+    pub fn set(&self, task: u8) {
+        self.0.fetch_or(task, Ordering::Relaxed);
+    }
+
+    /// Returns true and resets the progress if all `tasks` have made progress. The watchdog
+    /// should be fed if this returns true.
+    pub fn check(&self, tasks: u8) -> bool {
+        let p = self.0.load(Ordering::Relaxed);
+
+        if p & tasks == tasks {
+            self.0.fetch_and(!tasks, Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub static PROGRESS: Progress = Progress::new();
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum ResetReason {
+    PowerOn = 1,
+    Brownout = 2,
+    Watchdog = 3,
+    HardFault = 4,
+    Panic = 5,
+    Software = 6,
+    External = 7,
+    Debugger = 8,
+    Unknown = 9,
+}
+
+/// Reset status as read from the reset generator (`RSTGEN.STAT` on the Apollo3).
+#[derive(Debug, Default, Clone, Copy, PartialEq, defmt::Format)]
+pub struct ResetStatus {
+    pub power_on: bool,
+    pub brownout: bool,
+    pub watchdog: bool,
+    pub software: bool,
+    pub external: bool,
+    pub debugger: bool,
+}
+
+impl ResetReason {
+    pub fn as_str(&self) -> &'static str {
+        use ResetReason::*;
+
+        match self {
+            PowerOn => "power-on",
+            Brownout => "brownout",
+            Watchdog => "watchdog",
+            HardFault => "hardfault",
+            Panic => "panic",
+            Software => "software",
+            External => "external",
+            Debugger => "debugger",
+            Unknown => "unknown",
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<ResetReason> {
+        use ResetReason::*;
+
+        [
+            PowerOn, Brownout, Watchdog, HardFault, Panic, Software, External, Debugger, Unknown,
+        ]
+        .into_iter()
+        .find(|r| *r as u8 == v)
+    }
+
+    /// The reason for the last reset. Several status bits may be set, the most specific one is
+    /// used. The `marker` is only used for software resets.
+    pub fn from_status(status: ResetStatus, marker: Option<ResetReason>) -> ResetReason {
+        use ResetReason::*;
+
+        if status.watchdog {
+            Watchdog
+        } else if status.brownout {
+            Brownout
+        } else if status.software {
+            match marker {
+                Some(r @ (HardFault | Panic)) => r,
+                _ => Software,
+            }
+        } else if status.power_on {
+            PowerOn
+        } else if status.external {
+            External
+        } else if status.debugger {
+            Debugger
+        } else {
+            Unknown
+        }
+    }
+}
+
+const MARKER_MAGIC: u32 = 0x5f1a_0000;
+
+#[cfg_attr(not(test), link_section = ".uninit.SFY_RESET_MARKER")]
+static mut RESET_MARKER: MaybeUninit<u32> = MaybeUninit::uninit();
+
+fn marker() -> *mut u32 {
+    core::ptr::addr_of_mut!(RESET_MARKER).cast()
+}
+
+/// Leave a marker with the reason for the coming software reset.
+pub fn set_reset_marker(reason: ResetReason) {
+    unsafe { marker().write_volatile(MARKER_MAGIC | reason as u32) };
+}
+
+/// Read and clear the reset marker. The marker is garbage after a power-on reset, only use it
+/// for software resets.
+pub fn take_reset_marker() -> Option<ResetReason> {
+    let m = unsafe { marker().read_volatile() };
+    unsafe { marker().write_volatile(0) };
+
+    if m & 0xffff_0000 == MARKER_MAGIC {
+        ResetReason::from_u8((m & 0xff) as u8)
+    } else {
+        None
+    }
+}
+
+const COUNT_MAGIC: u32 = 0x5f1a_c0c0;
+
+/// Magic and number of resets since power-on.
+#[cfg_attr(not(test), link_section = ".uninit.SFY_RESET_COUNT")]
+static mut RESET_COUNT: MaybeUninit<[u32; 2]> = MaybeUninit::uninit();
+
+/// Count the resets since the last power-on, should be called once at boot. The count is kept in
+/// memory that is not initialized at boot, and is restarted if the memory has been lost.
+pub fn count_reset(reason: ResetReason) -> u32 {
+    let p: *mut u32 = core::ptr::addr_of_mut!(RESET_COUNT).cast();
+
+    let (magic, count) = unsafe { (p.read_volatile(), p.add(1).read_volatile()) };
+
+    let count = if reason == ResetReason::PowerOn || magic != COUNT_MAGIC {
+        0
+    } else {
+        count.wrapping_add(1)
+    };
+
+    unsafe {
+        p.write_volatile(COUNT_MAGIC);
+        p.add(1).write_volatile(count);
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress() {
+        let p = Progress::new();
+        let tasks = IMU | STORAGE | NOTE;
+
+        assert!(!p.check(tasks));
+
+        p.set(IMU);
+        p.set(NOTE);
+        assert!(!p.check(tasks));
+
+        p.set(STORAGE);
+        assert!(p.check(tasks));
+
+        // Reset after feeding.
+        assert!(!p.check(tasks));
+
+        p.set(IMU);
+        assert!(p.check(IMU));
+    }
+
+    #[test]
+    fn reset_reason() {
+        use ResetReason::*;
+
+        let status = |f: fn(&mut ResetStatus)| {
+            let mut s = ResetStatus::default();
+            f(&mut s);
+            s
+        };
+
+        // A power-on reset also sets the other bits.
+        let por = status(|s| {
+            s.power_on = true;
+            s.external = true;
+        });
+        assert_eq!(ResetReason::from_status(por, Some(Panic)), PowerOn);
+
+        let wdt = status(|s| {
+            s.watchdog = true;
+            s.external = true;
+        });
+        assert_eq!(ResetReason::from_status(wdt, None), Watchdog);
+
+        let sw = status(|s| s.software = true);
+        assert_eq!(ResetReason::from_status(sw, None), Software);
+        assert_eq!(ResetReason::from_status(sw, Some(HardFault)), HardFault);
+        assert_eq!(ResetReason::from_status(sw, Some(Watchdog)), Software);
+
+        let bor = status(|s| s.brownout = true);
+        assert_eq!(ResetReason::from_status(bor, None), Brownout);
+
+        assert_eq!(
+            ResetReason::from_status(ResetStatus::default(), None),
+            Unknown
+        );
+    }
+
+    #[test]
+    fn reset_count() {
+        assert_eq!(count_reset(ResetReason::PowerOn), 0);
+        assert_eq!(count_reset(ResetReason::Watchdog), 1);
+        assert_eq!(count_reset(ResetReason::Panic), 2);
+        assert_eq!(count_reset(ResetReason::PowerOn), 0);
+    }
+
+    #[test]
+    fn reset_marker() {
+        set_reset_marker(ResetReason::HardFault);
+        assert_eq!(take_reset_marker(), Some(ResetReason::HardFault));
+        assert_eq!(take_reset_marker(), None);
+    }
+}
@@ -6,21 +6,20 @@ use crate::axl::{AXL_SZ, SAMPLE_SZ};
 #[cfg(feature = "fir")]
 use crate::fir;
 
-use super::wire::{ScaledF32, A16};
+use super::wire::{accel_max, scale_f32_to_u16};
+use super::Rate;
 
 #[cfg(feature = "raw")]
-use super::wire::G16;
+use super::wire::{ScaledF32, A16, G16};
 
-// From Adafruit Sensors library.
-// pub const SENSORS_RADS_TO_DPS: f64 = 57.29577793;
-pub const SENSORS_DPS_TO_RADS: f64 = 0.017453293;
-pub const SENSORS_GRAVITY_STANDARD: f64 = 9.80665;
+pub use sfy_wire::{SENSORS_DPS_TO_RADS, SENSORS_GRAVITY_STANDARD};
 
+/// The raw buffer has room for the largest decimation of the selectable rates.
 #[cfg(feature = "fir")]
-pub const RAW_AXL_SZ: usize = 2 * AXL_SZ * fir::DECIMATE as usize;
+pub const RAW_AXL_SZ: usize = 2 * AXL_SZ * fir::MAX_DECIMATE as usize;
 
 #[cfg(feature = "fir")]
-pub const RAW_AXL_BYTE_SZ: usize = 2 * AXL_SZ * fir::DECIMATE as usize * 2;
+pub const RAW_AXL_BYTE_SZ: usize = 2 * AXL_SZ * fir::MAX_DECIMATE as usize * 2;
 
 #[cfg(not(feature = "fir"))]
 pub const RAW_AXL_SZ: usize = 2 * AXL_SZ;
@@ -31,11 +30,12 @@ pub const RAW_AXL_BYTE_SZ: usize = 2 * AXL_SZ * 2;
 pub type VecAxl = heapless::Vec<u16, AXL_SZ>;
 pub type VecRawAxl = heapless::Vec<u16, RAW_AXL_SZ>;
 
+/// Accelerometer range [g] of the samples, the samples and the raw values.
 #[cfg(feature = "raw")]
-pub type AxlBufT = (VecAxl, VecRawAxl);
+pub type AxlBufT = (f32, VecAxl, VecRawAxl);
 
 #[cfg(not(feature = "raw"))]
-pub type AxlBufT = (VecAxl,);
+pub type AxlBufT = (f32, VecAxl);
 
 #[derive(Debug, Clone, defmt::Format)]
 pub enum Error {
@@ -48,10 +48,11 @@ pub struct ImuBuf {
 
     filter: NxpFusion,
 
-    /// Buffer with values ready to be sent. Only `sample()` is allowed to grow the buf, and
+    /// Buffer with values ready to be sent (in m/s^2), they are scaled to `u16` with the range
+    /// of the package when the buf is taken. Only `sample()` is allowed to grow the buf, and
     /// it must always grow with `SAMPLE_SZ` samples. The buf must also be a multiple of
     /// `SAMPLE_SZ`.
-    pub axl: VecAxl,
+    pub axl: heapless::Vec<f32, AXL_SZ>,
 
     /// Buffer with raw values, is emptied whenever axl is emptied.
     #[cfg(feature = "raw")]
@@ -59,22 +60,22 @@ pub struct ImuBuf {
 }
 
 impl ImuBuf {
-    pub fn new(freq: f32) -> ImuBuf {
+    pub fn new(rate: Rate) -> ImuBuf {
         #[cfg(feature = "fir")]
         let fir = [
-            fir::FIR::new().into_decimator(),
-            fir::FIR::new().into_decimator(),
-            fir::FIR::new().into_decimator(),
+            fir::FIR::new(rate.filter).into_decimator(rate.decimate()),
+            fir::FIR::new(rate.filter).into_decimator(rate.decimate()),
+            fir::FIR::new(rate.filter).into_decimator(rate.decimate()),
         ];
 
-        let filter = NxpFusion::new(freq);
+        let filter = NxpFusion::new(rate.freq.value());
 
         ImuBuf {
             #[cfg(feature = "fir")]
             fir,
 
             filter,
-            axl: VecAxl::new(),
+            axl: heapless::Vec::new(),
 
             #[cfg(feature = "raw")]
             raw_axl: VecRawAxl::new(),
@@ -82,7 +83,15 @@ impl ImuBuf {
     }
 
     pub fn take_buf(&mut self) -> AxlBufT {
-        let b = self.axl.clone();
+        // The raw values share the range fields of the package, and include gravity.
+        #[cfg(feature = "raw")]
+        let range = super::ACCEL_RANGE;
+
+        #[cfg(not(feature = "raw"))]
+        let range = super::wire::accel_range(&self.axl);
+
+        let max = accel_max(range);
+        let b = self.axl.iter().map(|v| scale_f32_to_u16(max, *v)).collect();
 
         #[cfg(feature = "raw")]
         let r = self.raw_axl.clone();
@@ -93,10 +102,10 @@ impl ImuBuf {
         self.raw_axl.clear();
 
         #[cfg(feature = "raw")]
-        return (b, r);
+        return (range, b, r);
 
         #[cfg(not(feature = "raw"))]
-        return (b,);
+        return (range, b);
     }
 
     pub fn reset(&mut self) {
@@ -188,10 +197,10 @@ impl ImuBuf {
         {
             // x, y, z from axl is in m/s^2, the quaternion is only used to
             // rotate the instantanuous acceleration.
-            self.axl.push(A16::from_f32(axl.x).to_u16()).unwrap();
-            self.axl.push(A16::from_f32(axl.y).to_u16()).unwrap();
+            self.axl.push(axl.x).unwrap();
+            self.axl.push(axl.y).unwrap();
             self.axl
-                .push(A16::from_f32(axl.z - SENSORS_GRAVITY_STANDARD as f32).to_u16())
+                .push(axl.z - SENSORS_GRAVITY_STANDARD as f32)
                 .unwrap();
         }
 
@@ -207,9 +216,9 @@ impl ImuBuf {
             (Some(x), Some(y), Some(z)) => {
                 // x, y, z from axl is in m/s^2, the quaternion is only used to
                 // rotate the instantanuous acceleration.
-                self.axl.push(A16::from_f32(x).to_u16()).unwrap();
-                self.axl.push(A16::from_f32(y).to_u16()).unwrap();
-                self.axl.push(A16::from_f32(z).to_u16()).unwrap();
+                self.axl.push(x).unwrap();
+                self.axl.push(y).unwrap();
+                self.axl.push(z).unwrap();
             }
             (None, None, None) => {} // No filter output.
             _ => {
@@ -223,6 +232,37 @@ impl ImuBuf {
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn take_buf_scaled() {
+        use super::*;
+        use crate::waves::wire::scale_u16_to_f32;
+        use crate::waves::{ACCEL_RANGE, RATES};
+
+        let mut buf = ImuBuf::new(RATES[0]);
+        buf.axl
+            .extend((0..AXL_SZ).map(|i| 0.1 * libm::sinf(i as f32 / 20.)));
+
+        #[cfg(not(feature = "raw"))]
+        let (range, data) = buf.take_buf();
+
+        #[cfg(feature = "raw")]
+        let (range, data, _) = buf.take_buf();
+
+        #[cfg(not(feature = "raw"))]
+        assert!(range < ACCEL_RANGE);
+
+        #[cfg(feature = "raw")]
+        assert_eq!(range, ACCEL_RANGE);
+
+        assert_eq!(data.len(), AXL_SZ);
+        assert!(buf.axl.is_empty());
+
+        for (i, u) in data.iter().enumerate() {
+            let v = 0.1 * libm::sinf(i as f32 / 20.);
+            assert!((scale_u16_to_f32(accel_max(range), *u) - v).abs() < 0.01);
+        }
+    }
+
     #[cfg(feature = "fir")]
     #[test]
     fn filter_decimater() {
@@ -230,23 +270,25 @@ mod tests {
         use crate::axl::SAMPLE_NO;
         use ism330dhcx::{ctrl1xl, ctrl2g};
 
-        let mut buf = ImuBuf::new(200.);
+        for rate in crate::waves::RATES {
+            let mut buf = ImuBuf::new(rate);
 
-        for _ in 0..SAMPLE_NO {
-            buf.sample(
-                GyroValue::new(ctrl2g::Fs::Dps500, [0, 1, 2]),
-                AccelValue::new(ctrl1xl::Fs_Xl::G2, [0, 1, 2]),
-            )
-            .unwrap();
-        }
+            for _ in 0..SAMPLE_NO {
+                buf.sample(
+                    GyroValue::new(ctrl2g::Fs::Dps500, [0, 1, 2]),
+                    AccelValue::new(ctrl1xl::Fs_Xl::G2, [0, 1, 2]),
+                )
+                .unwrap();
+            }
 
-        assert_eq!(
-            buf.axl.len(),
-            SAMPLE_SZ * SAMPLE_NO / fir::DECIMATE as usize
-        );
-        assert_eq!(
-            buf.free(),
-            (AXL_SZ / SAMPLE_SZ) - (SAMPLE_NO / fir::DECIMATE as usize)
-        );
+            assert_eq!(
+                buf.axl.len(),
+                SAMPLE_SZ * SAMPLE_NO / rate.decimate() as usize
+            );
+            assert_eq!(
+                buf.free(),
+                (AXL_SZ / SAMPLE_SZ) - (SAMPLE_NO / rate.decimate() as usize)
+            );
+        }
     }
 }
@@ -2,6 +2,7 @@
 //! time-series or statistics.
 
 use core::fmt::Debug;
+use core::sync::atomic::{AtomicU8, Ordering};
 use embedded_hal::blocking::{
     delay::DelayMs,
     i2c::{Write, WriteRead},
@@ -41,19 +42,84 @@ pub const ACCEL_RANGE: f32 = 4.; // [g]
 #[cfg(not(feature = "surf"))]
 pub const GYRO_RANGE: f32 = 500.; // [dps]
 
-#[cfg(all(feature = "20Hz", not(feature = "fir")))]
-compile_error!("Feature 20Hz requires feature fir");
+#[cfg(feature = "fir")]
+sa::const_assert_eq!(FREQ.value(), fir::FREQ);
 
+/// The output rates that can be selected, the first is the default. With the FIR filter the IMU
+/// samples at `FREQ` and the output is filtered and decimated, without it the IMU samples at the
+/// output rate.
 #[cfg(feature = "fir")]
-pub const OUTPUT_FREQ: f32 = fir::OUT_FREQ;
+pub const RATES: [Rate; 2] = [
+    Rate {
+        freq: FREQ,
+        filter: fir::Filter::Hz50,
+    },
+    Rate {
+        freq: FREQ,
+        filter: fir::Filter::Hz20,
+    },
+];
 
 #[cfg(not(feature = "fir"))]
-pub const OUTPUT_FREQ: f32 = FREQ.value();
+pub const RATES: [Rate; 4] = [
+    Rate { freq: Freq::Hz208 },
+    Rate { freq: Freq::Hz104 },
+    Rate { freq: Freq::Hz52 },
+    Rate { freq: Freq::Hz26 },
+];
+
+/// The highest (and default) output frequency.
+pub const OUTPUT_FREQ: f32 = RATES[0].output_freq();
+
+/// The lowest output frequency.
+pub const MIN_OUTPUT_FREQ: f32 = RATES[RATES.len() - 1].output_freq();
+
+/// Sample rate of the IMU and the filter used to get to the output frequency.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Rate {
+    pub freq: Freq,
 
-#[cfg(feature = "fir")]
-sa::const_assert_eq!(FREQ.value(), fir::FREQ);
+    #[cfg(feature = "fir")]
+    pub filter: fir::Filter,
+}
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+impl Rate {
+    pub const fn decimate(&self) -> u8 {
+        #[cfg(feature = "fir")]
+        return self.filter.decimate();
+
+        #[cfg(not(feature = "fir"))]
+        return 1;
+    }
+
+    /// Frequency of the samples in the packages.
+    pub const fn output_freq(&self) -> f32 {
+        self.freq.value() / self.decimate() as f32
+    }
+
+    /// The supported rate with output frequency `hz`.
+    pub fn from_hz(hz: u32) -> Option<Rate> {
+        RATES.iter().find(|r| r.output_freq() as u32 == hz).copied()
+    }
+}
+
+/// Index in `RATES` of the rate requested for the IMU interrupt.
+static RATE: AtomicU8 = AtomicU8::new(0);
+
+/// Request the IMU interrupt to change to a new output rate.
+pub fn request_rate(rate: Rate) {
+    let i = RATES.iter().position(|r| *r == rate).unwrap_or(0);
+    RATE.store(i as u8, Ordering::SeqCst);
+}
+
+pub fn requested_rate() -> Rate {
+    RATES
+        .get(RATE.load(Ordering::SeqCst) as usize)
+        .copied()
+        .unwrap_or(RATES[0])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
 pub enum Freq {
     Hz26,
     Hz52,
@@ -136,6 +202,7 @@ pub struct Waves<I2C: WriteRead + Write> {
     pub imu: IMU,
     pub freq: Freq,
     pub output_freq: f32,
+    rate: Rate,
 
     /// Buffer with values ready to be sent.
     buf: ImuBuf,
@@ -150,6 +217,9 @@ pub struct Waves<I2C: WriteRead + Write> {
     /// Offset in FIFO _in samples_ (that is one gyro and one accel sample) when timestamp
     /// was set.
     pub fifo_offset: u16,
+
+    /// Start of the current burst [ms], 0 when sampling continuously.
+    pub burst_start: i64,
 }
 
 #[derive(Debug, defmt::Format)]
@@ -173,25 +243,27 @@ impl<E: Debug> From<E> for ImuError<E> {
 }
 
 impl<E: Debug, I2C: WriteRead<Error = E> + Write<Error = E>> Waves<I2C> {
-    pub fn new(mut i2c: I2C) -> Result<Waves<I2C>, E> {
+    pub fn new(mut i2c: I2C, rate: Rate) -> Result<Waves<I2C>, E> {
         defmt::debug!("setting up imu driver..");
         let imu = Ism330Dhcx::new_with_address(&mut i2c, 0x6a)?;
 
-        defmt::debug!("imu frequency: {}", FREQ.value());
-        defmt::debug!("output frequency: {}", OUTPUT_FREQ);
+        defmt::debug!("imu frequency: {}", rate.freq.value());
+        defmt::debug!("output frequency: {}", rate.output_freq());
 
         let mut w = Waves {
             i2c,
             imu,
-            freq: FREQ,
-            output_freq: OUTPUT_FREQ,
-            buf: ImuBuf::new(FREQ.value()),
+            freq: rate.freq,
+            output_freq: rate.output_freq(),
+            rate,
+            buf: ImuBuf::new(rate),
             timestamp: 0,
             position_time: 0,
             temperature: 0.0,
             lon: 0.0,
             lat: 0.0,
             fifo_offset: 0,
+            burst_start: 0,
         };
 
         defmt::debug!("booting imu..");
@@ -233,6 +305,25 @@ impl<E: Debug, I2C: WriteRead<Error = E> + Write<Error = E>> Waves<I2C> {
         Ok(())
     }
 
+    pub fn rate(&self) -> Rate {
+        self.rate
+    }
+
+    /// Change the output rate. The samples in the buffer are discarded, and the IMU must be
+    /// re-booted with `reset` for the new sample rate to take effect.
+    pub fn set_rate(&mut self, rate: Rate) {
+        defmt::info!(
+            "changing output frequency: {} -> {}",
+            self.output_freq,
+            rate.output_freq()
+        );
+
+        self.freq = rate.freq;
+        self.output_freq = rate.output_freq();
+        self.rate = rate;
+        self.buf = ImuBuf::new(rate);
+    }
+
     /// Temperature in Celsius.
     pub fn get_temperature(&mut self) -> Result<f32, E> {
         self.imu.get_temperature(&mut self.i2c)
@@ -405,10 +496,10 @@ impl<E: Debug, I2C: WriteRead<Error = E> + Write<Error = E>> Waves<I2C> {
     ) -> Result<AxlPacketT, E> {
         defmt::trace!("axl: taking buffer");
         #[cfg(feature = "raw")]
-        let (data, raw) = self.buf.take_buf();
+        let (accel_range, data, raw) = self.buf.take_buf();
 
         #[cfg(not(feature = "raw"))]
-        let (data,) = self.buf.take_buf();
+        let (accel_range, data) = self.buf.take_buf();
 
         let pck = AxlPacket {
             timestamp: self.timestamp,
@@ -421,8 +512,9 @@ impl<E: Debug, I2C: WriteRead<Error = E> + Write<Error = E>> Waves<I2C> {
             lon: self.lon,
             lat: self.lat,
             freq: self.output_freq,
-            accel_range: ACCEL_RANGE,
+            accel_range,
             gyro_range: GYRO_RANGE,
+            burst_start: self.burst_start,
         };
         defmt::trace!("axl: buffer taken: {:?}", pck);
 
@@ -10,6 +10,32 @@ pub const ACCEL_MAX: f32 = 2. * super::ACCEL_RANGE * SENSORS_GRAVITY_STANDARD as
 /// > Do not change without updating the storage version.
 pub const GYRO_MAX: f32 = 2. * super::GYRO_RANGE * SENSORS_DPS_TO_RADS as f32; // in rad/s
 
+/// Number of times the accelerometer range may be halved for a package with small
+/// accelerations, each halving gives one more bit of resolution.
+pub const ACCEL_RANGE_STEPS: u32 = 8;
+
+/// Scaling of acceleration values for an accelerometer range (in g).
+pub fn accel_max(range: f32) -> f32 {
+    2. * range * SENSORS_GRAVITY_STANDARD as f32 // in m/s^2
+}
+
+/// The smallest accelerometer range (in g) that fits all `values` (in m/s^2), found by halving
+/// `ACCEL_RANGE` at most `ACCEL_RANGE_STEPS` times. A power of two fraction of the range is
+/// exactly represented in the `accel_range` field of the package.
+pub fn accel_range(values: &[f32]) -> f32 {
+    let peak = values.iter().fold(0f32, |m, v| m.max(v.abs()));
+
+    let mut range = super::ACCEL_RANGE;
+    for _ in 0..ACCEL_RANGE_STEPS {
+        if peak > accel_max(range / 2.) {
+            break;
+        }
+        range /= 2.;
+    }
+
+    range
+}
+
 /// An acceleration value packed into an u16 between pre-determined limits.
 #[repr(transparent)]
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -105,16 +131,7 @@ pub fn scale_f32_to_u16(max: f32, v: f32) -> u16 {
     return libm::round(u) as u16; // will maybe panic if u is out-of-bounds?
 }
 
-/// Move an u16 on given -max to max range to its real value in f32.
-#[allow(unused)]
-pub fn scale_u16_to_f32(max: f32, u: u16) -> f32 {
-    debug_assert!(max > 0.);
-    let max = max as f64;
-    let v = u as f64;
-    let v = v * (2. * max) / u16::MAX as f64;
-    let v = v - max;
-    return v as f32;
-}
+pub use sfy_wire::scale_u16_to_f32;
 
 #[cfg(test)]
 mod tests {
@@ -131,6 +148,33 @@ mod tests {
         assert_eq!(scale_u16_to_f32(10., 0), -10.);
     }
 
+    #[test]
+    fn adaptive_range() {
+        use super::super::ACCEL_RANGE;
+
+        assert_eq!(accel_max(ACCEL_RANGE), ACCEL_MAX);
+
+        assert_eq!(accel_range(&[]), ACCEL_RANGE / 256.);
+        assert_eq!(accel_range(&[0., 0.01, -0.02]), ACCEL_RANGE / 256.);
+        assert_eq!(accel_range(&[0.1, -ACCEL_MAX / 2.]), ACCEL_RANGE / 2.);
+        assert_eq!(accel_range(&[0.1, ACCEL_MAX / 2. + 0.1]), ACCEL_RANGE);
+        assert_eq!(accel_range(&[2. * ACCEL_MAX]), ACCEL_RANGE);
+
+        // Calm sea: the resolution is much better than with the full range.
+        let v: std::vec::Vec<f32> = (0..1000)
+            .map(|i| ACCEL_MAX / 200. * libm::sinf(i as f32 / 10.))
+            .collect();
+        let range = accel_range(&v);
+        assert_eq!(range, ACCEL_RANGE / 128.);
+
+        let err = |max: f32| {
+            v.iter()
+                .map(|v| (scale_u16_to_f32(max, scale_f32_to_u16(max, *v)) - v).abs())
+                .fold(0f32, f32::max)
+        };
+        assert!(err(accel_max(range)) < err(ACCEL_MAX) / 32.);
+    }
+
     #[test]
     fn round_trip_integers() {
         const MAX: i32 = 1000;
@@ -40,7 +40,7 @@ mod tests {
         // let i2c = I2c::new(dp.IOM4, pins.d10, pins.d9, Freq::F1mHz);
 
         defmt::info!("Setting up wave sensor");
-        let waves = Waves::new(i2c).unwrap();
+        let waves = Waves::new(i2c, waves::RATES[0]).unwrap();
 
         State { waves, delay }
     }
@@ -0,0 +1,81 @@
+#!/usr/bin/env python3
+"""
+Write `fixture.bin`: the byte stream from the UART of a u-blox receiver configured like the
+ext-gps buoy (NMEA and UBX NAV-STATUS, NAV-PVT and TIM-TP at 1 Hz, 115200 baud).
+
+This is constructed from the u-blox interface description, it is not a recording. Recordings
+from a receiver can be added next to it, see `README.md`.
+"""
+
+import struct
+from functools import reduce
+from pathlib import Path
+
+
+def ubx(cls, id, payload):
+    body = bytes([cls, id]) + struct.pack('<H', len(payload)) + payload
+    a = b = 0
+    for v in body:
+        a = (a + v) & 0xff
+        b = (b + a) & 0xff
+    return b'\xb5\x62' + body + bytes([a, b])
+
+
+def nmea(s):
+    ck = reduce(lambda a, c: a ^ ord(c), s, 0)
+    return f'${s}*{ck:02X}\r\n'.encode()
+
+
+def nav_pvt(itow, sec, nano, lon, lat, h_msl, carr_soln, num_sv, vel):
+    vel_n, vel_e, vel_d = vel
+    return ubx(0x01, 0x07, struct.pack(
+        '<IHBBBBBBIiBBBBiiiiIIiiiiiIIHH4BihH',
+        itow, 2024, 10, 26, 7, 16, sec, 0b0011_0111, 21, nano,
+        3, 0b0000_0001 | (carr_soln << 6), 0b1110_0000, num_sv,
+        lon, lat, h_msl + 41_350, h_msl, 14, 10,
+        vel_n, vel_e, vel_d, 43, 2_451_000, 61, 66_120_000,
+        128, 0, 0, 0, 0, 0, 0, 0, 0))
+
+
+def nav_status(itow):
+    return ubx(0x01, 0x03, struct.pack('<IBBBBII', itow, 3, 0b1101, 0b01, 0b0000_1000, 24_830,
+                                       1_812_306))
+
+
+def tim_tp(itow):
+    return ubx(0x0d, 0x01, struct.pack('<IIiHBB', itow + 1000, 0, -1_173, 2338, 0b0011, 0))
+
+
+def main():
+    out = bytearray()
+
+    # The capture starts in the middle of a NAV-PVT frame, and the receiver acknowledges the
+    # configuration of the messages (ACK-ACK to CFG-VALSET).
+    out += nav_pvt(200_193_000, 32, 0, 0, 0, 0, 0, 0, (0, 0, 0))[57:]
+    out += ubx(0x05, 0x01, bytes([0x06, 0x8a]))
+
+    epochs = [
+        # sec, nano, lon, lat, h_msl, carr_soln, num_sv, (vel_n, vel_e, vel_d)
+        (34, -2_216, 53_200_147, 603_945_271, 3_412, 1, 27, (12, -31, 4)),
+        (35, -2_205, 53_200_151, 603_945_263, 3_398, 2, 28, (-8, 17, -6)),
+        (36, -2_193, 53_200_146, 603_945_268, 3_421, 2, 28, (5, -9, 11)),
+    ]
+
+    for i, (sec, nano, lon, lat, h_msl, carr_soln, num_sv, vel) in enumerate(epochs):
+        itow = 200_194_000 + i * 1000
+
+        lat_m = f'{lat // 10**7:02d}{(lat % 10**7) * 60 / 10**7:08.5f}'
+        lon_m = f'{lon // 10**7:03d}{(lon % 10**7) * 60 / 10**7:08.5f}'
+        q = {1: 5, 2: 4}[carr_soln]
+        out += nmea(f'GNRMC,0716{sec:02d}.00,A,{lat_m},N,{lon_m},E,0.065,,261024,,,R,V')
+        out += nmea(f'GNGGA,0716{sec:02d}.00,{lat_m},N,{lon_m},E,{q},{num_sv},0.61,'
+                    f'{h_msl / 1000:.1f},M,41.4,M,1.0,0000')
+        out += nav_status(itow)
+        out += nav_pvt(itow, sec, nano, lon, lat, h_msl, carr_soln, num_sv, vel)
+        out += tim_tp(itow)
+
+    Path(__file__).with_name('fixture.bin').write_bytes(out)
+
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,4 @@
+-- Deployments (campaigns) of buoys, time is in milliseconds like `received`. An open deployment has
+-- no end time. Features are the comma separated firmware features (e.g. `raw,20Hz,ext-gps`).
+CREATE TABLE IF NOT EXISTS deployments (id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT, dev TEXT NOT NULL, campaign TEXT, name TEXT, start_time UNSIGNED BIGINT NOT NULL, end_time UNSIGNED BIGINT, lon REAL, lat REAL, hardware TEXT, features TEXT, description TEXT);
+CREATE INDEX deployments_dev ON deployments (dev, start_time);
@@ -0,0 +1,4 @@
+-- Health and status of SFY buoys from the `status.qo` notes (see `sfy-buoy/src/status.rs`). The
+-- Notecard drops fields that are zero, so only `next_id` and `sd_free` (unknown without SD-card)
+-- are nullable.
+CREATE TABLE IF NOT EXISTS health (dev TEXT NOT NULL, received UNSIGNED BIGINT NOT NULL, event TEXT NOT NULL, uptime INTEGER NOT NULL, resets INTEGER NOT NULL, reset_reason TEXT, voltage REAL NOT NULL, imu_temperature REAL NOT NULL, next_id INTEGER, sd_free INTEGER, storage_queue INTEGER NOT NULL, note_queue INTEGER NOT NULL, egps_queue INTEGER NOT NULL, fifo_overrun INTEGER NOT NULL, too_few_samples INTEGER NOT NULL, PRIMARY KEY (dev, received, event));
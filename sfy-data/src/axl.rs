@@ -0,0 +1,234 @@
+//! Decoding of `axl.qo` events into physical time series.
+//!
+//! The body of the event is the `AxlPacketMeta` from `sfy-wire`, shared with the buoy, and the
+//! payload is the base64 encoded samples. Since storage version 5 the samples are `u16`s scaled
+//! between `-ACCEL_MAX` and `ACCEL_MAX` (see `sfy-buoy/src/waves/wire.rs`), before that they were
+//! `f16`s.
+//! Since version 8 the samples may be compressed, given by `codec` (see [`crate::rice`]).
+//! The samples may be decimated when the uplink is congested, given by `mode` (full when missing).
+//!
+//! > Keep in sync with `sfy-processing/sfy/axl.py`.
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+pub use sfy_wire::{scale_u16_to_f32, AxlPacketMeta, Codec, SENSORS_GRAVITY_STANDARD};
+
+/// Number of values in each sample (x, y, z).
+pub const SAMPLE_SZ: usize = 3;
+
+/// An `axl.qo` event as posted by Notehub.
+#[derive(Debug, Deserialize)]
+struct AxlEvent {
+    body: AxlPacketMeta,
+    payload: String,
+}
+
+/// A decoded `axl.qo` event.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Axl {
+    pub received: i64,
+    pub event: String,
+
+    #[serde(flatten)]
+    pub meta: AxlPacketMeta,
+
+    /// Time of each sample (UTC) in milliseconds, taking `offset` into account.
+    pub time: Vec<f64>,
+
+    /// Acceleration [m/s^2]
+    pub x: Vec<f32>,
+    pub y: Vec<f32>,
+    pub z: Vec<f32>,
+}
+
+impl Axl {
+    /// Parse and decode a raw `axl.qo` event.
+    pub fn from_event(received: i64, event: String, data: &[u8]) -> Result<Axl> {
+        let AxlEvent { body, payload } = json::from_slice(data)?;
+
+        let length = (body.length as usize).min(payload.len());
+        let payload = base64::decode(&payload[..length])?;
+
+        let payload: Vec<u8> = match body.codec {
+            c if c == Codec::Raw as u32 => payload,
+            c if c == Codec::Rice as u32 => crate::rice::decode(&payload, body.samples as usize)?
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+            codec => bail!("unknown codec: {}", codec),
+        };
+
+        ensure!(
+            payload.len() % (2 * SAMPLE_SZ) == 0,
+            "length of payload: {}, does not match expected number of values",
+            payload.len()
+        );
+
+        // The samples are serialized as little endian.
+        let values = payload.chunks_exact(2).map(|b| [b[0], b[1]]);
+
+        let values: Vec<f32> = if body.storage_version < 5 {
+            values
+                .map(|b| half::f16::from_le_bytes(b).to_f32())
+                .collect()
+        } else {
+            let accel_max = body.accel_max();
+            values
+                .map(|b| scale_u16_to_f32(accel_max, u16::from_le_bytes(b)))
+                .collect()
+        };
+
+        let x: Vec<f32> = values.iter().step_by(SAMPLE_SZ).copied().collect();
+        let y: Vec<f32> = values.iter().skip(1).step_by(SAMPLE_SZ).copied().collect();
+        let mut z: Vec<f32> = values.iter().skip(2).step_by(SAMPLE_SZ).copied().collect();
+
+        // Gravity is removed from the vertical component before it is scaled on the buoy.
+        if body.storage_version >= 5 {
+            z.iter_mut()
+                .for_each(|z| *z += SENSORS_GRAVITY_STANDARD as f32);
+        }
+
+        let dt = 1000. / body.freq as f64;
+        let time = (0..x.len())
+            .map(|i| body.timestamp as f64 + (i as f64 - body.offset as f64) * dt)
+            .collect();
+
+        Ok(Axl {
+            received,
+            event,
+            meta: body,
+            time,
+            x,
+            y,
+            z,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_limits() {
+        assert_eq!(scale_u16_to_f32(10., u16::MAX), 10.);
+        assert!((scale_u16_to_f32(10., u16::MAX / 2) - 0.).abs() < 0.001);
+        assert_eq!(scale_u16_to_f32(10., 0), -10.);
+    }
+
+    #[test]
+    fn decode_f16_event() {
+        let event = std::fs::read(
+            "tests/events/1647870799330-1876870b-4708-4366-8db5-68f872cc4e6d_axl.qo.json",
+        )
+        .unwrap();
+
+        let axl = Axl::from_event(1647870799330, "event".into(), &event).unwrap();
+
+        assert_eq!(axl.meta.storage_version, 1);
+        assert_eq!(axl.meta.freq, 52.);
+        assert_eq!(axl.x.len(), 1024);
+        assert_eq!(axl.y.len(), 1024);
+        assert_eq!(axl.z.len(), 1024);
+        assert_eq!(axl.time.len(), 1024);
+
+        assert_eq!(axl.time[0], 1647870457710.);
+        assert!((axl.time[1] - axl.time[0] - 1000. / 52.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn decode_u16_event() {
+        let accel_range = 4.;
+        let accel_max = 2. * accel_range * SENSORS_GRAVITY_STANDARD as f32;
+
+        // x = -max, y = 0, z = max
+        let data: Vec<u8> = [0u16, u16::MAX / 2 + 1, u16::MAX]
+            .iter()
+            .cycle()
+            .take(3 * 16)
+            .map(|u| u.to_le_bytes())
+            .flatten()
+            .collect();
+        let payload = base64::encode(&data);
+
+        let event = json::json!({
+            "body": {
+                "timestamp": 10_000,
+                "offset": 2,
+                "storage_version": 6,
+                "freq": 50.,
+                "accel_range": accel_range,
+                "gyro_range": 500.,
+                "length": payload.len(),
+            },
+            "payload": payload,
+        });
+        let event = json::to_vec(&event).unwrap();
+
+        let axl = Axl::from_event(0, "event".into(), &event).unwrap();
+
+        assert_eq!(axl.x.len(), 16);
+        assert!(axl.x.iter().all(|x| *x == -accel_max));
+        assert!(axl.y.iter().all(|y| y.abs() < 0.01));
+        assert!(axl
+            .z
+            .iter()
+            .all(|z| (*z - accel_max - SENSORS_GRAVITY_STANDARD as f32).abs() < 0.01));
+
+        assert_eq!(axl.time[0], 10_000. - 2. * 20.);
+        assert_eq!(axl.time[2], 10_000.);
+    }
+
+    #[test]
+    fn decode_rice_event() {
+        let accel_range = 4.;
+        let accel_max = 2. * accel_range * SENSORS_GRAVITY_STANDARD as f32;
+
+        // x = -max, y = 0, z = max: the residuals are all zero.
+        let bits: String = [0u16, u16::MAX / 2 + 1, u16::MAX]
+            .iter()
+            .map(|v| format!("{:016b}0000{}", v, "0".repeat(15)))
+            .collect();
+        let data: Vec<u8> = bits
+            .as_bytes()
+            .chunks(8)
+            .map(|b| u8::from_str_radix(&format!("{:0<8}", std::str::from_utf8(b).unwrap()), 2))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let payload = base64::encode(&data);
+
+        let event = json::json!({
+            "body": {
+                "timestamp": 10_000,
+                "offset": 2,
+                "storage_version": 8,
+                "freq": 50.,
+                "accel_range": accel_range,
+                "gyro_range": 500.,
+                "codec": Codec::Rice as u32,
+                "samples": 3 * 16,
+                "length": payload.len(),
+            },
+            "payload": payload,
+        });
+        let event = json::to_vec(&event).unwrap();
+
+        let axl = Axl::from_event(0, "event".into(), &event).unwrap();
+
+        assert_eq!(axl.x.len(), 16);
+        assert!(axl.x.iter().all(|x| *x == -accel_max));
+        assert!(axl.y.iter().all(|y| y.abs() < 0.01));
+        assert!(axl
+            .z
+            .iter()
+            .all(|z| (*z - accel_max - SENSORS_GRAVITY_STANDARD as f32).abs() < 0.01));
+    }
+
+    #[test]
+    fn bad_payload_length() {
+        let event = br#"{ "body": { "length": 4, "storage_version": 6 }, "payload": "AAAA" }"#;
+        assert!(Axl::from_event(0, "event".into(), event).is_err());
+    }
+}
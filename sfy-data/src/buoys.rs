@@ -1,24 +1,37 @@
 //! End-points for buoys.
 
+use crate::axl::Axl;
+use crate::database::{Buoy, BuoyType, Cursor};
+use crate::live::{LiveEvent, LiveFilter};
 use crate::State;
-use futures_util::future;
+use bytes::Bytes;
+use futures_util::{future, stream, Stream, StreamExt, TryStreamExt};
 use sanitize_filename::sanitize;
 use serde::{Deserialize, Serialize};
 use serde_json as json;
 use std::convert::Infallible;
+use std::io;
+use std::pin::Pin;
 use std::sync::Arc;
-use warp::{http::Response, http::StatusCode, reject, Filter, Rejection, Reply};
+use warp::{http::Response, http::StatusCode, hyper::Body, reject, Filter, Rejection, Reply};
+
+/// Number of entries fetched from the database at the time when streaming a range.
+pub const RANGE_PAGE_SZ: usize = 256;
 
 pub fn filters(
     state: State,
 ) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     append(state.clone())
         .or(append_omb(state.clone()))
+        .or(append_sbd(state.clone()))
         .or(list(state.clone()))
+        .or(live(state.clone()))
         .or(entries(state.clone()))
         .or(last(state.clone()))
         .or(range(state.clone()))
         .or(list_range(state.clone()))
+        .or(axl_range(state.clone()))
+        .or(netcdf_range(state.clone()))
         .or(entry(state.clone()))
 }
 
@@ -50,6 +63,21 @@ pub fn append_omb(
         .and_then(handlers::append_omb)
 }
 
+/// Iridium SBD messages from the RockBLOCK web service, posted as a form.
+pub fn append_sbd(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("buoy" / "sbd")
+        .and(warp::post())
+        .and(check_token_or_query(state.clone()))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::form())
+        .and(with_state(state.clone()))
+        .and_then(handlers::append_sbd)
+}
+
 pub fn list(
     state: State,
 ) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -62,6 +90,21 @@ pub fn list(
         .and_then(handlers::list)
 }
 
+/// Server-Sent Events of incoming events, filtered by the `dev` and `message_type` query
+/// parameters.
+pub fn live(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("buoys" / "live")
+        .and(warp::get())
+        .and(check_read_token_or_query(state.clone()))
+        .and(warp::query::<LiveFilter>())
+        .and(with_state(state.clone()))
+        .and_then(handlers::live)
+}
+
 pub fn entries(
     state: State,
 ) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -106,6 +149,7 @@ pub fn range(
     warp::path!("buoys" / String / "from" / i64 / "to" / i64)
         .and(warp::get())
         .and(check_read_token(state.clone()))
+        .and(warp::query::<RangeQuery>())
         .and(with_state(state.clone()))
         .and_then(handlers::range)
 }
@@ -118,15 +162,42 @@ pub fn list_range(
     warp::path!("buoys" / "list" / String / "from" / i64 / "to" / i64)
         .and(warp::get())
         .and(check_read_token(state.clone()))
+        .and(warp::query::<RangeQuery>())
         .and(with_state(state.clone()))
         .and_then(handlers::list_range)
 }
 
-fn with_state(state: State) -> impl Filter<Extract = (State,), Error = Infallible> + Clone {
+pub fn axl_range(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("buoys" / String / "axl" / "from" / i64 / "to" / i64)
+        .and(warp::get())
+        .and(check_read_token(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(handlers::axl_range)
+}
+
+pub fn netcdf_range(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("buoys" / String / "netcdf" / "from" / i64 / "to" / i64)
+        .and(warp::get())
+        .and(check_read_token(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(handlers::netcdf_range)
+}
+
+pub(crate) fn with_state(
+    state: State,
+) -> impl Filter<Extract = (State,), Error = Infallible> + Clone {
     warp::any().map(move || Arc::clone(&state))
 }
 
-fn check_token(state: State) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+pub(crate) fn check_token(state: State) -> impl Filter<Extract = (), Error = Rejection> + Clone {
     warp::header::<String>("SFY_AUTH_TOKEN")
         .and_then(move |v: String| {
             if state.config.tokens.contains(&v) {
@@ -139,7 +210,9 @@ fn check_token(state: State) -> impl Filter<Extract = (), Error = Rejection> + C
         .untuple_one()
 }
 
-fn check_read_token(state: State) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+pub(crate) fn check_read_token(
+    state: State,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
     warp::header::<String>("SFY_AUTH_TOKEN")
         .and_then(move |v: String| {
             if state.config.read_tokens.contains(&v) {
@@ -152,6 +225,47 @@ fn check_read_token(state: State) -> impl Filter<Extract = (), Error = Rejection
         .untuple_one()
 }
 
+#[derive(Debug, Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// Like `check_read_token`, but the token may also be passed as the `token` query parameter since
+/// browsers can not set headers on `EventSource` requests.
+fn check_read_token_or_query(state: State) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("SFY_AUTH_TOKEN")
+        .and(warp::query::<TokenQuery>())
+        .and_then(
+            move |header: Option<String>, query: TokenQuery| match header.or(query.token) {
+                Some(v) if state.config.read_tokens.contains(&v) => future::ok(()),
+                Some(v) => {
+                    warn!("rejected token: {}", v);
+                    future::err(reject::not_found())
+                }
+                None => future::err(reject::not_found()),
+            },
+        )
+        .untuple_one()
+}
+
+/// Like `check_token`, but the token may also be passed as the `token` query parameter since
+/// the RockBLOCK web service can not set headers.
+fn check_token_or_query(state: State) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("SFY_AUTH_TOKEN")
+        .and(warp::query::<TokenQuery>())
+        .and_then(
+            move |header: Option<String>, query: TokenQuery| match header.or(query.token) {
+                Some(v) if state.config.tokens.contains(&v) => future::ok(()),
+                Some(v) => {
+                    warn!("rejected token: {}", v);
+                    future::err(reject::not_found())
+                }
+                None => future::err(reject::not_found()),
+            },
+        )
+        .untuple_one()
+}
+
 #[derive(Debug)]
 struct OmbEvent {
     device: String,
@@ -204,11 +318,25 @@ struct Event {
     received: u64,
     name: Option<String>,
     file: Option<String>,
-    #[allow(unused)]
     body: json::Value,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Query parameters of range requests.
+#[derive(Debug, Deserialize)]
+pub struct RangeQuery {
+    /// Continue after this entry (`{received}-{event}`), e.g. the `X-Next` header of the
+    /// previous response or the last entry of an interrupted download.
+    pub after: Option<String>,
+
+    /// Number of entries in the response. Entries received while the response is streamed are
+    /// included when they fall within the page, so the response may hold more entries.
+    pub limit: Option<usize>,
+
+    /// `json` (default) or `ndjson` (one entry per line).
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct B64Event {
     pub received: i64,
     pub event: String,
@@ -284,6 +412,21 @@ pub mod handlers {
         Ok(warp::reply::json(&buoys))
     }
 
+    pub async fn live(
+        filter: LiveFilter,
+        state: State,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        debug!("new live subscriber: {:?}", filter);
+
+        let events = state.live.subscribe(filter).map(|e| {
+            warp::sse::Event::default()
+                .id(format!("{}-{}", e.event.received, e.event.event))
+                .json_data(&*e)
+        });
+
+        Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+    }
+
     pub async fn entries(buoy: String, state: State) -> Result<impl warp::Reply, warp::Rejection> {
         let buoy = sanitize(buoy);
 
@@ -339,7 +482,200 @@ pub mod handlers {
             .body(entry))
     }
 
+    /// Parse the cursor and format of a range query.
+    fn parse_range_query(query: &RangeQuery) -> eyre::Result<(Option<Cursor>, bool)> {
+        let after = query.after.as_deref().map(str::parse).transpose()?;
+
+        let ndjson = match query.format.as_deref() {
+            None | Some("json") => false,
+            Some("ndjson") => true,
+            Some(f) => return Err(eyre!("unknown format: {}", f)),
+        };
+
+        Ok((after, ndjson))
+    }
+
+    /// The cursor of the last entry of a response with `limit` entries, if there are that many
+    /// entries left in the range. The response is bounded by this cursor, so that resuming from it
+    /// does not skip entries received while the response is streamed.
+    async fn next_cursor(
+        buoy: &Buoy,
+        from: i64,
+        to: i64,
+        after: &Option<Cursor>,
+        limit: Option<usize>,
+    ) -> eyre::Result<Option<Cursor>> {
+        let limit = match limit {
+            Some(limit) if limit > 0 => limit,
+            _ => return Ok(None),
+        };
+
+        let after = after.clone().unwrap_or_else(|| Cursor::before(from));
+        buoy.range_cursor(from, to, &after, limit as i64 - 1).await
+    }
+
+    /// Serialize the entries while they are streamed from the database, either as a JSON array or
+    /// as newline delimited JSON.
+    async fn stream_response<T, S>(
+        entries: S,
+        ndjson: bool,
+        next: Option<Cursor>,
+    ) -> Result<warp::reply::Response, warp::Rejection>
+    where
+        T: Serialize + Send + 'static,
+        S: Stream<Item = eyre::Result<T>> + Send + 'static,
+    {
+        let mut entries = entries.boxed().peekable();
+
+        // Errors before the response has started (e.g. unknown buoy) can still be reported.
+        if let Some(Err(e)) = Pin::new(&mut entries).peek().await {
+            error!("failed to get range: {:?}", e);
+            return Err(reject::custom(AppendErrors::Internal));
+        }
+
+        let body = entries.enumerate().map(move |(i, e)| {
+            let e = e.map_err(|e| {
+                error!("failed to stream range: {:?}", e);
+                io::Error::new(io::ErrorKind::Other, e.to_string())
+            })?;
+
+            let mut b = Vec::new();
+            if i > 0 && !ndjson {
+                b.push(b',');
+            }
+            json::to_writer(&mut b, &e)?;
+            if ndjson {
+                b.push(b'\n');
+            }
+
+            Ok::<_, io::Error>(Bytes::from(b))
+        });
+
+        let body = if ndjson {
+            body.boxed()
+        } else {
+            stream::once(future::ok(Bytes::from_static(b"[")))
+                .chain(body)
+                .chain(stream::once(future::ok(Bytes::from_static(b"]"))))
+                .boxed()
+        };
+
+        let mut response = Response::builder().status(200).header(
+            "Content-Type",
+            if ndjson {
+                "application/x-ndjson"
+            } else {
+                "application/json"
+            },
+        );
+
+        if let Some(next) = next {
+            response = response.header("X-Next", next.to_string());
+        }
+
+        response
+            .body(Body::wrap_stream(body))
+            .map_err(|_| reject::custom(AppendErrors::Internal))
+    }
+
+    /// Stream the events of `buoy` in range, using the cursor, limit and format of `query`.
+    pub(crate) async fn stream_range(
+        buoy: Buoy,
+        from: i64,
+        to: i64,
+        query: RangeQuery,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        let (after, ndjson) = match parse_range_query(&query) {
+            Ok(q) => q,
+            Err(e) => {
+                warn!("bad range query: {:?}", e);
+                return Ok(StatusCode::BAD_REQUEST.into_response());
+            }
+        };
+
+        let next = next_cursor(&buoy, from, to, &after, query.limit)
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))?;
+
+        let page_sz = RANGE_PAGE_SZ.min(query.limit.unwrap_or(usize::MAX)).max(1) as i64;
+
+        let entries = buoy
+            .stream_range(from, to, after, next.clone(), page_sz)
+            .map_ok(|e| B64Event {
+                event: e.event,
+                received: e.received,
+                data: e.data.map(base64::encode),
+            });
+
+        stream_response(entries, ndjson, next).await
+    }
+
+    /// Stream the list of entries of `buoy` in range, using the cursor, limit and format of
+    /// `query`.
+    pub(crate) async fn stream_list_range(
+        buoy: Buoy,
+        from: i64,
+        to: i64,
+        query: RangeQuery,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        let (after, ndjson) = match parse_range_query(&query) {
+            Ok(q) => q,
+            Err(e) => {
+                warn!("bad range query: {:?}", e);
+                return Ok(StatusCode::BAD_REQUEST.into_response());
+            }
+        };
+
+        let next = next_cursor(&buoy, from, to, &after, query.limit)
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))?;
+
+        let page_sz = RANGE_PAGE_SZ.min(query.limit.unwrap_or(usize::MAX)).max(1) as i64;
+
+        let entries = buoy
+            .stream_list_range(from, to, after, next.clone(), page_sz)
+            .map_ok(|e| (format!("{}-{}", e.0, e.1), e.2));
+
+        stream_response(entries, ndjson, next).await
+    }
+
     pub async fn range(
+        buoy: String,
+        from: i64,
+        to: i64,
+        query: RangeQuery,
+        state: State,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        let buoy = sanitize(buoy);
+
+        let buoy = state
+            .db
+            .buoy(&buoy)
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))?;
+
+        stream_range(buoy, from, to, query).await
+    }
+
+    pub async fn list_range(
+        buoy: String,
+        from: i64,
+        to: i64,
+        query: RangeQuery,
+        state: State,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        let buoy = sanitize(buoy);
+
+        let buoy = state
+            .db
+            .buoy(&buoy)
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))?;
+
+        stream_list_range(buoy, from, to, query).await
+    }
+
+    pub async fn axl_range(
         buoy: String,
         from: i64,
         to: i64,
@@ -347,48 +683,90 @@ pub mod handlers {
     ) -> Result<impl warp::Reply, warp::Rejection> {
         let buoy = sanitize(buoy);
 
-        let entries: Vec<_> = state
+        let entries: Vec<Axl> = state
             .db
             .buoy(&buoy)
             .await
             .map_err(|_| reject::custom(AppendErrors::Internal))?
-            .get_range(from, to)
+            .get_axl_range(from, to)
             .await
             .map_err(|_| reject::custom(AppendErrors::Internal))?
             .into_iter()
-            .map(|e| B64Event {
-                event: e.event,
-                received: e.received,
-                data: e.data.map(base64::encode),
+            .filter_map(|e| {
+                let data = e.data?;
+
+                match Axl::from_event(e.received, e.event, &data) {
+                    Ok(axl) => Some(axl),
+                    Err(err) => {
+                        warn!("failed to decode axl event: {:?}", err);
+                        None
+                    }
+                }
             })
             .collect();
 
         Ok(warp::reply::json(&entries))
     }
 
-    pub async fn list_range(
+    pub async fn netcdf_range(
         buoy: String,
         from: i64,
         to: i64,
         state: State,
-    ) -> Result<impl warp::Reply, warp::Rejection> {
+    ) -> Result<warp::reply::Response, warp::Rejection> {
         let buoy = sanitize(buoy);
 
-        let entries = state
+        let b = state
             .db
             .buoy(&buoy)
             .await
-            .map_err(|_| reject::custom(AppendErrors::Internal))?
-            .list_range(from, to)
-            .await
             .map_err(|_| reject::custom(AppendErrors::Internal))?;
 
-        let entries: Vec<(String, String)> = entries
+        let entries: Vec<Axl> = b
+            .get_axl_range(from, to)
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))?
             .into_iter()
-            .map(|e| (format!("{}-{}", e.0, e.1), e.2))
+            .filter_map(|e| {
+                let data = e.data?;
+
+                match Axl::from_event(e.received, e.event, &data) {
+                    Ok(axl) => Some(axl),
+                    Err(err) => {
+                        warn!("failed to decode axl event: {:?}", err);
+                        None
+                    }
+                }
+            })
             .collect();
 
-        Ok(warp::reply::json(&entries))
+        let name = b.name().map(String::from);
+        let dev = buoy.clone();
+
+        // Writing NetCDF files is blocking.
+        let nc = tokio::task::spawn_blocking(move || {
+            let info = crate::nc::BuoyInfo {
+                dev: &dev,
+                name: name.as_deref(),
+            };
+            crate::nc::to_netcdf(&info, &entries)
+        })
+        .await
+        .map_err(|_| reject::custom(AppendErrors::Internal))?
+        .map_err(|e| {
+            error!("failed to export to netcdf: {:?}", e);
+            reject::custom(AppendErrors::Internal)
+        })?;
+
+        Ok(Response::builder()
+            .status(200)
+            .header("Content-Type", "application/x-netcdf")
+            .header(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}_{}_{}.nc\"", buoy, from, to),
+            )
+            .body(nc)
+            .into_response())
     }
 
     pub async fn append(
@@ -419,6 +797,8 @@ pub mod handlers {
                 let file = sanitize(&file);
                 debug!("writing to: {}", file);
 
+                let message_type = event.file.clone().unwrap_or_else(|| "unknown".into());
+
                 b.append(event.name, &file, event.received, event.file, &body)
                     .await
                     .map_err(|e| {
@@ -426,6 +806,28 @@ pub mod handlers {
                         reject::custom(AppendErrors::Database)
                     })?;
 
+                if message_type == "status.qo" {
+                    match crate::health::parse(&device, event.received as i64, &file, &event.body) {
+                        Ok(health) => {
+                            if let Err(e) = state.db.add_health(&health).await {
+                                error!("failed to add health for {}: {:?}", device, e);
+                            }
+                        }
+                        Err(e) => warn!("could not parse status from {}: {:?}", device, e),
+                    }
+                }
+
+                state.live.publish(LiveEvent {
+                    dev: device,
+                    buoy_type: BuoyType::SFY.into(),
+                    message_type,
+                    event: B64Event {
+                        received: event.received as i64,
+                        event: file,
+                        data: Some(base64::encode(&body)),
+                    },
+                });
+
                 Ok("".into_response())
             }
 
@@ -480,18 +882,87 @@ pub mod handlers {
                 reject::custom(AppendErrors::Database)
             })?;
 
-            b.append_omb(event.account, event.received, event.message_type, &body)
+            let id = b
+                .append_omb(event.account, event.received, event.message_type, &body)
                 .await
                 .map_err(|e| {
                     error!("failed to write file: {:?}", e);
                     reject::custom(AppendErrors::Database)
                 })?;
 
+            let message_type = event.message_type.to_str();
+
+            state.live.publish(LiveEvent {
+                dev: device,
+                buoy_type: BuoyType::OMB.into(),
+                message_type: message_type.into(),
+                event: B64Event {
+                    received: event.received as i64,
+                    event: format!("{}-{}", id, message_type),
+                    data: Some(base64::encode(&body)),
+                },
+            });
+
             return Ok("".into_response());
         }
 
         Ok(StatusCode::BAD_REQUEST.into_response())
     }
+
+    pub async fn append_sbd(
+        msg: crate::sbd::RockBlockMessage,
+        state: State,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        trace!("got sbd message: {:?}", msg);
+
+        let event = match msg.event() {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("could not decode sbd message: {:?}, error: {:?}", msg, e);
+                return Ok(StatusCode::BAD_REQUEST.into_response());
+            }
+        };
+
+        let device = sanitize(&event.device);
+        info!(
+            "sbd event: {} ({}), file: {}",
+            event.event, device, event.file
+        );
+
+        let mut b = state.db.buoy(&device).await.map_err(|e| {
+            error!("failed to open database for device: {}: {:?}", &device, e);
+            reject::custom(AppendErrors::Database)
+        })?;
+
+        let file = sanitize(&format!("{}_{}.json", event.event, event.file));
+        debug!("writing to: {}", file);
+
+        b.append(
+            None,
+            &file,
+            event.received,
+            Some(event.file.clone()),
+            &event.data,
+        )
+        .await
+        .map_err(|e| {
+            error!("failed to write file: {:?}", e);
+            reject::custom(AppendErrors::Database)
+        })?;
+
+        state.live.publish(LiveEvent {
+            dev: device,
+            buoy_type: BuoyType::SFY.into(),
+            message_type: event.file,
+            event: B64Event {
+                received: event.received as i64,
+                event: file,
+                data: Some(base64::encode(&event.data)),
+            },
+        });
+
+        Ok("".into_response())
+    }
 }
 
 #[cfg(test)]
@@ -597,6 +1068,58 @@ mod tests {
         assert_eq!(res.status(), 400);
     }
 
+    #[tokio::test]
+    async fn append_sbd() {
+        let state = crate::test_state().await;
+        let f = filters(state.clone());
+
+        let form = "imei=300434063000000&momsn=12&transmit_time=23-11-14%2022%3A19%3A12&iridium_latitude=60.4&iridium_longitude=5.3&iridium_cep=3.0&data=0101f0f353654ff2536560c8fe23803bd4fc";
+
+        let res = warp::test::request()
+            .path("/buoy/sbd?token=wrong-token")
+            .method("POST")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(form)
+            .reply(&f)
+            .await;
+
+        assert!(res.status() != 200);
+
+        let res = warp::test::request()
+            .path("/buoy/sbd?token=token1")
+            .method("POST")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(form)
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let e = state
+            .db
+            .buoy("imei300434063000000")
+            .await
+            .unwrap()
+            .get("1700000352000-300434063000000-12_pos.sbd.json")
+            .await
+            .unwrap();
+
+        let e: json::Value = json::from_slice(&e).unwrap();
+        assert_eq!(e["file"], "pos.sbd");
+        assert_eq!(e["body"]["lon"], -5.32);
+
+        // Not a valid message.
+        let res = warp::test::request()
+            .path("/buoy/sbd?token=token1")
+            .method("POST")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(form.replace("data=01", "data=09"))
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 400);
+    }
+
     #[tokio::test]
     async fn list_buoys() {
         let state = crate::test_state().await;
@@ -637,6 +1160,75 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn live_events() {
+        use warp::hyper::body::HttpBody;
+
+        let state = crate::test_state().await;
+        let f = filters(state);
+
+        // Missing token
+        let res = warp::test::request()
+            .path("/buoys/live")
+            .method("GET")
+            .reply(&f)
+            .await;
+
+        assert!(res.status() != 200);
+
+        let live = warp::test::request()
+            .path("/buoys/live?token=r-token1&dev=dev864475044203262&message_type=sensor.db")
+            .method("GET")
+            .filter(&f)
+            .await
+            .unwrap();
+        let mut body = live.into_response().into_body();
+
+        // Filtered out
+        let event = std::fs::read(
+            "tests/events/1647870799330-1876870b-4708-4366-8db5-68f872cc4e6d_axl.qo.json",
+        )
+        .unwrap();
+        let res = warp::test::request()
+            .path("/buoy")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .body(&event)
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let event = std::fs::read("tests/events/sensor.db_01.json").unwrap();
+        let res = warp::test::request()
+            .path("/buoy")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .body(&event)
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), body.data())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let chunk = std::str::from_utf8(&chunk).unwrap();
+
+        assert!(
+            chunk.contains("id:1639059643089-9ef2e080-f0b4-4036-8ccc-ec4206553537_sensor.db.json")
+        );
+
+        let data = chunk.lines().find_map(|l| l.strip_prefix("data:")).unwrap();
+        let live: LiveEvent = json::from_str(data).unwrap();
+
+        assert_eq!(live.dev, "dev864475044203262");
+        assert_eq!(live.message_type, "sensor.db");
+        assert_eq!(live.event.data, Some(base64::encode(&event)));
+    }
+
     #[tokio::test]
     async fn list_entries() {
         let state = crate::test_state().await;
@@ -876,4 +1468,207 @@ mod tests {
             ]
         );
     }
+
+    #[tokio::test]
+    async fn range_paged() {
+        let state = crate::test_state().await;
+
+        let f = filters(state);
+
+        let events = [
+            r#"{"received": 0, "event": "event-0", "device": "0", "name": "0", "file": "axl.qo" }"#,
+            r#"{"received": 1, "event": "event-1", "device": "0", "name": "0", "file": "axl.qo" }"#,
+            r#"{"received": 1, "event": "event-2", "device": "0", "name": "0", "file": "axl.qo" }"#,
+            r#"{"received": 2, "event": "event-3", "device": "0", "name": "0", "file": "axl.qo" }"#,
+        ];
+
+        for e0 in events {
+            let res = warp::test::request()
+                .path("/buoy")
+                .method("POST")
+                .header("SFY_AUTH_TOKEN", "token1")
+                .body(&e0)
+                .reply(&f)
+                .await;
+
+            assert_eq!(res.status(), 200);
+        }
+
+        let res = warp::test::request()
+            .path("/buoys/0/from/0/to/3000?limit=2")
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let revents: Vec<B64Event> = json::from_slice(res.body()).unwrap();
+        assert_eq!(revents.len(), 2);
+        assert_eq!(revents[1].event, "event-1_axl.qo.json");
+
+        let next = res.headers().get("X-Next").unwrap().to_str().unwrap();
+        assert_eq!(next, "1000-event-1_axl.qo.json");
+
+        // Continue as newline delimited JSON.
+        let res = warp::test::request()
+            .path(&format!(
+                "/buoys/0/from/0/to/3000?format=ndjson&after={}",
+                next
+            ))
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            res.headers().get("Content-Type").unwrap().to_str().unwrap(),
+            "application/x-ndjson"
+        );
+        assert!(res.headers().get("X-Next").is_none());
+
+        let revents: Vec<B64Event> = std::str::from_utf8(res.body())
+            .unwrap()
+            .lines()
+            .map(|l| json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(revents.len(), 2);
+        assert_eq!(revents[0].event, "event-2_axl.qo.json");
+        assert_eq!(revents[1].event, "event-3_axl.qo.json");
+
+        // List
+        let res = warp::test::request()
+            .path("/buoys/list/0/from/0/to/3000?after=1000-event-2_axl.qo.json")
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let revents: Vec<(String, String)> = json::from_slice(res.body()).unwrap();
+        assert_eq!(
+            &revents,
+            &[("2000-event-3_axl.qo.json".into(), "axl.qo".into())]
+        );
+
+        // Empty range
+        let res = warp::test::request()
+            .path("/buoys/0/from/5000/to/6000")
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.body(), "[]");
+
+        // Bad cursor and format
+        for q in ["after=bad", "format=xml"] {
+            let res = warp::test::request()
+                .path(&format!("/buoys/0/from/0/to/3000?{}", q))
+                .method("GET")
+                .header("SFY_AUTH_TOKEN", "r-token1")
+                .reply(&f)
+                .await;
+
+            assert_eq!(res.status(), 400);
+        }
+    }
+
+    #[tokio::test]
+    async fn axl_range() {
+        let state = crate::test_state().await;
+
+        let f = filters(state);
+
+        let event = std::fs::read(
+            "tests/events/1647870799330-1876870b-4708-4366-8db5-68f872cc4e6d_axl.qo.json",
+        )
+        .unwrap();
+        let res = warp::test::request()
+            .path("/buoy")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .body(&event)
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        // Missing token
+        let res = warp::test::request()
+            .path("/buoys/dev867730051260788/axl/from/0/to/1747870799330")
+            .method("GET")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 400);
+
+        let res = warp::test::request()
+            .path("/buoys/dev867730051260788/axl/from/0/to/1747870799330")
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let axl: Vec<Axl> = json::from_slice(res.body()).unwrap();
+        assert_eq!(axl.len(), 1);
+        assert_eq!(axl[0].received, 1647870799252);
+        assert_eq!(axl[0].meta.freq, 52.);
+        assert_eq!(axl[0].z.len(), 1024);
+        assert_eq!(axl[0].time.len(), 1024);
+
+        let res = warp::test::request()
+            .path("/buoys/dev867730051260788/axl/from/0/to/1000")
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let axl: Vec<Axl> = json::from_slice(res.body()).unwrap();
+        assert_eq!(axl.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn netcdf_range() {
+        let state = crate::test_state().await;
+
+        let f = filters(state);
+
+        let event = std::fs::read(
+            "tests/events/1647870799330-1876870b-4708-4366-8db5-68f872cc4e6d_axl.qo.json",
+        )
+        .unwrap();
+        let res = warp::test::request()
+            .path("/buoy")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .body(&event)
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let res = warp::test::request()
+            .path("/buoys/dev867730051260788/netcdf/from/0/to/1747870799330")
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            res.headers().get("Content-Type").unwrap().to_str().unwrap(),
+            "application/x-netcdf"
+        );
+
+        // HDF5 signature (NetCDF-4)
+        assert_eq!(&res.body()[..8], b"\x89HDF\r\n\x1a\n");
+    }
 }
@@ -11,6 +11,23 @@ pub struct Config {
     pub tokens: Vec<String>,
     pub read_tokens: Vec<String>,
     pub files: Option<PathBuf>,
+
+    /// Directory for data requests to buoys, when Notehub is not configured.
+    pub requests: Option<PathBuf>,
+    pub notehub: Option<Notehub>,
+}
+
+/// Access to the Notehub API for sending requests to the buoys.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notehub {
+    /// Project UID, e.g. `app:...`.
+    pub project: String,
+
+    /// Session token (`X-SESSION-TOKEN`).
+    pub token: String,
+
+    /// Defaults to `requests::NOTEHUB_API`.
+    pub api: Option<String>,
 }
 
 impl Config {
@@ -21,6 +38,8 @@ impl Config {
             tokens: Vec::new(),
             read_tokens: Vec::new(),
             files: None,
+            requests: None,
+            notehub: None,
         }
     }
 
@@ -32,6 +51,8 @@ impl Config {
             tokens: vec!["token1".into()],
             read_tokens: vec!["r-token1".into()],
             files: None,
+            requests: None,
+            notehub: None,
         }
     }
 
@@ -1,4 +1,5 @@
 use eyre::Result;
+use futures_util::{stream, Stream, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::{
     SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous,
@@ -159,6 +160,138 @@ impl Database {
         Ok(buoys)
     }
 
+    /// Get list of deployments, optionally only for buoy `dev`.
+    pub async fn deployments(&self, dev: Option<&str>) -> Result<Vec<Deployment>> {
+        let deployments = sqlx::query_as!(
+            Deployment,
+            "SELECT id, dev, campaign, name, start_time, end_time, lon, lat, hardware, features, description FROM deployments WHERE (?1 IS NULL OR dev = ?1) ORDER BY dev, start_time",
+            dev
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(deployments)
+    }
+
+    pub async fn deployment(&self, id: i64) -> Result<Option<Deployment>> {
+        let deployment = sqlx::query_as!(
+            Deployment,
+            "SELECT id, dev, campaign, name, start_time, end_time, lon, lat, hardware, features, description FROM deployments WHERE id = ?1",
+            id
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(deployment)
+    }
+
+    /// Add new deployment, the `id` is ignored and the id of the new deployment is returned.
+    pub async fn add_deployment(&self, d: &Deployment) -> Result<i64> {
+        d.validate()?;
+
+        let id = sqlx::query!(
+            "INSERT INTO deployments (dev, campaign, name, start_time, end_time, lon, lat, hardware, features, description) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10 )",
+            d.dev,
+            d.campaign,
+            d.name,
+            d.start_time,
+            d.end_time,
+            d.lon,
+            d.lat,
+            d.hardware,
+            d.features,
+            d.description
+        )
+        .execute(&self.db)
+        .await?
+        .last_insert_rowid();
+
+        info!("added deployment {} for {}", id, d.dev);
+
+        Ok(id)
+    }
+
+    /// Update the deployment with id `d.id`.
+    pub async fn update_deployment(&self, d: &Deployment) -> Result<()> {
+        d.validate()?;
+
+        let n = sqlx::query!(
+            "UPDATE deployments SET dev = ?2, campaign = ?3, name = ?4, start_time = ?5, end_time = ?6, lon = ?7, lat = ?8, hardware = ?9, features = ?10, description = ?11 WHERE id = ?1",
+            d.id,
+            d.dev,
+            d.campaign,
+            d.name,
+            d.start_time,
+            d.end_time,
+            d.lon,
+            d.lat,
+            d.hardware,
+            d.features,
+            d.description
+        )
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+
+        ensure!(n == 1, "No such deployment: {}", d.id);
+
+        Ok(())
+    }
+
+    pub async fn remove_deployment(&self, id: i64) -> Result<()> {
+        let n = sqlx::query!("DELETE FROM deployments WHERE id = ?1", id)
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+
+        ensure!(n == 1, "No such deployment: {}", id);
+
+        info!("removed deployment {}", id);
+
+        Ok(())
+    }
+
+    /// Add (or replace) the health and status of a buoy.
+    pub async fn add_health(&self, h: &Health) -> Result<()> {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO health (dev, received, event, uptime, resets, reset_reason, voltage, imu_temperature, next_id, sd_free, storage_queue, note_queue, egps_queue, fifo_overrun, too_few_samples) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15 )",
+            h.dev,
+            h.received,
+            h.event,
+            h.uptime,
+            h.resets,
+            h.reset_reason,
+            h.voltage,
+            h.imu_temperature,
+            h.next_id,
+            h.sd_free,
+            h.storage_queue,
+            h.note_queue,
+            h.egps_queue,
+            h.fifo_overrun,
+            h.too_few_samples
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Health and status of buoy `dev` between `start` and `end` (inclusive).
+    pub async fn health(&self, dev: &str, start: i64, end: i64) -> Result<Vec<Health>> {
+        let health = sqlx::query_as!(
+            Health,
+            "SELECT dev, received, event, uptime, resets, reset_reason, voltage, imu_temperature, next_id, sd_free, storage_queue, note_queue, egps_queue, fifo_overrun, too_few_samples FROM health WHERE dev = ?1 AND received >= ?2 AND received <= ?3 ORDER BY received",
+            dev,
+            start,
+            end
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(health)
+    }
+
     #[cfg(test)]
     pub async fn temporary() -> Database {
         warn!("create temporary database at in memory");
@@ -167,7 +300,7 @@ impl Database {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Buoy {
     dev: String,
     /// Does the buoy exist in the database already.
@@ -184,7 +317,123 @@ pub struct Event {
     pub data: Option<Vec<u8>>,
 }
 
+/// Health and status of a buoy from a `status.qo` note.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct Health {
+    pub dev: String,
+    pub received: i64,
+    pub event: String,
+    /// Time since start of main loop [s].
+    pub uptime: i64,
+    /// Resets since last power-on.
+    pub resets: i64,
+    pub reset_reason: Option<String>,
+    /// Supply voltage [V].
+    pub voltage: f64,
+    pub imu_temperature: f64,
+    pub next_id: Option<i64>,
+    /// Estimated free space on SD-card [MB].
+    pub sd_free: Option<i64>,
+    pub storage_queue: i64,
+    pub note_queue: i64,
+    pub egps_queue: i64,
+    pub fifo_overrun: i64,
+    pub too_few_samples: i64,
+}
+
+/// A deployment (e.g. as part of a campaign) of a buoy. Times are in milliseconds since epoch,
+/// like `received`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Deployment {
+    /// Set by the database.
+    #[serde(default)]
+    pub id: i64,
+    pub dev: String,
+    pub campaign: Option<String>,
+    pub name: Option<String>,
+    pub start_time: i64,
+    /// Ongoing deployments have no end time.
+    pub end_time: Option<i64>,
+    /// Position of deployment.
+    pub lon: Option<f64>,
+    pub lat: Option<f64>,
+    /// Hardware version.
+    pub hardware: Option<String>,
+    /// Comma separated firmware features, e.g. `raw,20Hz,ext-gps`.
+    pub features: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Deployment {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.dev.is_empty(), "Deployment has no buoy");
+        ensure!(
+            self.end_time.map_or(true, |end| end >= self.start_time),
+            "Deployment ends before it starts"
+        );
+
+        Ok(())
+    }
+
+    /// The time range of the deployment.
+    pub fn range(&self) -> (i64, i64) {
+        (self.start_time, self.end_time.unwrap_or(i64::MAX))
+    }
+}
+
+/// Position of an entry in a range, used to resume a range after the entry. Formatted as the entry
+/// names: `{received}-{event}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub received: i64,
+    pub event: String,
+}
+
+impl Cursor {
+    /// Cursor before all entries received at `received`.
+    pub fn before(received: i64) -> Cursor {
+        Cursor {
+            received,
+            event: String::new(),
+        }
+    }
+
+    /// The OMB event id is the first part of the event.
+    fn omb_event(&self) -> Result<i64> {
+        match self.event.split('-').next() {
+            Some("") | None => Ok(i64::MIN),
+            Some(e) => Ok(e.parse()?),
+        }
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Cursor> {
+        let (received, event) = s
+            .split_once('-')
+            .ok_or(eyre!("incorrect format of cursor"))?;
+
+        Ok(Cursor {
+            received: received.parse()?,
+            event: event.into(),
+        })
+    }
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.received, self.event)
+    }
+}
+
 impl Buoy {
+    /// Name (serial number) of buoy, if known.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Append new event to SFY buoy, `name` is parsed serial number of buoy.
     pub async fn append(
         &mut self,
@@ -248,14 +497,14 @@ impl Buoy {
         Ok(())
     }
 
-    /// Append to OpenMetBuoy (OMB)
+    /// Append to OpenMetBuoy (OMB), returns the id of the new event.
     pub async fn append_omb(
         &mut self,
         account: String,
         received: u64,
         message_type: OmbMessageType,
         data: impl AsRef<[u8]>,
-    ) -> eyre::Result<()> {
+    ) -> eyre::Result<i64> {
         let data = data.as_ref();
 
         self.buoy_type = BuoyType::OMB;
@@ -282,7 +531,7 @@ impl Buoy {
 
         let message_type = message_type.to_str();
         let r = received as i64;
-        sqlx::query!(
+        let event = sqlx::query!(
             "INSERT INTO omb_events (dev, received, account, message_type, data) VALUES ( ?1, ?2, ?3, ?4, ?5 )",
             self.dev,
             r,
@@ -291,9 +540,10 @@ impl Buoy {
             data
         )
         .execute(&self.db)
-        .await?;
+        .await?
+        .last_insert_rowid();
 
-        Ok(())
+        Ok(event)
     }
 
     pub async fn entries(&self) -> Result<Vec<(String, String)>> {
@@ -456,6 +706,260 @@ impl Buoy {
 
         Ok(events)
     }
+
+    /// List at most `limit` entries in range, after `after` and up to and including `until`.
+    pub async fn list_range_page(
+        &self,
+        start: i64,
+        end: i64,
+        after: &Cursor,
+        until: &Cursor,
+        limit: i64,
+    ) -> Result<Vec<(i64, String, String)>> {
+        ensure!(self.known, "No such buoy");
+
+        let events = match self.buoy_type {
+            BuoyType::SFY => {
+                sqlx::query!(
+                    "SELECT event, received, message_type FROM events WHERE dev = ?1 AND received >= ?2 AND received <= ?3 AND (received > ?4 OR (received = ?4 AND event > ?5)) AND (received < ?6 OR (received = ?6 AND event <= ?7)) ORDER BY received, event LIMIT ?8",
+                    self.dev,
+                    start,
+                    end,
+                    after.received,
+                    after.event,
+                    until.received,
+                    until.event,
+                    limit,
+                )
+                .map(|r| (r.received, r.event, r.message_type))
+                .fetch_all(&self.db)
+                .await?
+            },
+            BuoyType::OMB => {
+                let event = after.omb_event()?;
+                let until_event = until.omb_event()?;
+
+                sqlx::query!(
+                    "SELECT event, message_type, received FROM omb_events WHERE dev = ?1 AND received >= ?2 AND received <= ?3 AND (received > ?4 OR (received = ?4 AND event > ?5)) AND (received < ?6 OR (received = ?6 AND event <= ?7)) ORDER BY received, event LIMIT ?8",
+                    self.dev,
+                    start,
+                    end,
+                    after.received,
+                    event,
+                    until.received,
+                    until_event,
+                    limit,
+                )
+                .map(|r| (r.received, format!("{}-{}", r.event, r.message_type), r.message_type))
+                .fetch_all(&self.db)
+                .await?
+            },
+            BuoyType::Unknown => return Err(eyre!("Unknown buoy type"))
+        };
+
+        Ok(events)
+    }
+
+    /// Get at most `limit` events in range, after `after` and up to and including `until`.
+    pub async fn get_range_page(
+        &self,
+        start: i64,
+        end: i64,
+        after: &Cursor,
+        until: &Cursor,
+        limit: i64,
+    ) -> Result<Vec<Event>> {
+        ensure!(self.known, "No such buoy");
+
+        let events = match self.buoy_type {
+            BuoyType::SFY => {
+                sqlx::query_as!(
+                    Event,
+                    "SELECT event, received, data FROM events WHERE dev = ?1 AND received >= ?2 AND received <= ?3 AND (received > ?4 OR (received = ?4 AND event > ?5)) AND (received < ?6 OR (received = ?6 AND event <= ?7)) ORDER BY received, event LIMIT ?8",
+                    self.dev,
+                    start,
+                    end,
+                    after.received,
+                    after.event,
+                    until.received,
+                    until.event,
+                    limit,
+                )
+                .fetch_all(&self.db)
+                .await?
+            },
+            BuoyType::OMB => {
+                let event = after.omb_event()?;
+                let until_event = until.omb_event()?;
+
+                sqlx::query!(
+                    "SELECT event, message_type, received, data FROM omb_events WHERE dev = ?1 AND received >= ?2 AND received <= ?3 AND (received > ?4 OR (received = ?4 AND event > ?5)) AND (received < ?6 OR (received = ?6 AND event <= ?7)) ORDER BY received, event LIMIT ?8",
+                    self.dev,
+                    start,
+                    end,
+                    after.received,
+                    event,
+                    until.received,
+                    until_event,
+                    limit,
+                )
+                .map(|r| Event { event: format!("{}-{}", r.event, r.message_type), received: r.received, data: r.data })
+                .fetch_all(&self.db)
+                .await?
+            },
+            BuoyType::Unknown => return Err(eyre!("Unknown buoy type"))
+        };
+
+        Ok(events)
+    }
+
+    /// The cursor of the entry `offset` entries after `after` in range, if there is one.
+    pub async fn range_cursor(
+        &self,
+        start: i64,
+        end: i64,
+        after: &Cursor,
+        offset: i64,
+    ) -> Result<Option<Cursor>> {
+        ensure!(self.known, "No such buoy");
+
+        let cursor = match self.buoy_type {
+            BuoyType::SFY => {
+                sqlx::query!(
+                    "SELECT received, event FROM events WHERE dev = ?1 AND received >= ?2 AND received <= ?3 AND (received > ?4 OR (received = ?4 AND event > ?5)) ORDER BY received, event LIMIT 1 OFFSET ?6",
+                    self.dev,
+                    start,
+                    end,
+                    after.received,
+                    after.event,
+                    offset,
+                )
+                .map(|r| Cursor { received: r.received, event: r.event })
+                .fetch_optional(&self.db)
+                .await?
+            },
+            BuoyType::OMB => {
+                let event = after.omb_event()?;
+
+                sqlx::query!(
+                    "SELECT received, event, message_type FROM omb_events WHERE dev = ?1 AND received >= ?2 AND received <= ?3 AND (received > ?4 OR (received = ?4 AND event > ?5)) ORDER BY received, event LIMIT 1 OFFSET ?6",
+                    self.dev,
+                    start,
+                    end,
+                    after.received,
+                    event,
+                    offset,
+                )
+                .map(|r| Cursor { received: r.received, event: format!("{}-{}", r.event, r.message_type) })
+                .fetch_optional(&self.db)
+                .await?
+            },
+            BuoyType::Unknown => return Err(eyre!("Unknown buoy type"))
+        };
+
+        Ok(cursor)
+    }
+
+    /// Stream the entries in range (after `after` and up to and including `until`), fetching
+    /// `page_sz` entries from the database at the time.
+    pub fn stream_list_range(
+        self,
+        start: i64,
+        end: i64,
+        after: Option<Cursor>,
+        until: Option<Cursor>,
+        page_sz: i64,
+    ) -> impl Stream<Item = Result<(i64, String, String)>> + Send + 'static {
+        let after = after.unwrap_or_else(|| Cursor::before(start));
+        let until = until.unwrap_or_else(|| Cursor::before(end.saturating_add(1)));
+
+        stream::try_unfold(
+            (self, until, Some(after)),
+            move |(buoy, until, after)| async move {
+                let after = match after {
+                    Some(after) => after,
+                    None => return Ok(None),
+                };
+
+                let entries = buoy
+                    .list_range_page(start, end, &after, &until, page_sz)
+                    .await?;
+
+                let next = match entries.last() {
+                    Some((received, event, _)) if entries.len() as i64 == page_sz => Some(Cursor {
+                        received: *received,
+                        event: event.clone(),
+                    }),
+                    _ => None,
+                };
+
+                Ok(Some((entries, (buoy, until, next))))
+            },
+        )
+        .map_ok(|entries| stream::iter(entries.into_iter().map(Ok::<_, eyre::Report>)))
+        .try_flatten()
+    }
+
+    /// Stream the events in range (after `after` and up to and including `until`), fetching
+    /// `page_sz` events from the database at the time.
+    pub fn stream_range(
+        self,
+        start: i64,
+        end: i64,
+        after: Option<Cursor>,
+        until: Option<Cursor>,
+        page_sz: i64,
+    ) -> impl Stream<Item = Result<Event>> + Send + 'static {
+        let after = after.unwrap_or_else(|| Cursor::before(start));
+        let until = until.unwrap_or_else(|| Cursor::before(end.saturating_add(1)));
+
+        stream::try_unfold(
+            (self, until, Some(after)),
+            move |(buoy, until, after)| async move {
+                let after = match after {
+                    Some(after) => after,
+                    None => return Ok(None),
+                };
+
+                let events = buoy
+                    .get_range_page(start, end, &after, &until, page_sz)
+                    .await?;
+
+                let next = match events.last() {
+                    Some(e) if events.len() as i64 == page_sz => Some(Cursor {
+                        received: e.received,
+                        event: e.event.clone(),
+                    }),
+                    _ => None,
+                };
+
+                Ok(Some((events, (buoy, until, next))))
+            },
+        )
+        .map_ok(|events| stream::iter(events.into_iter().map(Ok::<_, eyre::Report>)))
+        .try_flatten()
+    }
+
+    /// Get the `axl.qo` events in the range.
+    pub async fn get_axl_range(&self, start: i64, end: i64) -> Result<Vec<Event>> {
+        ensure!(self.known, "No such buoy");
+        ensure!(
+            self.buoy_type == BuoyType::SFY,
+            "Only SFY buoys have axl events"
+        );
+
+        let events = sqlx::query_as!(
+            Event,
+            "SELECT event, received, data FROM events WHERE dev = ?1 AND message_type = 'axl.qo' AND received >= ?2 AND received <= ?3 ORDER BY received",
+            self.dev,
+            start,
+            end,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(events)
+    }
 }
 
 #[cfg(test)]
@@ -585,6 +1089,183 @@ mod tests {
         assert_eq!(data, &Some(b"data-3".to_vec()));
     }
 
+    #[tokio::test]
+    async fn stream_range_pages() {
+        let db = Database::temporary().await;
+        let mut b = db.buoy("buoy-01").await.unwrap();
+        b.append(None, "entry-0", 0, None, "data-0").await.unwrap();
+        b.append(None, "entry-1", 1, None, "data-1").await.unwrap();
+        b.append(None, "entry-2", 1, None, "data-2").await.unwrap();
+        b.append(None, "entry-3", 2, None, "data-3").await.unwrap();
+        b.append(None, "entry-4", 3, None, "data-4").await.unwrap();
+
+        for page_sz in [1, 2, 3, 10] {
+            let r: Vec<Event> = b
+                .clone()
+                .stream_range(0, 2, None, None, page_sz)
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(r, b.get_range(0, 2).await.unwrap());
+        }
+
+        let after: Cursor = "1-entry-1".parse().unwrap();
+        let r: Vec<Event> = b
+            .clone()
+            .stream_range(0, 2, Some(after), None, 2)
+            .try_collect()
+            .await
+            .unwrap();
+        let events: Vec<_> = r.iter().map(|e| e.event.as_str()).collect();
+        assert_eq!(events, ["entry-2", "entry-3"]);
+
+        let r: Vec<_> = b
+            .clone()
+            .stream_list_range(1, 3, None, None, 2)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(r, b.list_range(1, 3).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn range_cursor_bounds_page() {
+        let db = Database::temporary().await;
+        let mut b = db.buoy("buoy-01").await.unwrap();
+        b.append(None, "entry-0", 0, None, "data-0").await.unwrap();
+        b.append(None, "entry-1", 1, None, "data-1").await.unwrap();
+        b.append(None, "entry-3", 1, None, "data-3").await.unwrap();
+        b.append(None, "entry-4", 2, None, "data-4").await.unwrap();
+
+        let start = Cursor::before(0);
+        let next = b.range_cursor(0, 2, &start, 2).await.unwrap().unwrap();
+        assert_eq!(next.to_string(), "1-entry-3");
+        assert!(b.range_cursor(0, 2, &start, 4).await.unwrap().is_none());
+
+        // Received late, but within the page.
+        b.append(None, "entry-2", 1, None, "data-2").await.unwrap();
+
+        let r: Vec<Event> = b
+            .clone()
+            .stream_range(0, 2, None, Some(next.clone()), 2)
+            .try_collect()
+            .await
+            .unwrap();
+        let events: Vec<_> = r.iter().map(|e| e.event.as_str()).collect();
+        assert_eq!(events, ["entry-0", "entry-1", "entry-2", "entry-3"]);
+
+        let r: Vec<_> = b
+            .clone()
+            .stream_list_range(0, 2, Some(next), None, 2)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(r, [(2, "entry-4".to_string(), "unknown".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn stream_omb_range_pages() {
+        let db = Database::temporary().await;
+        let mut b = db.buoy("buoy 01-omb").await.unwrap();
+        for i in 0..5 {
+            b.append_omb("testacc".into(), i / 2, OmbMessageType::GPS, "data")
+                .await
+                .unwrap();
+        }
+
+        let r: Vec<Event> = b
+            .clone()
+            .stream_range(0, 10, None, None, 2)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(r, b.get_range(0, 10).await.unwrap());
+
+        let after = Cursor {
+            received: r[1].received,
+            event: r[1].event.clone(),
+        };
+        let r2: Vec<Event> = b
+            .clone()
+            .stream_range(0, 10, Some(after), None, 2)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(&r2[..], &r[2..]);
+    }
+
+    #[tokio::test]
+    async fn add_update_remove_deployment() {
+        let db = Database::temporary().await;
+
+        let mut d = Deployment {
+            id: 0,
+            dev: "buoy-01".into(),
+            campaign: Some("campaign-01".into()),
+            name: None,
+            start_time: 1000,
+            end_time: None,
+            lon: Some(5.3),
+            lat: Some(60.4),
+            hardware: Some("v3".into()),
+            features: Some("raw,20Hz".into()),
+            description: None,
+        };
+
+        d.id = db.add_deployment(&d).await.unwrap();
+        assert_eq!(db.deployment(d.id).await.unwrap(), Some(d.clone()));
+        assert_eq!(d.range(), (1000, i64::MAX));
+
+        d.end_time = Some(2000);
+        db.update_deployment(&d).await.unwrap();
+        assert_eq!(db.deployments(Some("buoy-01")).await.unwrap(), [d.clone()]);
+        assert_eq!(db.deployments(None).await.unwrap(), [d.clone()]);
+        assert!(db.deployments(Some("buoy-02")).await.unwrap().is_empty());
+
+        d.end_time = Some(0);
+        assert!(db.update_deployment(&d).await.is_err());
+
+        db.remove_deployment(d.id).await.unwrap();
+        assert_eq!(db.deployment(d.id).await.unwrap(), None);
+        assert!(db.remove_deployment(d.id).await.is_err());
+    }
+
+    #[test]
+    fn parse_cursor() {
+        let c: Cursor = "1639059643089-9ef2e080-f0b4_sensor.db.json"
+            .parse()
+            .unwrap();
+        assert_eq!(c.received, 1639059643089);
+        assert_eq!(c.event, "9ef2e080-f0b4_sensor.db.json");
+        assert_eq!(c.to_string(), "1639059643089-9ef2e080-f0b4_sensor.db.json");
+
+        assert!("entry".parse::<Cursor>().is_err());
+        assert!("a-entry".parse::<Cursor>().is_err());
+    }
+
+    #[tokio::test]
+    async fn append_get_axl_range() {
+        let db = Database::temporary().await;
+        let mut b = db.buoy("buoy-01").await.unwrap();
+        b.append(None, "entry-0", 0, Some("axl.qo".into()), "data-0")
+            .await
+            .unwrap();
+        b.append(None, "entry-1", 1, Some("sessi.qo".into()), "data-1")
+            .await
+            .unwrap();
+        b.append(None, "entry-2", 2, Some("axl.qo".into()), "data-2")
+            .await
+            .unwrap();
+        b.append(None, "entry-3", 3, Some("axl.qo".into()), "data-3")
+            .await
+            .unwrap();
+
+        let r = b.get_axl_range(0, 2).await.unwrap();
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].event, "entry-0");
+        assert_eq!(r[1].event, "entry-2");
+    }
+
     #[tokio::test]
     async fn append_last() {
         let db = Database::temporary().await;
@@ -0,0 +1,454 @@
+//! End-points for deployments of buoys.
+
+use crate::buoys::{check_read_token, check_token, with_state, AppendErrors, RangeQuery};
+use crate::database::Deployment;
+use crate::State;
+use serde::Deserialize;
+use warp::{http::StatusCode, reject, Filter, Reply};
+
+pub fn filters(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    list(state.clone())
+        .or(get(state.clone()))
+        .or(add(state.clone()))
+        .or(update(state.clone()))
+        .or(remove(state.clone()))
+        .or(range(state.clone()))
+        .or(list_range(state.clone()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeploymentsQuery {
+    /// Only deployments of this buoy.
+    pub dev: Option<String>,
+}
+
+pub fn list(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("deployments")
+        .and(warp::get())
+        .and(check_read_token(state.clone()))
+        .and(warp::query::<DeploymentsQuery>())
+        .and(with_state(state.clone()))
+        .and_then(handlers::list)
+}
+
+pub fn get(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("deployments" / i64)
+        .and(warp::get())
+        .and(check_read_token(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(handlers::get)
+}
+
+pub fn add(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("deployments")
+        .and(warp::post())
+        .and(check_token(state.clone()))
+        .and(warp::body::content_length_limit(64 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(handlers::add)
+}
+
+pub fn update(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("deployments" / i64)
+        .and(warp::put())
+        .and(check_token(state.clone()))
+        .and(warp::body::content_length_limit(64 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(handlers::update)
+}
+
+pub fn remove(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("deployments" / i64)
+        .and(warp::delete())
+        .and(check_token(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(handlers::remove)
+}
+
+/// Events of the buoy during the deployment, see `buoys::range`.
+pub fn range(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("deployments" / i64 / "events")
+        .and(warp::get())
+        .and(check_read_token(state.clone()))
+        .and(warp::query::<RangeQuery>())
+        .and(with_state(state.clone()))
+        .and_then(handlers::range)
+}
+
+/// List of events of the buoy during the deployment, see `buoys::list_range`.
+pub fn list_range(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("deployments" / i64 / "list")
+        .and(warp::get())
+        .and(check_read_token(state.clone()))
+        .and(warp::query::<RangeQuery>())
+        .and(with_state(state.clone()))
+        .and_then(handlers::list_range)
+}
+
+pub mod handlers {
+    use super::*;
+    use crate::buoys::handlers::{stream_list_range, stream_range};
+    use sanitize_filename::sanitize;
+
+    async fn get_deployment(id: i64, state: &State) -> Result<Option<Deployment>, warp::Rejection> {
+        state
+            .db
+            .deployment(id)
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))
+    }
+
+    pub async fn list(
+        query: DeploymentsQuery,
+        state: State,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let dev = query.dev.map(sanitize);
+
+        let deployments = state
+            .db
+            .deployments(dev.as_deref())
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))?;
+
+        Ok(warp::reply::json(&deployments))
+    }
+
+    pub async fn get(id: i64, state: State) -> Result<warp::reply::Response, warp::Rejection> {
+        match get_deployment(id, &state).await? {
+            Some(deployment) => Ok(warp::reply::json(&deployment).into_response()),
+            None => Ok(StatusCode::NOT_FOUND.into_response()),
+        }
+    }
+
+    pub async fn add(
+        deployment: Deployment,
+        state: State,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        let mut deployment = Deployment {
+            dev: sanitize(&deployment.dev),
+            ..deployment
+        };
+
+        if let Err(e) = deployment.validate() {
+            warn!("bad deployment: {:?}", e);
+            return Ok(StatusCode::BAD_REQUEST.into_response());
+        }
+
+        deployment.id = state.db.add_deployment(&deployment).await.map_err(|e| {
+            error!("failed to add deployment: {:?}", e);
+            reject::custom(AppendErrors::Database)
+        })?;
+
+        Ok(warp::reply::json(&deployment).into_response())
+    }
+
+    pub async fn update(
+        id: i64,
+        deployment: Deployment,
+        state: State,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        if get_deployment(id, &state).await?.is_none() {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        }
+
+        let deployment = Deployment {
+            id,
+            dev: sanitize(&deployment.dev),
+            ..deployment
+        };
+
+        if let Err(e) = deployment.validate() {
+            warn!("bad deployment: {:?}", e);
+            return Ok(StatusCode::BAD_REQUEST.into_response());
+        }
+
+        state.db.update_deployment(&deployment).await.map_err(|e| {
+            error!("failed to update deployment: {:?}", e);
+            reject::custom(AppendErrors::Database)
+        })?;
+
+        Ok(warp::reply::json(&deployment).into_response())
+    }
+
+    pub async fn remove(id: i64, state: State) -> Result<warp::reply::Response, warp::Rejection> {
+        if get_deployment(id, &state).await?.is_none() {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        }
+
+        state.db.remove_deployment(id).await.map_err(|e| {
+            error!("failed to remove deployment: {:?}", e);
+            reject::custom(AppendErrors::Database)
+        })?;
+
+        Ok("".into_response())
+    }
+
+    pub async fn range(
+        id: i64,
+        query: RangeQuery,
+        state: State,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        let deployment = match get_deployment(id, &state).await? {
+            Some(deployment) => deployment,
+            None => return Ok(StatusCode::NOT_FOUND.into_response()),
+        };
+        let (from, to) = deployment.range();
+
+        let buoy = state
+            .db
+            .buoy(&deployment.dev)
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))?;
+
+        stream_range(buoy, from, to, query).await
+    }
+
+    pub async fn list_range(
+        id: i64,
+        query: RangeQuery,
+        state: State,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        let deployment = match get_deployment(id, &state).await? {
+            Some(deployment) => deployment,
+            None => return Ok(StatusCode::NOT_FOUND.into_response()),
+        };
+        let (from, to) = deployment.range();
+
+        let buoy = state
+            .db
+            .buoy(&deployment.dev)
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))?;
+
+        stream_list_range(buoy, from, to, query).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buoys::B64Event;
+    use serde_json as json;
+
+    #[tokio::test]
+    async fn deployment_crud() {
+        let state = crate::test_state().await;
+        let f = filters(state);
+
+        let d = json::json!({
+            "dev": "dev864475044203262",
+            "campaign": "surf-2022",
+            "start_time": 1000,
+            "end_time": 2000,
+            "features": "raw,ext-gps",
+        });
+
+        // Read token can not add.
+        let res = warp::test::request()
+            .path("/deployments")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .json(&d)
+            .reply(&f)
+            .await;
+
+        assert!(res.status() != 200);
+
+        let res = warp::test::request()
+            .path("/deployments")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .json(&d)
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+        let d: Deployment = json::from_slice(res.body()).unwrap();
+        assert_eq!(d.campaign.as_deref(), Some("surf-2022"));
+        assert_eq!(d.lon, None);
+
+        let res = warp::test::request()
+            .path(&format!("/deployments/{}", d.id))
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+        assert_eq!(json::from_slice::<Deployment>(res.body()).unwrap(), d);
+
+        let update = Deployment {
+            end_time: None,
+            ..d.clone()
+        };
+        let res = warp::test::request()
+            .path(&format!("/deployments/{}", d.id))
+            .method("PUT")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .json(&update)
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let res = warp::test::request()
+            .path("/deployments?dev=dev864475044203262")
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+        let ds: Vec<Deployment> = json::from_slice(res.body()).unwrap();
+        assert_eq!(ds, [update]);
+
+        let res = warp::test::request()
+            .path("/deployments?dev=other")
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.body(), "[]");
+
+        // Ends before it starts.
+        let bad = Deployment {
+            end_time: Some(0),
+            ..d.clone()
+        };
+        let res = warp::test::request()
+            .path(&format!("/deployments/{}", d.id))
+            .method("PUT")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .json(&bad)
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 400);
+
+        let res = warp::test::request()
+            .path(&format!("/deployments/{}", d.id))
+            .method("DELETE")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let res = warp::test::request()
+            .path(&format!("/deployments/{}", d.id))
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn deployment_range() {
+        let state = crate::test_state().await;
+        let f = crate::buoys::filters(state.clone()).or(filters(state.clone()));
+
+        let events = [
+            r#"{"received": 0, "event": "event-0", "device": "0", "name": "0", "file": "axl.qo" }"#,
+            r#"{"received": 1, "event": "event-1", "device": "0", "name": "0", "file": "axl.qo" }"#,
+            r#"{"received": 2, "event": "event-2", "device": "0", "name": "0", "file": "axl.qo" }"#,
+            r#"{"received": 3, "event": "event-3", "device": "0", "name": "0", "file": "axl.qo" }"#,
+        ];
+
+        for e0 in events {
+            let res = warp::test::request()
+                .path("/buoy")
+                .method("POST")
+                .header("SFY_AUTH_TOKEN", "token1")
+                .body(&e0)
+                .reply(&f)
+                .await;
+
+            assert_eq!(res.status(), 200);
+        }
+
+        let id = state
+            .db
+            .add_deployment(&Deployment {
+                id: 0,
+                dev: "0".into(),
+                campaign: None,
+                name: None,
+                start_time: 1000,
+                end_time: None,
+                lon: None,
+                lat: None,
+                hardware: None,
+                features: None,
+                description: None,
+            })
+            .await
+            .unwrap();
+
+        let res = warp::test::request()
+            .path(&format!("/deployments/{}/events", id))
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let revents: Vec<B64Event> = json::from_slice(res.body()).unwrap();
+        assert_eq!(revents.len(), 3);
+        assert_eq!(revents[0].received, 1000);
+
+        let res = warp::test::request()
+            .path(&format!("/deployments/{}/list?limit=1", id))
+            .method("GET")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let revents: Vec<(String, String)> = json::from_slice(res.body()).unwrap();
+        assert_eq!(
+            &revents,
+            &[("1000-event-1_axl.qo.json".into(), "axl.qo".into())]
+        );
+        assert_eq!(
+            res.headers().get("X-Next").unwrap().to_str().unwrap(),
+            "1000-event-1_axl.qo.json"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Health and status of buoys from `status.qo` notes.
+//!
+//! The body of the note is the `StatusNote` from `sfy-buoy/src/status.rs`. The notes are stored
+//! as events like all other notes, and parsed into the `health` table when they are received.
+
+use crate::buoys::{check_read_token, with_state, AppendErrors};
+use crate::database::Health;
+use crate::State;
+use serde::Deserialize;
+use serde_json as json;
+use warp::{reject, Filter};
+
+pub fn filters(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    health_range(state.clone())
+}
+
+/// The body of a `status.qo` note. The Notecard drops fields that are zero.
+#[derive(Debug, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct StatusNote {
+    pub uptime: u32,
+    pub resets: u32,
+    pub reset_reason: Option<String>,
+    pub voltage: f32,
+    pub imu_temperature: f32,
+    pub next_id: Option<u32>,
+    pub sd_free: Option<u32>,
+    pub storage_queue: u32,
+    pub note_queue: u32,
+    pub egps_queue: u32,
+    pub fifo_overrun: u32,
+    pub too_few_samples: u32,
+}
+
+impl StatusNote {
+    pub fn into_health(self, dev: &str, received: i64, event: &str) -> Health {
+        Health {
+            dev: dev.to_string(),
+            received,
+            event: event.to_string(),
+            uptime: self.uptime.into(),
+            resets: self.resets.into(),
+            reset_reason: self.reset_reason,
+            voltage: self.voltage.into(),
+            imu_temperature: self.imu_temperature.into(),
+            next_id: self.next_id.map(Into::into),
+            sd_free: self.sd_free.map(Into::into),
+            storage_queue: self.storage_queue.into(),
+            note_queue: self.note_queue.into(),
+            egps_queue: self.egps_queue.into(),
+            fifo_overrun: self.fifo_overrun.into(),
+            too_few_samples: self.too_few_samples.into(),
+        }
+    }
+}
+
+/// Parse the health from the `body` of a Notehub `status.qo` event.
+pub fn parse(dev: &str, received: i64, event: &str, body: &json::Value) -> eyre::Result<Health> {
+    let note = body.get("body").cloned().unwrap_or(json::Value::Null);
+    let note: StatusNote = if note.is_null() {
+        StatusNote::default()
+    } else {
+        json::from_value(note)?
+    };
+
+    Ok(note.into_health(dev, received, event))
+}
+
+pub fn health_range(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("buoys" / String / "health" / "from" / i64 / "to" / i64)
+        .and(warp::get())
+        .and(check_read_token(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(handlers::health_range)
+}
+
+pub mod handlers {
+    use super::*;
+    use sanitize_filename::sanitize;
+
+    pub async fn health_range(
+        buoy: String,
+        from: i64,
+        to: i64,
+        state: State,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let buoy = sanitize(buoy);
+
+        let health = state
+            .db
+            .health(&buoy, from, to)
+            .await
+            .map_err(|_| reject::custom(AppendErrors::Internal))?;
+
+        Ok(warp::reply::json(&health))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status() {
+        let event = std::fs::read("tests/events/status.qo_01.json").unwrap();
+        let event: json::Value = json::from_slice(&event).unwrap();
+
+        let h = parse("dev864475044203262", 1667300000500, "e", &event).unwrap();
+        assert_eq!(h.uptime, 7200);
+        assert_eq!(h.resets, 2);
+        assert_eq!(h.reset_reason.as_deref(), Some("watchdog"));
+        assert!((h.voltage - 4.12).abs() < 1e-6);
+        assert_eq!(h.next_id, Some(12000));
+        assert_eq!(h.note_queue, 3);
+
+        // Dropped by the Notecard since they are zero.
+        assert_eq!(h.storage_queue, 0);
+        assert_eq!(h.fifo_overrun, 0);
+    }
+
+    #[tokio::test]
+    async fn append_status() {
+        let state = crate::test_state().await;
+        let event = std::fs::read("tests/events/status.qo_01.json").unwrap();
+
+        let res = warp::test::request()
+            .path("/buoy")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .body(&event)
+            .reply(&crate::buoys::filters(state.clone()))
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let f = filters(state.clone());
+
+        let res = warp::test::request()
+            .path("/buoys/dev864475044203262/health/from/0/to/1767300000000")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .reply(&f)
+            .await;
+        assert!(res.status() != 200);
+
+        let res = warp::test::request()
+            .path("/buoys/dev864475044203262/health/from/0/to/1767300000000")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let health: Vec<Health> = json::from_slice(res.body()).unwrap();
+        assert_eq!(health.len(), 1);
+        assert_eq!(health[0].received, 1667300000500);
+        assert_eq!(health[0].too_few_samples, 1);
+        assert_eq!(health[0].sd_free, Some(30200));
+
+        let res = warp::test::request()
+            .path("/buoys/dev864475044203262/health/from/0/to/1000")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .reply(&f)
+            .await;
+        let health: Vec<Health> = json::from_slice(res.body()).unwrap();
+        assert!(health.is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+//! Live push of incoming events to subscribers.
+//!
+//! Every event accepted by the append end-points is published on a broadcast channel, subscribers
+//! (e.g. the Server-Sent Events end-point) receive the events published after they subscribed.
+//! Subscribers that fall behind miss events rather than holding up the append end-points.
+
+use crate::buoys::B64Event;
+use futures_util::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Number of events kept for slow subscribers.
+pub const LIVE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LiveEvent {
+    pub dev: String,
+    pub buoy_type: String,
+    pub message_type: String,
+
+    #[serde(flatten)]
+    pub event: B64Event,
+}
+
+/// Which events a subscriber wants, the fields are comma separated lists. All events are matched
+/// if a field is not set.
+#[derive(Debug, Default, Deserialize)]
+pub struct LiveFilter {
+    pub dev: Option<String>,
+    pub message_type: Option<String>,
+}
+
+impl LiveFilter {
+    pub fn matches(&self, event: &LiveEvent) -> bool {
+        fn contains(list: &Option<String>, v: &str) -> bool {
+            list.as_ref()
+                .map_or(true, |list| list.split(',').any(|l| l.trim() == v))
+        }
+
+        contains(&self.dev, &event.dev) && contains(&self.message_type, &event.message_type)
+    }
+}
+
+pub struct Live {
+    tx: broadcast::Sender<Arc<LiveEvent>>,
+}
+
+impl Live {
+    pub fn new() -> Live {
+        let (tx, _) = broadcast::channel(LIVE_CAPACITY);
+
+        Live { tx }
+    }
+
+    /// Publish event to current subscribers.
+    pub fn publish(&self, event: LiveEvent) {
+        // Fails if there are no subscribers, which is fine.
+        if let Ok(n) = self.tx.send(Arc::new(event)) {
+            trace!("published live event to {} subscribers", n);
+        }
+    }
+
+    /// Subscribe to the events matching `filter`.
+    pub fn subscribe(&self, filter: LiveFilter) -> impl Stream<Item = Arc<LiveEvent>> + Send {
+        let rx = self.tx.subscribe();
+
+        stream::unfold((rx, filter), |(mut rx, filter)| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) if filter.matches(&event) => return Some((event, (rx, filter))),
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("live subscriber lagging behind, skipped {} events", n);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    fn event(dev: &str, message_type: &str) -> LiveEvent {
+        LiveEvent {
+            dev: dev.into(),
+            buoy_type: "sfy".into(),
+            message_type: message_type.into(),
+            event: B64Event {
+                received: 0,
+                event: "event-0".into(),
+                data: None,
+            },
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let all = LiveFilter::default();
+        assert!(all.matches(&event("dev01", "axl.qo")));
+
+        let f = LiveFilter {
+            dev: Some("dev01,dev02".into()),
+            message_type: Some("axl.qo".into()),
+        };
+        assert!(f.matches(&event("dev01", "axl.qo")));
+        assert!(f.matches(&event("dev02", "axl.qo")));
+        assert!(!f.matches(&event("dev03", "axl.qo")));
+        assert!(!f.matches(&event("dev01", "_track.qo")));
+    }
+
+    #[tokio::test]
+    async fn publish_subscribe() {
+        let live = Live::new();
+
+        // No subscribers.
+        live.publish(event("dev01", "axl.qo"));
+
+        let s = live.subscribe(LiveFilter {
+            dev: Some("dev02".into()),
+            message_type: None,
+        });
+        futures_util::pin_mut!(s);
+
+        live.publish(event("dev01", "axl.qo"));
+        live.publish(event("dev02", "axl.qo"));
+
+        assert_eq!(*s.next().await.unwrap(), event("dev02", "axl.qo"));
+    }
+}
@@ -21,13 +21,22 @@ struct Sfy {
     config: PathBuf,
 }
 
+mod axl;
 mod buoys;
 mod config;
 mod database;
+mod deployments;
+mod health;
+mod live;
+mod nc;
+mod requests;
+mod rice;
+mod sbd;
 
 pub struct SfyState {
     pub db: database::Database,
     pub config: config::Config,
+    pub live: live::Live,
 }
 
 pub type State = Arc<SfyState>;
@@ -50,14 +59,16 @@ async fn main() -> eyre::Result<()> {
     let state = Arc::new(SfyState {
         db: database,
         config: config.clone(),
+        live: live::Live::new(),
     });
 
     info!("listening on: {:?}", config.address);
 
     let cors = warp::cors()
         .allow_any_origin()
-        .allow_methods(&[Method::GET, Method::POST])
-        .allow_headers(["SFY_AUTH_TOKEN"]);
+        .allow_methods(&[Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers(["SFY_AUTH_TOKEN", "Content-Type"])
+        .expose_headers(["X-Next"]);
 
     if let Some(dir) = config.files {
         info!("serving files in directory: {:?}", dir);
@@ -74,12 +85,18 @@ async fn main() -> eyre::Result<()> {
 
         let sfy = redirect.or(warp::path("sfy").and(warp::fs::dir(dir)));
         let api = sfy
-            .or(buoys::filters(state))
+            .or(buoys::filters(state.clone()))
+            .or(deployments::filters(state.clone()))
+            .or(health::filters(state.clone()))
+            .or(requests::filters(state))
             .with(cors)
             .with(warp::log("sfy_data::api"));
         warp::serve(api).run(config.address).await;
     } else {
-        let api = buoys::filters(state)
+        let api = buoys::filters(state.clone())
+            .or(deployments::filters(state.clone()))
+            .or(health::filters(state.clone()))
+            .or(requests::filters(state))
             .with(cors)
             .with(warp::log("sfy_data::api"));
         warp::serve(api).run(config.address).await;
@@ -93,7 +110,11 @@ async fn test_state() -> State {
     let config = config::Config::test_config();
     let db = database::Database::temporary().await;
 
-    let state = SfyState { config, db };
+    let state = SfyState {
+        config,
+        db,
+        live: live::Live::new(),
+    };
     let state = Arc::new(state);
 
     state
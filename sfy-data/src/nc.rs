@@ -0,0 +1,418 @@
+//! Export of decoded `axl.qo` events to CF-1.8 NetCDF files.
+//!
+//! The variables follow the naming used by `sfy-processing/sfy/timeseries.py` (`to_dataset`), so
+//! that the exported files can be used in place of the ones generated with the Python tools.
+//!
+//! The frequency is a global attribute, but the rate of the buoy may be changed and decimated
+//! packages are sent when the uplink is congested. A range with several frequencies or modes is
+//! split into segments of consecutive packages with the same frequency and mode, and each segment
+//! is written to its own group (`segment_0`, `segment_1`, ..) with the same layout as a file with
+//! a single segment.
+
+use crate::axl::Axl;
+use eyre::Result;
+use netcdf::GroupMut;
+use sfy_wire::Mode;
+
+/// Units of time variables.
+const TIME_UNITS: &str = "milliseconds since 1970-01-01 00:00:00 +00:00";
+
+/// Written for an unknown position.
+const FILL_POSITION: f64 = f64::NAN;
+
+/// Buoy information added to the global attributes.
+pub struct BuoyInfo<'a> {
+    pub dev: &'a str,
+    pub name: Option<&'a str>,
+}
+
+/// Split the packages into runs of consecutive packages with the same frequency and mode.
+pub fn segments(pcks: &[Axl]) -> Vec<&[Axl]> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+
+    for i in 1..=pcks.len() {
+        if i == pcks.len()
+            || pcks[i].meta.freq != pcks[start].meta.freq
+            || pcks[i].meta.mode != pcks[start].meta.mode
+        {
+            segments.push(&pcks[start..i]);
+            start = i;
+        }
+    }
+
+    segments
+}
+
+fn mode_name(mode: u32) -> &'static str {
+    match mode {
+        m if m == Mode::Full as u32 => "full",
+        m if m == Mode::Decimated as u32 => "decimated",
+        _ => "unknown",
+    }
+}
+
+/// The position of the package, `None` when the buoy had no position. The Notecard gives 0, 0
+/// when there is no fix.
+fn position(p: &Axl) -> Option<(f64, f64)> {
+    (p.meta.lon != 0. || p.meta.lat != 0.).then(|| (p.meta.lon, p.meta.lat))
+}
+
+/// Write the packages to a NetCDF file and return the contents.
+pub fn to_netcdf(buoy: &BuoyInfo, pcks: &[Axl]) -> Result<Vec<u8>> {
+    let tmp = tempfile::Builder::new().suffix(".nc").tempfile()?;
+    write_netcdf(tmp.path(), buoy, pcks)?;
+
+    Ok(std::fs::read(tmp.path())?)
+}
+
+fn write_netcdf(path: &std::path::Path, buoy: &BuoyInfo, pcks: &[Axl]) -> Result<()> {
+    let mut file = netcdf::create(path)?;
+    let segments = segments(pcks);
+
+    {
+        let mut root = file.root_mut().ok_or_else(|| eyre!("no root group"))?;
+        write_global(&mut root, buoy, pcks)?;
+
+        if segments.len() <= 1 {
+            return write_segment(&mut root, pcks);
+        }
+
+        root.add_attribute("number_of_segments", segments.len() as u32)?;
+    }
+
+    for (i, segment) in segments.into_iter().enumerate() {
+        let mut group = file.add_group(&format!("segment_{}", i))?;
+        write_global(&mut group, buoy, segment)?;
+        write_segment(&mut group, segment)?;
+    }
+
+    Ok(())
+}
+
+/// The global attributes, and the trajectory (the buoy) that the samples belong to.
+fn write_global(file: &mut GroupMut, buoy: &BuoyInfo, pcks: &[Axl]) -> Result<()> {
+    file.add_attribute("Conventions", "CF-1.8")?;
+    file.add_attribute("featureType", "trajectory")?;
+    file.add_attribute("title", "Small Friendly Buoy: wave acceleration")?;
+    file.add_attribute("homepage", "https://github.com/gauteh/sfy")?;
+    file.add_attribute("buoy_type", "sfy")?;
+    file.add_attribute("buoy_device", buoy.dev)?;
+    file.add_attribute("buoy_name", buoy.name.unwrap_or(""))?;
+    file.add_attribute("number_of_packages", pcks.len() as u32)?;
+
+    let mut var = file.add_string_variable("trajectory", &[])?;
+    var.add_attribute("cf_role", "trajectory_id")?;
+    var.add_attribute("long_name", "Device ID of the buoy")?;
+    var.put_string(buoy.dev, None)?;
+
+    Ok(())
+}
+
+/// Write packages with the same frequency and mode.
+fn write_segment(file: &mut GroupMut, pcks: &[Axl]) -> Result<()> {
+    let n: usize = pcks.iter().map(|p| p.time.len()).sum();
+
+    if let Some(p) = pcks.first() {
+        file.add_attribute("frequency", p.meta.freq)?;
+        file.add_attribute("frequency:unit", "Hz")?;
+        file.add_attribute("mode", mode_name(p.meta.mode))?;
+        file.add_attribute("package_length", p.time.len() as u32)?;
+        // The range is chosen for each package, use the largest.
+        let accel_range = pcks.iter().map(|p| p.meta.accel_range).fold(0f32, f32::max);
+        file.add_attribute("accel_range", accel_range)?;
+        file.add_attribute("accel_range:unit", "g")?;
+        file.add_attribute("gyro_range", p.meta.gyro_range)?;
+        file.add_attribute("gyro_range:unit", "dps")?;
+    }
+
+    file.add_dimension("time", n)?;
+    file.add_dimension("package", pcks.len())?;
+
+    // Time series
+    let time: Vec<f64> = pcks.iter().flat_map(|p| p.time.iter().copied()).collect();
+    let mut var = file.add_variable::<f64>("time", &["time"])?;
+    var.add_attribute("units", TIME_UNITS)?;
+    var.add_attribute("standard_name", "time")?;
+    var.add_attribute("long_name", "time")?;
+    var.add_attribute("calendar", "standard")?;
+    var.add_attribute("axis", "T")?;
+    var.put_values(&time, None, None)?;
+
+    let lon = |p: &Axl| position(p).map_or(FILL_POSITION, |(lon, _)| lon);
+    let lat = |p: &Axl| position(p).map_or(FILL_POSITION, |(_, lat)| lat);
+
+    // The position of each package is used for all of the samples in the package.
+    let values: Vec<f64> = pcks
+        .iter()
+        .flat_map(|p| std::iter::repeat(lon(p)).take(p.time.len()))
+        .collect();
+    let mut var = file.add_variable::<f64>("lon", &["time"])?;
+    var.add_attribute("units", "degrees_east")?;
+    var.add_attribute("standard_name", "longitude")?;
+    var.add_attribute("long_name", "longitude")?;
+    var.add_attribute("_FillValue", FILL_POSITION)?;
+    var.add_attribute("missing_value", FILL_POSITION)?;
+    var.put_values(&values, None, None)?;
+
+    let values: Vec<f64> = pcks
+        .iter()
+        .flat_map(|p| std::iter::repeat(lat(p)).take(p.time.len()))
+        .collect();
+    let mut var = file.add_variable::<f64>("lat", &["time"])?;
+    var.add_attribute("units", "degrees_north")?;
+    var.add_attribute("standard_name", "latitude")?;
+    var.add_attribute("long_name", "latitude")?;
+    var.add_attribute("_FillValue", FILL_POSITION)?;
+    var.add_attribute("missing_value", FILL_POSITION)?;
+    var.put_values(&values, None, None)?;
+
+    for (name, axis, description, values) in [
+        (
+            "w_x",
+            "x",
+            "Horizontal x-axis acceleration",
+            pcks.iter()
+                .flat_map(|p| p.x.iter().copied())
+                .collect::<Vec<f32>>(),
+        ),
+        (
+            "w_y",
+            "y",
+            "Horizontal y-axis acceleration",
+            pcks.iter().flat_map(|p| p.y.iter().copied()).collect(),
+        ),
+        (
+            "w_z",
+            "z",
+            "Vertical acceleration (upward, including gravity)",
+            pcks.iter().flat_map(|p| p.z.iter().copied()).collect(),
+        ),
+    ] {
+        let mut var = file.add_variable::<f32>(name, &["time"])?;
+        var.add_attribute("units", "m s-2")?;
+        var.add_attribute("long_name", format!("sea_water_wave_{}_acceleration", axis))?;
+        var.add_attribute("description", description)?;
+        var.add_attribute("coordinates", "time lat lon")?;
+        var.put_values(&values, None, None)?;
+    }
+
+    // Package variables
+    let package: Vec<u32> = (0..pcks.len() as u32).collect();
+    let mut var = file.add_variable::<u32>("package", &["package"])?;
+    var.add_attribute("long_name", "Package number")?;
+    var.put_values(&package, None, None)?;
+
+    let package_start: Vec<f64> = pcks.iter().map(|p| p.meta.timestamp as f64).collect();
+    let mut var = file.add_variable::<f64>("package_start", &["package"])?;
+    var.add_attribute("units", TIME_UNITS)?;
+    var.add_attribute(
+        "long_name",
+        "Timestamp at `offset` sample from the start of each batch (package) of samples.",
+    )?;
+    var.put_values(&package_start, None, None)?;
+
+    let offset: Vec<u32> = pcks.iter().map(|p| p.meta.offset).collect();
+    let mut var = file.add_variable::<u32>("offset", &["package"])?;
+    var.add_attribute(
+        "long_name",
+        "The sample offset in the package where the package_start timestamp is taken.",
+    )?;
+    var.put_values(&offset, None, None)?;
+
+    let received: Vec<f64> = pcks.iter().map(|p| p.received as f64).collect();
+    let mut var = file.add_variable::<f64>("received", &["package"])?;
+    var.add_attribute("units", TIME_UNITS)?;
+    var.add_attribute("long_name", "Time package was received by data-hub")?;
+    var.put_values(&received, None, None)?;
+
+    let position_time: Vec<f64> = pcks
+        .iter()
+        .map(|p| p.meta.position_time as f64 * 1000.)
+        .collect();
+    let mut var = file.add_variable::<f64>("position_time", &["package"])?;
+    var.add_attribute("units", TIME_UNITS)?;
+    var.add_attribute("long_name", "Time of position fix for each package")?;
+    var.put_values(&position_time, None, None)?;
+
+    let pck_lon: Vec<f64> = pcks.iter().map(lon).collect();
+    let mut var = file.add_variable::<f64>("pck_lon", &["package"])?;
+    var.add_attribute("units", "degrees_east")?;
+    var.add_attribute("standard_name", "longitude")?;
+    var.add_attribute("long_name", "Longitude of position fix for each package")?;
+    var.add_attribute("_FillValue", FILL_POSITION)?;
+    var.add_attribute("missing_value", FILL_POSITION)?;
+    var.put_values(&pck_lon, None, None)?;
+
+    let pck_lat: Vec<f64> = pcks.iter().map(lat).collect();
+    let mut var = file.add_variable::<f64>("pck_lat", &["package"])?;
+    var.add_attribute("units", "degrees_north")?;
+    var.add_attribute("standard_name", "latitude")?;
+    var.add_attribute("long_name", "Latitude of position fix for each package")?;
+    var.add_attribute("_FillValue", FILL_POSITION)?;
+    var.add_attribute("missing_value", FILL_POSITION)?;
+    var.put_values(&pck_lat, None, None)?;
+
+    let storage_id: Vec<i64> = pcks
+        .iter()
+        .map(|p| p.meta.storage_id.map(i64::from).unwrap_or(-1))
+        .collect();
+    let mut var = file.add_variable::<i64>("storage_id", &["package"])?;
+    var.add_attribute("long_name", "ID of package on SD-card")?;
+    var.add_attribute("_FillValue", -1i64)?;
+    var.put_values(&storage_id, None, None)?;
+
+    let temperature: Vec<f32> = pcks.iter().map(|p| p.meta.temperature).collect();
+    let mut var = file.add_variable::<f32>("temperature", &["package"])?;
+    var.add_attribute("units", "degree_Celsius")?;
+    var.add_attribute("long_name", "Temperature measured by IMU")?;
+    var.put_values(&temperature, None, None)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_axl_event() {
+        let event = std::fs::read(
+            "tests/events/1647870799330-1876870b-4708-4366-8db5-68f872cc4e6d_axl.qo.json",
+        )
+        .unwrap();
+        let axl = Axl::from_event(1647870799330, "event".into(), &event).unwrap();
+
+        let buoy = BuoyInfo {
+            dev: "dev867730051260788",
+            name: Some("WAVEBUG03"),
+        };
+
+        let nc = to_netcdf(&buoy, &[axl]).unwrap();
+
+        let tmp = tempfile::Builder::new().suffix(".nc").tempfile().unwrap();
+        std::fs::write(tmp.path(), &nc).unwrap();
+
+        let file = netcdf::open(tmp.path()).unwrap();
+        assert_eq!(file.dimension("time").unwrap().len(), 1024);
+        assert_eq!(file.dimension("package").unwrap().len(), 1);
+
+        let time = file.variable("time").unwrap();
+        let time = time.values::<f64>(None, None).unwrap();
+        assert_eq!(time.iter().next(), Some(&1647870457710.));
+
+        let w_z = file.variable("w_z").unwrap();
+        assert_eq!(w_z.len(), 1024);
+
+        let trajectory = file.variable("trajectory").unwrap();
+        assert!(trajectory.dimensions().is_empty());
+        assert!(matches!(
+            trajectory.attribute("cf_role").unwrap().value(),
+            Ok(netcdf::AttrValue::Str(r)) if r == "trajectory_id"
+        ));
+    }
+
+    #[test]
+    fn export_unknown_position() {
+        let event = std::fs::read(
+            "tests/events/1647870799330-1876870b-4708-4366-8db5-68f872cc4e6d_axl.qo.json",
+        )
+        .unwrap();
+        let mut axl = Axl::from_event(1647870799330, "event".into(), &event).unwrap();
+        axl.meta.lon = 0.;
+        axl.meta.lat = 0.;
+
+        let buoy = BuoyInfo {
+            dev: "dev867730051260788",
+            name: None,
+        };
+
+        let nc = to_netcdf(&buoy, &[axl]).unwrap();
+
+        let tmp = tempfile::Builder::new().suffix(".nc").tempfile().unwrap();
+        std::fs::write(tmp.path(), &nc).unwrap();
+
+        let file = netcdf::open(tmp.path()).unwrap();
+        for name in ["lon", "lat", "pck_lon", "pck_lat"] {
+            let var = file.variable(name).unwrap();
+            assert!(var.attribute("missing_value").is_some());
+            assert!(var
+                .values::<f64>(None, None)
+                .unwrap()
+                .iter()
+                .all(|v| v.is_nan()));
+        }
+    }
+
+    #[test]
+    fn export_mixed_modes() {
+        let event = std::fs::read(
+            "tests/events/1647870799330-1876870b-4708-4366-8db5-68f872cc4e6d_axl.qo.json",
+        )
+        .unwrap();
+        let full = || Axl::from_event(1647870799330, "event".into(), &event).unwrap();
+        let decimated = || {
+            let mut p = full();
+            p.meta.mode = Mode::Decimated as u32;
+            p.meta.freq /= 4.;
+            p.time = p.time.iter().copied().step_by(4).collect();
+            p.x = p.x.iter().copied().step_by(4).collect();
+            p.y = p.y.iter().copied().step_by(4).collect();
+            p.z = p.z.iter().copied().step_by(4).collect();
+            p
+        };
+
+        let pcks = [full(), decimated(), decimated(), full()];
+        let lens: Vec<_> = segments(&pcks).iter().map(|s| s.len()).collect();
+        assert_eq!(lens, [1, 2, 1]);
+
+        let buoy = BuoyInfo {
+            dev: "dev867730051260788",
+            name: None,
+        };
+
+        let nc = to_netcdf(&buoy, &pcks).unwrap();
+
+        let tmp = tempfile::Builder::new().suffix(".nc").tempfile().unwrap();
+        std::fs::write(tmp.path(), &nc).unwrap();
+
+        let file = netcdf::open(tmp.path()).unwrap();
+        assert!(file.variable("time").is_none());
+        assert!(matches!(
+            file.attribute("number_of_segments").unwrap().value(),
+            Ok(netcdf::AttrValue::Uint(3))
+        ));
+
+        let freq = pcks[0].meta.freq;
+        for (i, (freq, mode, n)) in [
+            (freq, "full", 1024),
+            (freq / 4., "decimated", 2 * 256),
+            (freq, "full", 1024),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let group = file.group(&format!("segment_{}", i)).unwrap().unwrap();
+            assert!(matches!(
+                group.attribute("frequency").unwrap().value(),
+                Ok(netcdf::AttrValue::Float(f)) if f == freq
+            ));
+            assert!(matches!(
+                group.attribute("mode").unwrap().value(),
+                Ok(netcdf::AttrValue::Str(m)) if m == mode
+            ));
+            assert_eq!(group.dimension("time").unwrap().len(), n);
+            assert_eq!(group.variable("w_z").unwrap().len(), n);
+        }
+    }
+
+    #[test]
+    fn export_empty() {
+        let buoy = BuoyInfo {
+            dev: "dev867730051260788",
+            name: None,
+        };
+
+        to_netcdf(&buoy, &[]).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+//! End-points for requesting packages stored on the SD-card of buoys.
+//!
+//! The request is written as the `request-data` note (or `request-gps` for the packages from the
+//! external GPS) in `storage.db` on the Notecard, the buoy then sends the stored packages in the
+//! range. The note is updated through the Notehub API if it is configured, otherwise the request
+//! is written to a file in the requests directory which can be sent to the buoy by other means
+//! (e.g. the Notehub web interface).
+
+use crate::buoys::{check_token, with_state, AppendErrors};
+use crate::State;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use std::path::PathBuf;
+use warp::{http::StatusCode, reject, Filter, Reply};
+
+/// Default directory for request files.
+pub const REQUESTS_DIR: &str = "requests";
+
+pub const NOTEHUB_API: &str = "https://api.notefile.net";
+
+pub fn filters(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    request_data(state.clone()).or(request_gps(state.clone()))
+}
+
+/// The series of stored packages to request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Request {
+    /// Data packages, `request-data`.
+    Data,
+
+    /// Packages from the external GPS, `request-gps`.
+    Gps,
+}
+
+impl Request {
+    /// The note in `storage.db` on the Notecard.
+    pub fn note(&self) -> &'static str {
+        match self {
+            Request::Data => "request-data",
+            Request::Gps => "request-gps",
+        }
+    }
+}
+
+/// Range of storage IDs to request, inclusive. Matches `RequestData` in `sfy-buoy/src/note.rs`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RequestData {
+    pub request_start: u32,
+    pub request_end: u32,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    Notehub,
+    File,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RequestResponse {
+    pub device: String,
+    pub method: Method,
+    pub note: &'static str,
+
+    #[serde(flatten)]
+    pub request: RequestData,
+}
+
+pub fn request_data(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("buoy" / String / "request-data")
+        .and(warp::post())
+        .and(check_token(state.clone()))
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(handlers::request_data)
+}
+
+pub fn request_gps(
+    state: State,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let state = state.clone();
+
+    warp::path!("buoy" / String / "request-gps")
+        .and(warp::post())
+        .and(check_token(state.clone()))
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(handlers::request_gps)
+}
+
+/// The buoys are stored under the sanitized Notehub device UID, e.g. `dev864475044203262` for
+/// `dev:864475044203262`.
+fn notehub_device(dev: &str) -> String {
+    match dev.strip_prefix("dev") {
+        Some(imei) if !imei.is_empty() && imei.chars().all(|c| c.is_ascii_digit()) => {
+            format!("dev:{}", imei)
+        }
+        _ => dev.to_string(),
+    }
+}
+
+/// The Notecard request that writes the `request-data` or `request-gps` note.
+fn note_request(kind: Request, request: &RequestData) -> json::Value {
+    json::json!({
+        "req": "note.update",
+        "file": "storage.db",
+        "note": kind.note(),
+        "body": request,
+    })
+}
+
+pub mod handlers {
+    use super::*;
+    use sanitize_filename::sanitize;
+
+    async fn notehub_request(
+        notehub: &crate::config::Notehub,
+        device: &str,
+        kind: Request,
+        request: &RequestData,
+    ) -> eyre::Result<()> {
+        let api = notehub.api.as_deref().unwrap_or(NOTEHUB_API);
+
+        let response: json::Value = reqwest::Client::new()
+            .post(format!("{}/req", api))
+            .query(&[("project", notehub.project.as_str()), ("device", device)])
+            .header("X-SESSION-TOKEN", &notehub.token)
+            .json(&note_request(kind, request))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        match response.get("err") {
+            Some(err) => Err(eyre!("notehub: {}", err)),
+            None => Ok(()),
+        }
+    }
+
+    async fn file_request(
+        dir: PathBuf,
+        dev: &str,
+        kind: Request,
+        request: &RequestData,
+    ) -> eyre::Result<()> {
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let path = dir.join(format!("{}-{}.json", dev, kind.note()));
+        info!("writing request to: {:?}", path);
+
+        tokio::fs::write(&path, json::to_vec_pretty(&note_request(kind, request))?).await?;
+
+        Ok(())
+    }
+
+    pub async fn request_data(
+        dev: String,
+        request: RequestData,
+        state: State,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        self::request(Request::Data, dev, request, state).await
+    }
+
+    pub async fn request_gps(
+        dev: String,
+        request: RequestData,
+        state: State,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        self::request(Request::Gps, dev, request, state).await
+    }
+
+    async fn request(
+        kind: Request,
+        dev: String,
+        request: RequestData,
+        state: State,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        let dev = sanitize(&dev);
+        let device = notehub_device(&dev);
+
+        if request.request_start > request.request_end {
+            warn!("bad request for {}: {:?}", dev, request);
+            return Ok(StatusCode::BAD_REQUEST.into_response());
+        }
+
+        info!("requesting {} from {}: {:?}", kind.note(), device, request);
+
+        let method = if let Some(notehub) = &state.config.notehub {
+            notehub_request(notehub, &device, kind, &request)
+                .await
+                .map_err(|e| {
+                    error!("failed to send request to notehub: {:?}", e);
+                    reject::custom(AppendErrors::Internal)
+                })?;
+
+            Method::Notehub
+        } else {
+            let dir = state
+                .config
+                .requests
+                .clone()
+                .unwrap_or_else(|| REQUESTS_DIR.into());
+
+            file_request(dir, &dev, kind, &request).await.map_err(|e| {
+                error!("failed to write request: {:?}", e);
+                reject::custom(AppendErrors::Internal)
+            })?;
+
+            Method::File
+        };
+
+        Ok(warp::reply::json(&RequestResponse {
+            device,
+            method,
+            note: kind.note(),
+            request,
+        })
+        .into_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn device_uid() {
+        assert_eq!(notehub_device("dev864475044203262"), "dev:864475044203262");
+        assert_eq!(notehub_device("imei300434063000000"), "imei300434063000000");
+        assert_eq!(notehub_device("dev"), "dev");
+    }
+
+    #[tokio::test]
+    async fn request_data_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut config = crate::config::Config::test_config();
+        config.requests = Some(dir.path().to_path_buf());
+
+        let state = Arc::new(crate::SfyState {
+            config,
+            db: crate::database::Database::temporary().await,
+            live: crate::live::Live::new(),
+        });
+
+        let f = filters(state);
+
+        let res = warp::test::request()
+            .path("/buoy/dev864475044203262/request-data")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "r-token1")
+            .json(&RequestData {
+                request_start: 10,
+                request_end: 20,
+            })
+            .reply(&f)
+            .await;
+
+        assert!(res.status() != 200);
+
+        let res = warp::test::request()
+            .path("/buoy/dev864475044203262/request-data")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .json(&RequestData {
+                request_start: 20,
+                request_end: 10,
+            })
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 400);
+
+        let res = warp::test::request()
+            .path("/buoy/dev864475044203262/request-data")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .json(&RequestData {
+                request_start: 10,
+                request_end: 20,
+            })
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let r: json::Value = json::from_slice(res.body()).unwrap();
+        assert_eq!(r["device"], "dev:864475044203262");
+        assert_eq!(r["method"], "file");
+
+        let req = std::fs::read(dir.path().join("dev864475044203262-request-data.json")).unwrap();
+        let req: json::Value = json::from_slice(&req).unwrap();
+        assert_eq!(req["req"], "note.update");
+        assert_eq!(req["note"], "request-data");
+        assert_eq!(req["body"]["request_start"], 10);
+        assert_eq!(req["body"]["request_end"], 20);
+    }
+
+    #[tokio::test]
+    async fn request_gps_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut config = crate::config::Config::test_config();
+        config.requests = Some(dir.path().to_path_buf());
+
+        let state = Arc::new(crate::SfyState {
+            config,
+            db: crate::database::Database::temporary().await,
+            live: crate::live::Live::new(),
+        });
+
+        let f = filters(state);
+
+        let res = warp::test::request()
+            .path("/buoy/dev864475044203262/request-gps")
+            .method("POST")
+            .header("SFY_AUTH_TOKEN", "token1")
+            .json(&RequestData {
+                request_start: 3,
+                request_end: 5,
+            })
+            .reply(&f)
+            .await;
+
+        assert_eq!(res.status(), 200);
+
+        let r: json::Value = json::from_slice(res.body()).unwrap();
+        assert_eq!(r["method"], "file");
+        assert_eq!(r["note"], "request-gps");
+
+        let req = std::fs::read(dir.path().join("dev864475044203262-request-gps.json")).unwrap();
+        let req: json::Value = json::from_slice(&req).unwrap();
+        assert_eq!(req["file"], "storage.db");
+        assert_eq!(req["note"], "request-gps");
+        assert_eq!(req["body"]["request_start"], 3);
+        assert_eq!(req["body"]["request_end"], 5);
+
+        assert!(!dir
+            .path()
+            .join("dev864475044203262-request-data.json")
+            .exists());
+    }
+}
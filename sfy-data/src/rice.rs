@@ -0,0 +1,51 @@
+//! Decoding of delta and Rice coded samples (codec 1 in `axl.qo` notes since version 8).
+//!
+//! The decoder is shared with the buoy, see `sfy_wire::rice` and `sfy-buoy/src/rice.rs` for the
+//! format.
+
+use eyre::Result;
+
+use crate::axl::SAMPLE_SZ;
+
+/// Decompress `len` interleaved values.
+pub fn decode(data: &[u8], len: usize) -> Result<Vec<u16>> {
+    ensure!(
+        len % SAMPLE_SZ == 0,
+        "number of samples: {}, is not a multiple of {}",
+        len,
+        SAMPLE_SZ
+    );
+
+    let mut out = vec![0u16; len];
+    sfy_wire::rice::decode(data, &mut out)
+        .map_err(|e| eyre!("failed to decode compressed samples: {:?}", e))?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_stream() {
+        // Axis values [1, 2], [2, 2] and [3, 2]: residuals 1, 0 and -1, zig-zag 2, 0 and 1 with
+        // k = 1.
+        let bits = "0000000000000001 0001 100 \
+                    0000000000000010 0001 00 \
+                    0000000000000011 0001 01";
+        let bits: String = bits.chars().filter(|c| !c.is_whitespace()).collect();
+        let bytes: Vec<u8> = bits
+            .as_bytes()
+            .chunks(8)
+            .map(|b| {
+                let s = std::str::from_utf8(b).unwrap();
+                u8::from_str_radix(&format!("{:0<8}", s), 2).unwrap()
+            })
+            .collect();
+
+        assert_eq!(decode(&bytes, 6).unwrap(), vec![1, 2, 3, 2, 2, 2]);
+        assert!(decode(&bytes[..4], 6).is_err());
+        assert!(decode(&bytes, 4).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+//! Decoding of the Iridium SBD messages from the buoys, as delivered by the RockBLOCK web service.
+//!
+//! The messages are decoded and stored as events similar to the ones from notehub, with the
+//! decoded message as the body and the RockBLOCK fields in `sbd`.
+//!
+//! > Keep in sync with `sfy-buoy/src/iridium/wire.rs`.
+
+use chrono::NaiveDateTime;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+pub const SBD_VERSION: u8 = 1;
+
+/// Range of the log10 scaled spectrum.
+pub const SPEC_LOG_MIN: f64 = -10.;
+pub const SPEC_LOG_MAX: f64 = 4.;
+
+/// The fields posted by the RockBLOCK web service.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RockBlockMessage {
+    pub imei: String,
+    pub momsn: u32,
+
+    /// UTC, formatted as `YY-MM-DD HH:MM:SS`.
+    pub transmit_time: String,
+    pub iridium_latitude: f64,
+    pub iridium_longitude: f64,
+
+    /// Accuracy of the Iridium position [km].
+    pub iridium_cep: f64,
+
+    /// Hex-encoded message.
+    pub data: String,
+}
+
+/// A decoded message, ready to be stored.
+pub struct SbdEvent {
+    pub device: String,
+    pub event: String,
+    pub file: String,
+    pub received: u64,
+
+    /// The event as JSON.
+    pub data: Vec<u8>,
+}
+
+impl RockBlockMessage {
+    /// The time of the transmission in ms.
+    pub fn received(&self) -> Result<u64> {
+        let t = NaiveDateTime::parse_from_str(&self.transmit_time, "%y-%m-%d %H:%M:%S")?;
+        Ok(t.timestamp_millis() as u64)
+    }
+
+    pub fn event(&self) -> Result<SbdEvent> {
+        let data = hex::decode(&self.data)?;
+        let message = Message::decode(&data)?;
+        let received = self.received()?;
+
+        let device = format!("imei:{}", self.imei);
+        let event = format!("{}-{}", self.imei, self.momsn);
+        let file = message.file().to_string();
+
+        let data = json::to_vec(&json::json!({
+            "event": event,
+            "device": device,
+            "file": file,
+            "received": received as f64 / 1000.,
+            "body": message,
+            "sbd": self,
+        }))?;
+
+        Ok(SbdEvent {
+            device,
+            event,
+            file,
+            received,
+            data,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Header {
+    /// Timestamp [ms].
+    pub timestamp: i64,
+
+    /// Time of position [s].
+    pub position_time: u32,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Spectrum {
+    #[serde(flatten)]
+    pub header: Header,
+
+    /// Number of packages averaged.
+    pub packages: u16,
+
+    /// Frequency resolution, bin `i` is at `(i + 1) * df` [Hz].
+    pub df: f32,
+
+    /// Significant wave height [m].
+    pub hs: Option<f32>,
+
+    /// Peak and mean periods [s].
+    pub tp: Option<f32>,
+    pub tm01: Option<f32>,
+    pub tm02: Option<f32>,
+
+    /// Mean direction and spreading, relative to the AHRS frame [deg].
+    pub mdir: Option<f32>,
+    pub spread: Option<f32>,
+
+    /// Vertical displacement spectrum [m^2/Hz].
+    pub spec: Vec<f64>,
+
+    /// Directional moments.
+    pub a1: Vec<Option<f32>>,
+    pub b1: Vec<Option<f32>>,
+    pub a2: Vec<Option<f32>>,
+    pub b2: Vec<Option<f32>>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum Message {
+    Position(Header),
+    Spectrum(Spectrum),
+}
+
+/// Reads little endian values from the message.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        ensure!(self.0.len() >= N, "message is too short");
+
+        let (v, rest) = self.0.split_at(N);
+        self.0 = rest;
+
+        Ok(v.try_into()?)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn i8(&mut self) -> Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+
+    /// Scaled u16, `u16::MAX` is missing.
+    fn scaled_u16(&mut self, scale: f32) -> Result<Option<f32>> {
+        Ok(match self.u16()? {
+            u16::MAX => None,
+            v => Some(v as f32 / scale),
+        })
+    }
+
+    /// Log10 scaled spectral density.
+    fn log16(&mut self) -> Result<f64> {
+        Ok(match self.u16()? {
+            0 => 0.,
+            v => 10f64.powf(
+                SPEC_LOG_MIN
+                    + (v - 1) as f64 / (u16::MAX - 1) as f64 * (SPEC_LOG_MAX - SPEC_LOG_MIN),
+            ),
+        })
+    }
+
+    /// Directional moment scaled by 127, `i8::MIN` is missing.
+    fn moment8(&mut self) -> Result<Option<f32>> {
+        Ok(match self.i8()? {
+            i8::MIN => None,
+            v => Some(v as f32 / i8::MAX as f32),
+        })
+    }
+}
+
+impl Message {
+    pub fn decode(data: &[u8]) -> Result<Message> {
+        let mut r = Reader(data);
+
+        let tp = r.u8()?;
+        let version = r.u8()?;
+        ensure!(
+            version == SBD_VERSION,
+            "unsupported version of SBD message: {}",
+            version
+        );
+
+        let header = Header {
+            timestamp: r.u32()? as i64 * 1000,
+            position_time: r.u32()?,
+            lat: r.i32()? as f64 / 1e7,
+            lon: r.i32()? as f64 / 1e7,
+        };
+
+        match tp {
+            1 => Ok(Message::Position(header)),
+            2 => {
+                let packages = r.u16()?;
+                let df = r.f32()?;
+                let hs = r.scaled_u16(1000.)?;
+                let tp = r.scaled_u16(100.)?;
+                let tm01 = r.scaled_u16(100.)?;
+                let tm02 = r.scaled_u16(100.)?;
+                let mdir = match r.i16()? {
+                    i16::MIN => None,
+                    v => Some(v as f32 / 10.),
+                };
+                let spread = r.scaled_u16(10.)?;
+
+                let n = r.u8()? as usize;
+                let spec = (0..n).map(|_| r.log16()).collect::<Result<Vec<_>>>()?;
+
+                let (mut a1, mut b1, mut a2, mut b2) = (vec![], vec![], vec![], vec![]);
+                for _ in 0..n {
+                    a1.push(r.moment8()?);
+                    b1.push(r.moment8()?);
+                    a2.push(r.moment8()?);
+                    b2.push(r.moment8()?);
+                }
+
+                Ok(Message::Spectrum(Spectrum {
+                    header,
+                    packages,
+                    df,
+                    hs,
+                    tp,
+                    tm01,
+                    tm02,
+                    mdir,
+                    spread,
+                    spec,
+                    a1,
+                    b1,
+                    a2,
+                    b2,
+                }))
+            }
+            tp => Err(eyre!("unknown SBD message type: {}", tp)),
+        }
+    }
+
+    pub fn file(&self) -> &'static str {
+        match self {
+            Message::Position(_) => "pos.sbd",
+            Message::Spectrum(_) => "spec.sbd",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Position message from `sfy-buoy/src/iridium/wire.rs`.
+    pub const POSITION: &str = "0101f0f353654ff2536560c8fe23803bd4fc";
+
+    #[test]
+    fn decode_position() {
+        let data = hex::decode(POSITION).unwrap();
+        let m = Message::decode(&data).unwrap();
+
+        assert_eq!(
+            m,
+            Message::Position(Header {
+                timestamp: 1_700_000_752_000,
+                position_time: 1_700_000_335,
+                lat: 60.39,
+                lon: -5.32,
+            })
+        );
+        assert_eq!(m.file(), "pos.sbd");
+
+        assert!(Message::decode(&data[..10]).is_err());
+    }
+
+    #[test]
+    fn decode_spectrum() {
+        let mut data = vec![2, 1];
+        data.extend(1_700_000_000u32.to_le_bytes());
+        data.extend(1_700_000_000u32.to_le_bytes());
+        data.extend(603_900_000i32.to_le_bytes());
+        data.extend(53_200_000i32.to_le_bytes());
+        data.extend(60u16.to_le_bytes());
+        data.extend(0.05f32.to_le_bytes());
+        data.extend(1500u16.to_le_bytes());
+        data.extend(800u16.to_le_bytes());
+        data.extend(u16::MAX.to_le_bytes());
+        data.extend(600u16.to_le_bytes());
+        data.extend((-450i16).to_le_bytes());
+        data.extend(300u16.to_le_bytes());
+        data.push(2);
+        data.extend(0u16.to_le_bytes());
+        data.extend(u16::MAX.to_le_bytes());
+        data.extend([127, (-127i8) as u8, i8::MIN as u8, 0]);
+        data.extend([64, 0, 0, 0]);
+
+        let m = match Message::decode(&data).unwrap() {
+            Message::Spectrum(s) => s,
+            m => panic!("not a spectrum: {:?}", m),
+        };
+
+        assert_eq!(m.header.timestamp, 1_700_000_000_000);
+        assert_eq!(m.packages, 60);
+        assert_eq!(m.hs, Some(1.5));
+        assert_eq!(m.tp, Some(8.));
+        assert_eq!(m.tm01, None);
+        assert_eq!(m.mdir, Some(-45.));
+        assert_eq!(m.spread, Some(30.));
+        assert_eq!(m.spec[0], 0.);
+        assert!((m.spec[1] - 1e4).abs() < 1e-6);
+        assert_eq!(m.a1, [Some(1.), Some(64. / 127.)]);
+        assert_eq!(m.b1, [Some(-1.), Some(0.)]);
+        assert_eq!(m.a2, [None, Some(0.)]);
+
+        // Truncated.
+        assert!(Message::decode(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rockblock_event() {
+        let msg = RockBlockMessage {
+            imei: "300434063000000".into(),
+            momsn: 12,
+            transmit_time: "23-11-14 22:19:12".into(),
+            iridium_latitude: 60.4,
+            iridium_longitude: 5.3,
+            iridium_cep: 3.,
+            data: POSITION.into(),
+        };
+
+        let e = msg.event().unwrap();
+        assert_eq!(e.device, "imei:300434063000000");
+        assert_eq!(e.event, "300434063000000-12");
+        assert_eq!(e.file, "pos.sbd");
+        assert_eq!(e.received, 1_700_000_352_000);
+
+        let data: json::Value = json::from_slice(&e.data).unwrap();
+        assert_eq!(data["body"]["lat"], 60.39);
+        assert_eq!(data["sbd"]["momsn"], 12);
+    }
+}
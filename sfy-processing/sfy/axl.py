@@ -12,6 +12,7 @@ from datetime import datetime, timedelta
 
 from .timeseries import AxlTimeseries
 from .event import Event
+from . import rice
 
 logger = logging.getLogger(__name__)
 
@@ -94,7 +95,9 @@ class AxlCollection(AxlTimeseries):
 
     def segments(self, eps_gap=GAP_LIMIT):
         """
-        Return iterable of collections split at gaps (above eps) in packages.
+        Return iterable of collections split at gaps (above eps) in packages,
+        at the start of each burst, and where the packages change between full
+        and decimated.
         """
         pcks = self.pcks.copy()
         segment = []
@@ -103,7 +106,9 @@ class AxlCollection(AxlTimeseries):
             if len(segment) == 0:
                 segment.append(pcks.pop(0))
             elif np.abs(segment[-1].end.timestamp() -
-                        pcks[0].start.timestamp()) <= eps_gap:
+                        pcks[0].start.timestamp()) <= eps_gap and \
+                    segment[-1].burst_start == pcks[0].burst_start and \
+                    segment[-1].mode == pcks[0].mode:
                 segment.append(pcks.pop(0))
             else:
                 yield AxlCollection(segment, sorted_and_duplicates_removed=True)
@@ -258,9 +263,9 @@ class AxlCollection(AxlTimeseries):
         gyro_range = [ pck.gyro_range for pck in self.pcks if pck.gyro_range is not None ]
 
         if len(accel_range):
-            assert all((accel_range[0] == a for a in accel_range)), "varying acceleration range"
+            # The acceleration range is chosen for each package (since v8), use the largest.
             assert all((gyro_range[0] == g for g in gyro_range)), "varying gyro range"
-            accel_range = accel_range[0]
+            accel_range = max(accel_range)
             gyro_range = gyro_range[0]
 
             attrs['accel_range'] = accel_range
@@ -287,6 +292,8 @@ class Axl(Event, AxlTimeseries):
     freq: float = None
     accel_range: float = None # in [g]
     gyro_range: float = None  # in [dps]
+    burst_start: int = None  # milliseconds, start of burst, 0 when sampling continuously
+    mode: int = 0  # 0: full, 1: decimated (freq and offset are those of the decimated samples)
 
     # Acceleration in m/s^2
     x: np.ndarray = None
@@ -474,12 +481,21 @@ class Axl(Event, AxlTimeseries):
         data['freq'] = data['body'].get('freq', 208.)
         data['accel_range'] = data['body'].get('accel_range', 1.) # added in v6
         data['gyro_range'] = data['body'].get('gyro_range', 125.) # added in v6
+        data['burst_start'] = data['body'].get('burst_start', 0) # added in v7
+        data['mode'] = data['body'].get('mode', 0) # not tied to a version, full when missing
+        codec = data['body'].get('codec', 0) # added in v8
+        samples = data['body'].get('samples', 0) # added in v8
         del data['body']
 
         # decode x, y, z
         payload = payload[:data['length']]
         payload = base64.b64decode(payload)
 
+        if codec == 1:
+            payload = rice.decode(payload, samples).astype('<u2').tobytes()
+        elif codec != 0:
+            raise ValueError(f"unknown codec: {codec}")
+
         ACCEL_MAX = 2 * SENSORS_GRAVITY_STANDARD * data['accel_range'] # [m/s^2]
         GYRO_MAX = 2 * SENSORS_DPS_TO_RADS * data['gyro_range'] # [rad/s]
 
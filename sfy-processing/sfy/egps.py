@@ -230,6 +230,7 @@ class Egps(Event):
     lonlat_range: float = None  # in [deg * 1e7]
     msl_range: float = None  # in [mm]
     vel_range: float = None  # in [mm/s]
+    storage_id: int = None  # ID in the GPS collections on the SD-card
 
     # Position [m]
     n: np.ndarray = None
@@ -394,6 +395,7 @@ class Egps(Event):
         data['msl_range'] = data['body']['msl_range']
         data['vel_range'] = data['body'].get('vel_range',
                                              200.0 * 1.0e6 / 60. / 60)
+        data['storage_id'] = data['body'].get('storage_id')
         del data['body']
 
         # decode x, y, z
@@ -0,0 +1,83 @@
+"""
+Decoding of delta and Rice coded samples (codec 1 in `axl.qo` notes since version 8).
+
+See `sfy-buoy/src/rice.rs` for the format.
+
+> Keep in sync with `sfy-buoy/src/rice.rs` and `sfy-buoy/sfy-wire/src/rice.rs`.
+"""
+
+import numpy as np
+
+SAMPLE_SZ = 3
+
+# Number of residuals sharing a Rice parameter.
+BLOCK = 64
+
+# Length of the unary part that escapes to a 16 bit residual.
+ESCAPE = 24
+
+
+class BitReader:
+    def __init__(self, data: bytes):
+        self.data = data
+        self.pos = 0
+
+    def bit(self) -> int:
+        if self.pos // 8 >= len(self.data):
+            raise ValueError("compressed samples are truncated")
+
+        b = (self.data[self.pos // 8] >> (7 - self.pos % 8)) & 1
+        self.pos += 1
+        return b
+
+    def get(self, n: int) -> int:
+        v = 0
+        for _ in range(n):
+            v = (v << 1) | self.bit()
+        return v
+
+
+def unzigzag(u: int) -> int:
+    return (u >> 1) ^ -(u & 1)
+
+
+def decode(data: bytes, n: int) -> np.ndarray:
+    """
+    Decompress `n` interleaved values to an array of `np.uint16`.
+    """
+    if n % SAMPLE_SZ != 0:
+        raise ValueError(
+            f"number of samples: {n}, is not a multiple of {SAMPLE_SZ}")
+
+    m = n // SAMPLE_SZ
+    out = np.zeros((n, ), dtype=np.uint16)
+    r = BitReader(data)
+
+    for axis in range(SAMPLE_SZ):
+        if m == 0:
+            break
+
+        v = r.get(16)
+        out[axis] = v
+
+        i = 1
+        while i < m:
+            end = min(i + BLOCK, m)
+            k = r.get(4)
+
+            for j in range(i, end):
+                q = 0
+                while q < ESCAPE and r.bit() == 1:
+                    q += 1
+
+                if q == ESCAPE:
+                    u = r.get(16)
+                else:
+                    u = (q << k) | r.get(k)
+
+                v = (v + unzigzag(u)) & 0xffff
+                out[j * SAMPLE_SZ + axis] = v
+
+            i = end
+
+    return out
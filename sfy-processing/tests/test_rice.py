@@ -0,0 +1,33 @@
+import numpy as np
+import pytest
+from sfy import rice
+
+
+def to_bytes(bits):
+    bits = ''.join(bits.split())
+    bits += '0' * (-len(bits) % 8)
+    return bytes(int(bits[i:i + 8], 2) for i in range(0, len(bits), 8))
+
+
+def test_decode_stream():
+    # Axis values [1, 2], [2, 2] and [3, 2]: residuals 1, 0 and -1, zig-zag 2, 0 and 1 with k = 1.
+    d = to_bytes("""0000000000000001 0001 100
+                    0000000000000010 0001 00
+                    0000000000000011 0001 01""")
+
+    np.testing.assert_array_equal(rice.decode(d, 6), [1, 2, 3, 2, 2, 2])
+
+    with pytest.raises(ValueError):
+        rice.decode(d[:4], 6)
+
+    with pytest.raises(ValueError):
+        rice.decode(d, 4)
+
+
+def test_decode_escape():
+    # 0 -> 65535 is a residual of -1, 65535 -> 0 is 1, 0 -> 30000 escapes.
+    d = to_bytes('0000000000000000 0000 10 110 ' + '1' * 24 +
+                 format(30000 * 2, '016b') + ' 0000000000000000 0000 000' * 2)
+
+    np.testing.assert_array_equal(rice.decode(d, 12),
+                                  [0, 0, 0, 65535, 0, 0, 0, 0, 0, 30000, 0, 0])